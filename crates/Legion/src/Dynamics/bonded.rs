@@ -0,0 +1,638 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Dynamics::integrator::distance;
+use crate::ForceFields::potentials::{Coulomb, Harmonic, LennardJones, Morse, PairPotential};
+use crate::ForceFields::SIN::Elements;
+use crate::Topology::atom::Atomic;
+use crate::Topology::cell::ContainsParticles;
+use nalgebra::Vector3;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::f32::consts::PI;
+
+// Bonded interactions!  Bonds, angles, dihedrals and impropers, all worked out from who's bonded to who.
+// Parameters get set per element (by symbol, like DPD), either way round, then generate walks the connectivity graph
+// and makes a term for every bond i-j, angle i-j-k, dihedral i-j-k-l and improper (any atom with exactly three
+// neighbors) that has parameters.  The lists are public, so terms can be added or dropped by hand too.
+// Angles are all in degrees, and harmonic terms are k/2 (x - x0)^2 like GROMACS.
+//   Bonds:      harmonic, or Morse when they need to be able to break.
+//   Angles:     harmonic in the angle, or Urey-Bradley, which adds a spring between the two outer atoms.
+//   Dihedrals:  periodic, k (1 + cos(n phi - phase)), or Ryckaert-Bellemans, sum_n C_n cos^n(phi - 180) for n = 0..5.
+//   Impropers:  the same potentials in the dihedral angle of their four atoms, for keeping things flat (or chiral).
+//   Pairs:      Lennard-Jones plus Coulomb between two given atoms, for the 1-4 interactions force fields scale down.
+//               generate doesn't make these; the topology importers fill them in.
+// Dihedral angles are IUPAC: 0 is cis and 180 trans.
+// As a force term Bonded takes over the bonds, so the integrators stop pushing along them with the force field's pair
+// interactions.  Non-bonded terms should skip the 1-2, 1-3 and listed pairs too; exclusions lists them for Cell::exclude.
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "potential", rename_all = "kebab-case")]
+pub enum BondPotential {
+    Harmonic(Harmonic),
+    Morse(Morse),
+}
+
+impl PairPotential for BondPotential {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        match self {
+            BondPotential::Harmonic(bond) => bond.evaluate(r),
+            BondPotential::Morse(bond) => bond.evaluate(r),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "potential", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum AnglePotential {
+    Harmonic {
+        k: f32,
+        theta0: f32,
+    },
+    UreyBradley {
+        k: f32,
+        theta0: f32,
+        k_ub: f32,
+        r_ub: f32,
+    },
+}
+
+impl AnglePotential {
+    // (energy, dU/dtheta) with theta in radians.
+    fn evaluate(&self, theta: f32) -> (f32, f32) {
+        let (k, theta0) = match self {
+            AnglePotential::Harmonic { k, theta0 } => (k, theta0),
+            AnglePotential::UreyBradley { k, theta0, .. } => (k, theta0),
+        };
+        let bend = theta - theta0.to_radians();
+        (0.5 * k * bend * bend, k * bend)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "potential", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum DihedralPotential {
+    Periodic {
+        k: f32,
+        multiplicity: i32,
+        phase: f32,
+    },
+    RyckaertBellemans {
+        c: [f32; 6],
+    },
+    Harmonic {
+        k: f32,
+        xi0: f32,
+    },
+}
+
+impl DihedralPotential {
+    // (energy, dU/dphi) with phi in radians.
+    fn evaluate(&self, phi: f32) -> (f32, f32) {
+        match self {
+            DihedralPotential::Periodic {
+                k,
+                multiplicity,
+                phase,
+            } => {
+                let n = *multiplicity as f32;
+                let x = n * phi - phase.to_radians();
+                (k * (1.0 + x.cos()), -k * n * x.sin())
+            }
+            DihedralPotential::RyckaertBellemans { c } => {
+                // psi = phi - 180, so cos(psi) = -cos(phi)
+                let cos_psi = -phi.cos();
+                let mut energy = 0.0;
+                let mut slope = 0.0;
+                for (n, cn) in c.iter().enumerate() {
+                    energy += cn * cos_psi.powi(n as i32);
+                    if n > 0 {
+                        slope += n as f32 * cn * cos_psi.powi(n as i32 - 1) * phi.sin();
+                    }
+                }
+                (energy, slope)
+            }
+            DihedralPotential::Harmonic { k, xi0 } => {
+                // the short way round.
+                let mut twist = phi - xi0.to_radians();
+                twist -= 2.0 * PI * (twist / (2.0 * PI)).round();
+                (0.5 * k * twist * twist, k * twist)
+            }
+        }
+    }
+}
+
+// the same key either way along the chain.
+fn chain_key(symbols: Vec<&str>) -> Vec<String> {
+    let reversed: Vec<&str> = symbols.iter().rev().copied().collect();
+    let key = if reversed < symbols {
+        reversed
+    } else {
+        symbols
+    };
+    key.iter().map(|s| s.to_string()).collect()
+}
+
+pub struct Bonded {
+    pub bonds: Vec<([String; 2], BondPotential)>,
+    pub angles: Vec<([String; 3], AnglePotential)>,
+    pub dihedrals: Vec<([String; 4], DihedralPotential)>,
+    // evaluated as a dihedral in the order given.  generate puts the center first, GROMACS style; the AMBER and
+    // OpenMM importers put it third, the way those programs do.
+    pub impropers: Vec<([String; 4], DihedralPotential)>,
+    // already scaled, with the Coulomb constant folded into qq.
+    pub pairs: Vec<([String; 2], LennardJones, Coulomb)>,
+    bond_types: HashMap<Vec<String>, BondPotential>,
+    angle_types: HashMap<Vec<String>, AnglePotential>,
+    dihedral_types: HashMap<Vec<String>, DihedralPotential>,
+    improper_types: HashMap<String, DihedralPotential>,
+}
+
+impl Bonded {
+    pub fn new() -> Self {
+        Self {
+            bonds: Vec::new(),
+            angles: Vec::new(),
+            dihedrals: Vec::new(),
+            impropers: Vec::new(),
+            pairs: Vec::new(),
+            bond_types: HashMap::new(),
+            angle_types: HashMap::new(),
+            dihedral_types: HashMap::new(),
+            improper_types: HashMap::new(),
+        }
+    }
+
+    // the 1-2 and 1-3 pairs the bonds and angles cover, and the listed pairs, each once and sorted.
+    pub fn exclusions(&self) -> Vec<(String, String)> {
+        let ends = self
+            .bonds
+            .iter()
+            .map(|([i, j], _)| (i, j))
+            .chain(self.angles.iter().map(|([i, _, k], _)| (i, k)))
+            .chain(self.pairs.iter().map(|([i, j], _, _)| (i, j)));
+        let pairs: BTreeSet<(String, String)> = ends
+            .map(|(a, b)| {
+                if a < b {
+                    (a.clone(), b.clone())
+                } else {
+                    (b.clone(), a.clone())
+                }
+            })
+            .collect();
+        pairs.into_iter().collect()
+    }
+
+    pub fn set_bond(&mut self, e1: &Elements, e2: &Elements, bond: BondPotential) {
+        self.bond_types
+            .insert(chain_key(vec![e1.symbol(), e2.symbol()]), bond);
+    }
+
+    // e2 is the one in the middle.
+    pub fn set_angle(
+        &mut self,
+        e1: &Elements,
+        e2: &Elements,
+        e3: &Elements,
+        angle: AnglePotential,
+    ) {
+        self.angle_types.insert(
+            chain_key(vec![e1.symbol(), e2.symbol(), e3.symbol()]),
+            angle,
+        );
+    }
+
+    pub fn set_dihedral(&mut self, elements: [&Elements; 4], dihedral: DihedralPotential) {
+        self.dihedral_types.insert(
+            chain_key(elements.iter().map(|e| e.symbol()).collect()),
+            dihedral,
+        );
+    }
+
+    pub fn set_improper(&mut self, center: &Elements, improper: DihedralPotential) {
+        self.improper_types
+            .insert(center.symbol().to_string(), improper);
+    }
+
+    // Walks the bonds and makes every term there are parameters for, replacing whatever was in the lists before (bar
+    // the pairs, which are left alone).  Call it again whenever the bonds change.
+    pub fn generate<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+    ) {
+        let atoms = world.get_particles();
+        // bonds only need to be listed on one side, so build the graph both ways.  Sorted, so the terms always come out
+        // in the same order.
+        let mut graph: HashMap<&String, BTreeSet<&String>> =
+            atoms.keys().map(|name| (name, BTreeSet::new())).collect();
+        for (name, atom) in atoms.iter() {
+            for neighbor in atom.get_neighbors() {
+                if let Some((other, _)) = atoms.get_key_value(neighbor) {
+                    if other != name {
+                        graph.get_mut(name).unwrap().insert(other);
+                        graph.get_mut(other).unwrap().insert(name);
+                    }
+                }
+            }
+        }
+        let mut names: Vec<&String> = atoms.keys().collect();
+        names.sort();
+        let symbol = |name: &String| atoms[name].get_element().symbol();
+
+        self.bonds.clear();
+        self.angles.clear();
+        self.dihedrals.clear();
+        self.impropers.clear();
+        for &j in names.iter() {
+            let neighbors: Vec<&String> = graph[j].iter().copied().collect();
+            for &k in neighbors.iter().filter(|&&k| j < k) {
+                if let Some(bond) = self.bond_types.get(&chain_key(vec![symbol(j), symbol(k)])) {
+                    self.bonds.push(([j.clone(), k.clone()], bond.clone()));
+                }
+                // dihedrals around the j-k bond.
+                for &i in graph[j].iter().filter(|&&i| i != k) {
+                    for &l in graph[k].iter().filter(|&&l| l != j && l != i) {
+                        let key = chain_key(vec![symbol(i), symbol(j), symbol(k), symbol(l)]);
+                        if let Some(dihedral) = self.dihedral_types.get(&key) {
+                            self.dihedrals.push((
+                                [i.clone(), j.clone(), k.clone(), l.clone()],
+                                dihedral.clone(),
+                            ));
+                        }
+                    }
+                }
+            }
+            for (a, &i) in neighbors.iter().enumerate() {
+                for &k in neighbors[a + 1..].iter() {
+                    let key = chain_key(vec![symbol(i), symbol(j), symbol(k)]);
+                    if let Some(angle) = self.angle_types.get(&key) {
+                        self.angles
+                            .push(([i.clone(), j.clone(), k.clone()], angle.clone()));
+                    }
+                }
+            }
+            if neighbors.len() == 3 {
+                if let Some(improper) = self.improper_types.get(symbol(j)) {
+                    self.impropers.push((
+                        [
+                            j.clone(),
+                            neighbors[0].clone(),
+                            neighbors[1].clone(),
+                            neighbors[2].clone(),
+                        ],
+                        improper.clone(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+// a minus b, minimum imaged.
+fn separation<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    world: &dyn ContainsParticles<ParT>,
+    a: &String,
+    b: &String,
+) -> Vector3<f32> {
+    let atoms = world.get_particles();
+    Vector3::from_vec(distance(&atoms[a], &atoms[b], world.get_periodic_box()))
+}
+
+// The angle i-j-k, and its gradient with respect to i and k (j's is minus the sum).
+fn angle_gradient(rij: Vector3<f32>, rkj: Vector3<f32>) -> (f32, Vector3<f32>, Vector3<f32>) {
+    let (lij, lkj) = (rij.norm(), rkj.norm());
+    let (uij, ukj) = (rij / lij, rkj / lkj);
+    let cos_theta = uij.dot(&ukj).clamp(-1.0, 1.0);
+    // straight angles have no well defined direction to bend in; just don't blow up.
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt().max(1.0e-6);
+    (
+        cos_theta.acos(),
+        (cos_theta * uij - ukj) / (lij * sin_theta),
+        (cos_theta * ukj - uij) / (lkj * sin_theta),
+    )
+}
+
+// The dihedral i-j-k-l and its gradient with respect to each atom (Blondel & Karplus, J. Comput. Chem. 17, 1132 (1996)).
+fn dihedral_gradient(
+    f: Vector3<f32>, // i - j
+    g: Vector3<f32>, // j - k
+    h: Vector3<f32>, // l - k
+) -> (f32, [Vector3<f32>; 4]) {
+    let (a, b) = (f.cross(&g), h.cross(&g));
+    let (a2, b2, lg) = (
+        a.norm_squared().max(1.0e-12),
+        b.norm_squared().max(1.0e-12),
+        g.norm(),
+    );
+    let phi = b.cross(&a).dot(&g).atan2(a.dot(&b) * lg);
+    let (fg, hg) = (f.dot(&g) / (a2 * lg), h.dot(&g) / (b2 * lg));
+    let di = -lg / a2 * a;
+    let dl = lg / b2 * b;
+    let dj = -di + fg * a - hg * b;
+    let dk = -dl - fg * a + hg * b;
+    (phi, [di, dj, dk, dl])
+}
+
+fn add_force(forces: &mut HashMap<String, Vec<f32>>, name: &String, f: Vector3<f32>) {
+    let total = forces.get_mut(name).unwrap();
+    for k in 0..3 {
+        total[k] += f[k];
+    }
+}
+
+impl Bonded {
+    // all five kinds of term at once; with forces, or without if it's just the energy that's wanted.
+    fn evaluate<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+        mut forces: Option<&mut HashMap<String, Vec<f32>>>,
+    ) -> f32 {
+        let mut energy = 0.0;
+        for ([i, j], bond) in self.bonds.iter() {
+            let d = separation(world, i, j);
+            let r = d.norm();
+            let (u, f) = bond.evaluate(r);
+            energy += u;
+            if let Some(forces) = forces.as_deref_mut() {
+                add_force(forces, i, f * d / r);
+                add_force(forces, j, -f * d / r);
+            }
+        }
+        for ([i, j, k], angle) in self.angles.iter() {
+            let (rij, rkj) = (separation(world, i, j), separation(world, k, j));
+            let (theta, di, dk) = angle_gradient(rij, rkj);
+            let (mut u, slope) = angle.evaluate(theta);
+            let (mut fi, mut fk) = (-slope * di, -slope * dk);
+            if let AnglePotential::UreyBradley { k_ub, r_ub, .. } = angle {
+                let d = rij - rkj;
+                let r = d.norm();
+                let (u13, f13) = Harmonic::new(*k_ub, *r_ub).evaluate(r);
+                u += u13;
+                fi += f13 * d / r;
+                fk -= f13 * d / r;
+            }
+            energy += u;
+            if let Some(forces) = forces.as_deref_mut() {
+                add_force(forces, i, fi);
+                add_force(forces, k, fk);
+                add_force(forces, j, -fi - fk);
+            }
+        }
+        for ([i, j, k, l], dihedral) in self.dihedrals.iter().chain(self.impropers.iter()) {
+            let (phi, gradient) = dihedral_gradient(
+                separation(world, i, j),
+                separation(world, j, k),
+                separation(world, l, k),
+            );
+            let (u, slope) = dihedral.evaluate(phi);
+            energy += u;
+            if let Some(forces) = forces.as_deref_mut() {
+                for (name, d) in [i, j, k, l].into_iter().zip(gradient) {
+                    add_force(forces, name, -slope * d);
+                }
+            }
+        }
+        for ([i, j], lj, coulomb) in self.pairs.iter() {
+            let d = separation(world, i, j);
+            let r = d.norm();
+            let ((u_lj, f_lj), (u_qq, f_qq)) = (lj.evaluate(r), coulomb.evaluate(r));
+            energy += u_lj + u_qq;
+            if let Some(forces) = forces.as_deref_mut() {
+                add_force(forces, i, (f_lj + f_qq) * d / r);
+                add_force(forces, j, -(f_lj + f_qq) * d / r);
+            }
+        }
+        energy
+    }
+}
+
+impl<ParT: Atomic<Elements, f32, Vec<f32>>> ForceTerm<ParT> for Bonded {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let mut forces: HashMap<String, Vec<f32>> = world
+            .get_particles()
+            .keys()
+            .map(|name| (name.clone(), vec![0.0; 3]))
+            .collect();
+        self.evaluate(world, Some(&mut forces));
+        forces
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        self.evaluate(world, None)
+    }
+    fn replaces_neighbor_forces(&self) -> bool {
+        true
+    }
+}
+
+impl Default for Bonded {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+    use crate::Topology::particle::HasPhysics;
+
+    type World = Cell<Atom<Elements, f32, Vec<f32>>, f32>;
+
+    // a bent, twisted chain a-b-c-d, with an extra atom e on c so that c has three neighbors.
+    fn chain() -> World {
+        let atoms = [
+            ("a", Elements::H(0), vec![0.1, 1.0, 0.2], vec![]),
+            ("b", Elements::C(0), vec![0.0, 0.0, 0.0], vec!["a"]),
+            ("c", Elements::C(0), vec![1.5, 0.1, 0.0], vec!["b"]),
+            ("d", Elements::O(0), vec![2.0, 0.6, 1.2], vec!["c"]),
+            ("e", Elements::H(0), vec![2.1, -0.9, -0.3], vec!["c"]),
+        ];
+        let mut particles = HashMap::new();
+        for (name, element, position, bonds) in atoms {
+            let mut atom = AtomBuilder::new()
+                .element(element)
+                .id(Some(name.to_string()))
+                .position(position)
+                .build();
+            atom.neighbors = bonds.iter().map(|b| b.to_string()).collect();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = World::new();
+        cell.set_particles(particles);
+        cell
+    }
+
+    fn bonded() -> Bonded {
+        let (h, c, o) = (Elements::H(0), Elements::C(0), Elements::O(0));
+        let mut bonded = Bonded::new();
+        bonded.set_bond(&h, &c, BondPotential::Harmonic(Harmonic::new(300.0, 1.1)));
+        bonded.set_bond(&c, &c, BondPotential::Morse(Morse::new(4.0, 2.0, 1.4)));
+        bonded.set_bond(&o, &c, BondPotential::Harmonic(Harmonic::new(400.0, 1.3)));
+        bonded.set_angle(
+            &h,
+            &c,
+            &c,
+            AnglePotential::Harmonic {
+                k: 50.0,
+                theta0: 109.5,
+            },
+        );
+        bonded.set_angle(
+            &o,
+            &c,
+            &c,
+            AnglePotential::UreyBradley {
+                k: 40.0,
+                theta0: 120.0,
+                k_ub: 20.0,
+                r_ub: 2.4,
+            },
+        );
+        bonded.set_dihedral(
+            [&h, &c, &c, &o],
+            DihedralPotential::Periodic {
+                k: 2.0,
+                multiplicity: 3,
+                phase: 10.0,
+            },
+        );
+        bonded.set_dihedral(
+            [&h, &c, &c, &h],
+            DihedralPotential::RyckaertBellemans {
+                c: [9.28, 12.16, -13.12, -3.06, 26.24, -31.5],
+            },
+        );
+        bonded.set_improper(&c, DihedralPotential::Harmonic { k: 30.0, xi0: 0.0 });
+        bonded
+    }
+
+    #[test]
+    fn test_generate_from_bonds() {
+        let cell = chain();
+        let mut bonded = bonded();
+        bonded.generate(&cell);
+        assert_eq!(bonded.bonds.len(), 4);
+        // a-b-c, b-c-d and b-c-e have parameters; d-c-e doesn't.
+        assert_eq!(bonded.angles.len(), 3);
+        // a-b-c-d and a-b-c-e.
+        assert_eq!(bonded.dihedrals.len(), 2);
+        assert_eq!(bonded.impropers.len(), 1);
+        assert_eq!(bonded.impropers[0].0[0], "c");
+    }
+
+    #[test]
+    fn test_forces_match_energy() {
+        let mut cell = chain();
+        let mut bonded = bonded();
+        bonded.generate(&cell);
+        bonded.pairs.push((
+            ["a".to_string(), "d".to_string()],
+            LennardJones::new(0.5, 1.2),
+            Coulomb::new(-3.0),
+        ));
+        // pairs are taken over as well.
+        assert!(bonded
+            .exclusions()
+            .contains(&("a".to_string(), "d".to_string())));
+        let forces = ForceTerm::<Atom<Elements, f32, Vec<f32>>>::forces(&mut bonded, &cell, 0.0);
+        let energy =
+            |cell: &World| ForceTerm::<Atom<Elements, f32, Vec<f32>>>::energy(&bonded, cell, 0.0);
+        let h = 1.0e-3;
+        let mut total = vec![0.0; 3];
+        for name in ["a", "b", "c", "d", "e"] {
+            let name = name.to_string();
+            for k in 0..3 {
+                let start = cell.get_particles()[&name].get_position().clone();
+                let mut moved = start.clone();
+                moved[k] += h;
+                cell.get_mut_particles()
+                    .get_mut(&name)
+                    .unwrap()
+                    .set_position(moved.clone());
+                let up = energy(&cell);
+                moved[k] -= 2.0 * h;
+                cell.get_mut_particles()
+                    .get_mut(&name)
+                    .unwrap()
+                    .set_position(moved);
+                let down = energy(&cell);
+                cell.get_mut_particles()
+                    .get_mut(&name)
+                    .unwrap()
+                    .set_position(start);
+                let numerical = -(up - down) / (2.0 * h);
+                let f = forces[&name][k];
+                assert!(
+                    (numerical - f).abs() < 2.0e-2 * f.abs().max(1.0),
+                    "{name} {k}: numerical {numerical}, analytic {f}"
+                );
+                total[k] += f;
+            }
+        }
+        // all internal, so nothing net.
+        assert!(total.iter().all(|f| f.abs() < 1.0e-3));
+    }
+
+    #[test]
+    fn test_dihedral_convention() {
+        // cis is 0, trans is 180.
+        let cis = dihedral_gradient(
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        )
+        .0;
+        let trans = dihedral_gradient(
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+        )
+        .0;
+        assert!(cis.abs() < 1.0e-5);
+        assert!((trans.abs() - PI).abs() < 1.0e-5);
+        // and Ryckaert-Bellemans is at its minimum for trans butane.
+        let rb = DihedralPotential::RyckaertBellemans {
+            c: [9.28, 12.16, -13.12, -3.06, 26.24, -31.5],
+        };
+        assert!(rb.evaluate(PI).0.abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn test_takes_over_the_bonds() {
+        use crate::Dynamics::integrator::Leapfrog;
+        use crate::ForceFields::SIN::SIN;
+        let mut cell = chain();
+        for atom in cell.get_mut_particles().values_mut() {
+            atom.velocity = vec![0.0; 3];
+        }
+        let mut bonded = bonded();
+        bonded.generate(&cell);
+        assert_eq!(
+            bonded.exclusions(),
+            [
+                ("a", "b"),
+                ("a", "c"),
+                ("b", "c"),
+                ("b", "d"),
+                ("b", "e"),
+                ("c", "d"),
+                ("c", "e")
+            ]
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+        // one step from rest: the kick is just the bonded forces, with nothing from SIN along the bonds on top.
+        let forces = ForceTerm::<Atom<Elements, f32, Vec<f32>>>::forces(&mut bonded, &cell, 0.0);
+        let mut integrator = Leapfrog::<f32>::new();
+        integrator.dt = 1.0e-3;
+        integrator.step(&mut cell, &SIN::<Elements>::new(), &mut [&mut bonded]);
+        for (name, atom) in cell.get_particles().iter() {
+            for k in 0..3 {
+                let expected = 0.5 * integrator.dt * forces[name][k];
+                assert!((atom.velocity[k] - expected).abs() < 1.0e-5, "{name} {k}");
+            }
+        }
+    }
+}
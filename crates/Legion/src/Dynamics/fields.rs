@@ -0,0 +1,141 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::{HasCharge, HasMass, HasPhysics};
+use std::collections::HashMap;
+
+// Uniform external fields.  These push on every particle in the world the same way, no neighbors required.
+// (The magnetic field lives on the Boris integrator instead, as the Lorentz force depends on the velocity.)
+
+// F = m g.  Good for sedimentation demos.
+pub struct UniformGravity {
+    pub g: Vec<f32>,
+}
+
+impl<ParT: HasPhysics<Vec<f32>> + HasMass<f32>> ForceTerm<ParT> for UniformGravity {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        world
+            .get_particles()
+            .iter()
+            .map(|(name, p)| {
+                let m = *p.get_mass();
+                (name.clone(), self.g.iter().map(|&g| m * g).collect())
+            })
+            .collect()
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        world
+            .get_particles()
+            .values()
+            .map(|p| {
+                let x = p.get_position();
+                -p.get_mass() * self.g.iter().zip(x.iter()).map(|(g, x)| g * x).sum::<f32>()
+            })
+            .sum()
+    }
+}
+
+// E(t) = E0 cos(omega t + phase), F = q E(t).  Leave omega at zero for a static field.
+pub struct ElectricField {
+    pub amplitude: Vec<f32>,
+    pub frequency: f32, // angular frequency
+    pub phase: f32,
+}
+
+impl ElectricField {
+    pub fn new(amplitude: Vec<f32>) -> Self {
+        Self {
+            amplitude,
+            frequency: 0.0,
+            phase: 0.0,
+        }
+    }
+
+    pub fn oscillating(amplitude: Vec<f32>, frequency: f32, phase: f32) -> Self {
+        Self {
+            amplitude,
+            frequency,
+            phase,
+        }
+    }
+
+    pub fn field(&self, time: f32) -> Vec<f32> {
+        let scale = (self.frequency * time + self.phase).cos();
+        self.amplitude.iter().map(|&e| e * scale).collect()
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>> + HasCharge<f32>> ForceTerm<ParT> for ElectricField {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let e = self.field(time);
+        world
+            .get_particles()
+            .iter()
+            .map(|(name, p)| {
+                let q = *p.get_charge();
+                (name.clone(), e.iter().map(|&e| q * e).collect())
+            })
+            .collect()
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, time: f32) -> f32 {
+        let e = self.field(time);
+        world
+            .get_particles()
+            .values()
+            .map(|p| {
+                let x = p.get_position();
+                -p.get_charge() * e.iter().zip(x.iter()).map(|(e, x)| e * x).sum::<f32>()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{carbon, world, World};
+
+    fn charged() -> World {
+        world(
+            [("light", 1.0, -1.0), ("heavy", 3.0, 2.0)].map(|(name, mass, charge)| {
+                carbon(name, vec![0.0, 1.0, 0.0])
+                    .mass(mass)
+                    .charge(charge)
+                    .build()
+            }),
+        )
+    }
+
+    #[test]
+    fn test_gravity() {
+        let cell = charged();
+        let mut gravity = UniformGravity {
+            g: vec![0.0, -9.8, 0.0],
+        };
+        let forces = gravity.forces(&cell, 0.0);
+        assert_eq!(forces["light"], vec![0.0, -9.8, 0.0]);
+        assert!((forces["heavy"][1] + 29.4).abs() < 1.0e-5);
+        // both are one unit up, so U = (1 + 3) * 9.8
+        assert!((gravity.energy(&cell, 0.0) - 39.2).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn test_electric_field() {
+        let cell = charged();
+        let mut field = ElectricField::new(vec![1.0, 0.0, 0.0]);
+        let forces = field.forces(&cell, 5.0);
+        assert_eq!(forces["light"], vec![-1.0, 0.0, 0.0]);
+        assert_eq!(forces["heavy"], vec![2.0, 0.0, 0.0]);
+        // half a period later an oscillating field has flipped around.
+        let mut field = ElectricField::oscillating(vec![1.0, 0.0, 0.0], 2.0, 0.0);
+        let forces = field.forces(&cell, std::f32::consts::FRAC_PI_2);
+        assert!((forces["heavy"][0] + 2.0).abs() < 1.0e-5);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::Topology::cell::ContainsParticles;
+use std::collections::HashMap;
+
+// Anything that pushes on particles that isn't one of the ForceField pair interactions: biases, pulling, external fields, etc.
+// Terms get the whole world at once (rather than a single particle name) as most of them care about groups of atoms.
+// We take a trait object for the world so that a simulation can hold a list of different terms.
+pub trait ForceTerm<ParT> {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        time: f32,
+    ) -> HashMap<String, Vec<f32>>;
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, time: f32) -> f32;
+    // Terms that do the bonds themselves (Bonded) say so here, and then the integrators leave out the force field's
+    // pushes along each atom's neighbors, so no bond gets counted twice.
+    fn replaces_neighbor_forces(&self) -> bool {
+        false
+    }
+}
+
+// adds the extra forces into whatever we've already got, creating entries for particles we haven't seen yet.
+pub fn accumulate_forces(total: &mut HashMap<String, Vec<f32>>, extra: HashMap<String, Vec<f32>>) {
+    for (name, force) in extra {
+        match total.get_mut(&name) {
+            Some(f) => {
+                for (i, z) in force.iter().enumerate() {
+                    f[i] += z;
+                }
+            }
+            None => {
+                total.insert(name, force);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulate_forces() {
+        let mut total = HashMap::<String, Vec<f32>>::new();
+        total.insert("a".to_string(), vec![1.0, 0.0, 0.0]);
+        let mut extra = HashMap::<String, Vec<f32>>::new();
+        extra.insert("a".to_string(), vec![1.0, 2.0, 0.0]);
+        extra.insert("b".to_string(), vec![0.0, 0.0, 3.0]);
+        accumulate_forces(&mut total, extra);
+        assert_eq!(total["a"], vec![2.0, 2.0, 0.0]);
+        assert_eq!(total["b"], vec![0.0, 0.0, 3.0]);
+    }
+}
@@ -1,7 +1,14 @@
+use crate::Dynamics::forces::{accumulate_forces, ForceTerm};
+use crate::Dynamics::observers::Observer;
+use crate::ForceFields::DPD::DPD;
+use crate::ForceFields::potentials::PairPotential;
 use crate::ForceFields::SIN::{Elements, ForceField};
 use crate::Topology::atom::Atomic;
-use crate::Topology::particle::HasPhysics;
-use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::{HasMass, HasPhysics};
+use crate::Topology::cell::{Cell, ContainsParticles};
+use crate::Topology::periodic::PeriodicBox;
+use nalgebra::Vector3;
+use std::collections::HashMap;
 use num_traits::float::FloatCore;
 use num_traits::real::Real;
 use num_traits::{Float, Zero};
@@ -19,6 +26,8 @@ pub trait Integrator<ParT, EleT, NumT, VecT: IntoIterator<Item=NumT>> {
 
 pub enum IntegratorTypes {
     LeapfrogVelocityVerlet,
+    Boris,
+    GrootWarren,
 }
 
 pub struct Leapfrog<NumT> {
@@ -27,19 +36,18 @@ pub struct Leapfrog<NumT> {
     pub dt: NumT,
 }
 
-// The number has to support being subtracted!  See how we're doing it?
-pub fn distance<ParT: HasPhysics<Vec<NumT>>, NumT: std::ops::Sub<Output = NumT>>(
+// A minus B.  Under periodic boundaries that's the separation to whichever copy of B is closest (minimum image),
+// which only makes sense for boxes at least twice as wide as the longest cutoff.
+pub fn distance<ParT: HasPhysics<Vec<f32>>>(
     A: &ParT,
     B: &ParT,
-) -> Vec<NumT>
-where
-  NumT: Copy,
-{
-    let mut r: Vec<NumT> = Vec::new();
+    periodic: Option<&PeriodicBox>,
+) -> Vec<f32> {
     let other_ijk = B.get_position();
     let ijk = A.get_position();
-    for i in 0..ijk.len() {
-        r.push(ijk[i] - other_ijk[i]);
+    let mut r: Vec<f32> = (0..ijk.len()).map(|i| ijk[i] - other_ijk[i]).collect();
+    if let Some(periodic) = periodic {
+        periodic.minimum_image(&mut r);
     }
     return r;
 }
@@ -53,6 +61,137 @@ impl Leapfrog<f32> {
             dt: Zero::zero(), // Should be 0.002 but hey.
         }
     }
+
+    // One step for the whole cell, with any extra force terms (pulling, biases, fields) on top of the force field.
+    pub fn step<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+        terms: &mut [&mut dyn ForceTerm<ParT>],
+    ) {
+        step(self, self.dt, cell, sin, terms);
+    }
+
+    pub fn run<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+        terms: &mut [&mut dyn ForceTerm<ParT>],
+        observers: &mut [&mut dyn Observer<ParT>],
+        steps: usize,
+    ) {
+        run(self, self.dt, cell, sin, terms, observers, steps);
+    }
+}
+
+// Runs a number of steps, letting the observers (trajectory writers and the like) look at the world after each one.
+pub fn run<ParT, IntT>(
+    integrator: &IntT,
+    dt: f32,
+    cell: &mut Cell<ParT, f32>,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    terms: &mut [&mut dyn ForceTerm<ParT>],
+    observers: &mut [&mut dyn Observer<ParT>],
+    steps: usize,
+) where
+    ParT: Atomic<Elements, f32, Vec<f32>>,
+    IntT: Integrator<ParT, Elements, f32, Vec<f32>>,
+{
+    for i in 0..steps {
+        step(integrator, dt, cell, sin, terms);
+        for observer in observers.iter_mut() {
+            observer.observe(i + 1, cell.get_time(), cell);
+        }
+    }
+}
+
+// Shared by all of the integrators: pair forces from the force field plus the extra terms, then move everyone.
+// Forces are all calculated before anything moves.  If one of the terms does the bonds itself (Bonded), the force
+// field's pair forces along the neighbors are left out.
+pub fn step<ParT, IntT>(
+    integrator: &IntT,
+    dt: f32,
+    cell: &mut Cell<ParT, f32>,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    terms: &mut [&mut dyn ForceTerm<ParT>],
+) where
+    ParT: Atomic<Elements, f32, Vec<f32>>,
+    IntT: Integrator<ParT, Elements, f32, Vec<f32>>,
+{
+    let time = cell.get_time();
+    let bonded = terms.iter().any(|term| term.replaces_neighbor_forces());
+    let mut forces = HashMap::<String, Vec<f32>>::new();
+    for (name, atom) in cell.get_particles().iter() {
+        let force = if bonded {
+            vec![0.0; atom.get_position().len()]
+        } else {
+            integrator.calculate_forces(name.clone(), cell, sin)
+        };
+        forces.insert(name.clone(), force);
+    }
+    for term in terms.iter_mut() {
+        accumulate_forces(&mut forces, term.forces(cell, time));
+    }
+    for (name, acc) in forces {
+        if cell.is_frozen(&name) {
+            continue;
+        }
+        if let Some(a) = cell.get_mut_particles().get_mut(&name) {
+            let (pos, vel, _) = integrator.integrate(a, acc);
+            a.set_position(pos);
+            a.set_velocity(vel);
+        }
+    }
+    cell.wrap_positions();
+    cell.set_time(time + dt);
+}
+
+// particles that never had a mass set (the builder defaults it to zero) get treated as unit mass rather than blowing up.
+pub fn inverse_mass<ParT: HasMass<f32>>(particle: &ParT) -> f32 {
+    let mass = *particle.get_mass();
+    if mass > 0.0 {
+        1.0 / mass
+    } else {
+        1.0
+    }
+}
+
+// this is _probably_ not the ideal way to like, do this, but I don't care at the moment lmao.
+pub fn pairwise_forces<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    name: String,
+    world: &impl ContainsParticles<ParT>,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+) -> Vec<f32> {
+    let atoms = world.get_particles();
+    let atom = &atoms[&name];
+    // a bond only has to be listed on one of its ends, so go looking for whoever lists this atom too (like
+    // montecarlo::adjacency does), or half of every bond would only push one way.
+    let mut neighbors: Vec<&String> = atom.get_neighbors().iter().collect();
+    for (other, particle) in atoms.iter() {
+        if particle.get_neighbors().contains(&name) {
+            neighbors.push(other);
+        }
+    }
+    neighbors.sort();
+    neighbors.dedup();
+    neighbors.retain(|neighbor| **neighbor != name);
+    let mut force_sum: Vec<f32> =
+        vec![0.0; atom.get_position().len()]; // use the vec macro to prefill with 0.
+
+    for neighbor in neighbors {
+        // get the actual atom
+        let na = &atoms[neighbor];
+        let pair = sin.pair_potential(atom.get_element(), na.get_element());
+        let d = distance(atom, na, world.get_periodic_box());
+        let r = FloatCore::abs(num_traits::Float::sqrt(d.iter().map(|&z| z * z).sum::<f32>())); // wait, did this work?  Huh!  Crazy nifty.
+        let r_ijk = d.iter().map(|&z| z / r).collect::<Vec<f32>>(); // collect is what turns the iterator back in a vector, apparently.
+                                                                    // Now!  Get the forces!
+        let force = pair.force(r);
+        for (i, &z) in r_ijk.iter().enumerate() {
+            force_sum[i] += force * z; // cast back, etc.
+        }
+    }
+    return force_sum;
 }
 
 // this is KIND of a specific implementation, but also not really.  Trying to make it as generic as possible, although I'm not sure this is the way, so to speak.
@@ -61,7 +200,9 @@ impl Leapfrog<f32> {
 impl<ParT: Atomic<Elements, f32, Vec<f32>>> Integrator<ParT, Elements, f32, Vec<f32>>
     for Leapfrog<f32>
 {
-    fn integrate(&self, atom: &ParT, mut acc: Vec<f32>) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    fn integrate(&self, atom: &ParT, force: Vec<f32>) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+        let inv_mass = inverse_mass(atom);
+        let acc = force.iter().map(|&f| f * inv_mass).collect::<Vec<f32>>();
         let mut pos = atom.get_position().clone();
         let mut vel = atom.get_velocity().clone();
         for i in 0..pos.len() {
@@ -73,36 +214,182 @@ impl<ParT: Atomic<Elements, f32, Vec<f32>>> Integrator<ParT, Elements, f32, Vec<
         return (pos, vel, acc);
     }
 
-    // this is _probably_ not the ideal way to like, do this, but I don't care at the moment lmao.
     fn calculate_forces(
         &self,
         name: String,
         world: &impl ContainsParticles<ParT>,
         sin: &impl ForceField<Elements, f32, Vec<f32>>,
     ) -> Vec<f32> {
-        let atoms = world.get_particles();
-        let atom = &atoms[&name];
-        let neighbors = atom.get_neighbors();
-        let mut force_sum: Vec<f32> =
-            vec![0.0; atom.get_position().len()]; // use the vec macro to prefill with 0.
-
-        for neighbor in neighbors.iter() {
-            // get the actual atom
-            let na = &atoms[neighbor];
-            let pwi = sin.pairwise_interactions(atom.get_element(), na.get_element());
-            let d = distance(atom, na);
-            let r = FloatCore::abs(num_traits::Float::sqrt(d.iter().map(|&z| z * z).sum::<f32>())); // wait, did this work?  Huh!  Crazy nifty.
-            let r_ijk = d.iter().map(|&z| z / r).collect::<Vec<f32>>(); // collect is what turns the iterator back in a vector, apparently.
-                                                                        // Now!  Get the forces!
-            let force = pwi(r);
-            for (i, &z) in r_ijk.iter().enumerate() {
-                force_sum[i] = force * z; // cast back, etc.
+        pairwise_forces(name, world, sin)
+    }
+}
+
+// Boris pusher for charged particles in a uniform magnetic field.  Half a kick from the ordinary forces,
+// a rotation of the velocity around B (which can't change the speed), then the other half kick and a drift.
+// Velocities live on the half steps, same as a leapfrog.
+pub struct Boris<NumT> {
+    pub id: String,
+    pub integrator_type: IntegratorTypes,
+    pub dt: NumT,
+    pub magnetic_field: Vec<NumT>,
+}
+
+impl Boris<f32> {
+    pub fn new(magnetic_field: Vec<f32>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            integrator_type: IntegratorTypes::Boris,
+            dt: Zero::zero(),
+            magnetic_field,
+        }
+    }
+
+    pub fn step<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+        terms: &mut [&mut dyn ForceTerm<ParT>],
+    ) {
+        step(self, self.dt, cell, sin, terms);
+    }
+}
+
+impl<ParT: Atomic<Elements, f32, Vec<f32>>> Integrator<ParT, Elements, f32, Vec<f32>> for Boris<f32> {
+    fn integrate(&self, atom: &ParT, force: Vec<f32>) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+        let inv_mass = inverse_mass(atom);
+        let acc = Vector3::new(force[0], force[1], force[2]) * inv_mass;
+        let vel = atom.get_velocity();
+        let b = &self.magnetic_field;
+        let v_minus = Vector3::new(vel[0], vel[1], vel[2]) + acc * (0.5 * self.dt);
+        let t = Vector3::new(b[0], b[1], b[2]) * (atom.get_charge() * inv_mass * 0.5 * self.dt);
+        let s = t * (2.0 / (1.0 + t.norm_squared()));
+        let v_prime = v_minus + v_minus.cross(&t);
+        let v_plus = v_minus + v_prime.cross(&s);
+        let v_new = v_plus + acc * (0.5 * self.dt);
+        let pos = atom.get_position();
+        let pos = (0..3).map(|i| pos[i] + v_new[i] * self.dt).collect();
+        (pos, vec![v_new[0], v_new[1], v_new[2]], vec![acc[0], acc[1], acc[2]])
+    }
+
+    fn calculate_forces(
+        &self,
+        name: String,
+        world: &impl ContainsParticles<ParT>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    ) -> Vec<f32> {
+        pairwise_forces(name, world, sin)
+    }
+}
+
+// Groot & Warren's modified velocity Verlet for DPD.  The dissipative force depends on the velocities, so the forces
+// at the new positions get evaluated with a predicted velocity v + lambda dt a, then the velocities are finished off
+// with the average of the old and new accelerations.  lambda = 0.5 is plain velocity Verlet; 0.65 is their pick.
+// The accelerations get stashed on the particles between steps.
+pub struct GrootWarren<NumT> {
+    pub id: String,
+    pub integrator_type: IntegratorTypes,
+    pub dt: NumT,
+    pub lambda: NumT,
+}
+
+impl GrootWarren<f32> {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            integrator_type: IntegratorTypes::GrootWarren,
+            dt: 0.04,
+            lambda: 0.65,
+        }
+    }
+
+    fn accelerations<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        cell: &Cell<ParT, f32>,
+        dpd: &mut DPD,
+        terms: &mut [&mut dyn ForceTerm<ParT>],
+    ) -> HashMap<String, Vec<f32>> {
+        let mut forces = dpd.forces(cell, self.dt);
+        for term in terms.iter_mut() {
+            accumulate_forces(&mut forces, term.forces(cell, cell.get_time()));
+        }
+        for (name, force) in forces.iter_mut() {
+            let inv_mass = inverse_mass(&cell.get_particles()[name]);
+            for f in force.iter_mut() {
+                *f *= inv_mass;
+            }
+        }
+        forces
+    }
+
+    pub fn step<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        cell: &mut Cell<ParT, f32>,
+        dpd: &mut DPD,
+        terms: &mut [&mut dyn ForceTerm<ParT>],
+    ) {
+        let dt = self.dt;
+        // first step (or someone added particles): there aren't any accelerations to reuse yet.
+        if cell.get_particles().values().any(|a| a.get_acceleration().len() < 3) {
+            for (name, acc) in self.accelerations(cell, dpd, terms) {
+                cell.get_mut_particles().get_mut(&name).unwrap().set_acceleration(acc);
+            }
+        }
+        let mut old = HashMap::<String, (Vec<f32>, Vec<f32>)>::new();
+        let frozen: Vec<String> = cell
+            .get_particles()
+            .keys()
+            .filter(|name| cell.is_frozen(name))
+            .cloned()
+            .collect();
+        for (name, atom) in cell.get_mut_particles().iter_mut() {
+            if frozen.contains(name) {
+                continue;
+            }
+            let mut vel = atom.get_velocity().clone();
+            vel.resize(3, 0.0);
+            let acc = atom.get_acceleration().clone();
+            let pos = (0..3)
+                .map(|i| atom.get_position()[i] + vel[i] * dt + 0.5 * acc[i] * dt * dt)
+                .collect();
+            let predicted = (0..3).map(|i| vel[i] + self.lambda * dt * acc[i]).collect();
+            atom.set_position(pos);
+            atom.set_velocity(predicted);
+            old.insert(name.clone(), (vel, acc));
+        }
+        cell.wrap_positions();
+        cell.set_time(cell.get_time() + dt);
+        for (name, acc) in self.accelerations(cell, dpd, terms) {
+            let Some((vel, old_acc)) = old.get(&name) else {
+                continue;
+            };
+            let atom = cell.get_mut_particles().get_mut(&name).unwrap();
+            atom.set_velocity((0..3).map(|i| vel[i] + 0.5 * dt * (old_acc[i] + acc[i])).collect());
+            atom.set_acceleration(acc);
+        }
+    }
+
+    pub fn run<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        cell: &mut Cell<ParT, f32>,
+        dpd: &mut DPD,
+        terms: &mut [&mut dyn ForceTerm<ParT>],
+        observers: &mut [&mut dyn Observer<ParT>],
+        steps: usize,
+    ) {
+        for i in 0..steps {
+            self.step(cell, dpd, terms);
+            for observer in observers.iter_mut() {
+                observer.observe(i + 1, cell.get_time(), cell);
             }
         }
-        return force_sum;
     }
 }
 
+impl Default for GrootWarren<f32> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -110,6 +397,9 @@ mod tests {
     use crate::Legion::ForceFields::SIN::{SIN, Elements, ForceField};
     use crate::Legion::Topology::atom::{HasElement, Atom, Connected};
     use crate::Legion::Topology::cell::{Cell};
+    use crate::Dynamics::observers::XyzTrajectory;
+    use crate::Dynamics::pulling::ConstantForcePull;
+    use crate::Topology::particle::IsSpatial;
     use std::collections::HashMap;
 
     #[test]
@@ -120,27 +410,24 @@ mod tests {
 
     #[test]
     fn test_distance() {
-        let SinFF = SIN::<Elements> {
-            description: "SIN".to_string(),
-            particle_type: Vec::new(),
-        };
+        let SinFF = SIN::<Elements>::new();
         let mut atomA = SinFF.atom(Elements::H(0));
         let mut atomB = SinFF.atom(Elements::H(0));
         let posA = vec![1.0, 1.0, 1.0];
         let posB = vec![0.0, 0.0, 0.0];
         atomA.set_position(posA);
         atomB.set_position(posB);
-        let d = distance(&atomA, &atomB);
+        let d = distance(&atomA, &atomB, None);
         assert_eq!(d, vec![1.0, 1.0, 1.0]);
+        // in a box of 1.5 the nearest copy of B is on the other side.
+        let d = distance(&atomA, &atomB, Some(&PeriodicBox::cubic(1.5)));
+        assert_eq!(d, vec![-0.5, -0.5, -0.5]);
     }
 
     #[test]
     fn test_calculate_forces_and_integrate() {
         let integrator = Leapfrog::<f64>::new();
-        let SinFF = SIN::<Elements> {
-            description: "SIN".to_string(),
-            particle_type: Vec::new(),
-        };
+        let SinFF = SIN::<Elements>::new();
         let mut atomA = SinFF.atom(Elements::H(0));
         let mut atomB = SinFF.atom(Elements::H(0));
         // set positions
@@ -165,4 +452,89 @@ mod tests {
         let acc = integrator.calculate_forces(name.clone(), &cell, &SinFF);
         let (pos, vel, acc) = integrator.integrate(cell.get_particles().get(&name).unwrap(), acc);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_step_with_force_term() {
+        let mut integrator = Leapfrog::<f32>::new();
+        integrator.dt = 0.1;
+        let SinFF = SIN::<Elements>::new();
+        let atom = SinFF.atom(Elements::H(0));
+        let name = atom.id.clone();
+        let mut particles = HashMap::<String, Atom<Elements, f32, Vec<f32>>>::new();
+        particles.insert(name.clone(), atom);
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        cell.get_mut_particles().get_mut(&name).unwrap().generate_spatial_coordinates(3);
+        let mut pull = ConstantForcePull::new(vec![name.clone()], vec![2.0, 0.0, 0.0]);
+        integrator.step(&mut cell, &SinFF, &mut [&mut pull]);
+        let pos = cell.get_particles()[&name].get_position();
+        assert!((pos[0] - 0.01).abs() < 1.0e-6);
+        assert!((cell.get_time() - 0.1).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_boris_gyration() {
+        // a charge in a uniform B field goes around in a circle of radius m v / (q B) without speeding up.
+        let SinFF = SIN::<Elements>::new();
+        let mut atom = SinFF.atom(Elements::H(0));
+        atom.set_position(vec![0.0, 0.0, 0.0]);
+        atom.set_velocity(vec![1.0, 0.0, 0.0]);
+        let name = atom.id.clone();
+        let mut particles = HashMap::<String, Atom<Elements, f32, Vec<f32>>>::new();
+        particles.insert(name.clone(), atom);
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        let mut boris = Boris::<f32>::new(vec![0.0, 0.0, 2.0]);
+        boris.dt = 0.001;
+        // m = 1, q = 1, B = 2 so the radius is 0.5 and the period is pi.
+        let steps = (std::f32::consts::PI / boris.dt) as usize;
+        let mut max_y = 0.0_f32;
+        for _ in 0..steps {
+            boris.step(&mut cell, &SinFF, &mut []);
+            max_y = max_y.max(cell.get_particles()[&name].position[1].abs());
+        }
+        let atom = &cell.get_particles()[&name];
+        let speed = atom.velocity.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((speed - 1.0).abs() < 1.0e-4);
+        assert!((max_y - 1.0).abs() < 1.0e-2);
+        assert!(atom.position.iter().map(|x| x * x).sum::<f32>().sqrt() < 1.0e-2);
+    }
+
+    #[test]
+    fn test_run_with_observer() {
+        let mut integrator = Leapfrog::<f32>::new();
+        integrator.dt = 0.1;
+        let SinFF = SIN::<Elements>::new();
+        let mut atom = SinFF.atom(Elements::H(0));
+        atom.generate_spatial_coordinates(3);
+        let mut particles = HashMap::<String, Atom<Elements, f32, Vec<f32>>>::new();
+        particles.insert(atom.id.clone(), atom);
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        let mut trajectory = XyzTrajectory::new(Vec::new(), 1);
+        integrator.run(&mut cell, &SinFF, &mut [], &mut [&mut trajectory], 3);
+        let text = String::from_utf8(trajectory.writer).unwrap();
+        assert_eq!(text.lines().count(), 9);
+    }
+
+    #[test]
+    fn test_chain_forces_balance() {
+        // a - b - c in a line, each bond only listed on one end.  Every bond should still push both ways.
+        use crate::testing::{carbon, world, LJ};
+        let (a, b, c) = ("a".to_string(), "b".to_string(), "c".to_string());
+        let cell = world([
+            carbon("a", vec![0.0, 0.0, 0.0]).build(),
+            carbon("b", vec![1.2, 0.0, 0.0]).neighbors(vec![a.clone()]).build(),
+            carbon("c", vec![2.4, 0.0, 0.0]).neighbors(vec![b.clone()]).build(),
+        ]);
+        let forces = [&a, &b, &c].map(|name| pairwise_forces(name.clone(), &cell, &LJ));
+        let f = LJ.pair_potential(&Elements::C(0), &Elements::C(0)).force(1.2);
+        assert!(f.abs() > 0.1);
+        assert!((forces[0][0] + f).abs() < 1.0e-5);
+        assert!((forces[2][0] - f).abs() < 1.0e-5);
+        for k in 0..3 {
+            assert!(forces[1][k].abs() < 1.0e-5);
+            assert!(forces.iter().map(|force| force[k]).sum::<f32>().abs() < 1.0e-5);
+        }
+    }
+}
@@ -1 +1,11 @@
+pub mod fields;
+pub mod bonded;
+pub mod forces;
 pub mod integrator;
+pub mod montecarlo;
+pub mod nonbonded;
+pub mod observers;
+pub mod pulling;
+pub mod radioactive;
+pub mod reactions;
+pub mod velocities;
@@ -0,0 +1,450 @@
+use crate::Dynamics::integrator::distance;
+use crate::Dynamics::observers::Observer;
+use crate::ForceFields::potentials::PairPotential;
+use crate::ForceFields::SIN::{Elements, ForceField};
+use crate::Topology::atom::Atomic;
+use crate::Topology::cell::{Cell, ContainsParticles};
+use crate::Topology::periodic::PeriodicBox;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+// Metropolis Monte Carlo over the same cell and force field as the MD integrators.
+// Temperatures are kT (reduced units), same as the biases.
+// Energies are the force field's pair potential between everyone: along the bonds, like the integrators, and between
+// every other pair that hasn't been excluded, like NonBonded.  Cut the interactions off by wrapping the force field in a
+// CutoffForceField; with a periodic box pairs are taken at the minimum image, so keep the cutoff under half the box.
+
+pub enum Ensemble {
+    NVT,
+    NPT { pressure: f32 },
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Acceptance {
+    pub attempted: usize,
+    pub accepted: usize,
+}
+
+impl Acceptance {
+    pub fn ratio(&self) -> f32 {
+        if self.attempted == 0 {
+            return 0.0;
+        }
+        self.accepted as f32 / self.attempted as f32
+    }
+
+    fn record(&mut self, accepted: bool) {
+        self.attempted += 1;
+        if accepted {
+            self.accepted += 1;
+        }
+    }
+}
+
+// Neighbors are stored one way round on each atom (see cell_set_particles), so build the two way version for energies.
+pub fn adjacency<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    world: &impl ContainsParticles<ParT>,
+) -> HashMap<String, HashSet<String>> {
+    let mut adjacency = HashMap::<String, HashSet<String>>::new();
+    for (name, atom) in world.get_particles() {
+        adjacency.entry(name.clone()).or_default();
+        for neighbor in atom.get_neighbors() {
+            if neighbor != name {
+                adjacency
+                    .entry(name.clone())
+                    .or_default()
+                    .insert(neighbor.clone());
+                adjacency
+                    .entry(neighbor.clone())
+                    .or_default()
+                    .insert(name.clone());
+            }
+        }
+    }
+    adjacency
+}
+
+fn pair_energy<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    a: &ParT,
+    b: &ParT,
+    periodic: Option<&PeriodicBox>,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+) -> f32 {
+    let r = distance(a, b, periodic).iter().map(|z| z * z).sum::<f32>().sqrt();
+    sin.pair_potential(a.get_element(), b.get_element()).energy(r)
+}
+
+// bonded pairs always interact; exclusions only switch off the non-bonded ones.
+fn interacts<ParT>(
+    world: &impl ContainsParticles<ParT>,
+    adjacency: &HashMap<String, HashSet<String>>,
+    a: &String,
+    b: &String,
+) -> bool {
+    adjacency[a].contains(b) || !world.is_excluded(a, b)
+}
+
+// the energy of one particle with everything it interacts with.
+pub fn particle_energy<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    name: &String,
+    world: &impl ContainsParticles<ParT>,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    adjacency: &HashMap<String, HashSet<String>>,
+) -> f32 {
+    let atoms = world.get_particles();
+    let atom = &atoms[name];
+    // looking up the exclusions costs more than the energy does, so only bother for pairs that have some.
+    atoms
+        .iter()
+        .filter(|(other_name, _)| *other_name != name)
+        .map(|(other_name, other)| (other_name, pair_energy(atom, other, world.get_periodic_box(), sin)))
+        .filter(|(other_name, u)| *u != 0.0 && interacts(world, adjacency, name, other_name))
+        .map(|(_, u)| u)
+        .sum()
+}
+
+pub fn potential_energy<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    world: &impl ContainsParticles<ParT>,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+) -> f32 {
+    let adjacency = adjacency(world);
+    let atoms = world.get_particles();
+    let mut energy = 0.0;
+    for (name, atom) in atoms.iter() {
+        for (other_name, other) in atoms.iter().filter(|(n, _)| *n > name) {
+            let u = pair_energy(atom, other, world.get_periodic_box(), sin);
+            if u != 0.0 && interacts(world, &adjacency, name, other_name) {
+                energy += u;
+            }
+        }
+    }
+    energy
+}
+
+pub struct MonteCarlo {
+    pub id: String,
+    pub temperature: f32,
+    pub ensemble: Ensemble,
+    pub max_displacement: f32,
+    pub max_volume_change: f32,
+    pub target_acceptance: f32,
+    pub tune_interval: usize, // sweeps between step size adjustments; zero turns tuning off.
+    pub volume: f32,          // only used for NPT; positions get scaled about the origin.  Follows the cell's box if it has one.
+    pub displacements: Acceptance,
+    pub volume_moves: Acceptance,
+    pub rng: StdRng,
+    sweeps: usize,
+    recent_displacements: Acceptance,
+    recent_volume_moves: Acceptance,
+}
+
+impl MonteCarlo {
+    pub fn new(temperature: f32, ensemble: Ensemble) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            temperature,
+            ensemble,
+            max_displacement: 0.1,
+            max_volume_change: 1.0,
+            target_acceptance: 0.4,
+            tune_interval: 10,
+            volume: 1000.0,
+            displacements: Acceptance::default(),
+            volume_moves: Acceptance::default(),
+            rng: StdRng::from_entropy(),
+            sweeps: 0,
+            recent_displacements: Acceptance::default(),
+            recent_volume_moves: Acceptance::default(),
+        }
+    }
+
+    fn metropolis(&mut self, exponent: f32) -> bool {
+        exponent >= 0.0 || self.rng.gen::<f32>() < exponent.exp()
+    }
+
+    fn displacement_move<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &mut self,
+        name: &String,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+        adjacency: &HashMap<String, HashSet<String>>,
+    ) {
+        let old_energy = particle_energy(name, cell, sin, adjacency);
+        let old = cell.get_particles()[name].get_position().clone();
+        let d = self.max_displacement;
+        let new = old.iter().map(|x| x + self.rng.gen_range(-d..=d)).collect();
+        cell.get_mut_particles()
+            .get_mut(name)
+            .unwrap()
+            .set_position(new);
+        let new_energy = particle_energy(name, cell, sin, adjacency);
+        let accepted = self.metropolis(-(new_energy - old_energy) / self.temperature);
+        if !accepted {
+            cell.get_mut_particles()
+                .get_mut(name)
+                .unwrap()
+                .set_position(old);
+        }
+        self.displacements.record(accepted);
+        self.recent_displacements.record(accepted);
+    }
+
+    fn volume_move<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &mut self,
+        pressure: f32,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    ) {
+        // a periodic box on the cell takes over from our own volume, and gets resized along with the positions.
+        let periodic = cell.get_periodic_box().cloned();
+        if let Some(p) = periodic.as_ref() {
+            self.volume = p.volume();
+        }
+        let old_volume = self.volume;
+        let new_volume = old_volume + self.rng.gen_range(-1.0..=1.0) * self.max_volume_change;
+        if new_volume <= 0.0 {
+            self.volume_moves.record(false);
+            self.recent_volume_moves.record(false);
+            return;
+        }
+        let old_energy = potential_energy(cell, sin);
+        let scale = (new_volume / old_volume).cbrt();
+        let mut old_positions = HashMap::<String, Vec<f32>>::new();
+        for (name, atom) in cell.get_mut_particles().iter_mut() {
+            let pos = atom.get_position().clone();
+            atom.set_position(pos.iter().map(|x| x * scale).collect());
+            old_positions.insert(name.clone(), pos);
+        }
+        if let Some(p) = periodic.as_ref() {
+            cell.set_periodic_box(Some(p.scaled(scale)));
+        }
+        let new_energy = potential_energy(cell, sin);
+        let n = cell.get_particles().len() as f32;
+        let exponent = -(new_energy - old_energy + pressure * (new_volume - old_volume))
+            / self.temperature
+            + n * (new_volume / old_volume).ln();
+        let accepted = self.metropolis(exponent);
+        if accepted {
+            self.volume = new_volume;
+        } else {
+            for (name, pos) in old_positions {
+                cell.get_mut_particles()
+                    .get_mut(&name)
+                    .unwrap()
+                    .set_position(pos);
+            }
+            cell.set_periodic_box(periodic);
+        }
+        self.volume_moves.record(accepted);
+        self.recent_volume_moves.record(accepted);
+    }
+
+    // Nudges the step sizes towards the target acceptance, based on how the moves since the last tune went.
+    fn tune(&mut self) {
+        let factor = |recent: &Acceptance, target: f32| -> f32 {
+            if recent.attempted == 0 {
+                return 1.0;
+            }
+            (recent.ratio() / target).clamp(0.5, 1.5)
+        };
+        self.max_displacement *= factor(&self.recent_displacements, self.target_acceptance);
+        self.max_volume_change *= factor(&self.recent_volume_moves, self.target_acceptance);
+        if let Ensemble::NPT { .. } = self.ensemble {
+            // no point in moving further than half way across the box.
+            self.max_displacement = self.max_displacement.min(0.5 * self.volume.cbrt());
+            self.max_volume_change = self.max_volume_change.min(0.5 * self.volume);
+        }
+        self.recent_displacements = Acceptance::default();
+        self.recent_volume_moves = Acceptance::default();
+    }
+
+    // One sweep is one attempted displacement per particle (on average), plus a volume move for NPT.
+    pub fn sweep<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &mut self,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    ) {
+        let adjacency = adjacency(cell);
+        // sorted so that a seeded rng gives the same run every time.
+        let mut names: Vec<String> = cell.get_particles().keys().cloned().collect();
+        names.sort();
+        for _ in 0..names.len() {
+            let name = &names[self.rng.gen_range(0..names.len())];
+            self.displacement_move(name, cell, sin, &adjacency);
+        }
+        if let Ensemble::NPT { pressure } = self.ensemble {
+            self.volume_move(pressure, cell, sin);
+        }
+        cell.wrap_positions();
+        self.sweeps += 1;
+        if self.tune_interval > 0 && self.sweeps.is_multiple_of(self.tune_interval) {
+            self.tune();
+        }
+    }
+
+    pub fn run<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &mut self,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+        observers: &mut [&mut dyn Observer<ParT>],
+        sweeps: usize,
+    ) {
+        for _ in 0..sweeps {
+            self.sweep(cell, sin);
+            for observer in observers.iter_mut() {
+                observer.observe(self.sweeps, cell.get_time(), cell);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sin, world, World, LJ};
+    use crate::ForceFields::cutoff::{Cutoff, CutoffForceField};
+    use crate::Topology::particle::HasPhysics;
+
+    // hydrogens in a row 0.1 apart, each bonded to the one before if asked.
+    fn row(n: usize, bonded: bool) -> World {
+        let sin = sin();
+        world((0..n).map(|i| {
+            let mut atom = sin.atom(Elements::H(0));
+            atom.id = format!("h{i}");
+            atom.set_position(vec![0.1 * i as f32, 0.0, 0.0]);
+            if bonded && i > 0 {
+                atom.neighbors.push(format!("h{}", i - 1));
+            }
+            atom
+        }))
+    }
+
+    // n^3 atoms on a simple cubic lattice, filling a periodic box at the given number density.
+    fn lattice(n: usize, density: f32) -> World {
+        let side = ((n * n * n) as f32 / density).cbrt();
+        let spacing = side / n as f32;
+        let mut cell = world((0..n * n * n).map(|i| {
+            let mut atom = LJ.atom(Elements::H(0));
+            atom.id = format!("lj{i:03}");
+            let site = [i % n, i / n % n, i / (n * n)];
+            atom.set_position(site.iter().map(|&k| (k as f32 + 0.5) * spacing).collect());
+            atom
+        }));
+        cell.set_periodic_box(Some(PeriodicBox::cubic(side)));
+        cell
+    }
+
+    #[test]
+    fn test_potential_energy() {
+        let mut cell = row(3, true);
+        // two bonds of length 0.1 with U = 10 / r, plus the ends 0.2 apart.
+        assert!((potential_energy(&cell, &sin()) - 250.0).abs() < 1.0e-2);
+        let adjacency = adjacency(&cell);
+        assert!(
+            (particle_energy(&"h0".to_string(), &cell, &sin(), &adjacency) - 150.0).abs() < 1.0e-2
+        );
+        // excluding the ends leaves the bonds; excluding a bond doesn't take it away.
+        cell.exclude(&"h0".to_string(), &"h2".to_string());
+        cell.exclude(&"h0".to_string(), &"h1".to_string());
+        assert!((potential_energy(&cell, &sin()) - 200.0).abs() < 1.0e-2);
+    }
+
+    #[test]
+    fn test_nvt_relaxes() {
+        let mut cell = row(4, true);
+        let sin = sin();
+        let before = potential_energy(&cell, &sin);
+        let mut mc = MonteCarlo::new(1.0, Ensemble::NVT);
+        mc.rng = StdRng::seed_from_u64(7);
+        mc.run(&mut cell, &sin, &mut [], 200);
+        assert!(potential_energy(&cell, &sin) < 0.5 * before);
+        assert_eq!(mc.displacements.attempted, 800);
+        assert!(mc.displacements.accepted > 0);
+    }
+
+    #[test]
+    fn test_nvt_lennard_jones_liquid() {
+        // the textbook state point, rho = 0.8 and kT = 1, where a truncated Lennard-Jones liquid sits at about
+        // U/N = -5.2 (rc = 2.5, no tail correction).  An ideal gas would sit at zero.
+        let mut cell = lattice(5, 0.8);
+        let lj = CutoffForceField::new(LJ, Cutoff::new(2.5));
+        let mut mc = MonteCarlo::new(1.0, Ensemble::NVT);
+        mc.rng = StdRng::seed_from_u64(5);
+        mc.run(&mut cell, &lj, &mut [], 50);
+        let (mut total, samples) = (0.0, 25);
+        for _ in 0..samples {
+            mc.sweep(&mut cell, &lj);
+            total += potential_energy(&cell, &lj);
+        }
+        let per_atom = total / samples as f32 / 125.0;
+        assert!((per_atom + 5.2).abs() < 0.3, "U/N = {per_atom}");
+    }
+
+    #[test]
+    fn test_tuning() {
+        let mut mc = MonteCarlo::new(1.0, Ensemble::NVT);
+        mc.recent_displacements = Acceptance {
+            attempted: 10,
+            accepted: 10,
+        };
+        mc.tune();
+        assert!((mc.max_displacement - 0.15).abs() < 1.0e-6);
+        mc.recent_displacements = Acceptance {
+            attempted: 10,
+            accepted: 0,
+        };
+        mc.tune();
+        assert!((mc.max_displacement - 0.075).abs() < 1.0e-6);
+    }
+
+    // switches everyone off from everyone else.
+    fn exclude_all(cell: &mut World) {
+        let names: Vec<String> = cell.get_particles().keys().cloned().collect();
+        for a in names.iter() {
+            for b in names.iter().filter(|b| *b > a) {
+                cell.exclude(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_npt_ideal_gas_volume() {
+        // no interactions, so <V> = (N + 1) kT / P
+        let n = 9;
+        let mut cell = row(n, false);
+        exclude_all(&mut cell);
+        let sin = sin();
+        let mut mc = MonteCarlo::new(1.0, Ensemble::NPT { pressure: 2.0 });
+        mc.rng = StdRng::seed_from_u64(11);
+        mc.volume = 5.0;
+        mc.run(&mut cell, &sin, &mut [], 500);
+        let mut total = 0.0;
+        let samples = 4000;
+        for _ in 0..samples {
+            mc.sweep(&mut cell, &sin);
+            total += mc.volume;
+        }
+        let mean = total / samples as f32;
+        assert!((mean - 5.0).abs() < 0.5, "mean volume {mean}");
+    }
+
+    #[test]
+    fn test_npt_resizes_periodic_box() {
+        let mut cell = row(9, false);
+        exclude_all(&mut cell);
+        cell.set_periodic_box(Some(PeriodicBox::cubic(2.0)));
+        let sin = sin();
+        let mut mc = MonteCarlo::new(1.0, Ensemble::NPT { pressure: 2.0 });
+        mc.rng = StdRng::seed_from_u64(12);
+        mc.run(&mut cell, &sin, &mut [], 200);
+        let periodic = cell.get_periodic_box().unwrap();
+        assert!(mc.volume_moves.accepted > 0);
+        assert!((periodic.volume() - mc.volume).abs() < 1.0e-3 * mc.volume);
+        // and everyone is still inside it.
+        for atom in cell.get_particles().values() {
+            assert!(periodic.fractional(&atom.position).iter().all(|s| (0.0..1.0).contains(s)));
+        }
+    }
+}
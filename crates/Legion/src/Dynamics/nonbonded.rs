@@ -0,0 +1,124 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::ForceFields::potentials::PairPotential;
+use crate::ForceFields::SIN::{Elements, ForceField};
+use crate::Topology::atom::Atomic;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::neighbors::{Pair, VerletList};
+use std::collections::HashMap;
+
+// The force field's pair interactions between everyone that's close, not just along the bonds.  Pairs come out of a
+// Verlet list, so they get found automatically as things move around.  Bonded pairs are left to the integrator's
+// bonded forces (they're already handled through each atom's neighbors), and excluded pairs are skipped entirely.
+pub struct NonBonded<FF> {
+    pub forcefield: FF,
+    pub neighbors: VerletList,
+}
+
+impl<FF: ForceField<Elements, f32, Vec<f32>>> NonBonded<FF> {
+    pub fn new(forcefield: FF, cutoff: f32, skin: f32) -> Self {
+        Self {
+            forcefield,
+            neighbors: VerletList::new(cutoff, skin),
+        }
+    }
+
+    fn bonded<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        world: &dyn ContainsParticles<ParT>,
+        a: &String,
+        b: &String,
+    ) -> bool {
+        let atoms = world.get_particles();
+        atoms[a].get_neighbors().contains(b) || atoms[b].get_neighbors().contains(a)
+    }
+
+    fn pairs<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> Vec<Pair> {
+        self.neighbors
+            .pairs_or_fresh(world)
+            .into_iter()
+            .filter(|(a, b, _, r)| *r > 0.0 && !Self::bonded(world, a, b))
+            .collect()
+    }
+}
+
+impl<ParT: Atomic<Elements, f32, Vec<f32>>, FF: ForceField<Elements, f32, Vec<f32>>> ForceTerm<ParT>
+    for NonBonded<FF>
+{
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        self.neighbors.update(world);
+        let atoms = world.get_particles();
+        let mut forces: HashMap<String, Vec<f32>> = atoms
+            .keys()
+            .map(|name| (name.clone(), vec![0.0; 3]))
+            .collect();
+        for (a, b, d, r) in self.pairs(world) {
+            let f = self
+                .forcefield
+                .pair_potential(atoms[&a].get_element(), atoms[&b].get_element())
+                .force(r);
+            for (k, z) in d.iter().enumerate() {
+                forces.get_mut(&a).unwrap()[k] += f * z / r;
+                forces.get_mut(&b).unwrap()[k] -= f * z / r;
+            }
+        }
+        forces
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        let atoms = world.get_particles();
+        self.pairs(world)
+            .iter()
+            .map(|(a, b, _, r)| {
+                self.forcefield
+                    .pair_potential(atoms[a].get_element(), atoms[b].get_element())
+                    .energy(*r)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sin, world, TestAtom};
+    use crate::Topology::particle::HasPhysics;
+    use crate::Topology::periodic::PeriodicBox;
+
+    #[test]
+    fn test_unbonded_atoms_interact() {
+        // a bonded pair, plus a third atom that only ever meets them through the box edge.
+        let sin = sin();
+        let mut atoms: Vec<TestAtom> = [1.0, 1.5, 9.4]
+            .map(|x| {
+                let mut atom = sin.atom(Elements::H(0));
+                atom.set_position(vec![x, 5.0, 5.0]);
+                atom
+            })
+            .into();
+        let names: Vec<String> = atoms.iter().map(|atom| atom.id.clone()).collect();
+        atoms[1].neighbors.push(names[0].clone());
+        let mut cell = world(atoms);
+        cell.set_periodic_box(Some(PeriodicBox::cubic(10.0)));
+
+        let mut nonbonded = NonBonded::new(sin, 2.0, 0.3);
+        let forces = nonbonded.forces(&cell, 0.0);
+        // the bond is left alone, so the only push is between the first and third atoms (1.6 apart through the edge).
+        let pair = nonbonded
+            .forcefield
+            .pair_potential(&Elements::H(0), &Elements::H(0));
+        let (u, f) = pair.evaluate(1.6);
+        assert!((forces[&names[0]][0] - f).abs() < 1.0e-4);
+        assert!((forces[&names[2]][0] + f).abs() < 1.0e-4);
+        assert_eq!(forces[&names[1]], vec![0.0, 0.0, 0.0]);
+        assert!((ForceTerm::<TestAtom>::energy(&nonbonded, &cell, 0.0) - u).abs() < 1.0e-4);
+        // and excluding the pair turns it off.
+        cell.exclude(&names[0], &names[2]);
+        let forces = nonbonded.forces(&cell, 0.0);
+        assert_eq!(forces[&names[0]], vec![0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::ForceFields::SIN::Elements;
+use crate::Topology::atom::HasElement;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::HasPhysics;
+use std::io::Write;
+
+// Observers get a look at the world after every step (or sweep, for Monte Carlo), so the MD and MC
+// drivers can share the same analysis and trajectory output.
+pub trait Observer<ParT> {
+    fn observe(&mut self, step: usize, time: f32, world: &dyn ContainsParticles<ParT>);
+}
+
+// Writes frames in the plain XYZ format that just about every molecular viewer can read.
+// Particles are written in order of their ids so that they line up from frame to frame.
+pub struct XyzTrajectory<W: Write> {
+    pub writer: W,
+    pub interval: usize,
+    pub unwrapped: bool, // write positions as if the periodic box had never wrapped anyone, for diffusion and the like.
+}
+
+impl<W: Write> XyzTrajectory<W> {
+    pub fn new(writer: W, interval: usize) -> Self {
+        Self {
+            writer,
+            interval: interval.max(1),
+            unwrapped: false,
+        }
+    }
+
+    pub fn write_frame<ParT: HasPhysics<Vec<f32>> + HasElement<Elements>>(
+        &mut self,
+        step: usize,
+        time: f32,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> std::io::Result<()> {
+        let particles = world.get_particles();
+        let mut names: Vec<&String> = particles.keys().collect();
+        names.sort();
+        writeln!(self.writer, "{}", names.len())?;
+        writeln!(self.writer, "step={step} time={time}")?;
+        for name in names {
+            let p = &particles[name];
+            let mut pos = p.get_position().clone();
+            if let (true, Some(periodic), Some(image)) =
+                (self.unwrapped, world.get_periodic_box(), world.get_image(name))
+            {
+                let moved = periodic.translation(image);
+                for k in 0..3 {
+                    pos[k] += moved[k];
+                }
+            }
+            writeln!(
+                self.writer,
+                "{} {} {} {}",
+                p.get_element().symbol(),
+                pos[0],
+                pos[1],
+                pos[2]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>> + HasElement<Elements>, W: Write> Observer<ParT>
+    for XyzTrajectory<W>
+{
+    fn observe(&mut self, step: usize, time: f32, world: &dyn ContainsParticles<ParT>) {
+        if step.is_multiple_of(self.interval) {
+            if let Err(e) = self.write_frame(step, time, world) {
+                log::error!("couldn't write trajectory frame {step}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+    use crate::Topology::periodic::PeriodicBox;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_xyz_trajectory() {
+        let mut particles = HashMap::new();
+        for (name, element) in [("b", Elements::O(0)), ("a", Elements::H(0))] {
+            let atom = AtomBuilder::<Elements, f32, Vec<f32>>::new()
+                .element(element)
+                .id(Some(name.to_string()))
+                .position(vec![1.0, 2.0, 3.0])
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        let mut trajectory = XyzTrajectory::new(Vec::new(), 2);
+        for step in 0..4 {
+            trajectory.observe(step, 0.0, &cell);
+        }
+        let text = String::from_utf8(trajectory.writer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        // two frames of two atoms each.
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "2");
+        assert_eq!(lines[2], "H 1 2 3");
+        assert_eq!(lines[3], "O 1 2 3");
+        assert_eq!(lines[5], "step=2 time=0");
+    }
+
+    #[test]
+    fn test_unwrapped_trajectory() {
+        let atom = AtomBuilder::<Elements, f32, Vec<f32>>::new()
+            .element(Elements::H(0))
+            .id(Some("a".to_string()))
+            .position(vec![-1.0, 2.0, 7.0])
+            .build();
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(HashMap::from([(atom.id.clone(), atom)]));
+        cell.set_periodic_box(Some(PeriodicBox::cubic(4.0)));
+        cell.wrap_positions();
+        let mut wrapped = XyzTrajectory::new(Vec::new(), 1);
+        let mut unwrapped = XyzTrajectory::new(Vec::new(), 1);
+        unwrapped.unwrapped = true;
+        wrapped.observe(1, 0.0, &cell);
+        unwrapped.observe(1, 0.0, &cell);
+        let wrapped = String::from_utf8(wrapped.writer).unwrap();
+        let unwrapped = String::from_utf8(unwrapped.writer).unwrap();
+        assert_eq!(wrapped.lines().nth(2), Some("H 3 2 3"));
+        assert_eq!(unwrapped.lines().nth(2), Some("H -1 2 7"));
+    }
+}
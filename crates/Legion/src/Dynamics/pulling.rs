@@ -0,0 +1,194 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Sampling::colvars::centroid;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::HasPhysics;
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+// Steered MD!  Pull on the center of a group of atoms and keep track of how much work it took.
+// The pulling force is split evenly over the atoms in the group, same as the group centers in the CVs.
+
+fn spread_force(atoms: &[String], force: Vector3<f32>) -> HashMap<String, Vec<f32>> {
+    let f = force / atoms.len() as f32;
+    atoms
+        .iter()
+        .map(|name| (name.clone(), vec![f[0], f[1], f[2]]))
+        .collect()
+}
+
+// A constant force on the group.  Work is the force dotted with how far the group center has moved.
+pub struct ConstantForcePull {
+    pub atoms: Vec<String>,
+    pub force: Vec<f32>,
+    pub work: f32,
+    pub log_interval: usize,
+    last_center: Option<Vector3<f32>>,
+    calls: usize,
+}
+
+impl ConstantForcePull {
+    pub fn new(atoms: Vec<String>, force: Vec<f32>) -> Self {
+        Self {
+            atoms,
+            force,
+            work: 0.0,
+            log_interval: 100,
+            last_center: None,
+            calls: 0,
+        }
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>>> ForceTerm<ParT> for ConstantForcePull {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let force = Vector3::new(self.force[0], self.force[1], self.force[2]);
+        let center = centroid(world, &self.atoms);
+        if let Some(last) = self.last_center {
+            self.work += force.dot(&(center - last));
+        }
+        self.last_center = Some(center);
+        if self.calls.is_multiple_of(self.log_interval) {
+            log::info!("constant force pull: t = {time}, work = {}", self.work);
+        }
+        self.calls += 1;
+        spread_force(&self.atoms, force)
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        // the potential of a constant force is -F . x
+        let force = Vector3::new(self.force[0], self.force[1], self.force[2]);
+        -force.dot(&centroid(world, &self.atoms))
+    }
+}
+
+// A harmonic spring between the group center and an anchor that moves at constant velocity.
+// Work is the integral of the spring force along the anchor's path, F . v dt.
+pub struct ConstantVelocityPull {
+    pub atoms: Vec<String>,
+    pub k: f32,
+    pub velocity: Vec<f32>,
+    pub anchor: Option<Vec<f32>>, // where the anchor is at t = 0; defaults to wherever the group center starts.
+    pub work: f32,
+    pub log_interval: usize,
+    last: Option<(f32, f32)>, // (time, F . v) from the last evaluation, for the trapezoid rule.
+    calls: usize,
+}
+
+impl ConstantVelocityPull {
+    pub fn new(atoms: Vec<String>, k: f32, velocity: Vec<f32>) -> Self {
+        Self {
+            atoms,
+            k,
+            velocity,
+            anchor: None,
+            work: 0.0,
+            log_interval: 100,
+            last: None,
+            calls: 0,
+        }
+    }
+
+    pub fn anchor_position(&self, time: f32) -> Option<Vector3<f32>> {
+        self.anchor.as_ref().map(|a| {
+            Vector3::new(a[0], a[1], a[2])
+                + Vector3::new(self.velocity[0], self.velocity[1], self.velocity[2]) * time
+        })
+    }
+
+    fn spring_force<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+        time: f32,
+    ) -> Vector3<f32> {
+        let anchor = self.anchor_position(time).unwrap();
+        (anchor - centroid(world, &self.atoms)) * self.k
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>>> ForceTerm<ParT> for ConstantVelocityPull {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        if self.anchor.is_none() {
+            // start the anchor on the group center, backing out however far it would have moved by now.
+            let center = centroid(world, &self.atoms);
+            self.anchor = Some(
+                (0..3)
+                    .map(|i| center[i] - self.velocity[i] * time)
+                    .collect(),
+            );
+        }
+        let force = self.spring_force(world, time);
+        let power = force.dot(&Vector3::new(
+            self.velocity[0],
+            self.velocity[1],
+            self.velocity[2],
+        ));
+        if let Some((last_time, last_power)) = self.last {
+            self.work += 0.5 * (power + last_power) * (time - last_time);
+        }
+        self.last = Some((time, power));
+        if self.calls.is_multiple_of(self.log_interval) {
+            log::info!("constant velocity pull: t = {time}, work = {}", self.work);
+        }
+        self.calls += 1;
+        spread_force(&self.atoms, force)
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, time: f32) -> f32 {
+        match self.anchor {
+            Some(_) => self.spring_force(world, time).norm_squared() / (2.0 * self.k),
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{carbon, world, World};
+
+    fn pair() -> World {
+        world([("a", -0.5), ("b", 0.5)].map(|(name, x)| carbon(name, vec![x, 0.0, 0.0]).build()))
+    }
+
+    fn translate(cell: &mut World, dx: f32) {
+        for atom in cell.get_mut_particles().values_mut() {
+            atom.position[0] += dx;
+        }
+    }
+
+    #[test]
+    fn test_constant_force_work() {
+        let mut cell = pair();
+        let atoms = vec!["a".to_string(), "b".to_string()];
+        let mut pull = ConstantForcePull::new(atoms, vec![2.0, 0.0, 0.0]);
+        let forces = pull.forces(&cell, 0.0);
+        assert_eq!(forces["a"], vec![1.0, 0.0, 0.0]);
+        translate(&mut cell, 1.5);
+        pull.forces(&cell, 1.0);
+        assert!((pull.work - 3.0).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_constant_velocity_work() {
+        // hold the atoms still and drag the anchor away: W = k v^2 t^2 / 2
+        let cell = pair();
+        let atoms = vec!["a".to_string(), "b".to_string()];
+        let mut pull = ConstantVelocityPull::new(atoms, 4.0, vec![0.5, 0.0, 0.0]);
+        let dt = 0.01;
+        for step in 0..=100 {
+            pull.forces(&cell, step as f32 * dt);
+        }
+        let expected = 0.5 * 4.0 * 0.25 * 1.0;
+        assert!((pull.work - expected).abs() < 1.0e-4);
+        // the spring has been stretched by v t = 0.5, all of which went into the spring.
+        assert!((pull.energy(&cell, 1.0) - expected).abs() < 1.0e-4);
+        let forces = pull.forces(&cell, 1.0);
+        assert!((forces["a"][0] - 1.0).abs() < 1.0e-4);
+    }
+}
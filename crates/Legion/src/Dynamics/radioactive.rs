@@ -0,0 +1,278 @@
+use crate::ForceFields::SIN::{Elements, ForceField};
+use crate::Topology::atom::{Atom, HasElement};
+use crate::Topology::cell::{Cell, ContainsParticles};
+use crate::Topology::particle::{HasCharge, HasMass, HasPhysics};
+use nalgebra::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Radioactive decay!  The u32 on each element is its isotope; unstable isotopes turn into something else at random,
+// with a probability of 1 - exp(-ln(2) dt / half_life) per step.  The force field decides the new mass and charge.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecayChannel {
+    pub parent: Elements,
+    pub daughter: Elements,
+    pub half_life: f32,
+    pub product: Option<Elements>, // an emitted particle (alpha, etc.), if there is one.
+    pub product_speed: f32,        // how fast the product leaves, in the centre of mass frame.
+}
+
+impl DecayChannel {
+    pub fn new(parent: Elements, daughter: Elements, half_life: f32) -> Self {
+        Self {
+            parent,
+            daughter,
+            half_life,
+            product: None,
+            product_speed: 0.0,
+        }
+    }
+
+    pub fn emitting(mut self, product: Elements, product_speed: f32) -> Self {
+        self.product = Some(product);
+        self.product_speed = product_speed;
+        self
+    }
+
+    pub fn rate(&self) -> f32 {
+        std::f32::consts::LN_2 / self.half_life
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecayEvent {
+    pub time: f32,
+    pub particle: String,
+    pub channel: usize,
+    pub product: Option<String>,
+}
+
+pub struct RadioactiveDecay {
+    pub channels: Vec<DecayChannel>,
+    pub events: Vec<DecayEvent>,
+    pub rng: StdRng,
+}
+
+impl RadioactiveDecay {
+    pub fn new(channels: Vec<DecayChannel>) -> Self {
+        Self {
+            channels,
+            events: Vec::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    // every channel this isotope can decay through; more than one means a branching decay.
+    fn channels_for(&self, element: &Elements) -> Vec<usize> {
+        (0..self.channels.len())
+            .filter(|&i| self.channels[i].parent == *element)
+            .collect()
+    }
+
+    // Rolls the dice for every unstable particle over a step of length dt (call it once per step, after the integrator).
+    // Returns the events from this step; they're also kept in self.events.
+    pub fn apply(
+        &mut self,
+        cell: &mut Cell<Atom<Elements, f32, Vec<f32>>, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+        dt: f32,
+    ) -> Vec<DecayEvent> {
+        let time = cell.get_time();
+        let mut names: Vec<String> = cell.get_particles().keys().cloned().collect();
+        // sorted so that a seeded rng gives the same run every time.
+        names.sort();
+        let mut events = Vec::new();
+        for name in names {
+            let candidates = self.channels_for(cell.get_particles()[&name].get_element());
+            if candidates.is_empty() {
+                continue;
+            }
+            // competing channels: decay at the total rate, then pick a branch in proportion to its rate.
+            let total: f32 = candidates.iter().map(|&i| self.channels[i].rate()).sum();
+            if self.rng.gen::<f32>() >= 1.0 - (-total * dt).exp() {
+                continue;
+            }
+            let mut pick = self.rng.gen::<f32>() * total;
+            let mut channel = candidates[candidates.len() - 1];
+            for &i in candidates.iter() {
+                pick -= self.channels[i].rate();
+                if pick <= 0.0 {
+                    channel = i;
+                    break;
+                }
+            }
+            let product = self.transmute(&name, channel, cell, sin);
+            log::debug!("{name} decayed through channel {channel} at t = {time}");
+            events.push(DecayEvent {
+                time,
+                particle: name,
+                channel,
+                product,
+            });
+        }
+        self.events.extend(events.iter().cloned());
+        events
+    }
+
+    fn transmute(
+        &mut self,
+        name: &String,
+        channel: usize,
+        cell: &mut Cell<Atom<Elements, f32, Vec<f32>>, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    ) -> Option<String> {
+        let channel = self.channels[channel].clone();
+        let atom = cell.get_mut_particles().get_mut(name).unwrap();
+        let parent_mass = *atom.get_mass();
+        atom.set_element(channel.daughter.clone());
+        atom.set_mass(sin.mass(&channel.daughter));
+        atom.set_charge(sin.charge(&channel.daughter));
+        let emitted = channel.product?;
+
+        // fire the product off in a random direction and kick the daughter back the other way, in the frame of the centre
+        // of mass.  The force field's masses needn't add up, so that frame moves with the parent's momentum over the
+        // masses we actually end up with, which keeps the total momentum what it was.
+        let direction = loop {
+            let v = Vector3::new(
+                self.rng.gen_range(-1.0..=1.0),
+                self.rng.gen_range(-1.0..=1.0),
+                self.rng.gen_range(-1.0..=1.0_f32),
+            );
+            let norm = v.norm();
+            if norm > 1.0e-3 && norm <= 1.0 {
+                break v / norm;
+            }
+        };
+        let mut product = sin.atom(emitted);
+        let (daughter_mass, product_mass) = (*atom.get_mass(), *product.get_mass());
+        let velocity = atom.get_velocity();
+        let center = Vector3::new(velocity[0], velocity[1], velocity[2]) * parent_mass
+            / (daughter_mass + product_mass);
+        let momentum = direction * (channel.product_speed * product_mass);
+        let (daughter_velocity, product_velocity) = (
+            center - momentum / daughter_mass,
+            center + momentum / product_mass,
+        );
+        atom.set_velocity(daughter_velocity.iter().cloned().collect());
+        product.set_position(atom.get_position().clone());
+        product.set_velocity(product_velocity.iter().cloned().collect());
+        product.set_acceleration(vec![0.0; 3]);
+        let id = product.id.clone();
+        cell.get_mut_particles().insert(id.clone(), product);
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sin, world, World};
+
+    // n of the same isotope, all sitting still at the origin.
+    fn sample(element: Elements, n: usize) -> World {
+        let sin = sin();
+        world((0..n).map(|_| {
+            let mut atom = sin.atom(element.clone());
+            atom.set_position(vec![0.0; 3]);
+            atom.set_velocity(vec![0.0; 3]);
+            atom
+        }))
+    }
+
+    fn count(cell: &World, element: &Elements) -> usize {
+        cell.get_particles()
+            .values()
+            .filter(|a| a.get_element() == element)
+            .count()
+    }
+
+    #[test]
+    fn test_exponential_decay() {
+        let n = 4000;
+        let mut cell = sample(Elements::C(14), n);
+        let mut decay = RadioactiveDecay::new(vec![DecayChannel::new(
+            Elements::C(14),
+            Elements::X(14),
+            1.0,
+        )]);
+        decay.rng = StdRng::seed_from_u64(3);
+        let dt = 0.01;
+        for half_lives in 1..=3 {
+            for _ in 0..100 {
+                decay.apply(&mut cell, &sin(), dt);
+                let t = cell.get_time();
+                cell.set_time(t + dt);
+            }
+            // N(t) = N0 / 2^(t / half_life), give or take a few standard deviations of a binomial.
+            let expected = n as f32 * 0.5_f32.powi(half_lives);
+            let p = expected / n as f32;
+            let sigma = (n as f32 * p * (1.0 - p)).sqrt();
+            let remaining = count(&cell, &Elements::C(14)) as f32;
+            assert!(
+                (remaining - expected).abs() < 4.0 * sigma,
+                "{remaining} vs {expected}"
+            );
+        }
+        assert_eq!(
+            count(&cell, &Elements::X(14)) + count(&cell, &Elements::C(14)),
+            n
+        );
+        assert_eq!(decay.events.len(), count(&cell, &Elements::X(14)));
+        // the force field hands out the new mass.
+        let daughter = cell
+            .get_particles()
+            .values()
+            .find(|a| *a.get_element() == Elements::X(14))
+            .unwrap();
+        assert_eq!(daughter.mass, 99.0);
+    }
+
+    #[test]
+    fn test_stable_isotope_left_alone() {
+        let mut cell = sample(Elements::C(12), 100);
+        let mut decay = RadioactiveDecay::new(vec![DecayChannel::new(
+            Elements::C(14),
+            Elements::X(14),
+            0.001,
+        )]);
+        decay.apply(&mut cell, &sin(), 1.0);
+        assert_eq!(count(&cell, &Elements::C(12)), 100);
+    }
+
+    #[test]
+    fn test_emission_conserves_momentum() {
+        // a moving parent, and a force field whose masses don't add up: O (3) goes to X (99) and H (1).
+        let mut cell = sample(Elements::O(16), 1);
+        let parent_velocity = vec![1.0, -0.5, 0.25];
+        for atom in cell.get_mut_particles().values_mut() {
+            atom.set_velocity(parent_velocity.clone());
+        }
+        let mut decay = RadioactiveDecay::new(vec![DecayChannel::new(
+            Elements::O(16),
+            Elements::X(12),
+            1.0e-6,
+        )
+        .emitting(Elements::H(4), 2.0)]);
+        let events = decay.apply(&mut cell, &sin(), 1.0);
+        assert_eq!(events.len(), 1);
+        assert_eq!(cell.get_particles().len(), 2);
+        let mut momentum = vec![0.0; 3];
+        for atom in cell.get_particles().values() {
+            for i in 0..3 {
+                momentum[i] += atom.mass * atom.velocity[i];
+            }
+        }
+        for i in 0..3 {
+            assert!((momentum[i] - 3.0 * parent_velocity[i]).abs() < 1.0e-5);
+        }
+        // the product leaves at its speed relative to the centre of mass, which moves at 3 v / 100.
+        let product = &cell.get_particles()[events[0].product.as_ref().unwrap()];
+        let speed = (0..3)
+            .map(|i| product.velocity[i] - 0.03 * parent_velocity[i])
+            .map(|v| v * v)
+            .sum::<f32>()
+            .sqrt();
+        assert!((speed - 2.0).abs() < 1.0e-5);
+    }
+}
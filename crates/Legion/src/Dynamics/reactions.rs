@@ -0,0 +1,356 @@
+use crate::Dynamics::integrator::distance;
+use crate::ForceFields::SIN::{Elements, ForceField};
+use crate::Topology::atom::Atomic;
+use crate::Topology::cell::{Cell, ContainsParticles};
+use crate::Topology::periodic::PeriodicBox;
+use std::collections::HashSet;
+
+// Toy reactive chemistry.  Bonds form when two atoms of the right elements wander within a capture radius of each other,
+// and break when they get stretched past a threshold.  Either can change the elements involved (C(0) -> C(1), say),
+// which is how a rule marks an atom as "used up" or activated.  Neighbors and exclusions on the cell get kept in sync.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BondFormation {
+    pub a: Elements,
+    pub b: Elements,
+    pub capture_radius: f32,
+    pub products: Option<(Elements, Elements)>, // what a and b turn into once bonded.
+    pub max_bonds: usize, // neither atom can end up with more bonds than this.
+}
+
+impl BondFormation {
+    pub fn new(a: Elements, b: Elements, capture_radius: f32) -> Self {
+        Self {
+            a,
+            b,
+            capture_radius,
+            products: None,
+            max_bonds: usize::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BondBreaking {
+    pub a: Elements,
+    pub b: Elements,
+    pub threshold: f32,
+    pub products: Option<(Elements, Elements)>,
+}
+
+impl BondBreaking {
+    pub fn new(a: Elements, b: Elements, threshold: f32) -> Self {
+        Self {
+            a,
+            b,
+            threshold,
+            products: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReactionEvent {
+    Formed { a: String, b: String, time: f32 },
+    Broken { a: String, b: String, time: f32 },
+}
+
+pub struct Reactions {
+    pub formation: Vec<BondFormation>,
+    pub breaking: Vec<BondBreaking>,
+    pub events: Vec<ReactionEvent>,
+}
+
+// does the (x, y) pair match the (a, b) rule?  Returns whether it matched the other way around.
+fn matches(x: &Elements, y: &Elements, a: &Elements, b: &Elements) -> Option<bool> {
+    if x == a && y == b {
+        Some(false)
+    } else if x == b && y == a {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+fn separation<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    a: &ParT,
+    b: &ParT,
+    periodic: Option<&PeriodicBox>,
+) -> f32 {
+    distance(a, b, periodic).iter().map(|z| z * z).sum::<f32>().sqrt()
+}
+
+fn set_element<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    cell: &mut Cell<ParT, f32>,
+    name: &String,
+    element: Elements,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+) {
+    let atom = cell.get_mut_particles().get_mut(name).unwrap();
+    atom.set_mass(sin.mass(&element));
+    atom.set_charge(sin.charge(&element));
+    atom.set_element(element);
+}
+
+// the rule's products are in (a, b) order; flip them if the pair matched the other way around.
+fn apply_products<ParT: Atomic<Elements, f32, Vec<f32>>>(
+    cell: &mut Cell<ParT, f32>,
+    x: &String,
+    y: &String,
+    products: &Option<(Elements, Elements)>,
+    flipped: bool,
+    sin: &impl ForceField<Elements, f32, Vec<f32>>,
+) {
+    if let Some((pa, pb)) = products {
+        let (px, py) = if flipped { (pb, pa) } else { (pa, pb) };
+        set_element(cell, x, px.clone(), sin);
+        set_element(cell, y, py.clone(), sin);
+    }
+}
+
+impl Reactions {
+    pub fn new(formation: Vec<BondFormation>, breaking: Vec<BondBreaking>) -> Self {
+        Self {
+            formation,
+            breaking,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn bond<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        cell: &mut Cell<ParT, f32>,
+        a: &String,
+        b: &String,
+    ) {
+        for (x, y) in [(a, b), (b, a)] {
+            let atom = cell.get_mut_particles().get_mut(x).unwrap();
+            let mut neighbors = atom.get_neighbors().clone();
+            if !neighbors.contains(y) {
+                neighbors.push(y.clone());
+            }
+            atom.set_neighbors(neighbors);
+        }
+        cell.exclude(a, b);
+    }
+
+    pub fn unbond<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        cell: &mut Cell<ParT, f32>,
+        a: &String,
+        b: &String,
+    ) {
+        for (x, y) in [(a, b), (b, a)] {
+            let atom = cell.get_mut_particles().get_mut(x).unwrap();
+            let neighbors = atom
+                .get_neighbors()
+                .iter()
+                .filter(|n| *n != y)
+                .cloned()
+                .collect();
+            atom.set_neighbors(neighbors);
+        }
+        cell.include(a, b);
+    }
+
+    fn bonded<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        cell: &Cell<ParT, f32>,
+        a: &String,
+        b: &String,
+    ) -> bool {
+        let atoms = cell.get_particles();
+        atoms[a].get_neighbors().contains(b) || atoms[b].get_neighbors().contains(a)
+    }
+
+    fn bond_count<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        cell: &Cell<ParT, f32>,
+        a: &String,
+    ) -> usize {
+        let atoms = cell.get_particles();
+        let mut partners: HashSet<&String> = atoms[a].get_neighbors().iter().collect();
+        for (name, atom) in atoms.iter() {
+            if atom.get_neighbors().contains(a) {
+                partners.insert(name);
+            }
+        }
+        partners.len()
+    }
+
+    // Call once per step.  Bonds get broken first, then new ones formed; each atom reacts at most once per call
+    // so that the element changes from one reaction can't get stomped on by another.  Closer pairs get first go at
+    // forming bonds, so an atom bonds to whoever's nearest rather than whoever comes first by name.
+    pub fn apply<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &mut self,
+        cell: &mut Cell<ParT, f32>,
+        sin: &impl ForceField<Elements, f32, Vec<f32>>,
+    ) -> Vec<ReactionEvent> {
+        let time = cell.get_time();
+        let mut names: Vec<String> = cell.get_particles().keys().cloned().collect();
+        names.sort();
+        let mut reacted = HashSet::<String>::new();
+        let mut events = Vec::new();
+
+        // breaking
+        for x in names.iter() {
+            let mut neighbors = cell.get_particles()[x].get_neighbors().clone();
+            neighbors.sort();
+            for y in neighbors.iter() {
+                if reacted.contains(x)
+                    || reacted.contains(y)
+                    || !cell.get_particles().contains_key(y)
+                {
+                    continue;
+                }
+                let (ax, ay) = (&cell.get_particles()[x], &cell.get_particles()[y]);
+                let r = separation(ax, ay, cell.get_periodic_box());
+                let rule = self.breaking.iter().find_map(|rule| {
+                    matches(ax.get_element(), ay.get_element(), &rule.a, &rule.b)
+                        .filter(|_| r > rule.threshold)
+                        .map(|flipped| (rule.clone(), flipped))
+                });
+                if let Some((rule, flipped)) = rule {
+                    Self::unbond(cell, x, y);
+                    apply_products(cell, x, y, &rule.products, flipped, sin);
+                    reacted.insert(x.clone());
+                    reacted.insert(y.clone());
+                    events.push(ReactionEvent::Broken {
+                        a: x.clone(),
+                        b: y.clone(),
+                        time,
+                    });
+                }
+            }
+        }
+
+        // formation: find everyone close enough to react, then go through them closest first.
+        let mut candidates = Vec::new();
+        for (i, x) in names.iter().enumerate() {
+            for y in names[i + 1..].iter() {
+                if reacted.contains(x) || reacted.contains(y) || Self::bonded(cell, x, y) {
+                    continue;
+                }
+                let (ax, ay) = (&cell.get_particles()[x], &cell.get_particles()[y]);
+                let r = separation(ax, ay, cell.get_periodic_box());
+                let rule = self.formation.iter().find_map(|rule| {
+                    matches(ax.get_element(), ay.get_element(), &rule.a, &rule.b)
+                        .filter(|_| r < rule.capture_radius)
+                        .map(|flipped| (rule.clone(), flipped))
+                });
+                if let Some((rule, flipped)) = rule {
+                    candidates.push((r, x, y, rule, flipped));
+                }
+            }
+        }
+        // stable, so ties stay in name order.
+        candidates.sort_by(|p, q| p.0.total_cmp(&q.0));
+        for (_, x, y, rule, flipped) in candidates {
+            if reacted.contains(x)
+                || reacted.contains(y)
+                || Self::bond_count(cell, x) >= rule.max_bonds
+                || Self::bond_count(cell, y) >= rule.max_bonds
+            {
+                continue;
+            }
+            Self::bond(cell, x, y);
+            apply_products(cell, x, y, &rule.products, flipped, sin);
+            reacted.insert(x.clone());
+            reacted.insert(y.clone());
+            events.push(ReactionEvent::Formed {
+                a: x.clone(),
+                b: y.clone(),
+                time,
+            });
+        }
+
+        for event in events.iter() {
+            log::debug!("{:?}", event);
+        }
+        self.events.extend(events.iter().cloned());
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sin, world, World};
+    use crate::Topology::atom::HasElement;
+    use crate::Topology::particle::HasPhysics;
+
+    // named atoms strung out along x.
+    fn line(atoms: Vec<(&str, Elements, f32)>) -> World {
+        let sin = sin();
+        world(atoms.into_iter().map(|(name, element, x)| {
+            let mut atom = sin.atom(element);
+            atom.id = name.to_string();
+            atom.set_position(vec![x, 0.0, 0.0]);
+            atom
+        }))
+    }
+
+    #[test]
+    fn test_bond_formation() {
+        let mut cell = line(vec![
+            ("a", Elements::C(0), 0.0),
+            ("b", Elements::O(0), 0.5),
+            ("c", Elements::O(0), 5.0),
+        ]);
+        let mut rule = BondFormation::new(Elements::O(0), Elements::C(0), 1.0);
+        rule.products = Some((Elements::O(1), Elements::C(1)));
+        let mut reactions = Reactions::new(vec![rule], vec![]);
+        let events = reactions.apply(&mut cell, &sin());
+        assert_eq!(events.len(), 1);
+        let atoms = cell.get_particles();
+        assert_eq!(atoms["a"].neighbors, vec!["b".to_string()]);
+        assert_eq!(atoms["b"].neighbors, vec!["a".to_string()]);
+        assert!(atoms["c"].neighbors.is_empty());
+        // products are in rule order even though the pair matched the other way around.
+        assert_eq!(*atoms["a"].get_element(), Elements::C(1));
+        assert_eq!(*atoms["b"].get_element(), Elements::O(1));
+        assert!(cell.is_excluded(&"a".to_string(), &"b".to_string()));
+    }
+
+    #[test]
+    fn test_polymerization_respects_valence() {
+        // five monomers in a row, 0.8 apart, so each can reach its neighbors and the ones after.  Nearest neighbors bond
+        // first and each can only hold two bonds, so we should end up with a chain.
+        let mut cell = line(
+            (0..5)
+                .map(|i| (["a", "b", "c", "d", "e"][i], Elements::C(0), i as f32 * 0.8))
+                .collect(),
+        );
+        let mut rule = BondFormation::new(Elements::C(0), Elements::C(0), 1.7);
+        rule.max_bonds = 2;
+        let mut reactions = Reactions::new(vec![rule], vec![]);
+        for _ in 0..4 {
+            reactions.apply(&mut cell, &sin());
+        }
+        let neighbors = |name: &str| {
+            let mut neighbors = cell.get_particles()[name].neighbors.clone();
+            neighbors.sort();
+            neighbors
+        };
+        assert_eq!(neighbors("a"), vec!["b".to_string()]);
+        assert_eq!(neighbors("b"), vec!["a".to_string(), "c".to_string()]);
+        assert_eq!(neighbors("c"), vec!["b".to_string(), "d".to_string()]);
+        assert_eq!(neighbors("d"), vec!["c".to_string(), "e".to_string()]);
+        assert_eq!(neighbors("e"), vec!["d".to_string()]);
+        for name in ["a", "b", "c", "d", "e"] {
+            assert!(Reactions::bond_count(&cell, &name.to_string()) <= 2);
+        }
+        assert_eq!(reactions.events.len(), 4);
+    }
+
+    #[test]
+    fn test_bond_breaking() {
+        let mut cell = line(vec![("a", Elements::C(1), 0.0), ("b", Elements::O(1), 3.0)]);
+        Reactions::bond(&mut cell, &"a".to_string(), &"b".to_string());
+        let mut rule = BondBreaking::new(Elements::C(1), Elements::O(1), 2.0);
+        rule.products = Some((Elements::C(0), Elements::O(0)));
+        let mut reactions = Reactions::new(vec![], vec![rule]);
+        let events = reactions.apply(&mut cell, &sin());
+        assert!(matches!(events[0], ReactionEvent::Broken { .. }));
+        let atoms = cell.get_particles();
+        assert!(atoms["a"].neighbors.is_empty() && atoms["b"].neighbors.is_empty());
+        assert_eq!(*atoms["b"].get_element(), Elements::O(0));
+        assert!(!cell.is_excluded(&"a".to_string(), &"b".to_string()));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::Dynamics::integrator::inverse_mass;
+use crate::Topology::cell::{Cell, ContainsParticles};
+use crate::Topology::particle::{HasMass, HasPhysics};
+use nalgebra::{Matrix3, Vector3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Starting velocities.  Each component gets drawn from a Gaussian with variance kT/m, then the drift (and optionally
+// the spin) gets taken out and everything is scaled so the temperature comes out exactly on target.
+// kB = 1 like everywhere else, so temperatures are really kT.
+
+fn mass<ParT: HasMass<f32>>(particle: &ParT) -> f32 {
+    1.0 / inverse_mass(particle)
+}
+
+fn vector(v: &[f32]) -> Vector3<f32> {
+    Vector3::new(
+        v.first().copied().unwrap_or(0.0),
+        v.get(1).copied().unwrap_or(0.0),
+        v.get(2).copied().unwrap_or(0.0),
+    )
+}
+
+pub fn kinetic_energy<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+    world: &dyn ContainsParticles<ParT>,
+) -> f32 {
+    world
+        .get_particles()
+        .values()
+        .map(|p| 0.5 * mass(p) * vector(p.get_velocity()).norm_squared())
+        .sum()
+}
+
+// T = 2 KE / (degrees of freedom); pass in however many constraints (removed momenta, etc) the system has.
+pub fn temperature<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+    world: &dyn ContainsParticles<ParT>,
+    constraints: usize,
+) -> f32 {
+    let dof = (3 * world.get_particles().len())
+        .saturating_sub(constraints)
+        .max(1);
+    2.0 * kinetic_energy(world) / dof as f32
+}
+
+pub fn center_of_mass<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+    world: &dyn ContainsParticles<ParT>,
+) -> Vector3<f32> {
+    let mut total = 0.0;
+    let mut com = Vector3::zeros();
+    for p in world.get_particles().values() {
+        total += mass(p);
+        com += vector(p.get_position()) * mass(p);
+    }
+    com / total.max(f32::MIN_POSITIVE)
+}
+
+pub fn momentum<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+    world: &dyn ContainsParticles<ParT>,
+) -> Vector3<f32> {
+    world
+        .get_particles()
+        .values()
+        .map(|p| vector(p.get_velocity()) * mass(p))
+        .sum()
+}
+
+// about the center of mass.
+pub fn angular_momentum<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+    world: &dyn ContainsParticles<ParT>,
+) -> Vector3<f32> {
+    let com = center_of_mass(world);
+    world
+        .get_particles()
+        .values()
+        .map(|p| (vector(p.get_position()) - com).cross(&(vector(p.get_velocity()) * mass(p))))
+        .sum()
+}
+
+pub fn remove_momentum<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(cell: &mut Cell<ParT, f32>) {
+    let total: f32 = cell.get_particles().values().map(|p| mass(p)).sum();
+    let drift = momentum(cell) / total.max(f32::MIN_POSITIVE);
+    for p in cell.get_mut_particles().values_mut() {
+        let v = vector(p.get_velocity()) - drift;
+        p.set_velocity(vec![v[0], v[1], v[2]]);
+    }
+}
+
+// Takes out rigid body rotation, v -= w x (r - com) with I w = L.  Nothing happens if the inertia tensor can't be
+// inverted (a single atom, or everyone in a line).
+pub fn remove_angular_momentum<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+    cell: &mut Cell<ParT, f32>,
+) {
+    let com = center_of_mass(cell);
+    let mut inertia = Matrix3::zeros();
+    for p in cell.get_particles().values() {
+        let r = vector(p.get_position()) - com;
+        inertia += (Matrix3::identity() * r.norm_squared() - r * r.transpose()) * mass(p);
+    }
+    let Some(inverse) = inertia.try_inverse() else {
+        return;
+    };
+    let omega = inverse * angular_momentum(cell);
+    for p in cell.get_mut_particles().values_mut() {
+        let r = vector(p.get_position()) - com;
+        let v = vector(p.get_velocity()) - omega.cross(&r);
+        p.set_velocity(vec![v[0], v[1], v[2]]);
+    }
+}
+
+pub struct MaxwellBoltzmann {
+    pub temperature: f32,
+    pub remove_angular: bool, // for clusters out in vacuum; leave it off under periodic boundaries.
+    pub rng: StdRng,
+}
+
+impl MaxwellBoltzmann {
+    pub fn new(temperature: f32) -> Self {
+        Self {
+            temperature,
+            remove_angular: false,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    // Box-Muller; saves pulling in rand_distr for one distribution.
+    fn gaussian(&mut self) -> f32 {
+        let u: f32 = 1.0 - self.rng.gen::<f32>(); // (0, 1], so the log is fine.
+        let v: f32 = self.rng.gen();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f32::consts::PI * v).cos()
+    }
+
+    // the degrees of freedom the removed momenta take away.
+    pub fn constraints(&self, particles: usize) -> usize {
+        match (particles, self.remove_angular) {
+            (0 | 1, _) => 0,
+            (_, true) => 6,
+            (_, false) => 3,
+        }
+    }
+
+    pub fn assign<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+        &mut self,
+        cell: &mut Cell<ParT, f32>,
+    ) {
+        // sorted so that a seeded rng gives the same velocities every time.
+        let mut names: Vec<String> = cell.get_particles().keys().cloned().collect();
+        names.sort();
+        for name in names.iter() {
+            let sigma = (self.temperature * inverse_mass(&cell.get_particles()[name])).sqrt();
+            let v = (0..3).map(|_| sigma * self.gaussian()).collect();
+            cell.get_mut_particles()
+                .get_mut(name)
+                .unwrap()
+                .set_velocity(v);
+        }
+        if names.len() > 1 {
+            remove_momentum(cell);
+            if self.remove_angular {
+                remove_angular_momentum(cell);
+            }
+        }
+        let current = temperature(cell, self.constraints(names.len()));
+        if current > 0.0 {
+            let scale = (self.temperature / current).sqrt();
+            for p in cell.get_mut_particles().values_mut() {
+                let v = p.get_velocity().iter().map(|v| v * scale).collect();
+                p.set_velocity(v);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{carbon, world, World};
+
+    // n atoms scattered about, alternately light and heavy.
+    fn scattered(n: usize) -> World {
+        let mut rng = StdRng::seed_from_u64(2);
+        world((0..n).map(|i| {
+            let position = (0..3).map(|_| rng.gen_range(-5.0..5.0)).collect();
+            carbon(&format!("atom{i:04}"), position)
+                .mass(if i % 2 == 0 { 1.0 } else { 16.0 })
+                .build()
+        }))
+    }
+
+    #[test]
+    fn test_exact_temperature_and_no_drift() {
+        let mut cell = scattered(50);
+        let mut mb = MaxwellBoltzmann::new(2.5);
+        mb.rng = StdRng::seed_from_u64(9);
+        mb.remove_angular = true;
+        mb.assign(&mut cell);
+        assert!((temperature(&cell, 6) - 2.5).abs() < 1.0e-4);
+        assert!(momentum(&cell).norm() < 1.0e-3);
+        assert!(angular_momentum(&cell).norm() < 1.0e-2);
+    }
+
+    #[test]
+    fn test_equipartition_by_mass() {
+        // light and heavy atoms should share the kinetic energy evenly, so the light ones are 4x faster.
+        let mut cell = scattered(2000);
+        let mut mb = MaxwellBoltzmann::new(1.0);
+        mb.rng = StdRng::seed_from_u64(10);
+        mb.assign(&mut cell);
+        let (mut light, mut heavy) = (0.0, 0.0);
+        for p in cell.get_particles().values() {
+            let v2 = vector(&p.velocity).norm_squared();
+            if p.mass == 1.0 {
+                light += v2;
+            } else {
+                heavy += v2;
+            }
+        }
+        let ratio = light / heavy;
+        assert!((ratio - 16.0).abs() < 1.6, "ratio {ratio}");
+    }
+}
@@ -0,0 +1,340 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::ForceFields::potentials::PairPotential;
+use crate::ForceFields::tabulated::Tabulated;
+use crate::ForceFields::SIN::Elements;
+use crate::Topology::atom::Atomic;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::neighbors::CellList;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+// The embedded atom method, for metals.  Every atom sits in the electron density the others smear around it, and it
+// costs (or gains) something to embed it there, on top of ordinary pair repulsion:
+//   E = sum_i F_i(rho_i) + 1/2 sum_ij phi_ij(r_ij),   rho_i = sum_j f_j(r_ij)
+// (Daw & Baskes, Phys. Rev. B 29, 6443 (1984)).  The functions come tabulated, in DYNAMO setfl files (the eam/alloy
+// ones LAMMPS reads), and get splined the same way as the tabulated pair potentials.
+// setfl elements are matched up with ours by symbol; a file with only the one element applies to everything.
+// Atoms of elements the file doesn't know about don't feel (or contribute) anything.
+
+pub struct EAM {
+    pub elements: Vec<String>,
+    pub masses: Vec<f32>,
+    pub cutoff: f32,
+    embedding: Vec<Tabulated>, // F(rho), per element
+    density: Vec<Tabulated>,   // f(r), per element
+    pair: Vec<Tabulated>,      // phi(r), for element pairs i >= j at i (i + 1) / 2 + j
+    species: HashMap<String, usize>,
+}
+
+// Values on an even grid as a table, with the slopes by finite differences.
+fn grid_table(start: f32, step: f32, values: &[f32]) -> Result<Tabulated, String> {
+    let n = values.len();
+    if n < 3 {
+        return Err(format!("need at least three grid points, got {n}"));
+    }
+    let rows = (0..n)
+        .map(|i| {
+            let slope = match i {
+                0 => (-3.0 * values[0] + 4.0 * values[1] - values[2]) / (2.0 * step),
+                _ if i == n - 1 => {
+                    (3.0 * values[i] - 4.0 * values[i - 1] + values[i - 2]) / (2.0 * step)
+                }
+                _ => (values[i + 1] - values[i - 1]) / (2.0 * step),
+            };
+            [start + i as f32 * step, values[i], -slope]
+        })
+        .collect();
+    Tabulated::new(rows)
+}
+
+fn pair_index(a: usize, b: usize) -> usize {
+    let (i, j) = if a >= b { (a, b) } else { (b, a) };
+    i * (i + 1) / 2 + j
+}
+
+impl EAM {
+    // The setfl layout: three comment lines, then "N element1 element2 ...", then "Nrho drho Nr dr cutoff".  Then for
+    // each element a line of "atomic-number mass lattice-constant lattice-type" followed by Nrho values of F(rho) and
+    // Nr values of f(r), and last of all r phi(r) (note the r!) for every pair i >= j, Nr values each.  How the numbers
+    // are split over lines doesn't matter.
+    pub fn parse_setfl(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().skip(3);
+        let elements: Vec<String> = lines
+            .next()
+            .ok_or("missing the element line")?
+            .split_whitespace()
+            .skip(1)
+            .map(|s| s.to_string())
+            .collect();
+        if elements.is_empty() {
+            return Err("no elements".to_string());
+        }
+        let grid: Vec<&str> = lines
+            .next()
+            .ok_or("missing the grid line")?
+            .split_whitespace()
+            .collect();
+        if grid.len() < 5 {
+            return Err(format!(
+                "expected Nrho drho Nr dr cutoff, got '{}'",
+                grid.join(" ")
+            ));
+        }
+        let count = |s: &str| {
+            s.parse::<usize>()
+                .map_err(|e| format!("bad grid size '{s}': {e}"))
+        };
+        let number = |s: &str| {
+            s.parse::<f32>()
+                .map_err(|e| format!("bad number '{s}': {e}"))
+        };
+        let (n_rho, d_rho, n_r, d_r, cutoff) = (
+            count(grid[0])?,
+            number(grid[1])?,
+            count(grid[2])?,
+            number(grid[3])?,
+            number(grid[4])?,
+        );
+
+        let tokens: Vec<&str> = lines.flat_map(|line| line.split_whitespace()).collect();
+        let mut at = 0;
+        let mut take = |n: usize| -> Result<&[&str], String> {
+            let words = tokens.get(at..at + n).ok_or("file ended early")?;
+            at += n;
+            Ok(words)
+        };
+        let numbers = |words: &[&str]| {
+            words
+                .iter()
+                .map(|s| number(s))
+                .collect::<Result<Vec<f32>, String>>()
+        };
+
+        let (mut masses, mut embedding, mut density) = (Vec::new(), Vec::new(), Vec::new());
+        for _ in elements.iter() {
+            // atomic number, mass, lattice constant and lattice type; only the mass matters here.
+            masses.push(number(take(4)?[1])?);
+            embedding.push(grid_table(0.0, d_rho, &numbers(take(n_rho)?)?)?);
+            density.push(grid_table(0.0, d_r, &numbers(take(n_r)?)?)?);
+        }
+        let mut pair = Vec::new();
+        for _ in 0..elements.len() * (elements.len() + 1) / 2 {
+            // r phi(r) to phi(r), skipping r = 0.
+            let r_phi = numbers(take(n_r)?)?;
+            let phi: Vec<f32> = (1..n_r).map(|i| r_phi[i] / (i as f32 * d_r)).collect();
+            pair.push(grid_table(d_r, d_r, &phi)?);
+        }
+        let species = elements
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.clone(), i))
+            .collect();
+        Ok(Self {
+            elements,
+            masses,
+            cutoff,
+            embedding,
+            density,
+            pair,
+            species,
+        })
+    }
+
+    pub fn from_setfl_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        Self::parse_setfl(&text)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+    }
+
+    // for when the names in the file aren't our symbols.
+    pub fn assign(&mut self, element: &Elements, index: usize) {
+        self.species.insert(element.symbol().to_string(), index);
+    }
+
+    pub fn species(&self, element: &Elements) -> Option<usize> {
+        match self.species.get(element.symbol()) {
+            Some(&i) => Some(i),
+            None if self.elements.len() == 1 => Some(0),
+            None => None,
+        }
+    }
+
+    pub fn mass(&self, element: &Elements) -> Option<f32> {
+        self.species(element).map(|i| self.masses[i])
+    }
+
+    // F and dF/drho, holding rho at the end of the table rather than falling off it.
+    fn embed(&self, species: usize, rho: f32) -> (f32, f32) {
+        let table = &self.embedding[species];
+        let last = table.rows[table.rows.len() - 1][0];
+        let (u, f) = table.evaluate(rho.min(last));
+        (u, -f)
+    }
+
+    // (total energy, forces) for everyone.
+    fn evaluate<ParT: Atomic<Elements, f32, Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> (f32, HashMap<String, Vec<f32>>) {
+        let atoms = world.get_particles();
+        let neighbors = CellList::new(self.cutoff).neighbors(world);
+        let species: HashMap<&String, Option<usize>> = atoms
+            .iter()
+            .map(|(name, atom)| (name, self.species(atom.get_element())))
+            .collect();
+        let mut energy = 0.0;
+        // the slope of everyone's embedding energy, for the forces.
+        let mut embedded: HashMap<&String, f32> = HashMap::new();
+        for (name, list) in neighbors.iter() {
+            let Some(a) = species[name] else { continue };
+            let rho: f32 = list
+                .iter()
+                .filter_map(|(other, _, r)| species[other].map(|b| self.density[b].energy(*r)))
+                .sum();
+            let (u, slope) = self.embed(a, rho);
+            energy += u;
+            embedded.insert(name, slope);
+        }
+        let mut forces: HashMap<String, Vec<f32>> = atoms
+            .keys()
+            .map(|name| (name.clone(), vec![0.0; 3]))
+            .collect();
+        // every pair shows up on both sides, so each side only pushes on its own atom.
+        for (name, list) in neighbors.iter() {
+            let Some(a) = species[name] else { continue };
+            let slope_a = embedded[name];
+            for (other, d, r) in list.iter() {
+                let Some(b) = species[other] else { continue };
+                let slope_b = embedded[other];
+                let (phi, pair_force) = self.pair[pair_index(a, b)].evaluate(*r);
+                energy += 0.5 * phi;
+                // dE/dr for this pair: both embeddings and the pair term.  The tables hand back -slope.
+                let de_dr = -slope_a * self.density[b].force(*r)
+                    - slope_b * self.density[a].force(*r)
+                    - pair_force;
+                let f = forces.get_mut(name).unwrap();
+                for k in 0..3 {
+                    f[k] += de_dr * d[k] / r;
+                }
+            }
+        }
+        (energy, forces)
+    }
+}
+
+impl<ParT: Atomic<Elements, f32, Vec<f32>>> ForceTerm<ParT> for EAM {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        self.evaluate(world).1
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        self.evaluate(world).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+    use crate::Topology::particle::HasPhysics;
+
+    // A made up one element setfl file with smooth functions that all go to zero at the cutoff.
+    fn setfl() -> String {
+        let (n_rho, d_rho, n_r, d_r, cutoff) = (500, 0.1, 500, 0.01, 4.99);
+        let mut text = String::from("made up\nfor testing\n\n1 Cu\n");
+        text += &format!("{n_rho} {d_rho} {n_r} {d_r} {cutoff}\n29 63.546 3.615 fcc\n");
+        let values = |n: usize, f: &dyn Fn(f32) -> f32, step: f32| {
+            (0..n)
+                .map(|i| format!("{:e}", f(i as f32 * step)))
+                .collect::<Vec<String>>()
+                .chunks(5)
+                .map(|line| line.join(" ") + "\n")
+                .collect::<String>()
+        };
+        let tail = |r: f32| (cutoff - r).max(0.0).powi(4);
+        text += &values(n_rho, &|rho| -(rho + 0.5).sqrt(), d_rho);
+        text += &values(n_r, &|r| tail(r) * (-r).exp(), d_r);
+        text += &values(n_r, &|r| r * tail(r) * 0.2 * (-2.0 * r).exp(), d_r);
+        text
+    }
+
+    fn cluster() -> Cell<Atom<Elements, f32, Vec<f32>>, f32> {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [2.5, 0.1, 0.0],
+            [0.2, 2.4, 0.3],
+            [1.4, 1.2, 2.1],
+            [3.1, 2.6, 1.0],
+        ];
+        let mut particles = HashMap::new();
+        for (i, position) in positions.iter().enumerate() {
+            let atom = AtomBuilder::new()
+                .element(Elements::X(0))
+                .id(Some(format!("atom{i}")))
+                .position(position.to_vec())
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::new();
+        cell.set_particles(particles);
+        cell
+    }
+
+    #[test]
+    fn test_read_setfl() {
+        let eam = EAM::parse_setfl(&setfl()).unwrap();
+        assert_eq!(eam.elements, vec!["Cu".to_string()]);
+        assert_eq!(eam.mass(&Elements::H(0)), Some(63.546));
+        // phi came in as r phi.
+        let r: f32 = 2.0;
+        let expected = (4.99 - r).powi(4) * 0.2 * (-2.0 * r).exp();
+        assert!((eam.pair[0].energy(r) - expected).abs() < 1.0e-3 * expected);
+        assert!(EAM::parse_setfl("too\nshort\n").is_err());
+    }
+
+    #[test]
+    fn test_eam_forces_match_energy() {
+        let mut eam = EAM::parse_setfl(&setfl()).unwrap();
+        let mut cell = cluster();
+        let forces = ForceTerm::<Atom<Elements, f32, Vec<f32>>>::forces(&mut eam, &cell, 0.0);
+        let h = 1.0e-3;
+        for name in cell
+            .get_particles()
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>()
+        {
+            for k in 0..3 {
+                let start = cell.get_particles()[&name].get_position().clone();
+                let mut energies = Vec::new();
+                for step in [h, -h] {
+                    let mut moved = start.clone();
+                    moved[k] += step;
+                    cell.get_mut_particles()
+                        .get_mut(&name)
+                        .unwrap()
+                        .set_position(moved);
+                    energies.push(ForceTerm::<Atom<Elements, f32, Vec<f32>>>::energy(
+                        &eam, &cell, 0.0,
+                    ));
+                }
+                cell.get_mut_particles()
+                    .get_mut(&name)
+                    .unwrap()
+                    .set_position(start);
+                let numerical = -(energies[0] - energies[1]) / (2.0 * h);
+                let f = forces[&name][k];
+                assert!(
+                    (numerical - f).abs() < 2.0e-2 * f.abs().max(1.0),
+                    "{name} {k}: numerical {numerical}, analytic {f}"
+                );
+            }
+        }
+    }
+}
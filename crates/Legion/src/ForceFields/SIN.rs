@@ -1,6 +1,8 @@
 use num_traits::{float::FloatCore, real::Real, Float};
 use std::ops::Deref;
 
+use crate::ForceFields::mixing::{MixingRule, PairParameters};
+use crate::ForceFields::potentials::{PairPotential, SoftSphere};
 use crate::Topology::atom::{Atom, AtomBuilder};
 
 // it's useful to include the mass
@@ -12,32 +14,75 @@ pub enum Elements {
     X(u32),
 }
 
+impl Elements {
+    // what gets written out in trajectory files.
+    pub fn symbol(&self) -> &str {
+        match self {
+            Elements::H(_) => "H",
+            Elements::C(_) => "C",
+            Elements::O(_) => "O",
+            Elements::X(_) => "X",
+        }
+    }
+
+    // and back again, for reading files.
+    pub fn from_symbol(symbol: &str) -> Option<Elements> {
+        match symbol {
+            "H" => Some(Elements::H(0)),
+            "C" => Some(Elements::C(0)),
+            "O" => Some(Elements::O(0)),
+            "X" => Some(Elements::X(0)),
+            _ => None,
+        }
+    }
+}
+
 pub trait ForceField<EleT, NumT, VecT: IntoIterator<Item = NumT>> {
+    // whatever the pairs interact through; see potentials.rs.
+    type Pair: PairPotential;
     fn mass(&self, element: &EleT) -> NumT;
     fn charge(&self, element: &EleT) -> NumT;
     fn atom(&self, element: EleT) -> Atom<EleT, NumT, VecT>;
-    fn pairwise_interactions(&self, e1: &EleT, e2: &EleT) -> Box<dyn Fn(NumT) -> NumT>;
+    // the interaction between two elements, which knows both its energy (for Monte Carlo) and its force.
+    fn pair_potential(&self, e1: &EleT, e2: &EleT) -> Self::Pair;
 }
 
 pub trait ParticleGenerator<ParT, EleT> {
     fn generate_particle(&self, element: EleT) -> ParT;
 }
 
-fn GenerateBasicPairwiseInteractions(k: f32, l: f32, exp: f32) -> Box<dyn Fn(f32) -> f32> {
-    // we're creating a very simple, almost silly interaction: some coefficient divided by the pairwise distance.
-    // Frankly, it's mostly for just testing.  Also, we need to move k into the closure to ensure the lifetime is respected.
-    return Box::new(move |r: f32| -> f32 {
-        if r <= l {
-            return 0.0; // fake out for getting too close to the atomic radius.
-        } else {
-            return k / (r.powf(exp));
-        }
-    });
-}
-
 pub struct SIN<ParT> {
     pub description: String,
     pub particle_type: Vec<ParT>,
+    pub parameters: PairParameters, // (epsilon, sigma) for the soft spheres; override pairs in here.
+}
+
+impl<ParT> SIN<ParT> {
+    pub fn new() -> Self {
+        Self {
+            description: "SIN".to_string(),
+            particle_type: Vec::new(),
+            parameters: SIN::<ParT>::default_parameters(),
+        }
+    }
+
+    // what every SIN starts out with, mixed Lorentz-Berthelot.  These used to be (10, 1) for every pair; set that as the
+    // default and clear out the elements (or just set_element them all back) to get the old dynamics.
+    pub fn default_parameters() -> PairParameters {
+        let mut parameters = PairParameters::new(MixingRule::LorentzBerthelot);
+        parameters.default = (10.0, 1.0);
+        parameters.set_element(&Elements::H(0), 10.0, 1.0);
+        parameters.set_element(&Elements::C(0), 12.0, 1.2);
+        parameters.set_element(&Elements::O(0), 15.0, 1.1);
+        parameters.set_element(&Elements::X(0), 10.0, 1.0);
+        parameters
+    }
+}
+
+impl<ParT> Default for SIN<ParT> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ParticleGenerator<Atom<Elements, f32, Vec<f32>>, Elements> for SIN<Elements> {
@@ -48,6 +93,7 @@ impl ParticleGenerator<Atom<Elements, f32, Vec<f32>>, Elements> for SIN<Elements
 
 // very specific implementation!  Using elements, 64 bit floats, and the built in Vec type.
 impl ForceField<Elements, f32, Vec<f32>> for SIN<Elements> {
+    type Pair = SoftSphere;
     fn atom(&self, element: Elements) -> Atom<Elements, f32, Vec<f32>> {
         AtomBuilder::new()
             .element(element.clone())
@@ -71,13 +117,11 @@ impl ForceField<Elements, f32, Vec<f32>> for SIN<Elements> {
             Elements::X(_) => 99.0,
         }
     }
-    fn pairwise_interactions(&self, e1: &Elements, e2: &Elements) -> Box<dyn Fn(f32) -> f32> {
-        match e1 {
-            Elements::H(_) => GenerateBasicPairwiseInteractions(10.0, 0.0, 2.0),
-            Elements::C(_) => GenerateBasicPairwiseInteractions(10.0, 0.0, 2.0),
-            Elements::O(_) => GenerateBasicPairwiseInteractions(10.0, 0.0, 2.0),
-            Elements::X(_) => GenerateBasicPairwiseInteractions(10.0, 0.0, 2.0),
-        }
+    // we're using a very simple, almost silly interaction: a force of some coefficient over the distance squared.
+    // Frankly, it's mostly for just testing.  The coefficient comes out of the parameters, mixed or overridden.
+    fn pair_potential(&self, e1: &Elements, e2: &Elements) -> SoftSphere {
+        let (epsilon, sigma) = self.parameters.get_pair(e1, e2);
+        SoftSphere::new(epsilon, sigma, 1)
     }
 }
 
@@ -89,20 +133,43 @@ mod tests {
 
     #[test]
     fn test_create_force_field() {
-        let SinFF = SIN::<Elements> {
-            description: "SIN".to_string(),
-            particle_type: Vec::new(),
-        };
+        let SinFF = SIN::<Elements>::new();
         assert_eq!(SinFF.description, "SIN".to_string());
     }
 
     #[test]
     fn test_force_field_atom_builder() {
-        let SinFF = SIN::<Elements> {
-            description: "SIN".to_string(),
-            particle_type: Vec::new(),
-        };
+        let SinFF = SIN::<Elements>::new();
         let atom = SinFF.atom(Elements::H(0));
         matches!(atom.get_element(), Elements::H(0));
     }
+
+    #[test]
+    fn test_pairwise_potential_matches_force() {
+        let SinFF = SIN::<Elements>::new();
+        let pair = SinFF.pair_potential(&Elements::H(0), &Elements::C(0));
+        let (r, h) = (1.5, 1.0e-3);
+        let numerical = -(pair.energy(r + h) - pair.energy(r - h)) / (2.0 * h);
+        assert!((numerical - pair.force(r)).abs() < 1.0e-2);
+        // different pairs are actually different now.
+        let oxygen = SinFF.pair_potential(&Elements::H(0), &Elements::O(0));
+        let carbon = SinFF.pair_potential(&Elements::C(0), &Elements::C(0));
+        assert_ne!(oxygen.energy(r), carbon.energy(r));
+        assert_eq!(pair, SinFF.pair_potential(&Elements::C(0), &Elements::H(0)));
+    }
+
+    #[test]
+    fn test_pair_overrides() {
+        let mut SinFF = SIN::<Elements>::new();
+        let mixed = SinFF.pair_potential(&Elements::H(0), &Elements::O(0));
+        SinFF.parameters.set_pair(&Elements::O(0), &Elements::H(0), 1.0, 2.0);
+        let pair = SinFF.pair_potential(&Elements::H(1), &Elements::O(0));
+        assert_eq!(pair, SoftSphere::new(1.0, 2.0, 1));
+        assert_ne!(pair, mixed);
+        // everyone else still mixes.
+        assert_eq!(
+            SinFF.pair_potential(&Elements::C(0), &Elements::C(0)),
+            SoftSphere::new(12.0, 1.2, 1)
+        );
+    }
 }
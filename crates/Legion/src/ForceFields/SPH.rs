@@ -0,0 +1,370 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Dynamics::integrator::distance;
+use crate::ForceFields::SIN::Elements;
+use crate::Topology::atom::{Atom, AtomBuilder};
+use crate::Topology::cell::{Cell, ContainsParticles};
+use crate::Topology::particle::HasPhysics;
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+
+// Smoothed particle hydrodynamics!  Each particle is a little blob of fluid; density comes from summing up the neighbors
+// with a smoothing kernel, pressure from the density through an equation of state, and then pressure and viscosity
+// push everyone around.  Kernels and forces follow Muller, Charypar & Gross, "Particle-based fluid simulation for
+// interactive applications" (SCA 2003), which is the games end of SPH rather than the astrophysics end.
+// All the kernels have compact support h, the smoothing length.
+
+// for the density.
+pub fn poly6(r: f32, h: f32) -> f32 {
+    if r >= h {
+        return 0.0;
+    }
+    315.0 / (64.0 * PI * h.powi(9)) * (h * h - r * r).powi(3)
+}
+
+// dW/dr of the spiky kernel 15/(pi h^6) (h - r)^3, for the pressure.  Poly6's gradient goes to zero as r -> 0,
+// which lets particles clump up; this one doesn't.
+pub fn spiky_gradient(r: f32, h: f32) -> f32 {
+    if r >= h {
+        return 0.0;
+    }
+    -45.0 / (PI * h.powi(6)) * (h - r).powi(2)
+}
+
+// Laplacian of the viscosity kernel, for the viscosity.  Always positive, so viscosity only ever slows things down.
+pub fn viscosity_laplacian(r: f32, h: f32) -> f32 {
+    if r >= h {
+        return 0.0;
+    }
+    45.0 / (PI * h.powi(6)) * (h - r)
+}
+
+// Every particle in the world is treated as SPH fluid, except for the boundary ones: those count towards the density
+// and push back on the fluid, but never get pushed themselves.  They don't stay put on their own, though (the
+// integrators treat a zero mass as a unit one), so once they're in the cell, freeze_boundary pins them down.
+pub struct SPH {
+    pub smoothing_length: f32,
+    pub particle_mass: f32,
+    pub rest_density: f32,
+    pub stiffness: f32,
+    pub exponent: f32, // Tait equation of state, p = B ((rho/rho0)^gamma - 1); gamma = 7 for water, 1 for squishier stuff.
+    pub viscosity: f32,
+    pub boundary: HashSet<String>,
+    pub densities: HashMap<String, f32>,
+    pub pressures: HashMap<String, f32>,
+}
+
+impl SPH {
+    pub fn new(smoothing_length: f32, particle_mass: f32, rest_density: f32) -> Self {
+        Self {
+            smoothing_length,
+            particle_mass,
+            rest_density,
+            stiffness: 50.0,
+            exponent: 7.0,
+            viscosity: 0.1,
+            boundary: HashSet::new(),
+            densities: HashMap::new(),
+            pressures: HashMap::new(),
+        }
+    }
+
+    pub fn fluid_particle(&self, position: Vec<f32>) -> Atom<Elements, f32, Vec<f32>> {
+        AtomBuilder::new()
+            .element(Elements::H(0))
+            .mass(self.particle_mass)
+            .position(position)
+            .velocity(vec![0.0; 3])
+            .build()
+    }
+
+    pub fn boundary_particle(&mut self, position: Vec<f32>) -> Atom<Elements, f32, Vec<f32>> {
+        let atom = AtomBuilder::new()
+            .element(Elements::X(0))
+            .position(position)
+            .velocity(vec![0.0; 3])
+            .build();
+        self.boundary.insert(atom.id.clone());
+        atom
+    }
+
+    // Freezes every boundary particle that's in the cell, so the integrators leave them where they are.
+    pub fn freeze_boundary<ParT>(&self, cell: &mut Cell<ParT, f32>) {
+        for name in self.boundary.iter() {
+            if cell.get_particles().contains_key(name) {
+                cell.freeze(name);
+            }
+        }
+    }
+
+    // Negative pressures get clipped; otherwise the free surface pulls itself into clumps.
+    pub fn pressure(&self, density: f32) -> f32 {
+        let p = self.stiffness * ((density / self.rest_density).powf(self.exponent) - 1.0);
+        p.max(0.0)
+    }
+
+    // All the pairs closer than h, found by binning everyone into cubes of side h first.  Boundary-boundary pairs are skipped,
+    // so the densities on the boundary particles only count the fluid (and themselves).
+    // In a periodic box the bins are slices of the box in fractional coordinates instead, stretched a little so a whole
+    // number of them fit (at least h thick across, even when the box is skewed), and they wrap around at the edges.
+    fn pairs<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> Vec<(String, String, Vec<f32>, f32)> {
+        let h = self.smoothing_length;
+        let atoms = world.get_particles();
+        let periodic = world.get_periodic_box();
+        let counts: Option<[i64; 3]> =
+            periodic.map(|p| p.widths()).map(|w| [0, 1, 2].map(|k| ((w[k] / h).floor() as i64).max(1)));
+        let bin = |p: &Vec<f32>| -> [i64; 3] {
+            match (periodic, counts) {
+                (Some(periodic), Some(n)) => {
+                    let s = periodic.fractional(p);
+                    [0, 1, 2].map(|k| ((s[k] * n[k] as f32).floor() as i64).rem_euclid(n[k]))
+                }
+                _ => [0, 1, 2].map(|k| (p[k] / h).floor() as i64),
+            }
+        };
+        let mut bins = HashMap::<[i64; 3], Vec<&String>>::new();
+        for (name, atom) in atoms.iter() {
+            bins.entry(bin(atom.get_position())).or_default().push(name);
+        }
+        let mut pairs = Vec::new();
+        for (key, members) in bins.iter() {
+            let mut neighbors = HashSet::<[i64; 3]>::new();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let mut other = [key[0] + dx, key[1] + dy, key[2] + dz];
+                        if let Some(n) = counts {
+                            // small boxes have fewer than three bins across, so the same neighbor can turn up twice.
+                            other = [0, 1, 2].map(|k| other[k].rem_euclid(n[k]));
+                        }
+                        neighbors.insert(other);
+                    }
+                }
+            }
+            for other in neighbors.iter() {
+                let Some(others) = bins.get(other) else {
+                    continue;
+                };
+                for a in members.iter() {
+                    for b in others.iter() {
+                        // each pair once, and walls don't care about each other.
+                        if a >= b || (self.boundary.contains(*a) && self.boundary.contains(*b)) {
+                            continue;
+                        }
+                        let d = distance(&atoms[*a], &atoms[*b], periodic);
+                        let r = d.iter().map(|z| z * z).sum::<f32>().sqrt();
+                        if r < h {
+                            pairs.push(((*a).clone(), (*b).clone(), d, r));
+                        }
+                    }
+                }
+            }
+        }
+        pairs.sort_by(|x, y| (&x.0, &x.1).cmp(&(&y.0, &y.1)));
+        pairs
+    }
+
+    pub fn update_densities<ParT: HasPhysics<Vec<f32>>>(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        pairs: &[(String, String, Vec<f32>, f32)],
+    ) {
+        let h = self.smoothing_length;
+        let self_density = self.particle_mass * poly6(0.0, h);
+        self.densities = world
+            .get_particles()
+            .keys()
+            .map(|name| (name.clone(), self_density))
+            .collect();
+        for (a, b, _, r) in pairs.iter() {
+            let w = self.particle_mass * poly6(*r, h);
+            *self.densities.get_mut(a).unwrap() += w;
+            *self.densities.get_mut(b).unwrap() += w;
+        }
+        self.pressures = self
+            .densities
+            .iter()
+            .map(|(name, &rho)| (name.clone(), self.pressure(rho)))
+            .collect();
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>>> ForceTerm<ParT> for SPH {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let h = self.smoothing_length;
+        let m = self.particle_mass;
+        let pairs = self.pairs(world);
+        self.update_densities(world, &pairs);
+        let atoms = world.get_particles();
+        let mut forces: HashMap<String, Vec<f32>> = atoms
+            .keys()
+            .map(|name| (name.clone(), vec![0.0; 3]))
+            .collect();
+        for (a, b, d, r) in pairs.iter() {
+            let (a_wall, b_wall) = (self.boundary.contains(a), self.boundary.contains(b));
+            // boundary particles mirror whatever fluid particle they're pushing on.
+            let (mut rho_a, mut p_a) = (self.densities[a], self.pressures[a]);
+            let (mut rho_b, mut p_b) = (self.densities[b], self.pressures[b]);
+            if a_wall {
+                (rho_a, p_a) = (rho_b, p_b);
+            }
+            if b_wall {
+                (rho_b, p_b) = (rho_a, p_a);
+            }
+            // symmetric pressure force, so momentum is conserved: -m^2 (p_a/rho_a^2 + p_b/rho_b^2) grad W
+            let mut f = if *r > 0.0 {
+                let push = -m
+                    * m
+                    * (p_a / (rho_a * rho_a) + p_b / (rho_b * rho_b))
+                    * spiky_gradient(*r, h);
+                d.iter().map(|z| push * z / r).collect::<Vec<f32>>()
+            } else {
+                vec![0.0; 3]
+            };
+            let (va, vb) = (atoms[a].get_velocity(), atoms[b].get_velocity());
+            let lap = self.viscosity * m * m * viscosity_laplacian(*r, h) / (rho_a * rho_b);
+            for (k, z) in f.iter_mut().enumerate() {
+                let dv = vb.get(k).copied().unwrap_or(0.0) - va.get(k).copied().unwrap_or(0.0);
+                *z += lap * dv;
+            }
+            for (k, z) in f.iter().enumerate() {
+                forces.get_mut(a).unwrap()[k] += z;
+                forces.get_mut(b).unwrap()[k] -= z;
+            }
+        }
+        for name in self.boundary.iter() {
+            if let Some(f) = forces.get_mut(name) {
+                *f = vec![0.0; 3];
+            }
+        }
+        forces
+    }
+    // The fluid doesn't have a potential energy in the usual sense; the pressure work goes into internal energy we don't track.
+    fn energy(&self, _world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dynamics::fields::UniformGravity;
+    use crate::Dynamics::integrator::Leapfrog;
+    use crate::ForceFields::SIN::SIN;
+    use crate::Topology::cell::Cell;
+
+    #[test]
+    fn test_kernels_are_normalized() {
+        let h = 0.5;
+        let steps = 2000;
+        let dr = h / steps as f32;
+        let mut integral = 0.0;
+        for i in 0..steps {
+            let r = (i as f32 + 0.5) * dr;
+            integral += 4.0 * PI * r * r * poly6(r, h) * dr;
+        }
+        assert!((integral - 1.0).abs() < 1.0e-3);
+        assert!(spiky_gradient(0.0, h) < 0.0);
+        assert_eq!(viscosity_laplacian(h, h), 0.0);
+    }
+
+    fn lattice(sph: &SPH, n: usize, spacing: f32) -> Cell<Atom<Elements, f32, Vec<f32>>, f32> {
+        let mut particles = HashMap::new();
+        for i in 0..n * n * n {
+            let (x, y, z) = (i % n, (i / n) % n, i / (n * n));
+            let mut atom = sph.fluid_particle(vec![
+                x as f32 * spacing,
+                y as f32 * spacing,
+                z as f32 * spacing,
+            ]);
+            atom.id = format!("fluid{x}-{y}-{z}");
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::new();
+        cell.set_particles(particles);
+        cell
+    }
+
+    #[test]
+    fn test_density_summation() {
+        // a unit mass per unit cube should come out at a density of one in the middle of the lattice.
+        let mut sph = SPH::new(2.5, 1.0, 1.0);
+        let mut cell = lattice(&sph, 9, 1.0);
+        sph.forces(&cell, 0.0);
+        assert!((sph.densities["fluid4-4-4"] - 1.0).abs() < 0.05);
+        // squashing it raises the pressure, and the pressure forces cancel out.
+        sph.rest_density = 0.8;
+        cell.get_mut_particles()
+            .get_mut("fluid4-4-4")
+            .unwrap()
+            .velocity = vec![1.0, 0.0, 0.0];
+        let forces = sph.forces(&cell, 0.0);
+        assert!(sph.pressures["fluid4-4-4"] > 0.0);
+        // added up in f64, or which order the map hands them back in starts to matter.
+        let mut total = [0.0_f64; 3];
+        for f in forces.values() {
+            for (t, z) in total.iter_mut().zip(f) {
+                *t += *z as f64;
+            }
+        }
+        assert!(total.iter().all(|t| t.abs() < 1.0e-3), "{total:?}");
+        // viscosity drags the moving one back.
+        assert!(forces["fluid4-4-4"][0] < 0.0);
+    }
+
+    #[test]
+    fn test_fluid_stays_above_floor() {
+        let spacing = 0.5;
+        let mut sph = SPH::new(2.0 * spacing, 1.0, 6.0);
+        sph.stiffness = 500.0;
+        sph.viscosity = 1.0;
+        let mut particles = HashMap::new();
+        // a floor plenty wide enough that nobody runs off the edge while the drop spreads out.
+        for i in 0..16 {
+            for j in 0..16 {
+                let mut atom = sph.boundary_particle(vec![
+                    i as f32 * spacing - 2.0,
+                    j as f32 * spacing - 2.0,
+                    0.0,
+                ]);
+                // heavy enough for gravity to get a grip on; only being frozen keeps the floor up.
+                atom.mass = 1.0;
+                particles.insert(atom.id.clone(), atom);
+            }
+        }
+        for i in 0..27 {
+            let (x, y, z) = (i % 3, (i / 3) % 3, i / 9);
+            let atom = sph.fluid_particle(vec![
+                1.5 + x as f32 * spacing,
+                1.5 + y as f32 * spacing,
+                0.6 + z as f32 * spacing,
+            ]);
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        sph.freeze_boundary(&mut cell);
+        let sin = SIN::<Elements>::new();
+        let mut gravity = UniformGravity {
+            g: vec![0.0, 0.0, -9.8],
+        };
+        let mut integrator = Leapfrog::<f32>::new();
+        integrator.dt = 0.001;
+        for _ in 0..1000 {
+            integrator.step(&mut cell, &sin, &mut [&mut sph, &mut gravity]);
+        }
+        for (name, atom) in cell.get_particles().iter() {
+            if sph.boundary.contains(name) {
+                assert_eq!(atom.position[2], 0.0);
+            } else {
+                assert!(atom.position[2] > 0.0, "{name} fell through the floor");
+            }
+        }
+    }
+}
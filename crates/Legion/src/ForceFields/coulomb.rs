@@ -0,0 +1,325 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::ForceFields::ewald::erfc;
+use crate::ForceFields::potentials::PairPotential;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::neighbors::{CellList, Pair, VerletList};
+use crate::Topology::particle::{HasCharge, HasPhysics};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// Cheap electrostatics!  Both of these cut the Coulomb interaction off at rc and patch things up so that nothing jumps there,
+// which is good enough for demos and doesn't need any FFTs.  They're pair potentials like the ones in potentials.rs,
+// with the charges baked in as qq = q_i q_j.
+
+// Reaction field: everything past rc is a dielectric continuum with permittivity epsilon_rf (Tironi et al., J. Chem. Phys. 102, 5451 (1995)).
+//   U = qq (1/r + k_rf r^2 - c_rf),  k_rf = (eps - 1) / ((2 eps + 1) rc^3),  c_rf = 1/rc + k_rf rc^2
+// The energy goes to zero at the cutoff; with eps -> infinity (conducting boundaries) so does the force.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReactionField {
+    pub qq: f32,
+    pub cutoff: f32,
+    k_rf: f32,
+    c_rf: f32,
+}
+
+impl ReactionField {
+    pub fn new(qq: f32, cutoff: f32, epsilon_rf: f32) -> Self {
+        let k_rf = if epsilon_rf.is_infinite() {
+            0.5 / cutoff.powi(3)
+        } else {
+            (epsilon_rf - 1.0) / ((2.0 * epsilon_rf + 1.0) * cutoff.powi(3))
+        };
+        Self {
+            qq,
+            cutoff,
+            k_rf,
+            c_rf: 1.0 / cutoff + k_rf * cutoff * cutoff,
+        }
+    }
+}
+
+impl PairPotential for ReactionField {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        if r >= self.cutoff {
+            return (0.0, 0.0);
+        }
+        (
+            self.qq * (1.0 / r + self.k_rf * r * r - self.c_rf),
+            self.qq * (1.0 / (r * r) - 2.0 * self.k_rf * r),
+        )
+    }
+}
+
+// Damped shifted force, the Wolf sum done so that both the energy and the force go smoothly to zero at rc
+// (Fennell & Gezelter, J. Chem. Phys. 124, 234104 (2006)).  alpha is the damping; 0.2 with a cutoff of 12 (in Angstroms) is the usual pick.
+//   U = qq [erfc(a r)/r - erfc(a rc)/rc + (erfc(a rc)/rc^2 + 2a/sqrt(pi) exp(-a^2 rc^2)/rc)(r - rc)]
+fn damped_force(alpha: f32, r: f32) -> f32 {
+    erfc((alpha * r) as f64) as f32 / (r * r)
+        + 2.0 * alpha / PI.sqrt() * (-alpha * alpha * r * r).exp() / r
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DampedShiftedForce {
+    pub qq: f32,
+    pub cutoff: f32,
+    pub alpha: f32,
+    shift: f32,
+    at_cutoff: f32,
+}
+
+impl DampedShiftedForce {
+    pub fn new(qq: f32, cutoff: f32, alpha: f32) -> Self {
+        Self {
+            qq,
+            cutoff,
+            alpha,
+            shift: damped_force(alpha, cutoff),
+            at_cutoff: erfc((alpha * cutoff) as f64) as f32 / cutoff,
+        }
+    }
+}
+
+impl PairPotential for DampedShiftedForce {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        if r >= self.cutoff {
+            return (0.0, 0.0);
+        }
+        let bare = erfc((self.alpha * r) as f64) as f32 / r;
+        (
+            self.qq * (bare - self.at_cutoff + self.shift * (r - self.cutoff)),
+            self.qq * (damped_force(self.alpha, r) - self.shift),
+        )
+    }
+}
+
+// Each charge interacting with its own neutralizing shell; only matters for the total energy.
+pub fn wolf_self_energy(charges: &[f32], cutoff: f32, alpha: f32) -> f32 {
+    let per_charge = erfc((alpha * cutoff) as f64) as f32 / (2.0 * cutoff) + alpha / PI.sqrt();
+    -per_charge * charges.iter().map(|q| q * q).sum::<f32>()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoulombMethod {
+    ReactionField { epsilon_rf: f32 },
+    Wolf { alpha: f32 },
+}
+
+// Cut off Coulomb between every pair of charges in the world, as a force term.  The pairs come out of a Verlet list
+// (a skin of a fifth of the cutoff to start with; change neighbors.skin if that doesn't suit), and excluded pairs are
+// skipped.
+pub struct CutoffCoulomb {
+    pub method: CoulombMethod,
+    pub cutoff: f32,
+    pub coulomb: f32,
+    pub neighbors: VerletList,
+}
+
+impl CutoffCoulomb {
+    pub fn reaction_field(cutoff: f32, epsilon_rf: f32) -> Self {
+        Self {
+            method: CoulombMethod::ReactionField { epsilon_rf },
+            cutoff,
+            coulomb: 1.0,
+            neighbors: VerletList::new(cutoff, 0.2 * cutoff),
+        }
+    }
+
+    pub fn wolf(cutoff: f32, alpha: f32) -> Self {
+        Self {
+            method: CoulombMethod::Wolf { alpha },
+            cutoff,
+            coulomb: 1.0,
+            neighbors: VerletList::new(cutoff, 0.2 * cutoff),
+        }
+    }
+
+    // (energy, force) between two charges r apart.
+    pub fn evaluate(&self, q1: f32, q2: f32, r: f32) -> (f32, f32) {
+        let qq = self.coulomb * q1 * q2;
+        match self.method {
+            CoulombMethod::ReactionField { epsilon_rf } => {
+                ReactionField::new(qq, self.cutoff, epsilon_rf).evaluate(r)
+            }
+            CoulombMethod::Wolf { alpha } => {
+                DampedShiftedForce::new(qq, self.cutoff, alpha).evaluate(r)
+            }
+        }
+    }
+
+    // every pair inside the cutoff once.  If the cutoff's been changed since the list was made, forces will sort
+    // that out; until then the list can't be trusted to reach far enough.
+    fn pairs<ParT: HasPhysics<Vec<f32>> + HasCharge<f32>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> Vec<Pair> {
+        let pairs = if self.neighbors.cutoff < self.cutoff {
+            CellList::new(self.cutoff).pairs(world)
+        } else {
+            self.neighbors.pairs_or_fresh(world)
+        };
+        pairs
+            .into_iter()
+            .filter(|(_, _, _, r)| *r < self.cutoff && *r > 0.0)
+            .collect()
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>> + HasCharge<f32>> ForceTerm<ParT> for CutoffCoulomb {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        if self.neighbors.cutoff != self.cutoff {
+            self.neighbors = VerletList::new(self.cutoff, self.neighbors.skin);
+        }
+        self.neighbors.update(world);
+        let atoms = world.get_particles();
+        let mut forces: HashMap<String, Vec<f32>> = atoms
+            .keys()
+            .map(|name| (name.clone(), vec![0.0; 3]))
+            .collect();
+        for (a, b, d, r) in self.pairs(world) {
+            let (_, f) = self.evaluate(*atoms[&a].get_charge(), *atoms[&b].get_charge(), r);
+            for (k, z) in d.iter().enumerate() {
+                forces.get_mut(&a).unwrap()[k] += f * z / r;
+                forces.get_mut(&b).unwrap()[k] -= f * z / r;
+            }
+        }
+        forces
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        let atoms = world.get_particles();
+        let mut energy = 0.0;
+        for (a, b, _, r) in self.pairs(world) {
+            energy += self
+                .evaluate(*atoms[&a].get_charge(), *atoms[&b].get_charge(), r)
+                .0;
+        }
+        if let CoulombMethod::Wolf { alpha } = self.method {
+            let charges: Vec<f32> = atoms.values().map(|atom| *atom.get_charge()).collect();
+            energy += self.coulomb * wolf_self_energy(&charges, self.cutoff, alpha);
+        }
+        energy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dynamics::integrator::distance;
+    use crate::ForceFields::SIN::Elements;
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+
+    fn check_derivative(pair: &impl PairPotential, r: f32) {
+        let h = 1.0e-3;
+        let numerical = -(pair.energy(r + h) - pair.energy(r - h)) / (2.0 * h);
+        assert!((numerical - pair.force(r)).abs() < 1.0e-2 * pair.force(r).abs().max(1.0));
+    }
+
+    #[test]
+    fn test_reaction_field() {
+        let rf = ReactionField::new(-1.0, 3.0, 78.0);
+        for r in [0.8, 1.5, 2.5] {
+            check_derivative(&rf, r);
+        }
+        assert!(rf.energy(3.0 - 1.0e-4).abs() < 1.0e-3);
+        assert_eq!(rf.force(3.5), 0.0);
+        // conducting boundaries take the force to zero at the cutoff too.
+        let conducting = ReactionField::new(1.0, 3.0, f32::INFINITY);
+        assert!(conducting.force(3.0 - 1.0e-4).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn test_wolf_is_smooth_at_cutoff() {
+        let dsf = DampedShiftedForce::new(1.0, 4.0, 0.3);
+        for r in [0.7, 2.0, 3.5] {
+            check_derivative(&dsf, r);
+        }
+        assert!(dsf.force(4.0 - 1.0e-4).abs() < 1.0e-4);
+        assert!(dsf.energy(4.0 - 1.0e-4).abs() < 1.0e-4);
+        // at short range it's still mostly just Coulomb.
+        assert!((dsf.force(0.5) - 4.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_wolf_nacl_cluster() {
+        // a 7x7x7 chunk of rock salt; the DSF energy per ion of the middle ions should land near the Madelung energy.
+        let mut particles = HashMap::new();
+        for x in 0..7 {
+            for y in 0..7 {
+                for z in 0..7 {
+                    let atom = AtomBuilder::new()
+                        .element(Elements::X(0))
+                        .id(Some(format!("ion{x}-{y}-{z}")))
+                        .charge(if (x + y + z) % 2 == 0 { 1.0 } else { -1.0 })
+                        .position(vec![x as f32, y as f32, z as f32])
+                        .build();
+                    particles.insert(atom.id.clone(), atom);
+                }
+            }
+        }
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        let mut wolf = CutoffCoulomb::wolf(2.9, 0.5);
+        // the site energy of the ion in the middle: half of its pair energies, plus its self term.  That's -M/2 in a real crystal.
+        let center = &cell.get_particles()["ion3-3-3"];
+        let mut site = 0.0;
+        for atom in cell.get_particles().values() {
+            let d = distance(center, atom, None);
+            let r = d.iter().map(|z| z * z).sum::<f32>().sqrt();
+            if r > 0.0 {
+                site += 0.5 * wolf.evaluate(center.charge, atom.charge, r).0;
+            }
+        }
+        site += wolf_self_energy(&[1.0], 2.9, 0.5);
+        assert!((site + 1.747565 / 2.0).abs() < 0.05, "site energy {site}");
+        // and the forces on a perfect lattice cancel out.
+        let forces = wolf.forces(&cell, 0.0);
+        assert!(forces["ion3-3-3"].iter().all(|f| f.abs() < 1.0e-3));
+    }
+
+    #[test]
+    fn test_forces_match_all_pairs() {
+        // a loose jumble of charges in a box; the Verlet list should turn up the same forces as checking every pair.
+        let mut particles = HashMap::new();
+        for i in 0..20 {
+            let atom = AtomBuilder::new()
+                .element(Elements::X(0))
+                .id(Some(format!("q{i:02}")))
+                .charge(if i % 2 == 0 { 1.0 } else { -1.0 })
+                .position(vec![
+                    (i * 7 % 10) as f32 * 0.9,
+                    (i * 3 % 10) as f32 * 0.9,
+                    (i % 4) as f32 * 2.1,
+                ])
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        cell.set_periodic_box(Some(crate::Topology::periodic::PeriodicBox::cubic(9.0)));
+        let mut rf = CutoffCoulomb::reaction_field(3.0, 78.0);
+        let forces = rf.forces(&cell, 0.0);
+        let atoms = cell.get_particles();
+        for (name, atom) in atoms.iter() {
+            let mut expected = vec![0.0; 3];
+            for (other, b) in atoms.iter() {
+                let d = distance(b, atom, cell.get_periodic_box());
+                let r = d.iter().map(|z| z * z).sum::<f32>().sqrt();
+                if other != name && r < 3.0 {
+                    let f = rf.evaluate(atom.charge, b.charge, r).1;
+                    for k in 0..3 {
+                        expected[k] -= f * d[k] / r;
+                    }
+                }
+            }
+            for k in 0..3 {
+                assert!((forces[name][k] - expected[k]).abs() < 1.0e-3, "{name}");
+            }
+        }
+        rf.forces(&cell, 0.0);
+        assert_eq!(rf.neighbors.rebuilds, 1);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::ForceFields::potentials::PairPotential;
+use crate::ForceFields::SIN::ForceField;
+use crate::Topology::atom::{Atom, HasElement};
+use crate::Topology::cell::ContainsParticles;
+use std::f32::consts::PI;
+
+// Cutting pair interactions off at some radius rc, without the energy (or the force) jumping there.
+// Any pair potential can be wrapped up with a cutoff, and any force field in a CutoffForceField, which hands back its
+// pair potentials with the cutoff applied, so it drops straight into the integrators, Monte Carlo and NonBonded.
+//   Truncate:        just stop at rc.  Both the energy and the force jump.
+//   PotentialShift:  U(r) - U(rc).  The energy is continuous, the force still jumps.
+//   ForceShift:      F(r) - F(rc), and the energy that goes with it.  Both are continuous, at the price of changing the
+//                    interaction everywhere a little.
+//   Switch:          smoothly scales the interaction to zero between r_on and rc with S(x) = 1 - 10x^3 + 15x^4 - 6x^5,
+//                    which has zero first and second derivatives at both ends.  Untouched inside r_on.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CutoffScheme {
+    Truncate,
+    PotentialShift,
+    ForceShift,
+    Switch { switch_radius: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cutoff {
+    pub radius: f32,
+    pub scheme: CutoffScheme,
+}
+
+// S(r) and dS/dr.
+fn switching(r: f32, r_on: f32, r_off: f32) -> (f32, f32) {
+    if r <= r_on {
+        return (1.0, 0.0);
+    }
+    let x = (r - r_on) / (r_off - r_on);
+    let s = 1.0 - x.powi(3) * (10.0 - 15.0 * x + 6.0 * x * x);
+    let ds = -30.0 * x * x * (1.0 - x) * (1.0 - x) / (r_off - r_on);
+    (s, ds)
+}
+
+impl Cutoff {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            scheme: CutoffScheme::Truncate,
+        }
+    }
+
+    pub fn potential_shift(radius: f32) -> Self {
+        Self {
+            radius,
+            scheme: CutoffScheme::PotentialShift,
+        }
+    }
+
+    pub fn force_shift(radius: f32) -> Self {
+        Self {
+            radius,
+            scheme: CutoffScheme::ForceShift,
+        }
+    }
+
+    // switching starts at switch_radius and has to be done by the cutoff, so it needs 0 <= switch_radius < radius.
+    pub fn switched(switch_radius: f32, radius: f32) -> Self {
+        assert!(
+            (0.0..radius).contains(&switch_radius),
+            "switching has to start inside the cutoff: {switch_radius} isn't in [0, {radius})"
+        );
+        Self {
+            radius,
+            scheme: CutoffScheme::Switch { switch_radius },
+        }
+    }
+
+    // Wraps a pair potential up with this cutoff.
+    pub fn apply<P: PairPotential>(&self, pair: P) -> CutPair<P> {
+        let (energy_at_cutoff, force_at_cutoff) = pair.evaluate(self.radius);
+        CutPair {
+            pair,
+            cutoff: self.clone(),
+            energy_at_cutoff,
+            force_at_cutoff,
+        }
+    }
+}
+
+// A pair potential with a cutoff applied; the values at rc get worked out once, up front.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CutPair<P> {
+    pub pair: P,
+    pub cutoff: Cutoff,
+    energy_at_cutoff: f32,
+    force_at_cutoff: f32,
+}
+
+impl<P: PairPotential> PairPotential for CutPair<P> {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let rc = self.cutoff.radius;
+        if r >= rc {
+            return (0.0, 0.0);
+        }
+        let (u, f) = self.pair.evaluate(r);
+        match self.cutoff.scheme {
+            CutoffScheme::Truncate => (u, f),
+            CutoffScheme::PotentialShift => (u - self.energy_at_cutoff, f),
+            CutoffScheme::ForceShift => (
+                u - self.energy_at_cutoff + (r - rc) * self.force_at_cutoff,
+                f - self.force_at_cutoff,
+            ),
+            CutoffScheme::Switch { switch_radius } => {
+                // F = -d(S U)/dr = S F - S' U
+                let (s, ds) = switching(r, switch_radius, rc);
+                (s * u, s * f - ds * u)
+            }
+        }
+    }
+}
+
+// The integrals from rc out to infinity for the tail corrections, done with u = 1/r so the range is finite:
+//   int r^2 U dr = int U(1/u) / u^4 du  and  int r^3 F dr = int F(1/u) / u^5 du  over (0, 1/rc].
+// Only converges for interactions that die off faster than 1/r^3 (Lennard-Jones, yes; Coulomb, no).
+fn tail_integral(f: &dyn Fn(f32) -> f32, cutoff: f32, power: i32) -> f32 {
+    let steps = 4096;
+    let du = 1.0 / cutoff / steps as f32;
+    // midpoints, so we never actually have to evaluate anything at infinity.
+    (0..steps)
+        .map(|i| {
+            let u = (i as f32 + 0.5) * du;
+            f(1.0 / u) / u.powi(power + 2)
+        })
+        .sum::<f32>()
+        * du
+}
+
+pub struct CutoffForceField<FF> {
+    pub forcefield: FF,
+    pub cutoff: Cutoff,
+}
+
+impl<FF> CutoffForceField<FF> {
+    pub fn new(forcefield: FF, cutoff: Cutoff) -> Self {
+        Self { forcefield, cutoff }
+    }
+
+    // The energy and pressure that everything past the cutoff would have added, assuming the particles out there are
+    // spread out evenly (g(r) = 1).  Needs a periodic box for the density; open systems don't get one.
+    //   E_tail = 2 pi / V sum_ij N_i N_j int r^2 U_ij dr
+    //   P_tail = 2 pi / (3 V^2) sum_ij N_i N_j int r^3 F_ij dr
+    pub fn tail_corrections<EleT: Clone + PartialEq, ParT: HasElement<EleT>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> (f32, f32)
+    where
+        FF: ForceField<EleT, f32, Vec<f32>>,
+    {
+        let Some(periodic) = world.get_periodic_box() else {
+            return (0.0, 0.0);
+        };
+        let volume = periodic.volume();
+        let mut counts: Vec<(EleT, f32)> = Vec::new();
+        for particle in world.get_particles().values() {
+            let element = particle.get_element();
+            match counts.iter_mut().find(|(e, _)| e == element) {
+                Some((_, n)) => *n += 1.0,
+                None => counts.push((element.clone(), 1.0)),
+            }
+        }
+        let rc = self.cutoff.radius;
+        let (mut energy, mut pressure) = (0.0, 0.0);
+        for (e1, n1) in counts.iter() {
+            for (e2, n2) in counts.iter() {
+                let pair = self.forcefield.pair_potential(e1, e2);
+                let (potential, force) = (|r| pair.energy(r), |r| pair.force(r));
+                energy += 2.0 * PI / volume * n1 * n2 * tail_integral(&potential, rc, 2);
+                pressure +=
+                    2.0 * PI / (3.0 * volume * volume) * n1 * n2 * tail_integral(&force, rc, 3);
+            }
+        }
+        (energy, pressure)
+    }
+}
+
+impl<EleT, FF: ForceField<EleT, f32, Vec<f32>>> ForceField<EleT, f32, Vec<f32>>
+    for CutoffForceField<FF>
+{
+    type Pair = CutPair<FF::Pair>;
+    fn mass(&self, element: &EleT) -> f32 {
+        self.forcefield.mass(element)
+    }
+    fn charge(&self, element: &EleT) -> f32 {
+        self.forcefield.charge(element)
+    }
+    fn atom(&self, element: EleT) -> Atom<EleT, f32, Vec<f32>> {
+        self.forcefield.atom(element)
+    }
+    fn pair_potential(&self, e1: &EleT, e2: &EleT) -> CutPair<FF::Pair> {
+        self.cutoff.apply(self.forcefield.pair_potential(e1, e2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{world, LJ};
+    use crate::ForceFields::potentials::LennardJones;
+    use crate::ForceFields::SIN::Elements;
+    use crate::Topology::periodic::PeriodicBox;
+
+    fn check_derivative(pair: &impl PairPotential, r: f32) {
+        let h = 1.0e-3;
+        let numerical = -(pair.energy(r + h) - pair.energy(r - h)) / (2.0 * h);
+        assert!((numerical - pair.force(r)).abs() < 1.0e-2 * pair.force(r).abs().max(1.0));
+    }
+
+    #[test]
+    fn test_cutoff_schemes() {
+        let (h, rc) = (Elements::H(0), 2.5);
+        for cutoff in [
+            Cutoff::new(rc),
+            Cutoff::potential_shift(rc),
+            Cutoff::force_shift(rc),
+            Cutoff::switched(2.0, rc),
+        ] {
+            let pair = CutoffForceField::new(LJ, cutoff.clone()).pair_potential(&h, &h);
+            for r in [0.95, 1.3, 2.1, 2.4] {
+                check_derivative(&pair, r);
+            }
+            let (force, potential) = (|r| pair.force(r), |r| pair.energy(r));
+            assert_eq!(pair.evaluate(2.6), (0.0, 0.0));
+            let just_inside = rc - 1.0e-4;
+            match cutoff.scheme {
+                CutoffScheme::Truncate => assert!(potential(just_inside) < -0.01),
+                CutoffScheme::PotentialShift => {
+                    assert!(potential(just_inside).abs() < 1.0e-4);
+                    assert!(force(just_inside).abs() > 0.01);
+                }
+                _ => {
+                    assert!(potential(just_inside).abs() < 1.0e-4);
+                    assert!(force(just_inside).abs() < 1.0e-3);
+                }
+            }
+        }
+        // switching leaves everything inside r_on alone.
+        let switched = Cutoff::switched(2.0, rc).apply(LennardJones::new(1.0, 1.0));
+        assert_eq!(
+            switched.evaluate(1.5),
+            LJ.pair_potential(&h, &h).evaluate(1.5)
+        );
+    }
+
+    #[test]
+    fn test_lennard_jones_tail_corrections() {
+        let n = 500;
+        let length = 10.0;
+        let mut cell = world((0..n).map(|_| LJ.atom(Elements::H(0))));
+        cell.set_periodic_box(Some(PeriodicBox::cubic(length)));
+        let rc: f32 = 2.5;
+        let rho = n as f32 / length.powi(3);
+        let (energy, pressure) = CutoffForceField::new(LJ, Cutoff::new(rc)).tail_corrections(&cell);
+        let expected_energy = n as f32 * 8.0 / 3.0 * PI * rho * (rc.powi(-9) / 3.0 - rc.powi(-3));
+        let expected_pressure =
+            16.0 / 3.0 * PI * rho * rho * (2.0 / 3.0 * rc.powi(-9) - rc.powi(-3));
+        assert!((energy - expected_energy).abs() < 1.0e-3 * expected_energy.abs());
+        assert!((pressure - expected_pressure).abs() < 1.0e-3 * expected_pressure.abs());
+        // and nothing without a box.
+        cell.set_periodic_box(None);
+        assert_eq!(
+            CutoffForceField::new(LJ, Cutoff::new(rc)).tail_corrections(&cell),
+            (0.0, 0.0)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "switching has to start inside the cutoff")]
+    fn test_switch_past_cutoff() {
+        Cutoff::switched(2.5, 2.0);
+    }
+}
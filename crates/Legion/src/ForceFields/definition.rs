@@ -0,0 +1,712 @@
+use crate::Dynamics::bonded::{AnglePotential, BondPotential, Bonded, DihedralPotential};
+use crate::ForceFields::mixing::MixingRule;
+use crate::ForceFields::potentials::{
+    Buckingham, LennardJones, Morse, PairPotential, SoftSphere, Yukawa, WCA,
+};
+use crate::ForceFields::SIN::{Elements, ForceField};
+use crate::Topology::atom::{Atom, AtomBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+// Force fields written down in a file instead of in code.  TOML or JSON, same layout either way:
+//
+//   name = "toy water"
+//   mixing = "lorentz-berthelot"      # or "geometric"; optional
+//
+//   [[atoms]]
+//   type = "O"                        # an element symbol, and Elements only has H, C, O and X
+//   mass = 15.999
+//   charge = -0.82
+//   epsilon = 0.65                    # epsilon and sigma are optional, and only used for mixing
+//   sigma = 3.166
+//
+//   [[pairs]]                         # any pair potential from potentials.rs, by name
+//   types = ["O", "H"]
+//   potential = "lennard-jones"       # lennard-jones, buckingham, morse, yukawa, wca or soft-sphere
+//   epsilon = 0.1
+//   sigma = 2.0
+//
+//   [[bonds]]                         # harmonic or morse
+//   types = ["O", "H"]
+//   potential = "harmonic"
+//   k = 345000.0
+//   r0 = 1.0
+//
+//   [[angles]]                        # harmonic or urey-bradley; the middle type is the center
+//   [[dihedrals]]                     # periodic, ryckaert-bellemans or harmonic
+//   [[impropers]]                     # like dihedrals, but with "center" instead of "types"
+//
+// Pairs that aren't listed get Lennard-Jones mixed from the atoms' epsilon and sigma, if there's a mixing rule and both
+// atoms have them, and nothing at all otherwise.  Parameters match up by element, so each element gets one atom type.
+// Everything is checked on the way in, and what's wrong comes back naming the entry it's in (e.g. pairs[2]).
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AtomType {
+    #[serde(rename = "type")]
+    pub element: String,
+    pub mass: f32,
+    #[serde(default)]
+    pub charge: f32,
+    pub epsilon: Option<f32>,
+    pub sigma: Option<f32>,
+}
+
+// Every pair potential there is, for picking by name.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "potential", rename_all = "kebab-case")]
+pub enum Potential {
+    LennardJones(LennardJones),
+    Buckingham(Buckingham),
+    Morse(Morse),
+    Yukawa(Yukawa),
+    Wca(WCA),
+    SoftSphere(SoftSphere),
+    #[serde(skip)]
+    Nothing,
+}
+
+impl PairPotential for Potential {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        match self {
+            Potential::LennardJones(pair) => pair.evaluate(r),
+            Potential::Buckingham(pair) => pair.evaluate(r),
+            Potential::Morse(pair) => pair.evaluate(r),
+            Potential::Yukawa(pair) => pair.evaluate(r),
+            Potential::Wca(pair) => pair.evaluate(r),
+            Potential::SoftSphere(pair) => pair.evaluate(r),
+            Potential::Nothing => (0.0, 0.0),
+        }
+    }
+}
+
+// The entries are read one at a time (see from_value), so these aren't Deserialize themselves: the types come out,
+// and whatever's left has to be exactly the potential's parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairEntry {
+    pub types: [String; 2],
+    pub potential: Potential,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BondEntry {
+    pub types: [String; 2],
+    pub potential: BondPotential,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AngleEntry {
+    pub types: [String; 3],
+    pub potential: AnglePotential,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DihedralEntry {
+    pub types: [String; 4],
+    pub potential: DihedralPotential,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImproperEntry {
+    pub center: String,
+    pub potential: DihedralPotential,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub mixing: Option<MixingRule>,
+    pub atoms: Vec<AtomType>,
+    pub pairs: Vec<PairEntry>,
+    pub bonds: Vec<BondEntry>,
+    pub angles: Vec<AngleEntry>,
+    pub dihedrals: Vec<DihedralEntry>,
+    pub impropers: Vec<ImproperEntry>,
+}
+
+// The file as it first comes in: the top level gets checked, the entries are left as they are for now.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Sections {
+    name: String,
+    #[serde(default)]
+    mixing: Option<MixingRule>,
+    #[serde(default)]
+    atoms: Vec<Value>,
+    #[serde(default)]
+    pairs: Vec<Value>,
+    #[serde(default)]
+    bonds: Vec<Value>,
+    #[serde(default)]
+    angles: Vec<Value>,
+    #[serde(default)]
+    dihedrals: Vec<Value>,
+    #[serde(default)]
+    impropers: Vec<Value>,
+}
+
+// What's wrong, and where: the entry is something like "atoms[0]" or "pairs[3]", or "file" if it didn't even parse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefinitionError {
+    pub entry: String,
+    pub message: String,
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.entry, self.message)
+    }
+}
+
+impl std::error::Error for DefinitionError {}
+
+fn error(entry: String, message: String) -> DefinitionError {
+    DefinitionError { entry, message }
+}
+
+fn positive(entry: &str, name: &str, value: f32) -> Result<(), DefinitionError> {
+    if value > 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(error(
+            entry.to_string(),
+            format!("{name} has to be positive, got {value}"),
+        ))
+    }
+}
+
+fn not_negative(entry: &str, name: &str, value: f32) -> Result<(), DefinitionError> {
+    if value >= 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(error(
+            entry.to_string(),
+            format!("{name} can't be negative, got {value}"),
+        ))
+    }
+}
+
+fn finite(entry: &str, name: &str, value: f32) -> Result<(), DefinitionError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(error(
+            entry.to_string(),
+            format!("{name} has to be a number, got {value}"),
+        ))
+    }
+}
+
+fn same_types(a: &[String], b: &[String]) -> bool {
+    a == b || a.iter().rev().eq(b.iter())
+}
+
+// One entry of a section, e.g. pairs[2].
+fn entry<T: DeserializeOwned>(entry: &str, value: Value) -> Result<T, DefinitionError> {
+    serde_json::from_value(value).map_err(|e| error(entry.to_string(), e.to_string()))
+}
+
+// An entry that's some atom types (under key) plus a potential: the types come out, and the rest is the potential.
+fn with_types<T: DeserializeOwned, P: DeserializeOwned>(
+    name: &str,
+    value: Value,
+    key: &str,
+) -> Result<(T, P), DefinitionError> {
+    let Value::Object(mut fields) = value else {
+        return Err(error(name.to_string(), "should be a table".to_string()));
+    };
+    let types = fields
+        .remove(key)
+        .ok_or_else(|| error(name.to_string(), format!("missing field `{key}`")))?;
+    Ok((entry(name, types)?, entry(name, Value::Object(fields))?))
+}
+
+fn section<T>(
+    name: &str,
+    values: Vec<Value>,
+    read: impl Fn(&str, Value) -> Result<T, DefinitionError>,
+) -> Result<Vec<T>, DefinitionError> {
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| read(&format!("{name}[{i}]"), value))
+        .collect()
+}
+
+impl Definition {
+    pub fn from_toml(text: &str) -> Result<Self, DefinitionError> {
+        let value: toml::Value =
+            toml::from_str(text).map_err(|e| error("file".to_string(), e.to_string()))?;
+        let value =
+            serde_json::to_value(value).map_err(|e| error("file".to_string(), e.to_string()))?;
+        Self::from_value(value)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, DefinitionError> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|e| error("file".to_string(), e.to_string()))?;
+        Self::from_value(value)
+    }
+
+    // Either way it ends up as a JSON value, which gets read an entry at a time so that a bad one gets named.
+    pub fn from_value(value: Value) -> Result<Self, DefinitionError> {
+        let sections: Sections =
+            serde_json::from_value(value).map_err(|e| error("file".to_string(), e.to_string()))?;
+        let definition = Definition {
+            name: sections.name,
+            mixing: sections.mixing,
+            atoms: section("atoms", sections.atoms, entry)?,
+            pairs: section("pairs", sections.pairs, |name, value| {
+                let (types, potential) = with_types(name, value, "types")?;
+                Ok(PairEntry { types, potential })
+            })?,
+            bonds: section("bonds", sections.bonds, |name, value| {
+                let (types, potential) = with_types(name, value, "types")?;
+                Ok(BondEntry { types, potential })
+            })?,
+            angles: section("angles", sections.angles, |name, value| {
+                let (types, potential) = with_types(name, value, "types")?;
+                Ok(AngleEntry { types, potential })
+            })?,
+            dihedrals: section("dihedrals", sections.dihedrals, |name, value| {
+                let (types, potential) = with_types(name, value, "types")?;
+                Ok(DihedralEntry { types, potential })
+            })?,
+            impropers: section("impropers", sections.impropers, |name, value| {
+                let (center, potential) = with_types(name, value, "center")?;
+                Ok(ImproperEntry { center, potential })
+            })?,
+        };
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    // goes by the extension; anything that isn't .json is taken to be TOML.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, DefinitionError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| error(path.display().to_string(), e.to_string()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&text),
+            _ => Self::from_toml(&text),
+        }
+    }
+
+    // Everything has to refer to atom types that exist, and the parameters have to make sense.
+    pub fn validate(&self) -> Result<(), DefinitionError> {
+        for (i, atom) in self.atoms.iter().enumerate() {
+            let entry = format!("atoms[{i}]");
+            if Elements::from_symbol(&atom.element).is_none() {
+                return Err(error(
+                    entry,
+                    format!("'{}' isn't an element we know", atom.element),
+                ));
+            }
+            if self.atoms[..i].iter().any(|a| a.element == atom.element) {
+                return Err(error(entry, format!("'{}' is defined twice", atom.element)));
+            }
+            positive(&entry, "mass", atom.mass)?;
+            match (atom.epsilon, atom.sigma) {
+                (Some(epsilon), Some(sigma)) => {
+                    not_negative(&entry, "epsilon", epsilon)?;
+                    positive(&entry, "sigma", sigma)?;
+                }
+                (None, None) => {}
+                _ => {
+                    return Err(error(
+                        entry,
+                        "needs both epsilon and sigma, or neither".to_string(),
+                    ))
+                }
+            }
+        }
+        let known = |entry: &String, types: &[String]| -> Result<(), DefinitionError> {
+            match types
+                .iter()
+                .find(|t| !self.atoms.iter().any(|a| &a.element == *t))
+            {
+                Some(t) => Err(error(entry.clone(), format!("no atom type '{t}'"))),
+                None => Ok(()),
+            }
+        };
+        for (i, pair) in self.pairs.iter().enumerate() {
+            let entry = format!("pairs[{i}]");
+            known(&entry, &pair.types)?;
+            if self.pairs[..i]
+                .iter()
+                .any(|p| same_types(&p.types, &pair.types))
+            {
+                return Err(error(entry, format!("{:?} is given twice", pair.types)));
+            }
+            match &pair.potential {
+                Potential::LennardJones(LennardJones { epsilon, sigma })
+                | Potential::Wca(WCA { epsilon, sigma }) => {
+                    not_negative(&entry, "epsilon", *epsilon)?;
+                    positive(&entry, "sigma", *sigma)?;
+                }
+                Potential::Buckingham(pair) => positive(&entry, "rho", pair.rho)?,
+                Potential::Morse(pair) => {
+                    not_negative(&entry, "depth", pair.depth)?;
+                    positive(&entry, "alpha", pair.alpha)?;
+                }
+                Potential::Yukawa(pair) => not_negative(&entry, "kappa", pair.kappa)?,
+                Potential::SoftSphere(pair) => positive(&entry, "sigma", pair.sigma)?,
+                Potential::Nothing => {}
+            }
+        }
+        for (i, bond) in self.bonds.iter().enumerate() {
+            let entry = format!("bonds[{i}]");
+            known(&entry, &bond.types)?;
+            if self.bonds[..i]
+                .iter()
+                .any(|b| same_types(&b.types, &bond.types))
+            {
+                return Err(error(entry, format!("{:?} is given twice", bond.types)));
+            }
+            match &bond.potential {
+                BondPotential::Harmonic(spring) => {
+                    not_negative(&entry, "k", spring.k)?;
+                    positive(&entry, "r0", spring.r0)?;
+                }
+                BondPotential::Morse(morse) => positive(&entry, "alpha", morse.alpha)?,
+            }
+        }
+        for (i, angle) in self.angles.iter().enumerate() {
+            let entry = format!("angles[{i}]");
+            known(&entry, &angle.types)?;
+            if self.angles[..i]
+                .iter()
+                .any(|a| same_types(&a.types, &angle.types))
+            {
+                return Err(error(entry, format!("{:?} is given twice", angle.types)));
+            }
+            let (AnglePotential::Harmonic { k, theta0 }
+            | AnglePotential::UreyBradley { k, theta0, .. }) = &angle.potential;
+            not_negative(&entry, "k", *k)?;
+            if !(0.0..=180.0).contains(theta0) {
+                return Err(error(
+                    entry,
+                    format!("theta0 has to be 0 to 180 degrees, got {theta0}"),
+                ));
+            }
+        }
+        let torsion = |entry: &String, potential: &DihedralPotential| match potential {
+            DihedralPotential::Periodic {
+                k,
+                multiplicity,
+                phase,
+            } => {
+                finite(entry, "k", *k)?;
+                finite(entry, "phase", *phase)?;
+                if *multiplicity < 0 {
+                    return Err(error(
+                        entry.clone(),
+                        format!("multiplicity can't be negative, got {multiplicity}"),
+                    ));
+                }
+                Ok(())
+            }
+            DihedralPotential::RyckaertBellemans { c } => {
+                c.iter().try_for_each(|c| finite(entry, "c", *c))
+            }
+            DihedralPotential::Harmonic { k, xi0 } => {
+                not_negative(entry, "k", *k)?;
+                finite(entry, "xi0", *xi0)
+            }
+        };
+        for (i, dihedral) in self.dihedrals.iter().enumerate() {
+            let entry = format!("dihedrals[{i}]");
+            known(&entry, &dihedral.types)?;
+            if self.dihedrals[..i]
+                .iter()
+                .any(|d| same_types(&d.types, &dihedral.types))
+            {
+                return Err(error(entry, format!("{:?} is given twice", dihedral.types)));
+            }
+            torsion(&entry, &dihedral.potential)?;
+        }
+        for (i, improper) in self.impropers.iter().enumerate() {
+            let entry = format!("impropers[{i}]");
+            known(&entry, std::slice::from_ref(&improper.center))?;
+            if self.impropers[..i]
+                .iter()
+                .any(|d| d.center == improper.center)
+            {
+                return Err(error(
+                    entry,
+                    format!("'{}' is given twice", improper.center),
+                ));
+            }
+            torsion(&entry, &improper.potential)?;
+        }
+        Ok(())
+    }
+
+    fn atom_type(&self, element: &Elements) -> Option<&AtomType> {
+        self.atoms.iter().find(|a| a.element == element.symbol())
+    }
+
+    // All the bonded parameters, ready to generate terms from a topology.
+    pub fn bonded(&self) -> Bonded {
+        // validated, so every type is an element.
+        let element = |t: &String| Elements::from_symbol(t).unwrap();
+        let mut bonded = Bonded::new();
+        for bond in self.bonds.iter() {
+            let [a, b] = &bond.types;
+            bonded.set_bond(&element(a), &element(b), bond.potential.clone());
+        }
+        for angle in self.angles.iter() {
+            let [a, b, c] = &angle.types;
+            bonded.set_angle(
+                &element(a),
+                &element(b),
+                &element(c),
+                angle.potential.clone(),
+            );
+        }
+        for dihedral in self.dihedrals.iter() {
+            let [a, b, c, d] = dihedral.types.each_ref().map(element);
+            bonded.set_dihedral([&a, &b, &c, &d], dihedral.potential.clone());
+        }
+        for improper in self.impropers.iter() {
+            bonded.set_improper(&element(&improper.center), improper.potential.clone());
+        }
+        bonded
+    }
+}
+
+impl ForceField<Elements, f32, Vec<f32>> for Definition {
+    type Pair = Potential;
+    fn atom(&self, element: Elements) -> Atom<Elements, f32, Vec<f32>> {
+        AtomBuilder::new()
+            .element(element.clone())
+            .charge(self.charge(&element))
+            .mass(self.mass(&element))
+            .build()
+    }
+    fn mass(&self, element: &Elements) -> f32 {
+        self.atom_type(element).map_or(0.0, |a| a.mass)
+    }
+    fn charge(&self, element: &Elements) -> f32 {
+        self.atom_type(element).map_or(0.0, |a| a.charge)
+    }
+    fn pair_potential(&self, e1: &Elements, e2: &Elements) -> Potential {
+        let types = [e1.symbol().to_string(), e2.symbol().to_string()];
+        if let Some(pair) = self.pairs.iter().find(|p| same_types(&p.types, &types)) {
+            return pair.potential.clone();
+        }
+        let (Some(rule), Some(a), Some(b)) = (&self.mixing, self.atom_type(e1), self.atom_type(e2))
+        else {
+            return Potential::Nothing;
+        };
+        match (a.epsilon.zip(a.sigma), b.epsilon.zip(b.sigma)) {
+            (Some(a), Some(b)) => {
+                let (epsilon, sigma) = rule.mix(a, b);
+                Potential::LennardJones(LennardJones::new(epsilon, sigma))
+            }
+            _ => Potential::Nothing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Topology::cell::Cell;
+    use std::collections::HashMap;
+
+    const WATER: &str = r#"
+name = "toy water"
+mixing = "lorentz-berthelot"
+
+[[atoms]]
+type = "O"
+mass = 15.999
+charge = -0.82
+epsilon = 0.65
+sigma = 3.166
+
+[[atoms]]
+type = "H"
+mass = 1.008
+charge = 0.41
+epsilon = 0.1
+sigma = 1.0
+
+[[atoms]]
+type = "C"
+mass = 12.011
+
+[[pairs]]
+types = ["H", "H"]
+potential = "soft-sphere"
+epsilon = 1.0
+sigma = 1.0
+n = 12
+
+[[pairs]]
+types = ["C", "O"]
+potential = "morse"
+depth = 2
+alpha = 1.5
+r0 = 1.2
+
+[[bonds]]
+types = ["O", "H"]
+potential = "harmonic"
+k = 345000.0
+r0 = 1.0
+
+[[angles]]
+types = ["H", "O", "H"]
+potential = "harmonic"
+k = 383.0
+theta0 = 109.47
+"#;
+
+    #[test]
+    fn test_toml_force_field() {
+        let ff = Definition::from_toml(WATER).unwrap();
+        let (h, o, c) = (Elements::H(0), Elements::O(0), Elements::C(0));
+        assert_eq!(ff.mass(&o), 15.999);
+        assert_eq!(ff.atom(h.clone()).charge, 0.41);
+        assert_eq!(
+            ff.pair_potential(&h, &h),
+            Potential::SoftSphere(SoftSphere::new(1.0, 1.0, 12))
+        );
+        // either way round, and integers are fine for numbers.
+        assert_eq!(
+            ff.pair_potential(&o, &c),
+            Potential::Morse(Morse::new(2.0, 1.5, 1.2))
+        );
+        // mixed, since it isn't listed.
+        let mixed = ff.pair_potential(&o, &h);
+        let (epsilon, sigma) = MixingRule::LorentzBerthelot.mix((0.65, 3.166), (0.1, 1.0));
+        assert_eq!(
+            mixed,
+            Potential::LennardJones(LennardJones::new(epsilon, sigma))
+        );
+        // C has no epsilon or sigma to mix with.
+        assert_eq!(ff.pair_potential(&c, &c).evaluate(1.0), (0.0, 0.0));
+        // and the bonded parameters make terms for an actual molecule.
+        let mut particles = HashMap::new();
+        for (name, element, bonds) in [
+            ("o", &o, vec!["h1", "h2"]),
+            ("h1", &h, vec![]),
+            ("h2", &h, vec![]),
+        ] {
+            let mut atom = ff.atom(element.clone());
+            atom.id = name.to_string();
+            atom.neighbors = bonds.iter().map(|b| b.to_string()).collect();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        let mut bonded = ff.bonded();
+        bonded.generate(&cell);
+        assert_eq!((bonded.bonds.len(), bonded.angles.len()), (2, 1));
+    }
+
+    #[test]
+    fn test_json_force_field() {
+        let json = r#"{
+            "name": "beads",
+            "atoms": [{"type": "X", "mass": 1.0}],
+            "pairs": [{"types": ["X", "X"], "potential": "wca", "epsilon": 1.0, "sigma": 1.0}],
+            "dihedrals": [{"types": ["X", "X", "X", "X"], "potential": "ryckaert-bellemans",
+                           "c": [9.28, 12.16, -13.12, -3.06, 26.24, -31.5]}]
+        }"#;
+        let ff = Definition::from_json(json).unwrap();
+        let x = Elements::X(0);
+        assert_eq!(
+            ff.pair_potential(&x, &x),
+            Potential::Wca(WCA::new(1.0, 1.0))
+        );
+        assert_eq!(ff.mixing, None);
+    }
+
+    #[test]
+    fn test_errors_point_at_entries() {
+        let entry = |text: &str| Definition::from_toml(text).unwrap_err().entry;
+        let atoms = "name = \"bad\"\n[[atoms]]\ntype = \"H\"\nmass = 1.0\n";
+        assert_eq!(
+            entry("name = \"bad\"\n[[atoms]]\ntype = \"Q\"\nmass = 1.0\n"),
+            "atoms[0]"
+        );
+        assert_eq!(
+            entry(&format!("{atoms}[[atoms]]\ntype = \"O\"\nmass = -3.0\n")),
+            "atoms[1]"
+        );
+        let pair =
+            "[[pairs]]\ntypes = [\"H\", \"H\"]\npotential = \"lennard-jones\"\nepsilon = 1.0\n";
+        assert_eq!(
+            entry(&format!("{atoms}{pair}sigma = 1.0\n{pair}sigma = 0.0\n")),
+            "pairs[1]"
+        );
+        assert_eq!(
+            entry(&format!("{atoms}[[bonds]]\ntypes = [\"H\", \"O\"]\npotential = \"harmonic\"\nk = 1.0\nr0 = 1.0\n")),
+            "bonds[0]"
+        );
+        // ones that don't parse say why, and where too.
+        let broken = Definition::from_toml(&format!(
+            "{atoms}{pair}sigma = 1.0\n[[pairs]]\ntypes = [\"H\", \"H\"]\npotential = \"magic\"\n"
+        ))
+        .unwrap_err();
+        assert_eq!(broken.entry, "pairs[1]");
+        assert!(broken.message.contains("magic"), "{}", broken.message);
+        assert_eq!(entry(&format!("{atoms}{pair}\n")), "pairs[0]");
+        assert_eq!(
+            entry(&format!("{atoms}{pair}sigma = \"one\"\n")),
+            "pairs[0]"
+        );
+        // misspelled parameters don't just get ignored.
+        let typo =
+            Definition::from_toml(&format!("{atoms}{pair}sigma = 1.0\nsgima = 2.0\n")).unwrap_err();
+        assert_eq!(typo.entry, "pairs[0]");
+        assert!(typo.message.contains("sgima"), "{}", typo.message);
+        let angle = "[[angles]]\ntypes = [\"H\", \"H\", \"H\"]\npotential = \"harmonic\"\nk = 1.0\ntheta0 = 90.0\n";
+        assert_eq!(entry(&format!("{atoms}{angle}kay = 3.0\n")), "angles[0]");
+        // the same bond or angle twice, even written the other way round.
+        let water = format!("{atoms}[[atoms]]\ntype = \"O\"\nmass = 16.0\n");
+        let bond = |types: &str| {
+            format!("[[bonds]]\ntypes = {types}\npotential = \"harmonic\"\nk = 1.0\nr0 = 1.0\n")
+        };
+        assert_eq!(
+            entry(&format!(
+                "{water}{}{}",
+                bond("[\"H\", \"O\"]"),
+                bond("[\"O\", \"H\"]")
+            )),
+            "bonds[1]"
+        );
+        let angle = |types: &str| {
+            format!(
+                "[[angles]]\ntypes = {types}\npotential = \"harmonic\"\nk = 1.0\ntheta0 = 90.0\n"
+            )
+        };
+        assert_eq!(
+            entry(&format!(
+                "{water}{}{}{}",
+                angle("[\"H\", \"O\", \"H\"]"),
+                angle("[\"O\", \"H\", \"O\"]"),
+                angle("[\"H\", \"O\", \"H\"]")
+            )),
+            "angles[2]"
+        );
+        let dihedral = "[[dihedrals]]\ntypes = [\"H\", \"H\", \"H\", \"H\"]\npotential = \"periodic\"\nk = 1.0\nphase = 0.0\n";
+        assert_eq!(
+            entry(&format!("{atoms}{dihedral}multiplicity = -3\n")),
+            "dihedrals[0]"
+        );
+        let improper = "[[impropers]]\ncenter = \"H\"\npotential = \"harmonic\"\nxi0 = 0.0\n";
+        assert_eq!(
+            entry(&format!("{atoms}{improper}k = -1.0\n")),
+            "impropers[0]"
+        );
+        // the top level is still the file's.
+        assert_eq!(entry(&format!("colour = \"blue\"\n{atoms}")), "file");
+    }
+}
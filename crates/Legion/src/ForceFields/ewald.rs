@@ -0,0 +1,822 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::{HasCharge, HasPhysics};
+use crate::Topology::periodic::PeriodicBox;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+
+// Long range electrostatics for periodic boxes, skewed or not.  The 1/r sum gets split with a Gaussian of width 1/alpha:
+//   E = sum_{i<j} q_i q_j erfc(alpha r)/r                           real space, short ranged, cut off
+//     + 2 pi / V sum_{k != 0} exp(-k^2 / 4 alpha^2) / k^2 |S(k)|^2   reciprocal space, S(k) = sum_j q_j exp(i k . r_j)
+//     - alpha / sqrt(pi) sum_i q_i^2                                  self interaction
+// Ewald does the reciprocal sum directly over the k vectors; PME spreads the charges onto a grid and FFTs it
+// (Essmann et al., J. Chem. Phys. 103, 8577 (1995)), which is what you want once there are more than a few hundred charges.
+// Everything is done in f64 inside; the three pieces cancel each other a lot and f32 isn't up to it.
+// Units are Gaussian-ish: the energy of two unit charges at r = 1 is `coulomb`.
+// The box comes from the world every time, so NPT is fine; the number of k vectors (or grid points) goes with it.
+// Without a box there's nothing to sum over: both terms give no forces and zero energy, so only use them with one.
+// Excluded pairs (bonded atoms in a molecule, usually) are left out of the real space sum, and the part of their
+// interaction the reciprocal sum can't help including, erf(alpha r) / r at the nearest image, gets taken back off.
+
+// the splitting parameter that makes erfc(alpha rc) / rc come in under the tolerance.
+pub fn ewald_alpha(cutoff: f32, tolerance: f32) -> f32 {
+    let (rc, tol) = (cutoff as f64, tolerance as f64);
+    let (mut lo, mut hi) = (0.0, 1.0);
+    while erfc(hi * rc) / rc > tol {
+        hi *= 2.0;
+    }
+    for _ in 0..60 {
+        let mid = 0.5 * (lo + hi);
+        if erfc(mid * rc) / rc > tol {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    hi as f32
+}
+
+// Numerical Recipes' Chebyshev fit; relative error under 1.2e-7 everywhere, which is plenty.
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+struct Charges {
+    names: Vec<String>,
+    positions: Vec<[f64; 3]>,
+    charges: Vec<f64>,
+    excluded: HashSet<(usize, usize)>, // (i, j) with i < j.
+}
+
+fn charges<ParT: HasPhysics<Vec<f32>> + HasCharge<f32>>(
+    world: &dyn ContainsParticles<ParT>,
+) -> Charges {
+    let atoms = world.get_particles();
+    let mut names: Vec<String> = atoms.keys().cloned().collect();
+    names.sort();
+    let positions = names
+        .iter()
+        .map(|name| {
+            let p = atoms[name].get_position();
+            [p[0] as f64, p[1] as f64, p[2] as f64]
+        })
+        .collect();
+    let charges = names
+        .iter()
+        .map(|name| *atoms[name].get_charge() as f64)
+        .collect();
+    let mut excluded = HashSet::new();
+    for (i, a) in names.iter().enumerate() {
+        for (j, b) in names.iter().enumerate().skip(i + 1) {
+            if world.is_excluded(a, b) {
+                excluded.insert((i, j));
+            }
+        }
+    }
+    Charges {
+        names,
+        positions,
+        charges,
+        excluded,
+    }
+}
+
+fn to_map(names: &[String], forces: Vec<[f64; 3]>) -> HashMap<String, Vec<f32>> {
+    names
+        .iter()
+        .zip(forces)
+        .map(|(name, f)| (name.clone(), f.iter().map(|&z| z as f32).collect()))
+        .collect()
+}
+
+// The box in f64: its vectors, the reciprocal vectors (a* . a = 1, a* . b = 0 and so on) and the volume.
+struct Lattice {
+    vectors: [[f64; 3]; 3],
+    reciprocal: [[f64; 3]; 3],
+    volume: f64,
+    orthorhombic: bool,
+}
+
+fn cross(u: &[f64; 3], v: &[f64; 3]) -> [f64; 3] {
+    [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ]
+}
+
+fn dot(u: &[f64; 3], v: &[f64; 3]) -> f64 {
+    u[0] * v[0] + u[1] * v[1] + u[2] * v[2]
+}
+
+impl Lattice {
+    fn new<ParT: HasPhysics<Vec<f32>>>(world: &dyn ContainsParticles<ParT>) -> Option<Self> {
+        let periodic: &PeriodicBox = world.get_periodic_box()?;
+        let vectors = periodic.vectors.map(|v| v.map(|z| z as f64));
+        let [a, b, c] = vectors;
+        let volume = dot(&a, &cross(&b, &c));
+        let reciprocal =
+            [cross(&b, &c), cross(&c, &a), cross(&a, &b)].map(|v| v.map(|z| z / volume));
+        Some(Self {
+            vectors,
+            reciprocal,
+            volume: volume.abs(),
+            orthorhombic: periodic.is_orthorhombic(),
+        })
+    }
+
+    // the distance between opposite faces, for each pair of them.
+    fn width(&self, k: usize) -> f64 {
+        1.0 / dot(&self.reciprocal[k], &self.reciprocal[k]).sqrt()
+    }
+
+    fn fractional(&self, r: &[f64; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|k| dot(&self.reciprocal[k], r))
+    }
+
+    fn cartesian(&self, s: &[f64; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|x| (0..3).map(|k| s[k] * self.vectors[k][x]).sum())
+    }
+}
+
+// How many k vectors (or grid points) along each reciprocal vector it takes for the reciprocal sum to get down to the
+// tolerance: |k| out to 2 alpha sqrt(-ln tol), and each step along a* is 2 pi / width.
+fn kmax(lattice: &Lattice, alpha: f32, tolerance: f32) -> [usize; 3] {
+    let s = (-(tolerance as f64).ln()).sqrt();
+    [0, 1, 2].map(|k| (alpha as f64 * lattice.width(k) * s / PI).ceil() as usize)
+}
+
+// The real space, self and exclusion parts are the same for both methods.  The cutoff can't be more than half the box;
+// in a skewed box the nearest copy isn't always the one rounding the fractional coordinates gives, so the ones around
+// it get checked too.
+fn real_space(
+    system: &Charges,
+    lattice: &Lattice,
+    alpha: f64,
+    cutoff: f64,
+) -> (f64, Vec<[f64; 3]>) {
+    let n = system.charges.len();
+    let mut forces = vec![[0.0; 3]; n];
+    let mut energy = 0.0;
+    let images: Vec<[f64; 3]> = if lattice.orthorhombic {
+        vec![[0.0; 3]]
+    } else {
+        (0..27)
+            .map(|i| {
+                [
+                    (i / 9) as f64 - 1.0,
+                    (i / 3 % 3) as f64 - 1.0,
+                    (i % 3) as f64 - 1.0,
+                ]
+            })
+            .collect()
+    };
+    let gaussian = |r: f64| 2.0 * alpha / PI.sqrt() * (-alpha * alpha * r * r).exp();
+    for i in 0..n {
+        energy -= alpha / PI.sqrt() * system.charges[i] * system.charges[i];
+        for j in i + 1..n {
+            let separation = [0, 1, 2].map(|k| system.positions[i][k] - system.positions[j][k]);
+            let reduced = lattice.fractional(&separation).map(|s| s - s.round());
+            let copies = images
+                .iter()
+                .map(|image| lattice.cartesian(&[0, 1, 2].map(|k| reduced[k] + image[k])));
+            let qq = system.charges[i] * system.charges[j];
+            if system.excluded.contains(&(i, j)) {
+                // no real space part, and -qq erf(alpha r) / r for the nearest copy to cancel the reciprocal one.
+                let d = copies
+                    .min_by(|a, b| dot(a, a).total_cmp(&dot(b, b)))
+                    .unwrap();
+                let r = dot(&d, &d).sqrt();
+                if r == 0.0 {
+                    continue;
+                }
+                let erf = 1.0 - erfc(alpha * r);
+                energy -= qq * erf / r;
+                // -dU/dr / r
+                let f = qq * (gaussian(r) - erf / r) / (r * r);
+                for k in 0..3 {
+                    forces[i][k] += f * d[k];
+                    forces[j][k] -= f * d[k];
+                }
+                continue;
+            }
+            for d in copies {
+                let r = dot(&d, &d).sqrt();
+                if r > cutoff || r == 0.0 {
+                    continue;
+                }
+                energy += qq * erfc(alpha * r) / r;
+                // -dU/dr / r
+                let f = qq * (erfc(alpha * r) / r + gaussian(r)) / (r * r);
+                for k in 0..3 {
+                    forces[i][k] += f * d[k];
+                    forces[j][k] -= f * d[k];
+                }
+            }
+        }
+    }
+    (energy, forces)
+}
+
+// Plain Ewald summation.  The k vectors go out far enough to get the reciprocal part down to the tolerance too.
+pub struct Ewald {
+    pub alpha: f32,
+    pub cutoff: f32,
+    pub tolerance: f32,
+    pub coulomb: f32,
+}
+
+impl Ewald {
+    // picks alpha so the real space part is converged at the cutoff.
+    pub fn new(cutoff: f32, tolerance: f32) -> Self {
+        Self {
+            alpha: ewald_alpha(cutoff, tolerance),
+            cutoff,
+            tolerance,
+            coulomb: 1.0,
+        }
+    }
+
+    fn reciprocal(&self, system: &Charges, lattice: &Lattice) -> (f64, Vec<[f64; 3]>) {
+        let volume = lattice.volume;
+        let alpha = self.alpha as f64;
+        let n = system.charges.len();
+        let mut forces = vec![[0.0; 3]; n];
+        let mut energy = 0.0;
+        let [kx, ky, kz] = kmax(lattice, self.alpha, self.tolerance).map(|k| k as i64);
+        let b = &lattice.reciprocal;
+        for mx in -kx..=kx {
+            for my in -ky..=ky {
+                for mz in -kz..=kz {
+                    if mx == 0 && my == 0 && mz == 0 {
+                        continue;
+                    }
+                    let m = [mx as f64, my as f64, mz as f64];
+                    let k = [0, 1, 2]
+                        .map(|x| 2.0 * PI * (m[0] * b[0][x] + m[1] * b[1][x] + m[2] * b[2][x]));
+                    let k2 = k[0] * k[0] + k[1] * k[1] + k[2] * k[2];
+                    let a = (-k2 / (4.0 * alpha * alpha)).exp() / k2;
+                    let phases: Vec<f64> = system
+                        .positions
+                        .iter()
+                        .map(|r| k[0] * r[0] + k[1] * r[1] + k[2] * r[2])
+                        .collect();
+                    let c: f64 = (0..n).map(|i| system.charges[i] * phases[i].cos()).sum();
+                    let s: f64 = (0..n).map(|i| system.charges[i] * phases[i].sin()).sum();
+                    energy += 2.0 * PI / volume * a * (c * c + s * s);
+                    for i in 0..n {
+                        let f = 4.0 * PI / volume
+                            * a
+                            * system.charges[i]
+                            * (c * phases[i].sin() - s * phases[i].cos());
+                        for d in 0..3 {
+                            forces[i][d] += f * k[d];
+                        }
+                    }
+                }
+            }
+        }
+        (energy, forces)
+    }
+
+    fn evaluate(&self, system: &Charges, lattice: &Lattice) -> (f64, Vec<[f64; 3]>) {
+        let (real_energy, mut forces) =
+            real_space(system, lattice, self.alpha as f64, self.cutoff as f64);
+        let (recip_energy, recip_forces) = self.reciprocal(system, lattice);
+        for (f, g) in forces.iter_mut().zip(recip_forces) {
+            for k in 0..3 {
+                f[k] = (f[k] + g[k]) * self.coulomb as f64;
+            }
+        }
+        ((real_energy + recip_energy) * self.coulomb as f64, forces)
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>> + HasCharge<f32>> ForceTerm<ParT> for Ewald {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let Some(lattice) = Lattice::new(world) else {
+            return HashMap::new();
+        };
+        let system = charges(world);
+        to_map(&system.names, self.evaluate(&system, &lattice).1)
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        let Some(lattice) = Lattice::new(world) else {
+            return 0.0;
+        };
+        self.evaluate(&charges(world), &lattice).0 as f32
+    }
+}
+
+// Cardinal B-spline of order n; nonzero on (0, n).
+fn bspline(n: usize, u: f64) -> f64 {
+    if n == 2 {
+        return if u < 0.0 || u > 2.0 {
+            0.0
+        } else {
+            1.0 - (u - 1.0).abs()
+        };
+    }
+    let m = (n - 1) as f64;
+    u / m * bspline(n - 1, u) + (n as f64 - u) / m * bspline(n - 1, u - 1.0)
+}
+
+// Smooth particle mesh Ewald.  Charges get spread onto the grid with B-splines of the given order; the grid runs along
+// the box vectors, with about two points per Ewald k vector.
+pub struct PME {
+    pub alpha: f32,
+    pub cutoff: f32,
+    pub tolerance: f32,
+    pub order: usize,
+    pub coulomb: f32,
+}
+
+impl PME {
+    // same alpha as Ewald, and fourth order splines.
+    pub fn new(cutoff: f32, tolerance: f32) -> Self {
+        Self {
+            alpha: ewald_alpha(cutoff, tolerance),
+            cutoff,
+            tolerance,
+            order: 4,
+            coulomb: 1.0,
+        }
+    }
+
+    fn grid(&self, lattice: &Lattice) -> [usize; 3] {
+        kmax(lattice, self.alpha, self.tolerance).map(|k| (4 * k).max(2 * self.order))
+    }
+
+    // |b(m)|^2 along one axis; undoes the smoothing the splines do to the structure factor.
+    fn bspline_moduli(&self, k: usize) -> Vec<f64> {
+        let p = self.order;
+        (0..k)
+            .map(|m| {
+                let mut sum = Complex::new(0.0, 0.0);
+                for j in 0..p - 1 {
+                    let arg = 2.0 * PI * (m * j) as f64 / k as f64;
+                    sum += Complex::new(arg.cos(), arg.sin()) * bspline(p, (j + 1) as f64);
+                }
+                1.0 / sum.norm_sqr()
+            })
+            .collect()
+    }
+
+    fn reciprocal(&self, system: &Charges, lattice: &Lattice) -> (f64, Vec<[f64; 3]>) {
+        let volume = lattice.volume;
+        let alpha = self.alpha as f64;
+        let dims = self.grid(lattice);
+        let b = &lattice.reciprocal;
+        let p = self.order;
+        let index = |x: usize, y: usize, z: usize| (x * dims[1] + y) * dims[2] + z;
+
+        // spline weights and their derivatives for every particle, along each axis.
+        struct Stencil {
+            start: [i64; 3],
+            w: [Vec<f64>; 3],
+            dw: [Vec<f64>; 3],
+        }
+        let stencils: Vec<Stencil> = system
+            .positions
+            .iter()
+            .map(|r| {
+                let mut start = [0; 3];
+                let mut w: [Vec<f64>; 3] = Default::default();
+                let mut dw: [Vec<f64>; 3] = Default::default();
+                let fractional = lattice.fractional(r);
+                for d in 0..3 {
+                    let u = fractional[d].rem_euclid(1.0) * dims[d] as f64;
+                    let base = u.floor();
+                    let frac = u - base;
+                    start[d] = base as i64;
+                    // grid point (floor(u) - j) gets M_p(frac + j).
+                    w[d] = (0..p).map(|j| bspline(p, frac + j as f64)).collect();
+                    dw[d] = (0..p)
+                        .map(|j| {
+                            bspline(p - 1, frac + j as f64) - bspline(p - 1, frac + j as f64 - 1.0)
+                        })
+                        .collect();
+                }
+                Stencil { start, w, dw }
+            })
+            .collect();
+        let wrap = |i: i64, d: usize| i.rem_euclid(dims[d] as i64) as usize;
+
+        let mut q = vec![Complex::new(0.0, 0.0); dims[0] * dims[1] * dims[2]];
+        for (i, s) in stencils.iter().enumerate() {
+            for a in 0..p {
+                for b in 0..p {
+                    for c in 0..p {
+                        let at = index(
+                            wrap(s.start[0] - a as i64, 0),
+                            wrap(s.start[1] - b as i64, 1),
+                            wrap(s.start[2] - c as i64, 2),
+                        );
+                        q[at].re += system.charges[i] * s.w[0][a] * s.w[1][b] * s.w[2][c];
+                    }
+                }
+            }
+        }
+
+        let mut phi = q.clone();
+        fft3(&mut phi, dims, false);
+        let moduli = [
+            self.bspline_moduli(dims[0]),
+            self.bspline_moduli(dims[1]),
+            self.bspline_moduli(dims[2]),
+        ];
+        for x in 0..dims[0] {
+            for y in 0..dims[1] {
+                for z in 0..dims[2] {
+                    let at = index(x, y, z);
+                    if x == 0 && y == 0 && z == 0 {
+                        phi[at] = Complex::new(0.0, 0.0);
+                        continue;
+                    }
+                    let signed = |m: usize, k: usize| {
+                        if m > k / 2 {
+                            m as f64 - k as f64
+                        } else {
+                            m as f64
+                        }
+                    };
+                    let n = [signed(x, dims[0]), signed(y, dims[1]), signed(z, dims[2])];
+                    let m = [0, 1, 2].map(|k| n[0] * b[0][k] + n[1] * b[1][k] + n[2] * b[2][k]);
+                    let m2 = m[0] * m[0] + m[1] * m[1] + m[2] * m[2];
+                    let g = (-PI * PI * m2 / (alpha * alpha)).exp() / (PI * volume * m2)
+                        * moduli[0][x]
+                        * moduli[1][y]
+                        * moduli[2][z];
+                    phi[at] *= g;
+                }
+            }
+        }
+        fft3(&mut phi, dims, true);
+
+        let energy = 0.5
+            * q.iter()
+                .zip(phi.iter())
+                .map(|(a, b)| a.re * b.re)
+                .sum::<f64>();
+        let forces = stencils
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                // the derivatives along the grid axes first, then u_d = K_d (b_d . r) turns them into cartesians.
+                let mut du = [0.0; 3];
+                for i0 in 0..p {
+                    for i1 in 0..p {
+                        for i2 in 0..p {
+                            let at = index(
+                                wrap(s.start[0] - i0 as i64, 0),
+                                wrap(s.start[1] - i1 as i64, 1),
+                                wrap(s.start[2] - i2 as i64, 2),
+                            );
+                            let v = phi[at].re * system.charges[i];
+                            du[0] += v * s.dw[0][i0] * s.w[1][i1] * s.w[2][i2] * dims[0] as f64;
+                            du[1] += v * s.w[0][i0] * s.dw[1][i1] * s.w[2][i2] * dims[1] as f64;
+                            du[2] += v * s.w[0][i0] * s.w[1][i1] * s.dw[2][i2] * dims[2] as f64;
+                        }
+                    }
+                }
+                [0, 1, 2].map(|x| -(du[0] * b[0][x] + du[1] * b[1][x] + du[2] * b[2][x]))
+            })
+            .collect();
+        (energy, forces)
+    }
+
+    fn evaluate(&self, system: &Charges, lattice: &Lattice) -> (f64, Vec<[f64; 3]>) {
+        let (real_energy, mut forces) =
+            real_space(system, lattice, self.alpha as f64, self.cutoff as f64);
+        let (recip_energy, recip_forces) = self.reciprocal(system, lattice);
+        for (f, g) in forces.iter_mut().zip(recip_forces) {
+            for k in 0..3 {
+                f[k] = (f[k] + g[k]) * self.coulomb as f64;
+            }
+        }
+        ((real_energy + recip_energy) * self.coulomb as f64, forces)
+    }
+}
+
+// 3D FFT one axis at a time.  The inverse isn't normalized, which is what the PME convolution wants.
+fn fft3(data: &mut [Complex<f64>], dims: [usize; 3], inverse: bool) {
+    let mut planner = FftPlanner::<f64>::new();
+    let strides = [dims[1] * dims[2], dims[2], 1];
+    for axis in 0..3 {
+        let fft = if inverse {
+            planner.plan_fft_inverse(dims[axis])
+        } else {
+            planner.plan_fft_forward(dims[axis])
+        };
+        let mut line = vec![Complex::new(0.0, 0.0); dims[axis]];
+        let (a, b) = match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        for i in 0..dims[a] {
+            for j in 0..dims[b] {
+                let offset = i * strides[a] + j * strides[b];
+                for k in 0..dims[axis] {
+                    line[k] = data[offset + k * strides[axis]];
+                }
+                fft.process(&mut line);
+                for k in 0..dims[axis] {
+                    data[offset + k * strides[axis]] = line[k];
+                }
+            }
+        }
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>> + HasCharge<f32>> ForceTerm<ParT> for PME {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let Some(lattice) = Lattice::new(world) else {
+            return HashMap::new();
+        };
+        let system = charges(world);
+        to_map(&system.names, self.evaluate(&system, &lattice).1)
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        let Some(lattice) = Lattice::new(world) else {
+            return 0.0;
+        };
+        self.evaluate(&charges(world), &lattice).0 as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dynamics::integrator::distance;
+    use crate::ForceFields::SIN::Elements;
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const MADELUNG_NACL: f32 = 1.747565;
+
+    // rock salt with the ions a unit distance apart, cells x cells x cells conventional cells.
+    fn nacl(cells: usize) -> Cell<Atom<Elements, f32, Vec<f32>>, f32> {
+        let mut particles = HashMap::new();
+        let n = 2 * cells;
+        for x in 0..n {
+            for y in 0..n {
+                for z in 0..n {
+                    let charge = if (x + y + z) % 2 == 0 { 1.0 } else { -1.0 };
+                    let atom = AtomBuilder::new()
+                        .element(Elements::X(0))
+                        .id(Some(format!("ion{x}-{y}-{z}")))
+                        .charge(charge)
+                        .position(vec![x as f32, y as f32, z as f32])
+                        .build();
+                    particles.insert(atom.id.clone(), atom);
+                }
+            }
+        }
+        let mut cell = Cell::new();
+        cell.set_particles(particles);
+        cell.set_periodic_box(Some(PeriodicBox::cubic(n as f32)));
+        cell
+    }
+
+    fn madelung(energy: f32, ions: usize) -> f32 {
+        // E = -(N / 2) M q^2 / r0
+        -2.0 * energy / ions as f32
+    }
+
+    #[test]
+    fn test_erfc() {
+        assert!((erfc(0.0) - 1.0).abs() < 1.0e-7);
+        assert!((erfc(1.0) - 0.157299207).abs() < 1.0e-7);
+        assert!((erfc(-1.0) - 1.842700793).abs() < 1.0e-7);
+        let alpha = ewald_alpha(2.0, 1.0e-5);
+        assert!((erfc(alpha as f64 * 2.0) / 2.0 - 1.0e-5).abs() < 1.0e-7);
+    }
+
+    #[test]
+    fn test_ewald_madelung() {
+        for cells in [1, 2] {
+            let cell = nacl(cells);
+            let l = 2.0 * cells as f32;
+            let ewald = Ewald::new(l / 2.0, 1.0e-6);
+            let energy = ewald.energy(&cell, 0.0);
+            let m = madelung(energy, cell.get_particles().len());
+            assert!((m - MADELUNG_NACL).abs() < 1.0e-4, "M = {m}");
+        }
+    }
+
+    #[test]
+    fn test_pme_madelung() {
+        let cell = nacl(2);
+        let pme = PME::new(2.0, 1.0e-6);
+        let m = madelung(pme.energy(&cell, 0.0), cell.get_particles().len());
+        assert!((m - MADELUNG_NACL).abs() < 1.0e-3, "M = {m}");
+        // every ion sits at a center of symmetry, so nothing should be pushed anywhere.
+        let mut pme = pme;
+        for force in pme.forces(&cell, 0.0).values() {
+            assert!(force.iter().all(|f| f.abs() < 1.0e-3));
+        }
+    }
+
+    fn random_melt() -> Cell<Atom<Elements, f32, Vec<f32>>, f32> {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut particles = HashMap::new();
+        for i in 0..16 {
+            let atom = AtomBuilder::new()
+                .element(Elements::X(0))
+                .id(Some(format!("ion{i:02}")))
+                .charge(if i % 2 == 0 { 1.0 } else { -1.0 })
+                .position((0..3).map(|_| rng.gen_range(0.0..5.0)).collect())
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::new();
+        cell.set_particles(particles);
+        cell.set_periodic_box(Some(PeriodicBox::cubic(5.0)));
+        cell
+    }
+
+    // the energy in f64, for finite differences.
+    fn exact(ewald: &Ewald, cell: &Cell<Atom<Elements, f32, Vec<f32>>, f32>) -> f64 {
+        ewald
+            .evaluate(&charges(cell), &Lattice::new(cell).unwrap())
+            .0
+    }
+
+    #[test]
+    fn test_ewald_forces_match_energy() {
+        let mut cell = random_melt();
+        let mut ewald = Ewald::new(2.5, 1.0e-6);
+        let forces = ewald.forces(&cell, 0.0);
+        let h = 1.0e-3;
+        for k in 0..3 {
+            cell.get_mut_particles().get_mut("ion03").unwrap().position[k] += h;
+            let up = exact(&ewald, &cell);
+            cell.get_mut_particles().get_mut("ion03").unwrap().position[k] -= 2.0 * h;
+            let down = exact(&ewald, &cell);
+            cell.get_mut_particles().get_mut("ion03").unwrap().position[k] += h;
+            let numerical = -(up - down) / (2.0 * h as f64);
+            assert!((numerical as f32 - forces["ion03"][k]).abs() < 1.0e-2);
+        }
+    }
+
+    #[test]
+    fn test_pme_against_ewald() {
+        let cell = random_melt();
+        let mut ewald = Ewald::new(2.5, 1.0e-6);
+        let mut pme = PME::new(2.5, 1.0e-6);
+        assert!((ewald.energy(&cell, 0.0) - pme.energy(&cell, 0.0)).abs() < 1.0e-3);
+        let (a, b) = (ewald.forces(&cell, 0.0), pme.forces(&cell, 0.0));
+        for name in a.keys() {
+            for k in 0..3 {
+                assert!((a[name][k] - b[name][k]).abs() < 1.0e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_from_the_world() {
+        // rock salt again, but as its two ion primitive cell: a properly skewed box.
+        let mut particles = HashMap::new();
+        for (name, charge, x) in [("na", 1.0, 0.0), ("cl", -1.0, 1.0)] {
+            let atom = AtomBuilder::new()
+                .element(Elements::X(0))
+                .id(Some(name.to_string()))
+                .charge(charge)
+                .position(vec![x, 0.0, 0.0])
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        cell.set_periodic_box(Some(PeriodicBox::triclinic(
+            [0.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 0.0],
+        )));
+        let ewald = Ewald::new(0.5, 1.0e-6);
+        let pme = PME::new(0.5, 1.0e-6);
+        for energy in [ewald.energy(&cell, 0.0), pme.energy(&cell, 0.0)] {
+            assert!(
+                (madelung(energy, 2) - MADELUNG_NACL).abs() < 1.0e-3,
+                "M = {}",
+                madelung(energy, 2)
+            );
+        }
+
+        // and a box that changes size (NPT, say) gets picked up on the next call.
+        let mut melt = random_melt();
+        let mut pme = PME::new(2.5, 1.0e-6);
+        let before = pme.energy(&melt, 0.0);
+        for atom in melt.get_mut_particles().values_mut() {
+            atom.position = atom.position.iter().map(|z| z * 1.2).collect();
+        }
+        melt.set_periodic_box(Some(PeriodicBox::cubic(6.0)));
+        let after = pme.energy(&melt, 0.0);
+        // everything's 1.2 times further apart, so with nothing cut off it'd be exactly 1/1.2 the energy.
+        assert!((after - before / 1.2).abs() < 1.0e-3, "{before} -> {after}");
+        let ewald = Ewald::new(2.5, 1.0e-6);
+        assert!((ewald.energy(&melt, 0.0) - after).abs() < 1.0e-3);
+        let forces = pme.forces(&melt, 0.0);
+        assert_eq!(forces.len(), 16);
+    }
+
+    #[test]
+    fn test_skewed_forces_match_energy() {
+        let mut cell = random_melt();
+        cell.set_periodic_box(Some(PeriodicBox::from_lattice(
+            5.0, 5.0, 5.0, 75.0, 80.0, 65.0,
+        )));
+        let mut ewald = Ewald::new(2.0, 1.0e-6);
+        let mut pme = PME::new(2.0, 1.0e-6);
+        let forces = ewald.forces(&cell, 0.0);
+        let mesh = pme.forces(&cell, 0.0);
+        assert!((ewald.energy(&cell, 0.0) - pme.energy(&cell, 0.0)).abs() < 1.0e-3);
+        let h = 1.0e-3;
+        for k in 0..3 {
+            cell.get_mut_particles().get_mut("ion03").unwrap().position[k] += h;
+            let up = exact(&ewald, &cell);
+            cell.get_mut_particles().get_mut("ion03").unwrap().position[k] -= 2.0 * h;
+            let down = exact(&ewald, &cell);
+            cell.get_mut_particles().get_mut("ion03").unwrap().position[k] += h;
+            let numerical = (-(up - down) / (2.0 * h as f64)) as f32;
+            assert!(
+                (numerical - forces["ion03"][k]).abs() < 2.0e-2,
+                "{numerical} vs {}",
+                forces["ion03"][k]
+            );
+            assert!((mesh["ion03"][k] - forces["ion03"][k]).abs() < 1.0e-2);
+        }
+    }
+
+    #[test]
+    fn test_excluded_pairs() {
+        // excluding a pair should take exactly its bare Coulomb interaction, at the nearest image, off the total.
+        let mut cell = random_melt();
+        let (a, b) = ("ion00".to_string(), "ion01".to_string());
+        let mut ewald = Ewald::new(2.5, 1.0e-6);
+        let mut pme = PME::new(2.5, 1.0e-6);
+        let full = ewald.energy(&cell, 0.0);
+        let atoms = cell.get_particles();
+        let d = distance(&atoms[&a], &atoms[&b], cell.get_periodic_box());
+        let r = d.iter().map(|z| z * z).sum::<f32>().sqrt();
+        cell.exclude(&a, &b);
+        let excluded = ewald.energy(&cell, 0.0);
+        assert!((excluded - (full + 1.0 / r)).abs() < 1.0e-3);
+        assert!((pme.energy(&cell, 0.0) - excluded).abs() < 1.0e-3);
+
+        let forces = ewald.forces(&cell, 0.0);
+        let mesh = pme.forces(&cell, 0.0);
+        let h = 1.0e-3;
+        for k in 0..3 {
+            cell.get_mut_particles().get_mut(&a).unwrap().position[k] += h;
+            let up = exact(&ewald, &cell);
+            cell.get_mut_particles().get_mut(&a).unwrap().position[k] -= 2.0 * h;
+            let down = exact(&ewald, &cell);
+            cell.get_mut_particles().get_mut(&a).unwrap().position[k] += h;
+            let numerical = (-(up - down) / (2.0 * h as f64)) as f32;
+            assert!((numerical - forces[&a][k]).abs() < 1.0e-2);
+            assert!((mesh[&a][k] - forces[&a][k]).abs() < 1.0e-2);
+        }
+    }
+
+    #[test]
+    fn test_no_box() {
+        let mut cell = random_melt();
+        cell.set_periodic_box(None);
+        let mut ewald = Ewald::new(2.5, 1.0e-6);
+        let mut pme = PME::new(2.5, 1.0e-6);
+        assert_eq!(ewald.energy(&cell, 0.0), 0.0);
+        assert_eq!(pme.energy(&cell, 0.0), 0.0);
+        assert!(ewald.forces(&cell, 0.0).is_empty());
+        assert!(pme.forces(&cell, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,345 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::{HasMass, HasPhysics};
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+// Self-gravity for N-body runs.  Everybody attracts everybody else with a softened 1/r^2 force,
+//   F_i = G m_i sum_j m_j (r_j - r_i) / (|r_j - r_i|^2 + eps^2)^(3/2)
+// where the softening eps keeps close encounters from blowing up the timestep.
+// Rather than doing all N^2 pairs, far away clumps get lumped together into their center of mass using an octree
+// (Barnes & Hut, Nature 324, 446 (1986)).  A cell of size s at distance d gets opened up when s/d > theta;
+// theta = 0 opens everything and gives back the direct sum.
+pub struct Gravity {
+    pub g: f32,
+    pub softening: f32,
+    pub theta: f32,
+}
+
+impl Gravity {
+    pub fn new(g: f32, softening: f32, theta: f32) -> Self {
+        Self {
+            g,
+            softening,
+            theta,
+        }
+    }
+
+    // Every pair, no tree.  Mostly for checking the tree against.
+    pub fn direct_forces<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>> {
+        let (names, bodies) = bodies(world);
+        let eps2 = self.softening * self.softening;
+        let mut forces = vec![Vector3::zeros(); bodies.len()];
+        for i in 0..bodies.len() {
+            for j in i + 1..bodies.len() {
+                let d = bodies[j].0 - bodies[i].0;
+                let r2 = d.norm_squared() + eps2;
+                let f = d * (self.g * bodies[i].1 * bodies[j].1 / (r2 * r2.sqrt()));
+                forces[i] += f;
+                forces[j] -= f;
+            }
+        }
+        collect(names, forces)
+    }
+
+    pub fn tree_forces<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>> {
+        let (names, bodies) = bodies(world);
+        let tree = Octree::new(&bodies);
+        let forces = (0..bodies.len())
+            .map(|i| tree.force(i, &bodies, self) * bodies[i].1)
+            .collect();
+        collect(names, forces)
+    }
+}
+
+fn bodies<ParT: HasPhysics<Vec<f32>> + HasMass<f32>>(
+    world: &dyn ContainsParticles<ParT>,
+) -> (Vec<String>, Vec<(Vector3<f32>, f32)>) {
+    let atoms = world.get_particles();
+    let mut names: Vec<String> = atoms.keys().cloned().collect();
+    names.sort();
+    let bodies = names
+        .iter()
+        .map(|name| {
+            let p = atoms[name].get_position();
+            (Vector3::new(p[0], p[1], p[2]), *atoms[name].get_mass())
+        })
+        .collect();
+    (names, bodies)
+}
+
+fn collect(names: Vec<String>, forces: Vec<Vector3<f32>>) -> HashMap<String, Vec<f32>> {
+    names
+        .into_iter()
+        .zip(forces)
+        .map(|(name, f)| (name, vec![f[0], f[1], f[2]]))
+        .collect()
+}
+
+// past this depth bodies just pile up in the same leaf; otherwise two particles sitting on top of each other
+// would have us splitting forever.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone)]
+struct Node {
+    center: Vector3<f32>, // geometric center of the cube
+    half: f32,            // half the side length
+    mass: f32,
+    com: Vector3<f32>, // weighted by mass, so it's divided through once the tree is built.
+    children: Option<[usize; 8]>,
+    bodies: Vec<usize>, // only leaves hold bodies.
+}
+
+impl Node {
+    fn new(center: Vector3<f32>, half: f32) -> Self {
+        Self {
+            center,
+            half,
+            mass: 0.0,
+            com: Vector3::zeros(),
+            children: None,
+            bodies: Vec::new(),
+        }
+    }
+
+    fn octant(&self, p: &Vector3<f32>) -> usize {
+        (p[0] > self.center[0]) as usize
+            | ((p[1] > self.center[1]) as usize) << 1
+            | ((p[2] > self.center[2]) as usize) << 2
+    }
+
+    // never lump a body in with itself.
+    fn contains(&self, p: &Vector3<f32>) -> bool {
+        (0..3).all(|k| (p[k] - self.center[k]).abs() <= self.half)
+    }
+}
+
+struct Octree {
+    nodes: Vec<Node>,
+}
+
+impl Octree {
+    fn new(bodies: &Vec<(Vector3<f32>, f32)>) -> Self {
+        let mut min = Vector3::repeat(f32::MAX);
+        let mut max = Vector3::repeat(f32::MIN);
+        for (p, _) in bodies.iter() {
+            min = min.inf(p);
+            max = max.sup(p);
+        }
+        let center = (min + max) * 0.5;
+        let half = ((max - min).max() * 0.5).max(1.0e-6) * 1.0001;
+        let mut tree = Self {
+            nodes: vec![Node::new(center, half)],
+        };
+        for i in 0..bodies.len() {
+            tree.insert(0, i, bodies, 0);
+        }
+        for node in tree.nodes.iter_mut() {
+            if node.mass > 0.0 {
+                node.com /= node.mass;
+            } else {
+                node.com = node.center;
+            }
+        }
+        tree
+    }
+
+    fn insert(
+        &mut self,
+        node: usize,
+        body: usize,
+        bodies: &Vec<(Vector3<f32>, f32)>,
+        depth: usize,
+    ) {
+        let (p, m) = bodies[body];
+        self.nodes[node].mass += m;
+        self.nodes[node].com += p * m;
+        if let Some(children) = self.nodes[node].children {
+            let child = children[self.nodes[node].octant(&p)];
+            self.insert(child, body, bodies, depth + 1);
+            return;
+        }
+        if self.nodes[node].bodies.is_empty() || depth >= MAX_DEPTH {
+            self.nodes[node].bodies.push(body);
+            return;
+        }
+        // an occupied leaf: split it and push everybody down a level.
+        let (center, half) = (self.nodes[node].center, self.nodes[node].half * 0.5);
+        let mut children = [0; 8];
+        for (octant, child) in children.iter_mut().enumerate() {
+            let offset = Vector3::new(
+                if octant & 1 == 1 { half } else { -half },
+                if octant & 2 == 2 { half } else { -half },
+                if octant & 4 == 4 { half } else { -half },
+            );
+            *child = self.nodes.len();
+            self.nodes.push(Node::new(center + offset, half));
+        }
+        self.nodes[node].children = Some(children);
+        // the masses up here already count everybody, so just walk them down into the children.
+        let residents = std::mem::take(&mut self.nodes[node].bodies);
+        for resident in residents.into_iter().chain(std::iter::once(body)) {
+            let child = children[self.nodes[node].octant(&bodies[resident].0)];
+            self.insert(child, resident, bodies, depth + 1);
+        }
+    }
+
+    // acceleration (force per unit mass) on body i
+    fn force(
+        &self,
+        i: usize,
+        bodies: &[(Vector3<f32>, f32)],
+        gravity: &Gravity,
+    ) -> Vector3<f32> {
+        let p = bodies[i].0;
+        let eps2 = gravity.softening * gravity.softening;
+        let pull = |target: Vector3<f32>, mass: f32| -> Vector3<f32> {
+            let d = target - p;
+            let r2 = d.norm_squared() + eps2;
+            d * (gravity.g * mass / (r2 * r2.sqrt()))
+        };
+        let mut acc = Vector3::zeros();
+        let mut stack = vec![0];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if node.mass == 0.0 {
+                continue;
+            }
+            match node.children {
+                None => {
+                    for &j in node.bodies.iter() {
+                        if j != i {
+                            acc += pull(bodies[j].0, bodies[j].1);
+                        }
+                    }
+                }
+                Some(children) => {
+                    let d = (node.com - p).norm();
+                    if d > 0.0 && 2.0 * node.half / d < gravity.theta && !node.contains(&p) {
+                        acc += pull(node.com, node.mass);
+                    } else {
+                        stack.extend(children.iter());
+                    }
+                }
+            }
+        }
+        acc
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>> + HasMass<f32>> ForceTerm<ParT> for Gravity {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        self.tree_forces(world)
+    }
+    // the energy is always done the slow, exact way; it's only for diagnostics.
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        let (_, bodies) = bodies(world);
+        let eps2 = self.softening * self.softening;
+        let mut energy = 0.0;
+        for i in 0..bodies.len() {
+            for j in i + 1..bodies.len() {
+                let r2 = (bodies[j].0 - bodies[i].0).norm_squared() + eps2;
+                energy -= self.g * bodies[i].1 * bodies[j].1 / r2.sqrt();
+            }
+        }
+        energy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ForceFields::SIN::Elements;
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn cluster(n: usize, seed: u64) -> Cell<Atom<Elements, f32, Vec<f32>>, f32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut particles = HashMap::new();
+        for i in 0..n {
+            // a lumpy cluster, so there's some actual structure in the tree.
+            let r = rng.gen_range(0.0_f32..1.0).powi(2) * 10.0;
+            let (cos_t, phi) = (
+                rng.gen_range(-1.0_f32..1.0),
+                rng.gen_range(0.0..std::f32::consts::TAU),
+            );
+            let sin_t = (1.0 - cos_t * cos_t).sqrt();
+            let atom = AtomBuilder::new()
+                .element(Elements::X(0))
+                .id(Some(format!("star{i}")))
+                .mass(rng.gen_range(0.5..2.0))
+                .position(vec![
+                    r * sin_t * phi.cos(),
+                    r * sin_t * phi.sin(),
+                    r * cos_t,
+                ])
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::new();
+        cell.set_particles(particles);
+        cell
+    }
+
+    fn errors(a: &HashMap<String, Vec<f32>>, b: &HashMap<String, Vec<f32>>) -> Vec<f32> {
+        a.keys()
+            .map(|name| {
+                let diff: f32 = (0..3).map(|k| (a[name][k] - b[name][k]).powi(2)).sum();
+                let size: f32 = (0..3).map(|k| b[name][k].powi(2)).sum();
+                (diff / size).sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_theta_zero_is_direct() {
+        let cell = cluster(100, 1);
+        let gravity = Gravity::new(1.0, 0.01, 0.0);
+        let error = errors(&gravity.tree_forces(&cell), &gravity.direct_forces(&cell));
+        assert!(error.iter().all(|&e| e < 1.0e-4));
+    }
+
+    #[test]
+    fn test_tree_against_direct_summation() {
+        for seed in 0..3 {
+            let cell = cluster(400, seed);
+            let gravity = Gravity::new(1.0, 0.05, 0.5);
+            let error = errors(&gravity.tree_forces(&cell), &gravity.direct_forces(&cell));
+            let mean = error.iter().sum::<f32>() / error.len() as f32;
+            assert!(mean < 5.0e-3, "mean relative error {mean}");
+            assert!(error.iter().cloned().fold(0.0, f32::max) < 5.0e-2);
+        }
+    }
+
+    #[test]
+    fn test_two_bodies() {
+        let mut cell = cluster(0, 0);
+        for (name, x) in [("a", 0.0), ("b", 2.0)] {
+            let atom = AtomBuilder::new()
+                .element(Elements::X(0))
+                .id(Some(name.to_string()))
+                .mass(3.0)
+                .position(vec![x, 0.0, 0.0])
+                .build();
+            cell.get_mut_particles().insert(atom.id.clone(), atom);
+        }
+        let mut gravity = Gravity::new(2.0, 0.0, 0.7);
+        let forces = gravity.forces(&cell, 0.0);
+        // G m m / r^2 = 2 * 9 / 4, pulling them together.
+        assert!((forces["a"][0] - 4.5).abs() < 1.0e-5);
+        assert!((forces["b"][0] + 4.5).abs() < 1.0e-5);
+        assert!((gravity.energy(&cell, 0.0) + 9.0).abs() < 1.0e-5);
+    }
+}
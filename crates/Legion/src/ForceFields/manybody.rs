@@ -0,0 +1,453 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::neighbors::CellList;
+use crate::Topology::particle::HasPhysics;
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// Many-body potentials for covalent solids, where how strongly two atoms bond depends on where everyone else around
+// them is.  Silicon mostly.  Both of these treat every particle as the same species.
+//   Stillinger-Weber:  a pair term plus a three-body term that wants every angle tetrahedral
+//                      (Stillinger & Weber, Phys. Rev. B 31, 5262 (1985)).
+//   Tersoff:           pair repulsion and attraction, with the attraction weakened the more neighbors there are
+//                      (Tersoff, Phys. Rev. B 38, 9902 (1988)).
+// Neither is a sum over pairs, so they're force terms rather than pair potentials; hand them to the integrators
+// alongside everything else.
+
+// Everyone's neighbors as vectors: (name, r_j - r_i, r).
+type Neighbors = HashMap<String, Vec<(String, Vector3<f32>, f32)>>;
+
+fn neighbors<ParT: HasPhysics<Vec<f32>>>(
+    world: &dyn ContainsParticles<ParT>,
+    cutoff: f32,
+) -> Neighbors {
+    CellList::new(cutoff)
+        .neighbors(world)
+        .into_iter()
+        .map(|(name, list)| {
+            let list = list
+                .into_iter()
+                .map(|(other, d, r)| (other, Vector3::from_vec(d), r))
+                .collect();
+            (name, list)
+        })
+        .collect()
+}
+
+// cos of the angle j-i-k, and its gradient with respect to j and k (i's is minus the sum).
+fn cos_gradient(rij: &Vector3<f32>, rik: &Vector3<f32>) -> (f32, Vector3<f32>, Vector3<f32>) {
+    let (lij, lik) = (rij.norm(), rik.norm());
+    let (uij, uik) = (rij / lij, rik / lik);
+    let cos = uij.dot(&uik);
+    (cos, (uik - cos * uij) / lij, (uij - cos * uik) / lik)
+}
+
+fn zeros<ParT>(world: &dyn ContainsParticles<ParT>) -> HashMap<String, Vec<f32>> {
+    world
+        .get_particles()
+        .keys()
+        .map(|name| (name.clone(), vec![0.0; 3]))
+        .collect()
+}
+
+fn add_force(forces: &mut HashMap<String, Vec<f32>>, name: &String, f: Vector3<f32>) {
+    let total = forces.get_mut(name).unwrap();
+    for k in 0..3 {
+        total[k] += f[k];
+    }
+}
+
+// E = sum_i<j phi2(r_ij) + sum_i sum_j<k phi3(r_ij, r_ik, theta_jik)
+//   phi2 = A eps (B (sigma/r)^p - (sigma/r)^q) exp(sigma / (r - a sigma))
+//   phi3 = lambda eps (cos theta - cos theta0)^2 exp(gamma sigma / (r_ij - a sigma)) exp(gamma sigma / (r_ik - a sigma))
+// Everything goes to zero (smoothly) at a sigma.
+pub struct StillingerWeber {
+    pub epsilon: f32,
+    pub sigma: f32,
+    pub a: f32,
+    pub lambda: f32,
+    pub gamma: f32,
+    pub big_a: f32,
+    pub big_b: f32,
+    pub p: i32,
+    pub q: i32,
+    pub cos_theta0: f32,
+}
+
+impl StillingerWeber {
+    // the original silicon parameters, in eV and Angstroms (A and B are 7.049556277 and 0.6022245584, to as many
+    // digits as an f32 holds).
+    pub fn silicon() -> Self {
+        Self {
+            epsilon: 2.1683,
+            sigma: 2.0951,
+            a: 1.80,
+            lambda: 21.0,
+            gamma: 1.20,
+            big_a: 7.049556,
+            big_b: 0.6022246,
+            p: 4,
+            q: 0,
+            cos_theta0: -1.0 / 3.0,
+        }
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        self.a * self.sigma
+    }
+
+    // phi2 and its slope.
+    fn two_body(&self, r: f32) -> (f32, f32) {
+        let (s, rc) = (self.sigma / r, self.cutoff());
+        let decay = (self.sigma / (r - rc)).exp();
+        let power = self.big_b * s.powi(self.p) - s.powi(self.q);
+        let power_slope =
+            (-(self.p as f32) * self.big_b * s.powi(self.p) + self.q as f32 * s.powi(self.q)) / r;
+        let decay_slope = -self.sigma / ((r - rc) * (r - rc));
+        let scale = self.big_a * self.epsilon * decay;
+        (scale * power, scale * (power_slope + power * decay_slope))
+    }
+
+    // exp(gamma sigma / (r - a sigma)) and its slope.
+    fn three_body_decay(&self, r: f32) -> (f32, f32) {
+        let gs = self.gamma * self.sigma;
+        let dr = r - self.cutoff();
+        let e = (gs / dr).exp();
+        (e, -e * gs / (dr * dr))
+    }
+
+    fn evaluate<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+        mut forces: Option<&mut HashMap<String, Vec<f32>>>,
+    ) -> f32 {
+        let neighbors = neighbors(world, self.cutoff());
+        let mut energy = 0.0;
+        for (i, list) in neighbors.iter() {
+            for (a, (j, rij, lij)) in list.iter().enumerate() {
+                // pairs once, from the side with the smaller name.
+                if i < j {
+                    let (u, slope) = self.two_body(*lij);
+                    energy += u;
+                    if let Some(forces) = forces.as_deref_mut() {
+                        add_force(forces, i, slope * rij / *lij);
+                        add_force(forces, j, -slope * rij / *lij);
+                    }
+                }
+                for (k, rik, lik) in list[a + 1..].iter() {
+                    let (cos, dcos_j, dcos_k) = cos_gradient(rij, rik);
+                    let ((ej, dej), (ek, dek)) =
+                        (self.three_body_decay(*lij), self.three_body_decay(*lik));
+                    let bend = cos - self.cos_theta0;
+                    let scale = self.lambda * self.epsilon;
+                    energy += scale * bend * bend * ej * ek;
+                    if let Some(forces) = forces.as_deref_mut() {
+                        let gj = scale
+                            * (2.0 * bend * ej * ek * dcos_j + bend * bend * ek * dej * rij / *lij);
+                        let gk = scale
+                            * (2.0 * bend * ej * ek * dcos_k + bend * bend * ej * dek * rik / *lik);
+                        add_force(forces, j, -gj);
+                        add_force(forces, k, -gk);
+                        add_force(forces, i, gj + gk);
+                    }
+                }
+            }
+        }
+        energy
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>>> ForceTerm<ParT> for StillingerWeber {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let mut forces = zeros(world);
+        self.evaluate(world, Some(&mut forces));
+        forces
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        self.evaluate(world, None)
+    }
+}
+
+// E = 1/2 sum_i sum_j!=i fc(r_ij) (A exp(-lambda1 r_ij) - b_ij B exp(-lambda2 r_ij))
+//   b_ij   = (1 + beta^n zeta_ij^n)^(-1/2n)
+//   zeta_ij = sum_k!=i,j fc(r_ik) g(theta_ijk) exp(lambda3^3 (r_ij - r_ik)^3)
+//   g      = gamma (1 + c^2/d^2 - c^2 / (d^2 + (h - cos theta)^2))
+//   fc     = 1 inside R - D, 0 past R + D, and half a sine wave in between.
+pub struct Tersoff {
+    pub a: f32,
+    pub b: f32,
+    pub lambda1: f32,
+    pub lambda2: f32,
+    pub lambda3: f32,
+    pub beta: f32,
+    pub n: f32,
+    pub c: f32,
+    pub d: f32,
+    pub h: f32,
+    pub gamma: f32,
+    pub radius: f32, // R
+    pub width: f32,  // D
+}
+
+impl Tersoff {
+    // Tersoff's Si(C), in eV and Angstroms.
+    pub fn silicon() -> Self {
+        Self {
+            a: 1830.8,
+            b: 471.18,
+            lambda1: 2.4799,
+            lambda2: 1.7322,
+            lambda3: 0.0,
+            beta: 1.1e-6,
+            n: 0.78734,
+            c: 1.0039e5,
+            d: 16.217,
+            h: -0.59825,
+            gamma: 1.0,
+            radius: 2.85,
+            width: 0.15,
+        }
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        self.radius + self.width
+    }
+
+    fn fc(&self, r: f32) -> (f32, f32) {
+        if r < self.radius - self.width {
+            (1.0, 0.0)
+        } else if r > self.radius + self.width {
+            (0.0, 0.0)
+        } else {
+            let x = 0.5 * PI * (r - self.radius) / self.width;
+            (0.5 - 0.5 * x.sin(), -0.25 * PI / self.width * x.cos())
+        }
+    }
+
+    // g(cos theta) and its slope.  c^2/d^2 - c^2/(d^2 + x^2) is written as one fraction, since c is huge and the two
+    // halves nearly cancel.
+    fn g(&self, cos: f32) -> (f32, f32) {
+        let (c2, d2) = (self.c * self.c, self.d * self.d);
+        let x = self.h - cos;
+        let denominator = d2 + x * x;
+        (
+            self.gamma * (1.0 + c2 * x * x / (d2 * denominator)),
+            -2.0 * self.gamma * c2 * x / (denominator * denominator),
+        )
+    }
+
+    // exp(lambda3^3 (r_ij - r_ik)^3) and its slope with respect to r_ij (it's minus that for r_ik).
+    fn stretch(&self, dr: f32) -> (f32, f32) {
+        let l3 = self.lambda3.powi(3);
+        let e = (l3 * dr.powi(3)).exp();
+        (e, 3.0 * l3 * dr * dr * e)
+    }
+
+    // b(zeta) and its slope.
+    fn bond_order(&self, zeta: f32) -> (f32, f32) {
+        if zeta <= 0.0 {
+            return (1.0, 0.0);
+        }
+        let bz = (self.beta * zeta).powf(self.n);
+        let b = (1.0 + bz).powf(-0.5 / self.n);
+        (b, -0.5 * b / (1.0 + bz) * bz / zeta)
+    }
+
+    fn evaluate<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+        mut forces: Option<&mut HashMap<String, Vec<f32>>>,
+    ) -> f32 {
+        let neighbors = neighbors(world, self.cutoff());
+        let mut energy = 0.0;
+        for (i, list) in neighbors.iter() {
+            for (j, rij, lij) in list.iter() {
+                let uij = rij / *lij;
+                // zeta, and each k's part of its gradient: (k, d/dr_j, d/dr_k).
+                let mut zeta = 0.0;
+                let mut gradients = Vec::new();
+                for (k, rik, lik) in list.iter().filter(|(k, _, _)| k != j) {
+                    let uik = rik / *lik;
+                    let (cos, dcos_j, dcos_k) = cos_gradient(rij, rik);
+                    let ((fc, dfc), (g, dg), (e, de)) =
+                        (self.fc(*lik), self.g(cos), self.stretch(lij - lik));
+                    zeta += fc * g * e;
+                    if forces.is_some() {
+                        let dj = fc * (dg * e * dcos_j + g * de * uij);
+                        let dk = dfc * g * e * uik + fc * (dg * e * dcos_k - g * de * uik);
+                        gradients.push((k, dj, dk));
+                    }
+                }
+                let (fc, dfc) = self.fc(*lij);
+                let repulsion = self.a * (-self.lambda1 * lij).exp();
+                let attraction = -self.b * (-self.lambda2 * lij).exp();
+                let (b, db) = self.bond_order(zeta);
+                energy += 0.5 * fc * (repulsion + b * attraction);
+                if let Some(forces) = forces.as_deref_mut() {
+                    let de_dr = 0.5
+                        * (dfc * (repulsion + b * attraction)
+                            + fc * (-self.lambda1 * repulsion - self.lambda2 * b * attraction));
+                    add_force(forces, i, de_dr * uij);
+                    add_force(forces, j, -de_dr * uij);
+                    let de_dzeta = 0.5 * fc * attraction * db;
+                    for (k, dj, dk) in gradients {
+                        add_force(forces, j, -de_dzeta * dj);
+                        add_force(forces, k, -de_dzeta * dk);
+                        add_force(forces, i, de_dzeta * (dj + dk));
+                    }
+                }
+            }
+        }
+        energy
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>>> ForceTerm<ParT> for Tersoff {
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let mut forces = zeros(world);
+        self.evaluate(world, Some(&mut forces));
+        forces
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        self.evaluate(world, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ForceFields::SIN::Elements;
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+    use crate::Topology::periodic::PeriodicBox;
+
+    type World = Cell<Atom<Elements, f32, Vec<f32>>, f32>;
+
+    fn world(positions: Vec<Vec<f32>>) -> World {
+        let mut particles = HashMap::new();
+        for (i, position) in positions.into_iter().enumerate() {
+            let atom = AtomBuilder::new()
+                .element(Elements::X(0))
+                .id(Some(format!("si{i}")))
+                .position(position)
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = World::new();
+        cell.set_particles(particles);
+        cell
+    }
+
+    // 2x2x2 unit cells of diamond silicon.
+    fn diamond(lattice: f32) -> World {
+        let basis = [
+            [0.0, 0.0, 0.0],
+            [0.0, 0.5, 0.5],
+            [0.5, 0.0, 0.5],
+            [0.5, 0.5, 0.0],
+        ];
+        let mut positions = Vec::new();
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    for b in basis {
+                        for shift in [0.0, 0.25] {
+                            positions.push(vec![
+                                (x as f32 + b[0] + shift) * lattice,
+                                (y as f32 + b[1] + shift) * lattice,
+                                (z as f32 + b[2] + shift) * lattice,
+                            ]);
+                        }
+                    }
+                }
+            }
+        }
+        let mut cell = world(positions);
+        cell.set_periodic_box(Some(PeriodicBox::cubic(2.0 * lattice)));
+        cell
+    }
+
+    // a squashed, bent little cluster, so that every term gets a workout.
+    fn cluster() -> World {
+        world(vec![
+            vec![0.0, 0.0, 0.0],
+            vec![2.3, 0.2, 0.1],
+            vec![-0.4, 2.2, 0.3],
+            vec![0.5, 0.6, 2.4],
+            vec![2.0, 2.1, 1.5],
+        ])
+    }
+
+    fn check_forces(term: &mut dyn ForceTerm<Atom<Elements, f32, Vec<f32>>>, mut cell: World) {
+        let forces = term.forces(&cell, 0.0);
+        let h = 5.0e-3;
+        let mut total = vec![0.0; 3];
+        for name in cell
+            .get_particles()
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>()
+        {
+            for k in 0..3 {
+                let start = cell.get_particles()[&name].get_position().clone();
+                let mut energies = Vec::new();
+                for step in [h, -h] {
+                    let mut moved = start.clone();
+                    moved[k] += step;
+                    cell.get_mut_particles()
+                        .get_mut(&name)
+                        .unwrap()
+                        .set_position(moved);
+                    energies.push(term.energy(&cell, 0.0));
+                }
+                cell.get_mut_particles()
+                    .get_mut(&name)
+                    .unwrap()
+                    .set_position(start);
+                let numerical = -(energies[0] - energies[1]) / (2.0 * h);
+                let f = forces[&name][k];
+                assert!(
+                    (numerical - f).abs() < 2.0e-2 * f.abs().max(1.0),
+                    "{name} {k}: numerical {numerical}, analytic {f}"
+                );
+                total[k] += f;
+            }
+        }
+        assert!(total.iter().all(|f| f.abs() < 1.0e-3));
+    }
+
+    #[test]
+    fn test_stillinger_weber() {
+        // the perfect crystal sits at -2 epsilon per atom, with nothing pushing anyone.
+        let mut sw = StillingerWeber::silicon();
+        let cell = diamond(5.431);
+        let energy = ForceTerm::<Atom<Elements, f32, Vec<f32>>>::energy(&sw, &cell, 0.0) / 64.0;
+        assert!((energy + 2.0 * sw.epsilon).abs() < 1.0e-2, "{energy}");
+        let forces = sw.forces(&cell, 0.0);
+        assert!(forces.values().flatten().all(|f| f.abs() < 1.0e-3));
+        check_forces(&mut sw, cluster());
+    }
+
+    #[test]
+    fn test_tersoff() {
+        // Si(C) gives a cohesive energy of 4.63 eV at 5.432 Angstroms.
+        let mut tersoff = Tersoff::silicon();
+        let cell = diamond(5.432);
+        let energy =
+            ForceTerm::<Atom<Elements, f32, Vec<f32>>>::energy(&tersoff, &cell, 0.0) / 64.0;
+        assert!((energy + 4.63).abs() < 1.0e-2, "{energy}");
+        check_forces(&mut tersoff, cluster());
+        // and again with the lambda3 part switched on.
+        tersoff.lambda3 = 1.3;
+        check_forces(&mut tersoff, cluster());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::ForceFields::potentials::LennardJones;
+use crate::ForceFields::SIN::{Elements, ForceField};
+use crate::Topology::atom::Atom;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Mixing rules!  Force fields give an (epsilon, sigma) for each element, and the pairs between different elements get
+// theirs by combining the two:
+//   Lorentz-Berthelot:  epsilon_ij = sqrt(epsilon_i epsilon_j),  sigma_ij = (sigma_i + sigma_j) / 2   (AMBER, CHARMM)
+//   Geometric:          epsilon_ij = sqrt(epsilon_i epsilon_j),  sigma_ij = sqrt(sigma_i sigma_j)      (OPLS)
+// Any pair can also be given its own parameters outright, which always wins over the mixing.
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MixingRule {
+    LorentzBerthelot,
+    Geometric,
+}
+
+impl MixingRule {
+    // (epsilon, sigma) for the pair.
+    pub fn mix(&self, a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+        let epsilon = (a.0 * b.0).sqrt();
+        match self {
+            MixingRule::LorentzBerthelot => (epsilon, 0.5 * (a.1 + b.1)),
+            MixingRule::Geometric => (epsilon, (a.1 * b.1).sqrt()),
+        }
+    }
+}
+
+// the same key either way round; elements only differ by symbol here.
+pub fn pair_key(e1: &Elements, e2: &Elements) -> (String, String) {
+    let (a, b) = (e1.symbol().to_string(), e2.symbol().to_string());
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PairParameters {
+    pub rule: MixingRule,
+    pub default: (f32, f32), // (epsilon, sigma) for any element that hasn't been given its own.
+    elements: HashMap<String, (f32, f32)>,
+    pairs: HashMap<(String, String), (f32, f32)>,
+}
+
+impl PairParameters {
+    pub fn new(rule: MixingRule) -> Self {
+        Self {
+            rule,
+            default: (1.0, 1.0),
+            elements: HashMap::new(),
+            pairs: HashMap::new(),
+        }
+    }
+
+    pub fn set_element(&mut self, element: &Elements, epsilon: f32, sigma: f32) {
+        self.elements
+            .insert(element.symbol().to_string(), (epsilon, sigma));
+    }
+
+    pub fn get_element(&self, element: &Elements) -> (f32, f32) {
+        *self.elements.get(element.symbol()).unwrap_or(&self.default)
+    }
+
+    // skips the mixing rule for this one pair.
+    pub fn set_pair(&mut self, e1: &Elements, e2: &Elements, epsilon: f32, sigma: f32) {
+        self.pairs.insert(pair_key(e1, e2), (epsilon, sigma));
+    }
+
+    pub fn get_pair(&self, e1: &Elements, e2: &Elements) -> (f32, f32) {
+        match self.pairs.get(&pair_key(e1, e2)) {
+            Some(parameters) => *parameters,
+            None => self.rule.mix(self.get_element(e1), self.get_element(e2)),
+        }
+    }
+}
+
+// Lennard-Jones between every pair, with the parameters coming out of a PairParameters.
+// Masses, charges and atoms come from the force field underneath, like the other wrappers.
+pub struct MixedForceField<FF> {
+    pub forcefield: FF,
+    pub parameters: PairParameters,
+}
+
+impl<FF> MixedForceField<FF> {
+    pub fn new(forcefield: FF, parameters: PairParameters) -> Self {
+        Self {
+            forcefield,
+            parameters,
+        }
+    }
+}
+
+impl<FF: ForceField<Elements, f32, Vec<f32>>> ForceField<Elements, f32, Vec<f32>>
+    for MixedForceField<FF>
+{
+    type Pair = LennardJones;
+    fn mass(&self, element: &Elements) -> f32 {
+        self.forcefield.mass(element)
+    }
+    fn charge(&self, element: &Elements) -> f32 {
+        self.forcefield.charge(element)
+    }
+    fn atom(&self, element: Elements) -> Atom<Elements, f32, Vec<f32>> {
+        self.forcefield.atom(element)
+    }
+    fn pair_potential(&self, e1: &Elements, e2: &Elements) -> LennardJones {
+        let (epsilon, sigma) = self.parameters.get_pair(e1, e2);
+        LennardJones::new(epsilon, sigma)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ForceFields::SIN::SIN;
+
+    #[test]
+    fn test_mixing_rules() {
+        let (a, b) = ((1.0, 2.0), (4.0, 8.0));
+        assert_eq!(MixingRule::LorentzBerthelot.mix(a, b), (2.0, 5.0));
+        assert_eq!(MixingRule::Geometric.mix(a, b), (2.0, 4.0));
+        // like with like is just itself either way.
+        assert_eq!(MixingRule::Geometric.mix(b, b), b);
+    }
+
+    #[test]
+    fn test_pair_overrides() {
+        let mut parameters = PairParameters::new(MixingRule::LorentzBerthelot);
+        parameters.set_element(&Elements::H(0), 0.25, 1.0);
+        parameters.set_element(&Elements::O(0), 1.0, 3.0);
+        assert_eq!(
+            parameters.get_pair(&Elements::H(0), &Elements::O(16)),
+            (0.5, 2.0)
+        );
+        parameters.set_pair(&Elements::O(0), &Elements::H(0), 0.1, 1.5);
+        assert_eq!(
+            parameters.get_pair(&Elements::H(1), &Elements::O(0)),
+            (0.1, 1.5)
+        );
+        // untouched elements get the default, and mix with it.
+        assert_eq!(
+            parameters.get_pair(&Elements::C(0), &Elements::C(0)),
+            (1.0, 1.0)
+        );
+        assert_eq!(
+            parameters.get_pair(&Elements::C(0), &Elements::O(0)),
+            (1.0, 2.0)
+        );
+
+        let ff = MixedForceField::new(
+            SIN::<Elements>::new(),
+            parameters,
+        );
+        assert_eq!(
+            ff.pair_potential(&Elements::H(0), &Elements::O(0)),
+            LennardJones::new(0.1, 1.5)
+        );
+        assert_eq!(ff.mass(&Elements::O(0)), 3.0);
+    }
+}
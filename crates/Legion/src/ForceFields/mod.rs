@@ -1,3 +1,27 @@
 // SIN!  Sorta INaccurate forcefield.
+// DPD!  Soft beads for mesoscale fluids.
+// gravity!  For the astronomers.
+// ewald!  Long range electrostatics, directly or on a mesh.
+// coulomb!  Cut off electrostatics for when ewald is too much.
+// SPH!  Splashy fluids.
+// cutoff!  Stopping pair interactions at some distance, gracefully.
+// potentials!  Lennard-Jones and friends, the parts force fields are built from.
+// EAM!  Embedded atoms, for metals.
+// manybody!  Stillinger-Weber and Tersoff, for silicon and friends.
+// mixing!  Per element parameters, and how pairs of different elements combine them.
+// definition!  Force fields read in from TOML or JSON files.
+// tabulated!  Pair potentials read in from files, for when there's no formula.
 
+pub mod coulomb;
+pub mod cutoff;
+pub mod definition;
+pub mod DPD;
+pub mod EAM;
+pub mod ewald;
+pub mod gravity;
+pub mod manybody;
+pub mod mixing;
+pub mod potentials;
 pub mod SIN;
+pub mod SPH;
+pub mod tabulated;
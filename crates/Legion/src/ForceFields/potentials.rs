@@ -0,0 +1,257 @@
+use serde::Deserialize;
+
+// Pair potentials!  The pieces force fields get built out of.  Each one is a plain struct holding its parameters, so they
+// cost nothing to make per pair, get dispatched statically, and can be tested on their own.
+// Forces are the size of -dU/dr along the line between the pair, so positive pushes them apart (same as always).
+
+pub trait PairPotential {
+    // (energy, force) at separation r.
+    fn evaluate(&self, r: f32) -> (f32, f32);
+    fn energy(&self, r: f32) -> f32 {
+        self.evaluate(r).0
+    }
+    fn force(&self, r: f32) -> f32 {
+        self.evaluate(r).1
+    }
+}
+
+// U = 4 epsilon ((sigma/r)^12 - (sigma/r)^6).  The minimum (-epsilon) is at 2^(1/6) sigma.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LennardJones {
+    pub epsilon: f32,
+    pub sigma: f32,
+}
+
+impl LennardJones {
+    pub fn new(epsilon: f32, sigma: f32) -> Self {
+        Self { epsilon, sigma }
+    }
+}
+
+impl PairPotential for LennardJones {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let s6 = (self.sigma / r).powi(6);
+        let s12 = s6 * s6;
+        (
+            4.0 * self.epsilon * (s12 - s6),
+            24.0 * self.epsilon * (2.0 * s12 - s6) / r,
+        )
+    }
+}
+
+// Bare Coulomb, U = qq / r, with whatever the Coulomb constant is in your units already folded into qq.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Coulomb {
+    pub qq: f32,
+}
+
+impl Coulomb {
+    pub fn new(qq: f32) -> Self {
+        Self { qq }
+    }
+}
+
+impl PairPotential for Coulomb {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        (self.qq / r, self.qq / (r * r))
+    }
+}
+
+// U = A exp(-r/rho) - C / r^6.  Good for ionic solids; watch out, it turns over and dives to -infinity at very short range.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Buckingham {
+    pub a: f32,
+    pub rho: f32,
+    pub c: f32,
+}
+
+impl Buckingham {
+    pub fn new(a: f32, rho: f32, c: f32) -> Self {
+        Self { a, rho, c }
+    }
+}
+
+impl PairPotential for Buckingham {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let repulsion = self.a * (-r / self.rho).exp();
+        let dispersion = self.c / r.powi(6);
+        (
+            repulsion - dispersion,
+            repulsion / self.rho - 6.0 * dispersion / r,
+        )
+    }
+}
+
+// U = D ((1 - exp(-alpha (r - r0)))^2 - 1), a well of depth D at r0 that goes to zero far away.  Bonds that can break.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Morse {
+    pub depth: f32,
+    pub alpha: f32,
+    pub r0: f32,
+}
+
+impl Morse {
+    pub fn new(depth: f32, alpha: f32, r0: f32) -> Self {
+        Self { depth, alpha, r0 }
+    }
+}
+
+impl PairPotential for Morse {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let e = (-self.alpha * (r - self.r0)).exp();
+        (
+            self.depth * ((1.0 - e).powi(2) - 1.0),
+            -2.0 * self.depth * self.alpha * e * (1.0 - e),
+        )
+    }
+}
+
+// U = k/2 (r - r0)^2, a spring.  Mostly for bonds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Harmonic {
+    pub k: f32,
+    pub r0: f32,
+}
+
+impl Harmonic {
+    pub fn new(k: f32, r0: f32) -> Self {
+        Self { k, r0 }
+    }
+}
+
+impl PairPotential for Harmonic {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let stretch = r - self.r0;
+        (0.5 * self.k * stretch * stretch, -self.k * stretch)
+    }
+}
+
+// Screened Coulomb, U = A exp(-kappa r) / r.  Colloids and plasmas; kappa is the inverse screening length.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Yukawa {
+    pub a: f32,
+    pub kappa: f32,
+}
+
+impl Yukawa {
+    pub fn new(a: f32, kappa: f32) -> Self {
+        Self { a, kappa }
+    }
+}
+
+impl PairPotential for Yukawa {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let u = self.a * (-self.kappa * r).exp() / r;
+        (u, u * (self.kappa + 1.0 / r))
+    }
+}
+
+// Weeks-Chandler-Andersen: just the repulsive part of Lennard-Jones, cut at the minimum and lifted up by epsilon so it
+// goes smoothly to zero there.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WCA {
+    pub epsilon: f32,
+    pub sigma: f32,
+}
+
+impl WCA {
+    pub fn new(epsilon: f32, sigma: f32) -> Self {
+        Self { epsilon, sigma }
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        2.0_f32.powf(1.0 / 6.0) * self.sigma
+    }
+}
+
+impl PairPotential for WCA {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        if r >= self.cutoff() {
+            return (0.0, 0.0);
+        }
+        let (u, f) = LennardJones::new(self.epsilon, self.sigma).evaluate(r);
+        (u + self.epsilon, f)
+    }
+}
+
+// U = epsilon (sigma / r)^n, purely repulsive.  n = 12 is the usual; n = 1 with sigma = 1 is plain 1/r.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoftSphere {
+    pub epsilon: f32,
+    pub sigma: f32,
+    pub n: i32,
+}
+
+impl SoftSphere {
+    pub fn new(epsilon: f32, sigma: f32, n: i32) -> Self {
+        Self { epsilon, sigma, n }
+    }
+}
+
+impl PairPotential for SoftSphere {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let u = self.epsilon * (self.sigma / r).powi(self.n);
+        (u, self.n as f32 * u / r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the force had better be minus the slope of the energy.
+    fn check_derivative(pair: &impl PairPotential, r: f32) {
+        let h = 1.0e-3;
+        let numerical = -(pair.energy(r + h) - pair.energy(r - h)) / (2.0 * h);
+        let force = pair.force(r);
+        assert!(
+            (numerical - force).abs() < 1.0e-2 * force.abs().max(1.0),
+            "at r = {r}: numerical {numerical}, analytic {force}"
+        );
+    }
+
+    #[test]
+    fn test_forces_match_energies() {
+        for r in [0.9, 1.1, 1.6, 2.4] {
+            check_derivative(&LennardJones::new(1.0, 1.0), r);
+            check_derivative(&Coulomb::new(-2.0), r);
+            check_derivative(&Buckingham::new(1000.0, 0.3, 10.0), r);
+            check_derivative(&Morse::new(2.0, 1.5, 1.2), r);
+            check_derivative(&Harmonic::new(100.0, 1.2), r);
+            check_derivative(&Yukawa::new(3.0, 0.8), r);
+            check_derivative(&WCA::new(1.0, 1.0), r);
+            check_derivative(&SoftSphere::new(1.0, 1.0, 12), r);
+        }
+    }
+
+    #[test]
+    fn test_minima() {
+        let lj = LennardJones::new(1.5, 2.0);
+        let r_min = 2.0_f32.powf(1.0 / 6.0) * 2.0;
+        assert!((lj.energy(r_min) + 1.5).abs() < 1.0e-5);
+        assert!(lj.force(r_min).abs() < 1.0e-5);
+        let morse = Morse::new(2.0, 1.5, 1.2);
+        assert_eq!(morse.evaluate(1.2), (-2.0, 0.0));
+        assert!(morse.energy(50.0).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_wca_is_continuous() {
+        let wca = WCA::new(1.0, 1.0);
+        let rc = wca.cutoff();
+        assert!(wca.energy(rc - 1.0e-4).abs() < 1.0e-4);
+        assert!(wca.force(rc - 1.0e-4).abs() < 1.0e-2);
+        assert_eq!(wca.evaluate(rc + 0.1), (0.0, 0.0));
+        // everywhere else inside it's Lennard-Jones, shifted.
+        let lj = LennardJones::new(1.0, 1.0);
+        assert!((wca.energy(0.95) - lj.energy(0.95) - 1.0).abs() < 1.0e-5);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::ForceFields::mixing::pair_key;
+use crate::ForceFields::potentials::PairPotential;
+use crate::ForceFields::SIN::{Elements, ForceField};
+use crate::Topology::atom::Atom;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+// Tabulated pair potentials, for when there's no formula: coarse-grained models out of iterative Boltzmann inversion,
+// potentials of mean force and so on.  Tables are plain text, one point per line:
+//   r  V(r)  F(r)
+// with F = -dV/dr as usual.  Blank lines and anything after a # are skipped, and extra columns are ignored.
+// In between the points it's a cubic Hermite spline through the energies with the tabulated forces as the slopes, so
+// it goes through every point exactly and the force handed back is always exactly minus the slope of the energy.
+// Inside the first point the force is held constant (so the wall keeps pushing), and past the last point it's all zero.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tabulated {
+    // (r, V, F) rows, shared so handing out a copy for every pair costs nothing.
+    pub rows: Arc<Vec<[f32; 3]>>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl Tabulated {
+    pub fn new(rows: Vec<[f32; 3]>) -> Result<Self, String> {
+        if rows.len() < 2 {
+            return Err(format!("need at least two points, got {}", rows.len()));
+        }
+        if let Some(i) = (1..rows.len()).find(|&i| rows[i][0] <= rows[i - 1][0]) {
+            return Err(format!(
+                "r has to keep increasing, but goes from {} to {} at point {}",
+                rows[i - 1][0],
+                rows[i][0],
+                i
+            ));
+        }
+        Ok(Self {
+            rows: Arc::new(rows),
+        })
+    }
+
+    // Nothing at all, for pairs that don't have a table.
+    pub fn zero() -> Self {
+        Self {
+            rows: Arc::new(Vec::new()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rows = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let columns: Result<Vec<f32>, _> =
+                line.split_whitespace().take(3).map(|x| x.parse()).collect();
+            match columns {
+                Ok(columns) if columns.len() == 3 => {
+                    rows.push([columns[0], columns[1], columns[2]])
+                }
+                _ => {
+                    return Err(format!(
+                        "line {}: expected r, V and F, got '{}'",
+                        number + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        Self::new(rows)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    // How far each tabulated force is from minus the slope of the tabulated energies.  The slopes come from the
+    // quadratic through each point and its neighbors (one sided at the ends), which is plenty for a table fine enough
+    // to be worth using.
+    pub fn force_errors(&self) -> Vec<f32> {
+        let rows = &self.rows;
+        let n = rows.len();
+        if n < 3 {
+            return rows
+                .iter()
+                .map(|row| {
+                    let slope = (rows[1][1] - rows[0][1]) / (rows[1][0] - rows[0][0]);
+                    (row[2] + slope).abs()
+                })
+                .collect();
+        }
+        (0..n)
+            .map(|i| {
+                let j = i.clamp(1, n - 2);
+                let ([x0, y0, _], [x1, y1, _], [x2, y2, _]) = (rows[j - 1], rows[j], rows[j + 1]);
+                let x = rows[i][0];
+                // derivative of the Lagrange quadratic through the three points, at x.
+                let slope = y0 * (2.0 * x - x1 - x2) / ((x0 - x1) * (x0 - x2))
+                    + y1 * (2.0 * x - x0 - x2) / ((x1 - x0) * (x1 - x2))
+                    + y2 * (2.0 * x - x0 - x1) / ((x2 - x0) * (x2 - x1));
+                (rows[i][2] + slope).abs()
+            })
+            .collect()
+    }
+
+    // Checks the forces actually go with the energies, relative to the size of the force (or 1, for small ones).
+    // Tables that got shifted, smoothed or have a typo in them usually don't.
+    pub fn validate(&self, tolerance: f32) -> Result<(), String> {
+        for (row, error) in self.rows.iter().zip(self.force_errors()) {
+            if error > tolerance * row[2].abs().max(1.0) {
+                return Err(format!(
+                    "at r = {} the tabulated force {} is off from -dV/dr by {}",
+                    row[0], row[2], error
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PairPotential for Tabulated {
+    fn evaluate(&self, r: f32) -> (f32, f32) {
+        let rows = &self.rows;
+        let Some(last) = rows.last() else {
+            return (0.0, 0.0);
+        };
+        if r > last[0] {
+            return (0.0, 0.0);
+        }
+        let [r0, v0, f0] = rows[0];
+        if r < r0 {
+            return (v0 + f0 * (r0 - r), f0);
+        }
+        // the segment [r_i, r_i+1] that r is in.
+        let i = rows
+            .partition_point(|row| row[0] <= r)
+            .clamp(1, rows.len() - 1)
+            - 1;
+        let ([ra, va, fa], [rb, vb, fb]) = (rows[i], rows[i + 1]);
+        let h = rb - ra;
+        let t = (r - ra) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        // slopes are -F.
+        let energy = (2.0 * t3 - 3.0 * t2 + 1.0) * va - (t3 - 2.0 * t2 + t) * h * fa
+            + (-2.0 * t3 + 3.0 * t2) * vb
+            - (t3 - t2) * h * fb;
+        let slope = (6.0 * t2 - 6.0 * t) * (va - vb) / h
+            - (3.0 * t2 - 4.0 * t + 1.0) * fa
+            - (3.0 * t2 - 2.0 * t) * fb;
+        (energy, -slope)
+    }
+}
+
+// Any force field, with tabulated pair interactions on top.  Tables go by element symbol (so C(12) and C(14) share
+// one) and either way round; pairs without a table don't interact.  The masses, charges and atoms still come from the
+// force field underneath.
+pub struct TabulatedForceField<FF> {
+    pub forcefield: FF,
+    pub tables: HashMap<(String, String), Tabulated>,
+}
+
+impl<FF> TabulatedForceField<FF> {
+    pub fn new(forcefield: FF) -> Self {
+        Self {
+            forcefield,
+            tables: HashMap::new(),
+        }
+    }
+
+    pub fn add_table(&mut self, e1: &Elements, e2: &Elements, table: Tabulated) {
+        self.tables.insert(pair_key(e1, e2), table);
+    }
+
+    pub fn table(&self, e1: &Elements, e2: &Elements) -> Option<&Tabulated> {
+        self.tables.get(&pair_key(e1, e2))
+    }
+}
+
+impl<FF: ForceField<Elements, f32, Vec<f32>>> ForceField<Elements, f32, Vec<f32>>
+    for TabulatedForceField<FF>
+{
+    type Pair = Tabulated;
+    fn mass(&self, element: &Elements) -> f32 {
+        self.forcefield.mass(element)
+    }
+    fn charge(&self, element: &Elements) -> f32 {
+        self.forcefield.charge(element)
+    }
+    fn atom(&self, element: Elements) -> Atom<Elements, f32, Vec<f32>> {
+        self.forcefield.atom(element)
+    }
+    fn pair_potential(&self, e1: &Elements, e2: &Elements) -> Tabulated {
+        self.table(e1, e2).cloned().unwrap_or_else(Tabulated::zero)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ForceFields::potentials::LennardJones;
+    use crate::ForceFields::SIN::SIN;
+
+    // Lennard-Jones written out as a table, the way a file would have it.
+    fn lj_table(spacing: f32) -> String {
+        let lj = LennardJones::new(1.0, 1.0);
+        let mut text = String::from("# r V F\n\n");
+        let mut r = 0.8;
+        while r <= 3.0 {
+            let (u, f) = lj.evaluate(r);
+            text += &format!("{r} {u} {f}  # extra columns are fine too\n");
+            r += spacing;
+        }
+        text
+    }
+
+    #[test]
+    fn test_spline_follows_lennard_jones() {
+        let table = Tabulated::parse(&lj_table(0.01)).unwrap();
+        assert!(table.validate(5.0e-2).is_ok());
+        let lj = LennardJones::new(1.0, 1.0);
+        for r in [0.853, 1.0, 1.1225, 1.57, 2.95] {
+            let ((u, f), (u_lj, f_lj)) = (table.evaluate(r), lj.evaluate(r));
+            assert!(
+                (u - u_lj).abs() < 1.0e-3 * u_lj.abs().max(1.0),
+                "energy at {r}"
+            );
+            assert!(
+                (f - f_lj).abs() < 1.0e-2 * f_lj.abs().max(1.0),
+                "force at {r}"
+            );
+            // and the force is exactly the slope of the interpolated energy.
+            let h = 1.0e-3;
+            let numerical = -(table.energy(r + h) - table.energy(r - h)) / (2.0 * h);
+            assert!((numerical - f).abs() < 1.0e-2 * f.abs().max(1.0));
+        }
+        // past the end it stops, and before the start it keeps pushing.
+        assert_eq!(table.evaluate(3.5), (0.0, 0.0));
+        assert_eq!(table.force(0.5), table.rows[0][2]);
+        assert!(table.energy(0.5) > table.rows[0][1]);
+    }
+
+    #[test]
+    fn test_bad_tables() {
+        assert!(Tabulated::parse("1.0 2.0 3.0\n").is_err());
+        assert!(Tabulated::parse("1.0 2.0 3.0\n0.5 1.0 1.0\n").is_err());
+        assert!(Tabulated::parse("1.0 2.0\n2.0 1.0\n").is_err());
+        // forces that don't go with the energies get caught.
+        let mut text = lj_table(0.01);
+        text += "3.1 0.0 5.0\n";
+        let table = Tabulated::parse(&text).unwrap();
+        assert!(table.validate(5.0e-2).is_err());
+    }
+
+    #[test]
+    fn test_tables_for_element_pairs() {
+        let sin = SIN::<Elements>::new();
+        let mut ff = TabulatedForceField::new(sin);
+        // a file of its own, so tests running side by side don't trip over each other.
+        let path =
+            std::env::temp_dir().join(format!("legion_lj_table_{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, lj_table(0.05)).unwrap();
+        let table = Tabulated::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let table = table.unwrap();
+        ff.add_table(&Elements::H(0), &Elements::O(0), table.clone());
+        assert_eq!(ff.pair_potential(&Elements::O(0), &Elements::H(0)), table);
+        // isotopes and charge states share the element's table.
+        assert_eq!(ff.pair_potential(&Elements::O(1), &Elements::H(2)), table);
+        assert_eq!(
+            ff.pair_potential(&Elements::H(0), &Elements::H(0))
+                .evaluate(1.0),
+            (0.0, 0.0)
+        );
+        assert_eq!(ff.mass(&Elements::H(0)), 1.0);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::Dynamics::forces::ForceTerm;
+use crate::Sampling::colvars::CollectiveVariable;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::HasPhysics;
+use std::collections::HashMap;
+use std::io::Write;
+
+// A bias is a potential energy that only depends on the value of a collective variable.
+// Everything here is in reduced units; temperatures are really kT (Boltzmann's constant is 1).
+pub trait Bias {
+    fn energy(&self, s: f32) -> f32;
+    // dU/ds
+    fn derivative(&self, s: f32) -> f32;
+    // called once per force evaluation so that biases which change over time (metadynamics!) can do so.
+    fn update(&mut self, _s: f32) {}
+    // periodic CVs need their differences wrapped; BiasedVariable hands this over from the CV.
+    fn set_period(&mut self, period: Option<f32>);
+}
+
+// the shortest signed difference between two CV values.
+pub fn difference(a: f32, b: f32, period: Option<f32>) -> f32 {
+    let d = a - b;
+    match period {
+        Some(p) => d - p * (d / p).round(),
+        None => d,
+    }
+}
+
+// Umbrella sampling restraint: U = k/2 (s - s0)^2
+pub struct HarmonicRestraint {
+    pub k: f32,
+    pub center: f32,
+    period: Option<f32>,
+}
+
+impl HarmonicRestraint {
+    pub fn new(k: f32, center: f32) -> Self {
+        Self {
+            k,
+            center,
+            period: None,
+        }
+    }
+}
+
+impl Bias for HarmonicRestraint {
+    fn energy(&self, s: f32) -> f32 {
+        0.5 * self.k * difference(s, self.center, self.period).powi(2)
+    }
+    fn derivative(&self, s: f32) -> f32 {
+        self.k * difference(s, self.center, self.period)
+    }
+    fn set_period(&mut self, period: Option<f32>) {
+        self.period = period;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hill {
+    pub center: f32,
+    pub height: f32,
+}
+
+// The bias tabulated on a regular grid so that it can be written out and plotted.
+#[derive(Debug, Clone)]
+pub struct BiasGrid {
+    pub min: f32,
+    pub max: f32,
+    pub values: Vec<f32>,
+}
+
+impl BiasGrid {
+    pub fn new(min: f32, max: f32, bins: usize) -> Self {
+        Self {
+            min,
+            max,
+            values: vec![0.0; bins],
+        }
+    }
+
+    pub fn point(&self, bin: usize) -> f32 {
+        let spacing = (self.max - self.min) / (self.values.len() - 1).max(1) as f32;
+        self.min + spacing * bin as f32
+    }
+
+    // writes "s V(s)" pairs, one per line.
+    pub fn dump(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(writer, "# s bias")?;
+        for (bin, v) in self.values.iter().enumerate() {
+            writeln!(writer, "{} {}", self.point(bin), v)?;
+        }
+        Ok(())
+    }
+}
+
+// (Well-tempered) metadynamics: Gaussian hills get dropped on the CV every `pace` evaluations.
+// With a bias factor the hill heights shrink as the bias grows (Barducci, Bussi & Parrinello, PRL 100, 020603 (2008)).
+pub struct Metadynamics {
+    pub height: f32,
+    pub width: f32,
+    pub pace: usize,
+    pub temperature: f32,
+    pub bias_factor: Option<f32>,
+    pub hills: Vec<Hill>,
+    pub grid: BiasGrid,
+    period: Option<f32>,
+    calls: usize,
+}
+
+pub struct MetadynamicsBuilder {
+    pub height: Option<f32>,
+    pub width: Option<f32>,
+    pub pace: Option<usize>,
+    pub temperature: Option<f32>,
+    pub bias_factor: Option<f32>,
+    pub grid: Option<BiasGrid>,
+}
+
+impl MetadynamicsBuilder {
+    pub fn new() -> Self {
+        Self {
+            height: None,
+            width: None,
+            pace: None,
+            temperature: None,
+            bias_factor: None,
+            grid: None,
+        }
+    }
+    pub fn height(mut self, height: f32) -> Self {
+        self.height = Some(height);
+        self
+    }
+    pub fn width(mut self, width: f32) -> Self {
+        self.width = Some(width);
+        self
+    }
+    pub fn pace(mut self, pace: usize) -> Self {
+        self.pace = Some(pace);
+        self
+    }
+    // turns on well-tempering; without this it's plain old metadynamics.
+    pub fn well_tempered(mut self, bias_factor: f32, temperature: f32) -> Self {
+        self.bias_factor = Some(bias_factor);
+        self.temperature = Some(temperature);
+        self
+    }
+    pub fn grid(mut self, min: f32, max: f32, bins: usize) -> Self {
+        self.grid = Some(BiasGrid::new(min, max, bins));
+        self
+    }
+    pub fn build(self) -> Metadynamics {
+        Metadynamics {
+            height: self.height.unwrap_or(1.0),
+            width: self.width.unwrap_or(0.1),
+            pace: self.pace.unwrap_or(500).max(1),
+            temperature: self.temperature.unwrap_or(1.0),
+            bias_factor: self.bias_factor,
+            hills: Vec::new(),
+            grid: self.grid.unwrap_or_else(|| BiasGrid::new(0.0, 1.0, 101)),
+            period: None,
+            calls: 0,
+        }
+    }
+}
+
+impl Default for MetadynamicsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metadynamics {
+    fn gaussian(&self, s: f32, hill: &Hill) -> f32 {
+        let d = difference(s, hill.center, self.period);
+        hill.height * (-d * d / (2.0 * self.width * self.width)).exp()
+    }
+
+    pub fn deposit(&mut self, s: f32) {
+        let height = match self.bias_factor {
+            Some(gamma) => {
+                self.height * (-self.energy(s) / (self.temperature * (gamma - 1.0))).exp()
+            }
+            None => self.height,
+        };
+        let hill = Hill { center: s, height };
+        for bin in 0..self.grid.values.len() {
+            let v = self.gaussian(self.grid.point(bin), &hill);
+            self.grid.values[bin] += v;
+        }
+        self.hills.push(hill);
+    }
+
+    // For well-tempered runs the bias converges to -(1 - 1/gamma) F(s), so flip it back around.
+    pub fn free_energy(&self, s: f32) -> f32 {
+        match self.bias_factor {
+            Some(gamma) => -self.energy(s) * gamma / (gamma - 1.0),
+            None => -self.energy(s),
+        }
+    }
+}
+
+impl Bias for Metadynamics {
+    fn energy(&self, s: f32) -> f32 {
+        self.hills.iter().map(|hill| self.gaussian(s, hill)).sum()
+    }
+    fn derivative(&self, s: f32) -> f32 {
+        self.hills
+            .iter()
+            .map(|hill| {
+                -self.gaussian(s, hill) * difference(s, hill.center, self.period)
+                    / (self.width * self.width)
+            })
+            .sum()
+    }
+    fn update(&mut self, s: f32) {
+        if self.calls.is_multiple_of(self.pace) {
+            self.deposit(s);
+        }
+        self.calls += 1;
+    }
+    fn set_period(&mut self, period: Option<f32>) {
+        self.period = period;
+    }
+}
+
+// Glues a bias onto a CV so that it can be used as a force term: F = -dU/ds * ds/dx
+pub struct BiasedVariable<CvT, BiasT> {
+    pub cv: CvT,
+    pub bias: BiasT,
+}
+
+impl<CvT, BiasT: Bias> BiasedVariable<CvT, BiasT> {
+    pub fn new(cv: CvT, mut bias: BiasT) -> Self
+    where
+        CvT: CollectiveVariable,
+    {
+        bias.set_period(cv.period());
+        Self { cv, bias }
+    }
+}
+
+impl<ParT, CvT, BiasT> ForceTerm<ParT> for BiasedVariable<CvT, BiasT>
+where
+    ParT: HasPhysics<Vec<f32>>,
+    CvT: CollectiveVariable,
+    BiasT: Bias,
+{
+    fn forces(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+        _time: f32,
+    ) -> HashMap<String, Vec<f32>> {
+        let s = self.cv.value(world);
+        self.bias.update(s);
+        let du = self.bias.derivative(s);
+        let mut forces = self.cv.gradient(world);
+        for force in forces.values_mut() {
+            for z in force.iter_mut() {
+                *z *= -du;
+            }
+        }
+        forces
+    }
+    fn energy(&self, world: &dyn ContainsParticles<ParT>, _time: f32) -> f32 {
+        self.bias.energy(self.cv.value(world))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ForceFields::SIN::Elements;
+    use crate::Sampling::colvars::{Dihedral, Distance};
+    use crate::Topology::atom::{Atom, AtomBuilder};
+    use crate::Topology::cell::Cell;
+
+    #[test]
+    fn test_harmonic_restraint() {
+        let umbrella = HarmonicRestraint::new(10.0, 1.0);
+        assert_eq!(umbrella.energy(1.0), 0.0);
+        assert!((umbrella.energy(2.0) - 5.0).abs() < 1.0e-6);
+        assert!((umbrella.derivative(0.5) + 5.0).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_periodic_difference() {
+        let p = Some(2.0 * std::f32::consts::PI);
+        assert!((difference(3.0, -3.0, p) - (6.0 - 2.0 * std::f32::consts::PI)).abs() < 1.0e-5);
+        assert_eq!(difference(3.0, -3.0, None), 6.0);
+    }
+
+    #[test]
+    fn test_well_tempered_hills_shrink() {
+        let mut metad = MetadynamicsBuilder::new()
+            .height(1.0)
+            .width(0.2)
+            .pace(1)
+            .well_tempered(10.0, 1.0)
+            .grid(-1.0, 1.0, 21)
+            .build();
+        for _ in 0..5 {
+            metad.update(0.0);
+        }
+        assert_eq!(metad.hills.len(), 5);
+        for pair in metad.hills.windows(2) {
+            assert!(pair[1].height < pair[0].height);
+        }
+        // the grid should agree with summing the hills; bin 10 sits right on s = 0.
+        assert!((metad.grid.values[10] - metad.energy(0.0)).abs() < 1.0e-4);
+        let mut out = Vec::new();
+        metad.grid.dump(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 22);
+    }
+
+    #[test]
+    fn test_metadynamics_pace() {
+        let mut metad = MetadynamicsBuilder::new().pace(3).build();
+        for _ in 0..7 {
+            metad.update(0.5);
+        }
+        assert_eq!(metad.hills.len(), 3);
+    }
+
+    #[test]
+    fn test_biased_variable_forces() {
+        let mut particles = HashMap::new();
+        for (name, x) in [("a", 0.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+            let atom = AtomBuilder::<Elements, f32, Vec<f32>>::new()
+                .element(Elements::H(0))
+                .id(Some(name.to_string()))
+                .position(vec![x, 0.0, x * x])
+                .build();
+            particles.insert(atom.id.clone(), atom);
+        }
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        cell.set_particles(particles);
+        let cv = Distance {
+            a: vec!["a".to_string()],
+            b: vec!["b".to_string()],
+        };
+        let mut umbrella = BiasedVariable::new(cv, HarmonicRestraint::new(1.0, 1.0));
+        let forces = umbrella.forces(&cell, 0.0);
+        // stretched past the center, so the restraint pulls the atoms together.
+        assert!(forces["a"][0] > 0.0);
+        assert!(forces["b"][0] < 0.0);
+        assert!((forces["a"][0] + forces["b"][0]).abs() < 1.0e-6);
+
+        // dihedrals hand their period over to the bias.
+        let cv = Dihedral {
+            a: vec!["a".to_string()],
+            b: vec!["b".to_string()],
+            c: vec!["c".to_string()],
+            d: vec!["d".to_string()],
+        };
+        let metad = BiasedVariable::new(cv, MetadynamicsBuilder::new().build());
+        assert_eq!(metad.bias.period, Some(2.0 * std::f32::consts::PI));
+    }
+}
@@ -0,0 +1,386 @@
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::HasPhysics;
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+// A collective variable (CV) is just some scalar function of the atomic positions; a distance, an angle, etc.
+// Selections are given as lists of particle ids, and a group of atoms is represented by its geometric center.
+pub trait CollectiveVariable {
+    fn value<ParT: HasPhysics<Vec<f32>>>(&self, world: &dyn ContainsParticles<ParT>) -> f32;
+    // ds/dx for every particle the CV depends on.
+    fn gradient<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>>;
+    // angles wrap around!  Biases need to know this when measuring how far apart two CV values are.
+    fn period(&self) -> Option<f32> {
+        None
+    }
+}
+
+fn position<ParT: HasPhysics<Vec<f32>>>(
+    world: &dyn ContainsParticles<ParT>,
+    name: &String,
+) -> Vector3<f32> {
+    let pos = world.get_particles()[name].get_position();
+    Vector3::new(pos[0], pos[1], pos[2])
+}
+
+pub fn centroid<ParT: HasPhysics<Vec<f32>>>(
+    world: &dyn ContainsParticles<ParT>,
+    group: &[String],
+) -> Vector3<f32> {
+    let mut center = Vector3::zeros();
+    for name in group.iter() {
+        center += position(world, name);
+    }
+    center / group.len() as f32
+}
+
+// spreads the gradient with respect to a group center evenly over the atoms in the group.
+fn add_group_gradient(gradient: &mut HashMap<String, Vec<f32>>, group: &[String], g: Vector3<f32>) {
+    let g = g / group.len() as f32;
+    for name in group.iter() {
+        let entry = gradient.entry(name.clone()).or_insert_with(|| vec![0.0; 3]);
+        for i in 0..3 {
+            entry[i] += g[i];
+        }
+    }
+}
+
+pub struct Distance {
+    pub a: Vec<String>,
+    pub b: Vec<String>,
+}
+
+pub struct Angle {
+    pub a: Vec<String>,
+    pub b: Vec<String>, // the vertex
+    pub c: Vec<String>,
+}
+
+pub struct Dihedral {
+    pub a: Vec<String>,
+    pub b: Vec<String>,
+    pub c: Vec<String>,
+    pub d: Vec<String>,
+}
+
+pub struct RadiusOfGyration {
+    pub atoms: Vec<String>,
+}
+
+// The usual switching function s = (1 - (r/r0)^n) / (1 - (r/r0)^m), summed over every pair between the two selections.
+pub struct CoordinationNumber {
+    pub a: Vec<String>,
+    pub b: Vec<String>,
+    pub r0: f32,
+    pub n: i32,
+    pub m: i32,
+}
+
+impl CoordinationNumber {
+    pub fn new(a: Vec<String>, b: Vec<String>, r0: f32) -> Self {
+        Self {
+            a,
+            b,
+            r0,
+            n: 6,
+            m: 12,
+        }
+    }
+
+    // returns the switching function and its derivative with respect to r.
+    fn switch(&self, r: f32) -> (f32, f32) {
+        let mut x = r / self.r0;
+        // the function is 0/0 at x == 1; nudge it off so the limit does the right thing.
+        if (x - 1.0).abs() < 1.0e-4 {
+            x += 1.0e-4;
+        }
+        let num = 1.0 - x.powi(self.n);
+        let den = 1.0 - x.powi(self.m);
+        let dnum = -(self.n as f32) * x.powi(self.n - 1);
+        let dden = -(self.m as f32) * x.powi(self.m - 1);
+        let s = num / den;
+        let ds = (dnum * den - num * dden) / (den * den) / self.r0;
+        (s, ds)
+    }
+}
+
+impl CollectiveVariable for Distance {
+    fn value<ParT: HasPhysics<Vec<f32>>>(&self, world: &dyn ContainsParticles<ParT>) -> f32 {
+        (centroid(world, &self.a) - centroid(world, &self.b)).norm()
+    }
+    fn gradient<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>> {
+        let d = centroid(world, &self.a) - centroid(world, &self.b);
+        let u = d / d.norm();
+        let mut gradient = HashMap::new();
+        add_group_gradient(&mut gradient, &self.a, u);
+        add_group_gradient(&mut gradient, &self.b, -u);
+        gradient
+    }
+}
+
+impl CollectiveVariable for Angle {
+    fn value<ParT: HasPhysics<Vec<f32>>>(&self, world: &dyn ContainsParticles<ParT>) -> f32 {
+        let b = centroid(world, &self.b);
+        let u = centroid(world, &self.a) - b;
+        let v = centroid(world, &self.c) - b;
+        u.angle(&v)
+    }
+    fn gradient<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>> {
+        let b = centroid(world, &self.b);
+        let u = centroid(world, &self.a) - b;
+        let v = centroid(world, &self.c) - b;
+        let (nu, nv) = (u.norm(), v.norm());
+        let cos = (u.dot(&v) / (nu * nv)).clamp(-1.0, 1.0);
+        // the gradient blows up at 0 and 180 degrees; there's not a lot we can do about that.
+        let sin = (1.0 - cos * cos).sqrt().max(1.0e-6);
+        let da = -(v / (nu * nv) - u * (cos / (nu * nu))) / sin;
+        let dc = -(u / (nu * nv) - v * (cos / (nv * nv))) / sin;
+        let mut gradient = HashMap::new();
+        add_group_gradient(&mut gradient, &self.a, da);
+        add_group_gradient(&mut gradient, &self.b, -da - dc);
+        add_group_gradient(&mut gradient, &self.c, dc);
+        gradient
+    }
+}
+
+impl Dihedral {
+    fn vectors<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let a = centroid(world, &self.a);
+        let b = centroid(world, &self.b);
+        let c = centroid(world, &self.c);
+        let d = centroid(world, &self.d);
+        (b - a, c - b, d - c)
+    }
+}
+
+impl CollectiveVariable for Dihedral {
+    // IUPAC convention; runs from -pi to pi.
+    fn value<ParT: HasPhysics<Vec<f32>>>(&self, world: &dyn ContainsParticles<ParT>) -> f32 {
+        let (b1, b2, b3) = self.vectors(world);
+        let n1 = b1.cross(&b2);
+        let n2 = b2.cross(&b3);
+        (b2.norm() * b1.dot(&n2)).atan2(n1.dot(&n2))
+    }
+    fn gradient<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>> {
+        // Blondel & Karplus, J. Comput. Chem. 17, 1132 (1996).
+        let (b1, b2, b3) = self.vectors(world);
+        let n1 = b1.cross(&b2);
+        let n2 = b2.cross(&b3);
+        let nb2 = b2.norm();
+        let da = -n1 * (nb2 / n1.norm_squared());
+        let dd = n2 * (nb2 / n2.norm_squared());
+        let p = b1.dot(&b2) / (nb2 * nb2);
+        let q = b3.dot(&b2) / (nb2 * nb2);
+        let db = da * (-p - 1.0) + dd * q;
+        let dc = dd * (-q - 1.0) + da * p;
+        let mut gradient = HashMap::new();
+        add_group_gradient(&mut gradient, &self.a, da);
+        add_group_gradient(&mut gradient, &self.b, db);
+        add_group_gradient(&mut gradient, &self.c, dc);
+        add_group_gradient(&mut gradient, &self.d, dd);
+        gradient
+    }
+    fn period(&self) -> Option<f32> {
+        Some(2.0 * std::f32::consts::PI)
+    }
+}
+
+impl CollectiveVariable for RadiusOfGyration {
+    fn value<ParT: HasPhysics<Vec<f32>>>(&self, world: &dyn ContainsParticles<ParT>) -> f32 {
+        let center = centroid(world, &self.atoms);
+        let sum: f32 = self
+            .atoms
+            .iter()
+            .map(|name| (position(world, name) - center).norm_squared())
+            .sum();
+        (sum / self.atoms.len() as f32).sqrt()
+    }
+    fn gradient<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>> {
+        // the center moves too, but those terms sum to zero so we can ignore them.
+        let center = centroid(world, &self.atoms);
+        let rg = self.value(world);
+        let n = self.atoms.len() as f32;
+        let mut gradient = HashMap::new();
+        for name in self.atoms.iter() {
+            let g = (position(world, name) - center) / (n * rg);
+            gradient.insert(name.clone(), vec![g[0], g[1], g[2]]);
+        }
+        gradient
+    }
+}
+
+impl CollectiveVariable for CoordinationNumber {
+    fn value<ParT: HasPhysics<Vec<f32>>>(&self, world: &dyn ContainsParticles<ParT>) -> f32 {
+        let mut s = 0.0;
+        for i in self.a.iter() {
+            for j in self.b.iter().filter(|j| *j != i) {
+                s += self
+                    .switch((position(world, i) - position(world, j)).norm())
+                    .0;
+            }
+        }
+        s
+    }
+    fn gradient<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<f32>> {
+        let mut gradient = HashMap::new();
+        for i in self.a.iter() {
+            for j in self.b.iter().filter(|j| *j != i) {
+                let d = position(world, i) - position(world, j);
+                let r = d.norm();
+                let (_, ds) = self.switch(r);
+                let g = d * (ds / r);
+                add_group_gradient(&mut gradient, std::slice::from_ref(i), g);
+                add_group_gradient(&mut gradient, std::slice::from_ref(j), -g);
+            }
+        }
+        gradient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{carbon, world, World};
+
+    // carbons named by their index.
+    fn carbons(positions: Vec<Vec<f32>>) -> World {
+        world(
+            positions
+                .into_iter()
+                .enumerate()
+                .map(|(i, pos)| carbon(&i.to_string(), pos).build()),
+        )
+    }
+
+    fn ids(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    // compares the analytical gradient against central differences.
+    fn check_gradient(cv: &impl CollectiveVariable, cell: &mut World) {
+        let h = 1.0e-2;
+        let gradient = cv.gradient(cell);
+        let names: Vec<String> = cell.get_particles().keys().cloned().collect();
+        for name in names.iter() {
+            for i in 0..3 {
+                let original = cell.get_particles()[name].position[i];
+                cell.get_mut_particles().get_mut(name).unwrap().position[i] = original + h;
+                let up = cv.value(cell);
+                cell.get_mut_particles().get_mut(name).unwrap().position[i] = original - h;
+                let down = cv.value(cell);
+                cell.get_mut_particles().get_mut(name).unwrap().position[i] = original;
+                let numerical = (up - down) / (2.0 * h);
+                let analytical = gradient.get(name).map_or(0.0, |g| g[i]);
+                assert!(
+                    (numerical - analytical).abs() < 1.0e-2,
+                    "{name}[{i}]: {numerical} vs {analytical}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_distance() {
+        let mut cell = carbons(vec![
+            vec![0.0, 0.0, 0.0],
+            vec![2.0, 0.0, 0.0],
+            vec![0.0, 3.0, 0.0],
+            vec![0.0, 5.0, 0.0],
+        ]);
+        let cv = Distance {
+            a: ids(&["0", "1"]),
+            b: ids(&["2", "3"]),
+        };
+        assert!((cv.value(&cell) - 17.0_f32.sqrt()).abs() < 1.0e-5);
+        check_gradient(&cv, &mut cell);
+    }
+
+    #[test]
+    fn test_angle() {
+        let mut cell = carbons(vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.3],
+        ]);
+        let cv = Angle {
+            a: ids(&["0"]),
+            b: ids(&["1"]),
+            c: ids(&["2"]),
+        };
+        assert!((cv.value(&cell) - std::f32::consts::FRAC_PI_2).abs() < 1.0e-5);
+        check_gradient(&cv, &mut cell);
+    }
+
+    #[test]
+    fn test_dihedral() {
+        let mut cell = carbons(vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+            vec![0.0, 1.0, 1.0],
+        ]);
+        let cv = Dihedral {
+            a: ids(&["0"]),
+            b: ids(&["1"]),
+            c: ids(&["2"]),
+            d: ids(&["3"]),
+        };
+        assert!((cv.value(&cell) - std::f32::consts::FRAC_PI_2).abs() < 1.0e-5);
+        check_gradient(&cv, &mut cell);
+        // and trans is pi.
+        cell.get_mut_particles().get_mut("3").unwrap().position = vec![-1.0, 0.0, 1.0];
+        assert!((cv.value(&cell).abs() - std::f32::consts::PI).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn test_radius_of_gyration() {
+        let mut cell = carbons(vec![
+            vec![1.0, 0.0, 0.0],
+            vec![-1.0, 0.0, 0.0],
+            vec![0.0, 0.5, 2.0],
+        ]);
+        let cv = RadiusOfGyration {
+            atoms: ids(&["0", "1"]),
+        };
+        assert!((cv.value(&cell) - 1.0).abs() < 1.0e-5);
+        let cv = RadiusOfGyration {
+            atoms: ids(&["0", "1", "2"]),
+        };
+        check_gradient(&cv, &mut cell);
+    }
+
+    #[test]
+    fn test_coordination_number() {
+        let mut cell = carbons(vec![
+            vec![0.0, 0.0, 0.0],
+            vec![0.5, 0.0, 0.0],
+            vec![0.0, 3.0, 0.0],
+        ]);
+        let cv = CoordinationNumber::new(ids(&["0"]), ids(&["1", "2"]), 1.0);
+        // one close neighbor, one far away.
+        let s = cv.value(&cell);
+        assert!(s > 0.9 && s < 1.1);
+        check_gradient(&cv, &mut cell);
+    }
+}
@@ -0,0 +1,4 @@
+// Enhanced sampling!  Collective variables and the biases we hang off of them.
+
+pub mod bias;
+pub mod colvars;
@@ -9,6 +9,7 @@ use uuid::Uuid;
 
 pub trait HasElement<EleT> {
     fn get_element(&self) -> &EleT;
+    fn set_element(&mut self, element: EleT);
 }
 
 pub trait Connected<VecT: IntoIterator> {
@@ -19,7 +20,7 @@ pub trait Connected<VecT: IntoIterator> {
 }
 
 pub trait Atomic<EleT, NumT, VecT: IntoIterator<Item = NumT>>:
-    HasPhysics<VecT> + HasElement<EleT> + Connected<Vec<String>>
+    HasPhysics<VecT> + HasElement<EleT> + Connected<Vec<String>> + HasMass<NumT> + HasCharge<NumT>
 {
 }
 
@@ -126,6 +127,9 @@ impl<EleT, NumT, VecT: IntoIterator<Item = NumT>> HasElement<EleT>
     fn get_element(&self) -> &EleT {
         return &self.element;
     }
+    fn set_element(&mut self, element: EleT) {
+        self.element = element;
+    }
 }
 
 impl<EleT, NumT, VecT: IntoIterator<Item = NumT>> Connected<Vec<String>>
@@ -147,16 +151,19 @@ impl<EleT, NumT, VecT: IntoIterator<Item = NumT>> HasMass<NumT> for Atom<EleT, N
     fn set_mass(&mut self, mass: NumT) {
         self.mass = mass;
     }
+    fn get_mass(&self) -> &NumT {
+        return &self.mass;
+    }
 }
 
 impl<EleT, NumT, VecT: IntoIterator<Item = NumT>> HasCharge<NumT>
     for Atom<EleT, NumT, VecT>
 {
-    fn force(&self) {
-        todo!()
+    fn set_charge(&mut self, charge: NumT) {
+        self.charge = charge;
     }
-    fn set_charge(&self, charge: NumT) {
-        todo!()
+    fn get_charge(&self) -> &NumT {
+        return &self.charge;
     }
 }
 
@@ -1,17 +1,47 @@
+use crate::Topology::particle::HasPhysics;
+use crate::Topology::periodic::PeriodicBox;
 use num_traits::{Float, Zero, float::FloatCore};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 // #[derive(Debug)]
 
 pub trait ContainsParticles<ParT> {
     fn get_particles(&self) -> &HashMap<String, ParT>;
     fn get_mut_particles(&mut self) -> &mut HashMap<String, ParT>;
+    // worlds without a box are just open space.
+    fn get_periodic_box(&self) -> Option<&PeriodicBox> {
+        None
+    }
+    // how many box vectors a particle has been wrapped by, along each one.
+    fn get_image(&self, _name: &String) -> Option<&Vec<i32>> {
+        None
+    }
+    // pairs that the non-bonded interactions should skip.
+    fn is_excluded(&self, _a: &String, _b: &String) -> bool {
+        false
+    }
 }
 
 pub struct Cell<ParT, NumT> {
     particles: HashMap<String, ParT>,
     time: NumT,
     dimensions: u32,
+    // pairs that shouldn't see each other's non-bonded interactions (usually because they're bonded).
+    exclusions: HashSet<(String, String)>,
+    periodic_box: Option<PeriodicBox>,
+    // image counters, so trajectories can be unwrapped after the fact (diffusion and the like).
+    images: HashMap<String, Vec<i32>>,
+    // particles the integrators leave exactly where they are (walls and the like).  They still push on everyone else.
+    frozen: HashSet<String>,
+}
+
+// exclusions don't care which way round the pair is.
+fn ordered_pair(a: &String, b: &String) -> (String, String) {
+    if a < b {
+        (a.clone(), b.clone())
+    } else {
+        (b.clone(), a.clone())
+    }
 }
 
 impl<ParT> Cell<ParT, f32> {
@@ -20,12 +50,92 @@ impl<ParT> Cell<ParT, f32> {
             particles: HashMap::<String, ParT>::new(),
             time: Zero::zero(),
             dimensions: 3,
+            exclusions: HashSet::new(),
+            periodic_box: None,
+            images: HashMap::new(),
+            frozen: HashSet::new(),
         }
     }
 
     pub fn set_particles(&mut self, particles: HashMap<String, ParT>) {
         self.particles = particles;
     }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn exclude(&mut self, a: &String, b: &String) {
+        self.exclusions.insert(ordered_pair(a, b));
+    }
+
+    pub fn include(&mut self, a: &String, b: &String) {
+        self.exclusions.remove(&ordered_pair(a, b));
+    }
+
+    pub fn is_excluded(&self, a: &String, b: &String) -> bool {
+        self.exclusions.contains(&ordered_pair(a, b))
+    }
+
+    pub fn get_exclusions(&self) -> &HashSet<(String, String)> {
+        &self.exclusions
+    }
+
+    pub fn freeze(&mut self, name: &str) {
+        self.frozen.insert(name.to_string());
+    }
+
+    pub fn unfreeze(&mut self, name: &str) {
+        self.frozen.remove(name);
+    }
+
+    pub fn is_frozen(&self, name: &String) -> bool {
+        self.frozen.contains(name)
+    }
+
+    // None turns the periodic boundaries back off.  Image counters are kept, so a box can be resized (NPT) mid run.
+    pub fn set_periodic_box(&mut self, periodic_box: Option<PeriodicBox>) {
+        self.periodic_box = periodic_box;
+    }
+}
+
+impl<ParT: HasPhysics<Vec<f32>>> Cell<ParT, f32> {
+    // Moves everyone back inside the box, keeping count of how many times each particle has crossed it.
+    pub fn wrap_positions(&mut self) {
+        let Some(periodic) = self.periodic_box.as_ref() else {
+            return;
+        };
+        for (name, particle) in self.particles.iter_mut() {
+            let (wrapped, shifts) = periodic.wrap(particle.get_position());
+            if shifts.iter().all(|&n| n == 0) {
+                continue;
+            }
+            particle.set_position(wrapped);
+            let image = self
+                .images
+                .entry(name.clone())
+                .or_insert_with(|| vec![0; shifts.len()]);
+            for (i, n) in image.iter_mut().zip(shifts.iter()) {
+                *i += n;
+            }
+        }
+    }
+
+    // where the particle would be if it had never been wrapped.
+    pub fn unwrapped_position(&self, name: &String) -> Option<Vec<f32>> {
+        let mut position = self.particles.get(name)?.get_position().clone();
+        if let (Some(periodic), Some(image)) = (self.periodic_box.as_ref(), self.images.get(name)) {
+            let moved = periodic.translation(image);
+            for i in 0..position.len().min(moved.len()) {
+                position[i] += moved[i];
+            }
+        }
+        Some(position)
+    }
 }
 
 impl<ParT, NumT> ContainsParticles<ParT> for Cell<ParT, NumT> {
@@ -35,6 +145,15 @@ impl<ParT, NumT> ContainsParticles<ParT> for Cell<ParT, NumT> {
     fn get_particles(&self) -> &HashMap<String, ParT> {
         return &self.particles;
     }
+    fn get_periodic_box(&self) -> Option<&PeriodicBox> {
+        self.periodic_box.as_ref()
+    }
+    fn get_image(&self, name: &String) -> Option<&Vec<i32>> {
+        self.images.get(name)
+    }
+    fn is_excluded(&self, a: &String, b: &String) -> bool {
+        self.exclusions.contains(&ordered_pair(a, b))
+    }
 }
 
 #[cfg(test)]
@@ -47,24 +166,46 @@ mod tests {
     fn test_create_cell() {
         let cell = Cell::<Atom<Elements, f64, Vec<f64>>, f64>::new();
         assert_eq!(cell.dimensions, 3);
-        let SinFF = SIN::<Elements> {
-            description: "SIN".to_string(),
-            particle_type: Vec::new(),
-        };
+        let SinFF = SIN::<Elements>::new();
         assert_eq!(SinFF.description, "SIN".to_string());
     }
 
     #[test]
     fn test_get_and_set_particles() {
         let mut cell = Cell::<Atom<Elements, f64, Vec<f64>>, f64>::new();
-        let SinFF = SIN::<Elements> {
-            description: "SIN".to_string(),
-            particle_type: Vec::new(),
-        };
+        let SinFF = SIN::<Elements>::new();
         let atom = SinFF.atom(Elements::H(0));
         let mut particles = HashMap::<String, Atom<Elements, f64, Vec<f64>>>::new();
         particles.insert(atom.id.clone(), atom);
         cell.set_particles(particles.clone());
         assert_eq!(*cell.get_particles(), particles);
     }
+
+    #[test]
+    fn test_exclusions() {
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        let (a, b) = ("a".to_string(), "b".to_string());
+        cell.exclude(&a, &b);
+        assert!(cell.is_excluded(&b, &a));
+        cell.include(&b, &a);
+        assert!(!cell.is_excluded(&a, &b));
+    }
+
+    #[test]
+    fn test_wrap_and_unwrap() {
+        use crate::Topology::atom::AtomBuilder;
+        let mut cell = Cell::<Atom<Elements, f32, Vec<f32>>, f32>::new();
+        let atom = AtomBuilder::new()
+            .element(Elements::H(0))
+            .id(Some("a".to_string()))
+            .position(vec![11.5, -0.5, 2.0])
+            .build();
+        cell.set_particles(HashMap::from([(atom.id.clone(), atom)]));
+        cell.set_periodic_box(Some(PeriodicBox::cubic(5.0)));
+        cell.wrap_positions();
+        let name = "a".to_string();
+        assert_eq!(cell.get_particles()[&name].position, vec![1.5, 4.5, 2.0]);
+        assert_eq!(cell.get_image(&name), Some(&vec![2, -1, 0]));
+        assert_eq!(cell.unwrapped_position(&name), Some(vec![11.5, -0.5, 2.0]));
+    }
 }
@@ -1,3 +1,5 @@
 pub mod atom;
 pub mod particle;
 pub mod cell;
+pub mod neighbors;
+pub mod periodic;
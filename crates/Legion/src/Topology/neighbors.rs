@@ -0,0 +1,348 @@
+use crate::Dynamics::integrator::distance;
+use crate::Topology::cell::ContainsParticles;
+use crate::Topology::particle::HasPhysics;
+use crate::Topology::periodic::PeriodicBox;
+use std::collections::{HashMap, HashSet};
+
+// Finding out who's close to whom, for the non-bonded interactions.  This is kept apart from the bonded connectivity
+// (the neighbors on each atom): those are a fixed graph, these get rediscovered from the positions as things move.
+// Excluded pairs (see Cell::exclude) never show up.
+
+// One nearby pair: the two names (in order), their separation a - b and its length.
+pub type Pair = (String, String, Vec<f32>, f32);
+
+// which bin a position falls in.
+type Binning<'a> = Box<dyn Fn(&Vec<f32>) -> [i64; 3] + 'a>;
+
+// Cell lists.  Space gets chopped into bins at least a cutoff wide, every particle goes into a bin, and then only
+// particles in the same or adjacent bins get checked.  O(N) rather than O(N^2).  Under periodic boundaries the bins are
+// slices of the box in fractional coordinates, and wrap around at the edges.  In open space the bins are just a grid
+// anchored at the origin, and only the occupied ones are kept, so a few particles far apart don't cost a huge grid.
+pub struct CellList {
+    pub cutoff: f32,
+}
+
+impl CellList {
+    pub fn new(cutoff: f32) -> Self {
+        Self { cutoff }
+    }
+
+    // bins per side (periodic only, where they wrap) and a function from a position to its bin.
+    fn grid<'a>(&self, periodic: Option<&'a PeriodicBox>) -> (Option<[i64; 3]>, Binning<'a>) {
+        let cutoff = self.cutoff;
+        match periodic {
+            Some(periodic) => {
+                let widths = periodic.widths();
+                let counts = [0, 1, 2].map(|k| ((widths[k] / cutoff).floor() as i64).max(1));
+                let bin = move |p: &Vec<f32>| {
+                    let s = periodic.fractional(p);
+                    [0, 1, 2]
+                        .map(|k| ((s[k] * counts[k] as f32).floor() as i64).rem_euclid(counts[k]))
+                };
+                (Some(counts), Box::new(bin))
+            }
+            None => {
+                let bin = move |p: &Vec<f32>| [0, 1, 2].map(|k| (p[k] / cutoff).floor() as i64);
+                (None, Box::new(bin))
+            }
+        }
+    }
+
+    // Every pair closer than the cutoff, each once and sorted by name.
+    pub fn pairs<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> Vec<Pair> {
+        let atoms = world.get_particles();
+        let periodic = world.get_periodic_box();
+        let mut names: Vec<&String> = atoms.keys().collect();
+        names.sort();
+        let positions: Vec<&Vec<f32>> = names.iter().map(|n| atoms[*n].get_position()).collect();
+        let (counts, bin) = self.grid(periodic);
+
+        let mut bins: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        let mut homes = Vec::with_capacity(names.len());
+        for (i, p) in positions.iter().enumerate() {
+            let b = bin(p);
+            bins.entry(b).or_default().push(i);
+            homes.push(b);
+        }
+
+        let mut pairs = Vec::new();
+        for i in 0..names.len() {
+            let b = homes[i];
+            // with fewer than three bins across a periodic box, the same bin turns up more than once.
+            let mut adjacent = HashSet::<[i64; 3]>::new();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let mut other = [b[0] + dx, b[1] + dy, b[2] + dz];
+                        if let Some(counts) = counts {
+                            other = [0, 1, 2].map(|k| other[k].rem_euclid(counts[k]));
+                        }
+                        adjacent.insert(other);
+                    }
+                }
+            }
+            for other in adjacent {
+                let Some(members) = bins.get(&other) else {
+                    continue;
+                };
+                for &j in members.iter() {
+                    if j <= i {
+                        continue;
+                    }
+                    let (a, b) = (names[i], names[j]);
+                    if world.is_excluded(a, b) {
+                        continue;
+                    }
+                    let d = distance(&atoms[a], &atoms[b], periodic);
+                    let r = d.iter().map(|z| z * z).sum::<f32>().sqrt();
+                    if r < self.cutoff {
+                        pairs.push((a.clone(), b.clone(), d, r));
+                    }
+                }
+            }
+        }
+        pairs.sort_by(|x, y| (&x.0, &x.1).cmp(&(&y.0, &y.1)));
+        pairs
+    }
+
+    // Everyone's neighbors, both ways round: for each particle, the others inside the cutoff with their separation
+    // (neighbor minus particle) and distance.  Many-body potentials want it this way rather than as pairs.
+    pub fn neighbors<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> HashMap<String, Vec<(String, Vec<f32>, f32)>> {
+        let mut neighbors: HashMap<String, Vec<(String, Vec<f32>, f32)>> = world
+            .get_particles()
+            .keys()
+            .map(|name| (name.clone(), Vec::new()))
+            .collect();
+        for (a, b, d, r) in self.pairs(world) {
+            let minus: Vec<f32> = d.iter().map(|z| -z).collect();
+            neighbors.get_mut(&a).unwrap().push((b.clone(), minus, r));
+            neighbors.get_mut(&b).unwrap().push((a, d, r));
+        }
+        neighbors
+    }
+}
+
+// Verlet lists: remember everyone within cutoff + skin, and keep reusing that list until someone has moved far enough
+// (half the skin; two particles heading straight at each other close the gap twice as fast) that a pair from outside
+// the list could have made it inside the cutoff.  Then rebuild it from a cell list.
+pub struct VerletList {
+    pub cutoff: f32,
+    pub skin: f32,
+    pub rebuilds: usize,
+    candidates: Vec<(String, String)>,
+    reference: HashMap<String, Vec<f32>>,
+    reference_box: Option<PeriodicBox>,
+}
+
+impl VerletList {
+    pub fn new(cutoff: f32, skin: f32) -> Self {
+        Self {
+            cutoff,
+            skin,
+            rebuilds: 0,
+            candidates: Vec::new(),
+            reference: HashMap::new(),
+            reference_box: None,
+        }
+    }
+
+    // Does the list need rebuilding?  Yes if it's never been built, particles came or went, the box changed (NPT)
+    // or someone has moved more than half the skin since the last build.
+    pub fn is_stale<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> bool {
+        let atoms = world.get_particles();
+        if self.reference.len() != atoms.len()
+            || world.get_periodic_box() != self.reference_box.as_ref()
+        {
+            return true;
+        }
+        let limit = 0.25 * self.skin * self.skin;
+        atoms
+            .iter()
+            .any(|(name, atom)| match self.reference.get(name) {
+                None => true,
+                Some(old) => {
+                    let mut d: Vec<f32> = (0..old.len())
+                        .map(|k| atom.get_position()[k] - old[k])
+                        .collect();
+                    if let Some(periodic) = world.get_periodic_box() {
+                        periodic.minimum_image(&mut d);
+                    }
+                    d.iter().map(|z| z * z).sum::<f32>() > limit
+                }
+            })
+    }
+
+    pub fn build<ParT: HasPhysics<Vec<f32>>>(&mut self, world: &dyn ContainsParticles<ParT>) {
+        self.candidates = CellList::new(self.cutoff + self.skin)
+            .pairs(world)
+            .into_iter()
+            .map(|(a, b, _, _)| (a, b))
+            .collect();
+        self.reference = world
+            .get_particles()
+            .iter()
+            .map(|(name, atom)| (name.clone(), atom.get_position().clone()))
+            .collect();
+        self.reference_box = world.get_periodic_box().cloned();
+        self.rebuilds += 1;
+    }
+
+    // rebuilds if it has to; returns whether it did.
+    pub fn update<ParT: HasPhysics<Vec<f32>>>(
+        &mut self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> bool {
+        let stale = self.is_stale(world);
+        if stale {
+            self.build(world);
+        }
+        stale
+    }
+
+    // The pairs inside the cutoff right now, out of the remembered candidates.  Only right if the list isn't stale.
+    pub fn pairs<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> Vec<Pair> {
+        let atoms = world.get_particles();
+        let periodic = world.get_periodic_box();
+        let mut pairs = Vec::new();
+        for (a, b) in self.candidates.iter() {
+            let (Some(pa), Some(pb)) = (atoms.get(a), atoms.get(b)) else {
+                continue;
+            };
+            // exclusions can change between rebuilds (reactions).
+            if world.is_excluded(a, b) {
+                continue;
+            }
+            let d = distance(pa, pb, periodic);
+            let r = d.iter().map(|z| z * z).sum::<f32>().sqrt();
+            if r < self.cutoff {
+                pairs.push((a.clone(), b.clone(), d, r));
+            }
+        }
+        pairs
+    }
+
+    // The pairs inside the cutoff for anyone who can't rebuild the list (a force term's energy only gets &self): off
+    // the list if it's still good, otherwise out of a one off cell list.
+    pub fn pairs_or_fresh<ParT: HasPhysics<Vec<f32>>>(
+        &self,
+        world: &dyn ContainsParticles<ParT>,
+    ) -> Vec<Pair> {
+        if self.is_stale(world) {
+            CellList::new(self.cutoff).pairs(world)
+        } else {
+            self.pairs(world)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{carbon, world, World};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // n atoms dropped at random into a cube of the given size.
+    fn scattered(n: usize, size: f32, seed: u64) -> World {
+        let mut rng = StdRng::seed_from_u64(seed);
+        world((0..n).map(|i| {
+            let position = (0..3).map(|_| rng.gen_range(0.0..size)).collect();
+            carbon(&format!("atom{i:04}"), position).build()
+        }))
+    }
+
+    fn brute_force(cell: &World, cutoff: f32) -> Vec<(String, String)> {
+        let atoms = cell.get_particles();
+        let mut names: Vec<&String> = atoms.keys().collect();
+        names.sort();
+        let mut pairs = Vec::new();
+        for (i, a) in names.iter().enumerate() {
+            for b in names[i + 1..].iter() {
+                let d = distance(&atoms[*a], &atoms[*b], cell.get_periodic_box());
+                if d.iter().map(|z| z * z).sum::<f32>().sqrt() < cutoff && !cell.is_excluded(a, b) {
+                    pairs.push(((*a).clone(), (*b).clone()));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn names(pairs: Vec<Pair>) -> Vec<(String, String)> {
+        pairs.into_iter().map(|(a, b, _, _)| (a, b)).collect()
+    }
+
+    #[test]
+    fn test_cell_list_matches_brute_force() {
+        let mut cell = scattered(300, 10.0, 1);
+        cell.exclude(&"atom0001".to_string(), &"atom0002".to_string());
+        // open space, an orthorhombic box, a skewed one, and one only two bins across.
+        assert_eq!(
+            names(CellList::new(1.5).pairs(&cell)),
+            brute_force(&cell, 1.5)
+        );
+        // a pair way off on its own in open space shouldn't need a grid reaching out to it.
+        for (name, x) in [("far0", 1.0e7), ("far1", 1.0e7 + 1.0)] {
+            let atom = carbon(name, vec![x, -x, x]).build();
+            cell.get_mut_particles().insert(name.to_string(), atom);
+        }
+        assert_eq!(
+            names(CellList::new(1.5).pairs(&cell)),
+            brute_force(&cell, 1.5)
+        );
+        for name in ["far0", "far1"] {
+            cell.get_mut_particles().remove(name);
+        }
+        cell.set_periodic_box(Some(PeriodicBox::cubic(10.0)));
+        assert_eq!(
+            names(CellList::new(1.5).pairs(&cell)),
+            brute_force(&cell, 1.5)
+        );
+        cell.set_periodic_box(Some(PeriodicBox::from_lattice(
+            10.0, 10.0, 10.0, 80.0, 95.0, 70.0,
+        )));
+        cell.wrap_positions();
+        assert_eq!(
+            names(CellList::new(1.5).pairs(&cell)),
+            brute_force(&cell, 1.5)
+        );
+        assert_eq!(
+            names(CellList::new(4.0).pairs(&cell)),
+            brute_force(&cell, 4.0)
+        );
+    }
+
+    #[test]
+    fn test_verlet_list_rebuilds() {
+        let mut cell = scattered(100, 6.0, 2);
+        cell.set_periodic_box(Some(PeriodicBox::cubic(6.0)));
+        let mut list = VerletList::new(1.2, 0.4);
+        assert!(list.update(&cell));
+        assert!(!list.update(&cell));
+        // small moves get by on the old list, and it still finds exactly the right pairs.
+        let name = "atom0000".to_string();
+        let mut position = cell.get_particles()[&name].position.clone();
+        position[0] += 0.15;
+        cell.get_mut_particles().get_mut(&name).unwrap().position = position.clone();
+        assert!(!list.update(&cell));
+        assert_eq!(names(list.pairs(&cell)), brute_force(&cell, 1.2));
+        // but past half the skin it has to start over; until it does, the pairs come from scratch.
+        position[0] += 0.1;
+        cell.get_mut_particles().get_mut(&name).unwrap().position = position;
+        assert_eq!(names(list.pairs_or_fresh(&cell)), brute_force(&cell, 1.2));
+        assert!(list.update(&cell));
+        assert_eq!(list.rebuilds, 2);
+        assert_eq!(names(list.pairs(&cell)), brute_force(&cell, 1.2));
+    }
+}
@@ -1,5 +1,6 @@
 pub trait HasMass<NumT> {
     fn set_mass(&mut self, mass: NumT);
+    fn get_mass(&self) -> &NumT;
 }
 
 pub trait HasPhysics<VecT> {
@@ -14,9 +15,10 @@ pub trait HasPhysics<VecT> {
 pub trait IsSpatial {
     fn generate_spatial_coordinates(&mut self, nDim: u32);
 }
+// the force on a charge depends on whatever field it's sitting in; see Dynamics::fields.
 pub trait HasCharge<NumT> {
-    fn force(&self);
-    fn set_charge(&self, charge: NumT);
+    fn set_charge(&mut self, charge: NumT);
+    fn get_charge(&self) -> &NumT;
 }
 
 #[derive(Debug)]
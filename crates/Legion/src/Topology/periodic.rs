@@ -0,0 +1,284 @@
+use nalgebra::{Matrix3, Vector3};
+
+// Periodic boundaries!  The cell gets tiled infinitely in every direction, so a few hundred atoms can stand in for bulk matter.
+// The box is three vectors a, b and c; positions inside it are r = s_a a + s_b b + s_c c with every fractional s in [0, 1).
+// Orthorhombic boxes are the special case where the vectors are along x, y and z, and get a cheaper path everywhere.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodicBox {
+    pub vectors: [[f32; 3]; 3], // a, b, c
+}
+
+// cos of an angle in degrees, but exactly zero for right angles so orthorhombic crystals stay orthorhombic.
+fn cos_degrees(angle: f32) -> f32 {
+    if angle == 90.0 {
+        0.0
+    } else {
+        angle.to_radians().cos()
+    }
+}
+
+fn angle_between(u: &Vector3<f32>, v: &Vector3<f32>) -> f32 {
+    (u.dot(v) / (u.norm() * v.norm()))
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees()
+}
+
+impl PeriodicBox {
+    // orthorhombic, with a length along each axis.
+    pub fn new(lengths: Vec<f32>) -> Self {
+        Self {
+            vectors: [
+                [lengths[0], 0.0, 0.0],
+                [0.0, lengths[1], 0.0],
+                [0.0, 0.0, lengths[2]],
+            ],
+        }
+    }
+
+    pub fn cubic(length: f32) -> Self {
+        Self::new(vec![length; 3])
+    }
+
+    pub fn triclinic(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> Self {
+        Self { vectors: [a, b, c] }
+    }
+
+    // From the crystallographer's a, b, c, alpha, beta, gamma (angles in degrees, alpha between b and c and so on), as
+    // found in a PDB CRYST1 record.  Uses the usual orientation: a along x, b in the xy plane.
+    pub fn from_lattice(a: f32, b: f32, c: f32, alpha: f32, beta: f32, gamma: f32) -> Self {
+        let (cos_alpha, cos_beta, cos_gamma) =
+            (cos_degrees(alpha), cos_degrees(beta), cos_degrees(gamma));
+        let sin_gamma = gamma.to_radians().sin();
+        let cx = c * cos_beta;
+        let cy = c * (cos_alpha - cos_beta * cos_gamma) / sin_gamma;
+        let cz = (c * c - cx * cx - cy * cy).max(0.0).sqrt();
+        Self::triclinic(
+            [a, 0.0, 0.0],
+            [b * cos_gamma, b * sin_gamma, 0.0],
+            [cx, cy, cz],
+        )
+    }
+
+    // back to [a, b, c, alpha, beta, gamma].
+    pub fn to_lattice(&self) -> [f32; 6] {
+        let [a, b, c] = self.vectors.map(Vector3::from);
+        [
+            a.norm(),
+            b.norm(),
+            c.norm(),
+            angle_between(&b, &c),
+            angle_between(&a, &c),
+            angle_between(&a, &b),
+        ]
+    }
+
+    pub fn is_orthorhombic(&self) -> bool {
+        (0..3).all(|i| (0..3).all(|k| i == k || self.vectors[i][k] == 0.0))
+    }
+
+    // the box vectors as the columns, so r = M s.
+    fn matrix(&self) -> Matrix3<f32> {
+        Matrix3::from_columns(&self.vectors.map(Vector3::from))
+    }
+
+    pub fn lengths(&self) -> Vec<f32> {
+        self.vectors
+            .iter()
+            .map(|v| Vector3::from(*v).norm())
+            .collect()
+    }
+
+    // How thick the box is across each pair of faces.  Cutoffs have to fit in half of the smallest of these.
+    pub fn widths(&self) -> Vec<f32> {
+        let [a, b, c] = self.vectors.map(Vector3::from);
+        let volume = self.volume();
+        vec![
+            volume / b.cross(&c).norm(),
+            volume / c.cross(&a).norm(),
+            volume / a.cross(&b).norm(),
+        ]
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.matrix().determinant().abs()
+    }
+
+    // the same shape, stretched by the same factor along every axis.
+    pub fn scaled(&self, factor: f32) -> Self {
+        Self {
+            vectors: self.vectors.map(|v| v.map(|z| z * factor)),
+        }
+    }
+
+    pub fn fractional(&self, position: &[f32]) -> Vec<f32> {
+        if self.is_orthorhombic() {
+            return (0..3).map(|k| position[k] / self.vectors[k][k]).collect();
+        }
+        let inverse = self.matrix().try_inverse().unwrap_or_else(Matrix3::zeros);
+        let s = inverse * Vector3::new(position[0], position[1], position[2]);
+        vec![s[0], s[1], s[2]]
+    }
+
+    pub fn cartesian(&self, fractional: &[f32]) -> Vec<f32> {
+        let r = self.matrix() * Vector3::new(fractional[0], fractional[1], fractional[2]);
+        vec![r[0], r[1], r[2]]
+    }
+
+    // how far a particle that's been wrapped by the given number of boxes has actually moved.
+    pub fn translation(&self, image: &[i32]) -> Vec<f32> {
+        self.cartesian(&image.iter().map(|&n| n as f32).collect::<Vec<f32>>())
+    }
+
+    // Minimum image convention: swaps a separation for the one to the closest periodic copy.
+    // For skewed boxes rounding the fractional coordinates gets close, but the true nearest copy can be one box over,
+    // so the neighbors get checked too.
+    pub fn minimum_image(&self, d: &mut Vec<f32>) {
+        if self.is_orthorhombic() {
+            for (k, z) in d.iter_mut().enumerate().take(3) {
+                let l = self.vectors[k][k];
+                *z -= l * (*z / l).round();
+            }
+            return;
+        }
+        let reduced: Vec<f32> = self.fractional(d).iter().map(|s| s - s.round()).collect();
+        let reduced = Vector3::from_vec(self.cartesian(&reduced));
+        let m = self.matrix();
+        let mut best = reduced;
+        for i in -1..=1 {
+            for j in -1..=1 {
+                for k in -1..=1 {
+                    let candidate = reduced + m * Vector3::new(i as f32, j as f32, k as f32);
+                    if candidate.norm_squared() < best.norm_squared() {
+                        best = candidate;
+                    }
+                }
+            }
+        }
+        *d = vec![best[0], best[1], best[2]];
+    }
+
+    // Puts a position back inside the box, along with how many box vectors it got moved by along each one.
+    pub fn wrap(&self, position: &[f32]) -> (Vec<f32>, Vec<i32>) {
+        let mut s = self.fractional(position);
+        let mut shifts = vec![0; 3];
+        for k in 0..3 {
+            let mut n = s[k].floor();
+            s[k] -= n;
+            // floating point can land us right on 1; that's the same as 0, one more box over.
+            if s[k] >= 1.0 {
+                s[k] -= 1.0;
+                n += 1.0;
+            }
+            shifts[k] = n as i32;
+        }
+        if shifts.iter().all(|&n| n == 0) {
+            return (position.to_vec(), shifts);
+        }
+        let moved = self.translation(&shifts);
+        let wrapped = (0..3).map(|k| position[k] - moved[k]).collect();
+        (wrapped, shifts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minimum_image() {
+        let pbc = PeriodicBox::new(vec![10.0, 10.0, 4.0]);
+        let mut d = vec![9.0, -6.0, 1.0];
+        pbc.minimum_image(&mut d);
+        assert_eq!(d, vec![-1.0, 4.0, 1.0]);
+        assert_eq!(pbc.volume(), 400.0);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let pbc = PeriodicBox::cubic(5.0);
+        let (wrapped, shifts) = pbc.wrap(&vec![12.0, -1.0, 3.0]);
+        assert_eq!(wrapped, vec![2.0, 4.0, 3.0]);
+        assert_eq!(shifts, vec![2, -1, 0]);
+        // just under zero, where the fractional coordinate rounds up to exactly 1 and gets folded back to 0: that's
+        // no box over at all, so the position comes back as it was rather than on the far edge.
+        let position = vec![-1.0e-8, 1.0, 1.0];
+        let (wrapped, shifts) = pbc.wrap(&position);
+        assert_eq!(shifts, vec![0, 0, 0]);
+        assert_eq!(wrapped, position);
+    }
+
+    #[test]
+    fn test_lattice_round_trip() {
+        // a monoclinic-ish cell and a properly triclinic one.
+        for lattice in [
+            [10.0, 12.0, 14.0, 90.0, 105.0, 90.0],
+            [8.0, 9.0, 11.0, 70.0, 80.0, 100.0],
+        ] {
+            let [a, b, c, alpha, beta, gamma] = lattice;
+            let pbc = PeriodicBox::from_lattice(a, b, c, alpha, beta, gamma);
+            for (x, y) in pbc.to_lattice().iter().zip(lattice.iter()) {
+                assert!(
+                    (x - y).abs() < 1.0e-3,
+                    "{:?} vs {:?}",
+                    pbc.to_lattice(),
+                    lattice
+                );
+            }
+        }
+        let cube = PeriodicBox::from_lattice(5.0, 5.0, 5.0, 90.0, 90.0, 90.0);
+        assert!(cube.is_orthorhombic());
+        assert_eq!(cube, PeriodicBox::cubic(5.0));
+    }
+
+    #[test]
+    fn test_triclinic_minimum_image() {
+        // brute force over lots of images should never beat the minimum image.
+        let pbc = PeriodicBox::from_lattice(5.0, 6.0, 7.0, 60.0, 75.0, 110.0);
+        let m = pbc.matrix();
+        for d in [
+            vec![4.0, 3.0, -2.0],
+            vec![-7.5, 8.0, 6.0],
+            vec![0.3, -0.2, 0.1],
+            vec![12.0, -3.0, 9.0],
+        ] {
+            let mut image = d.clone();
+            pbc.minimum_image(&mut image);
+            let image = Vector3::from_vec(image);
+            let original = Vector3::from_vec(d.clone());
+            let mut best = f32::INFINITY;
+            for i in -4..=4 {
+                for j in -4..=4 {
+                    for k in -4..=4 {
+                        let shift = m * Vector3::new(i as f32, j as f32, k as f32);
+                        best = best.min((original + shift).norm());
+                    }
+                }
+            }
+            assert!((image.norm() - best).abs() < 1.0e-4);
+            // and it has to still be a lattice translation away from where it started.
+            let s = pbc.fractional(&vec![
+                image[0] - original[0],
+                image[1] - original[1],
+                image[2] - original[2],
+            ]);
+            assert!(s.iter().all(|z| (z - z.round()).abs() < 1.0e-4));
+        }
+    }
+
+    #[test]
+    fn test_triclinic_wrap() {
+        let pbc = PeriodicBox::from_lattice(4.0, 4.0, 4.0, 90.0, 90.0, 60.0);
+        let position = vec![-3.0, 9.0, 5.0];
+        let (wrapped, shifts) = pbc.wrap(&position);
+        assert!(pbc
+            .fractional(&wrapped)
+            .iter()
+            .all(|s| (0.0..1.0).contains(s)));
+        let moved = pbc.translation(&shifts);
+        for k in 0..3 {
+            assert!((wrapped[k] + moved[k] - position[k]).abs() < 1.0e-4);
+        }
+    }
+}
@@ -1,3 +1,7 @@
 pub mod Dynamics;
 pub mod ForceFields;
-pub mod Topology;
\ No newline at end of file
+pub mod Sampling;
+pub mod Topology;
+
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,52 @@
+// setup shared by the unit tests: SIN, plain Lennard-Jones, carbon atoms placed by name, and cells to hold them.
+
+use crate::ForceFields::potentials::LennardJones;
+use crate::ForceFields::SIN::{Elements, ForceField, SIN};
+use crate::Topology::atom::{Atom, AtomBuilder};
+use crate::Topology::cell::Cell;
+
+pub type TestAtom = Atom<Elements, f32, Vec<f32>>;
+pub type World = Cell<TestAtom, f32>;
+
+pub fn sin() -> SIN<Elements> {
+    SIN::<Elements>::new()
+}
+
+// plain Lennard-Jones with epsilon = sigma = 1 between everyone, and unit masses.
+pub struct LJ;
+
+impl ForceField<Elements, f32, Vec<f32>> for LJ {
+    type Pair = LennardJones;
+    fn mass(&self, _element: &Elements) -> f32 {
+        1.0
+    }
+    fn charge(&self, _element: &Elements) -> f32 {
+        0.0
+    }
+    fn atom(&self, element: Elements) -> TestAtom {
+        AtomBuilder::new().element(element).mass(1.0).build()
+    }
+    fn pair_potential(&self, _e1: &Elements, _e2: &Elements) -> LennardJones {
+        LennardJones::new(1.0, 1.0)
+    }
+}
+
+// a bare carbon, ready for whatever else the test wants set before it's built.
+pub fn carbon(id: &str, position: Vec<f32>) -> AtomBuilder<Elements, f32, Vec<f32>> {
+    AtomBuilder::new()
+        .element(Elements::C(0))
+        .id(Some(id.to_string()))
+        .position(position)
+}
+
+// a cell with the atoms in it, keyed by id.
+pub fn world(atoms: impl IntoIterator<Item = TestAtom>) -> World {
+    let mut cell = Cell::new();
+    cell.set_particles(
+        atoms
+            .into_iter()
+            .map(|atom| (atom.id.clone(), atom))
+            .collect(),
+    );
+    cell
+}
@@ -0,0 +1,564 @@
+use super::inpcrd::Inpcrd;
+use super::prmtop::{AmberError, Prmtop, NATOM, NRES, NTYPES};
+use crate::system::{element, ElementTypes, System};
+use std::collections::BTreeSet;
+use Legion::Dynamics::bonded::{AnglePotential, BondPotential, Bonded, DihedralPotential};
+use Legion::ForceFields::mixing::{MixingRule, PairParameters};
+use Legion::ForceFields::potentials::{Coulomb, Harmonic, LennardJones};
+use Legion::Topology::atom::AtomBuilder;
+use Legion::Topology::periodic::PeriodicBox;
+
+// A prmtop into Legion.  Atoms are named by residue, atom number and atom name (ALA2:15:CA), bonds become neighbor
+// lists, and the bonds, angles and dihedrals (with and without hydrogen) go straight into a Bonded.  Things to know:
+//   charges are stored multiplied by 18.2223 so that q1 q2 / r comes out in kcal/mol; they're divided back to e here.
+//   AMBER's harmonic terms are K (x - x0)^2, without the half, so K gets doubled; angles and phases are radians in
+//   the file and degrees in Legion.
+//   atom indices in the bonded lists are 3 (i - 1), the offset into the coordinate array.  A negative third atom in a
+//   dihedral just means no 1-4 for it, and a negative fourth that it's an improper (which has its center third).
+//   Lennard-Jones comes as A = 4 epsilon sigma^12 and B = 4 epsilon sigma^6 for every pair of types; each element
+//   gets the diagonal of its types, which have to agree (Legion only keys on element), and is mixed Lorentz-Berthelot
+//   like AMBER does.
+//   exclusions come straight from EXCLUDED_ATOMS_LIST.  That takes in the 1-4s, which come back as Bonded's pairs,
+//   one for the ends of each proper dihedral that wants it: that pair's own A and B over SCNB, and the charges over
+//   SCEE (both per dihedral type; 2.0 and 1.2 when the file's too old to say).
+// CHARMM extras (Urey-Bradley, CMAP, harmonic impropers) aren't read.  Units stay AMBER's: Angstroms, kcal/mol, amu,
+// and so the time unit that goes with them, 1/20.455 ps.  That's what inpcrd velocities are in already, so they go in
+// as they are; timesteps want multiplying by PICOSECOND to match.
+
+const CHARGE_SCALE: f64 = 18.2223;
+
+// One picosecond, in AMBER's time unit.
+pub const PICOSECOND: f64 = 20.455;
+
+fn error(section: &str, message: String) -> AmberError {
+    AmberError {
+        section: section.to_string(),
+        message,
+    }
+}
+
+// an index from one of the bonded lists back to the atom.
+fn atom_index(flag: &str, value: i64, natom: usize) -> Result<usize, AmberError> {
+    let index = value.unsigned_abs() as usize / 3;
+    if index >= natom {
+        return Err(error(flag, format!("there's no atom {}", index + 1)));
+    }
+    Ok(index)
+}
+
+// a parameter type (from 1) out of its list.
+fn parameter(flag: &str, values: &[f64], value: i64) -> Result<f32, AmberError> {
+    values
+        .get((value - 1) as usize)
+        .map(|&p| p as f32)
+        .ok_or_else(|| error(flag, format!("there's no parameter type {}", value)))
+}
+
+// Both lists of a kind (with hydrogen, without) cut into entries of the given size.
+fn entries(prmtop: &Prmtop, flags: [&str; 2], size: usize) -> Result<Vec<Vec<i64>>, AmberError> {
+    let mut entries = Vec::new();
+    for flag in flags {
+        let values = prmtop.integers(flag)?;
+        if values.len() % size != 0 {
+            return Err(error(flag, format!("should be in groups of {}", size)));
+        }
+        entries.extend(values.chunks(size).map(|entry| entry.to_vec()));
+    }
+    Ok(entries)
+}
+
+impl System {
+    pub fn from_prmtop(prmtop: &Prmtop) -> Result<Self, AmberError> {
+        let natom = prmtop.pointer(NATOM)?;
+        let ntypes = prmtop.pointer(NTYPES)?;
+        let nres = prmtop.pointer(NRES)?;
+        let names = prmtop.strings("ATOM_NAME")?;
+        let charges = prmtop.reals("CHARGE")?;
+        let masses = prmtop.reals("MASS")?;
+        let types = prmtop.integers("ATOM_TYPE_INDEX")?;
+        let atomic_numbers = if prmtop.has("ATOMIC_NUMBER") {
+            prmtop.integers("ATOMIC_NUMBER")?
+        } else {
+            Vec::new()
+        };
+        let counts = prmtop.integers("NUMBER_EXCLUDED_ATOMS")?;
+        for (flag, count) in [
+            ("ATOM_NAME", names.len()),
+            ("CHARGE", charges.len()),
+            ("MASS", masses.len()),
+            ("ATOM_TYPE_INDEX", types.len()),
+            ("NUMBER_EXCLUDED_ATOMS", counts.len()),
+        ] {
+            if count < natom {
+                return Err(error(
+                    flag,
+                    format!("has {} entries for {} atoms", count, natom),
+                ));
+            }
+        }
+
+        // residue k runs from its pointer up to the next one.
+        let labels = prmtop.strings("RESIDUE_LABEL")?;
+        let pointers = prmtop.integers("RESIDUE_POINTER")?;
+        if labels.len() < nres || pointers.len() < nres {
+            return Err(error("RESIDUE_POINTER", format!("needs {} residues", nres)));
+        }
+        let mut ids = Vec::new();
+        let mut residue = 0;
+        for (k, name) in names.iter().enumerate().take(natom) {
+            while residue + 1 < nres && pointers[residue + 1] as usize <= k + 1 {
+                residue += 1;
+            }
+            ids.push(format!(
+                "{}{}:{}:{}",
+                labels[residue],
+                residue + 1,
+                k + 1,
+                name
+            ));
+        }
+        let elements: Vec<_> = (0..natom)
+            .map(|k| {
+                let z = atomic_numbers.get(k).filter(|&&z| z > 0).map(|&z| z as u32);
+                element(z, masses[k])
+            })
+            .collect();
+
+        if let Some(&t) = types[..natom]
+            .iter()
+            .find(|&&t| t < 1 || t as usize > ntypes)
+        {
+            return Err(error("ATOM_TYPE_INDEX", format!("there's no type {}", t)));
+        }
+        // (epsilon, sigma) between two atoms, out of the A and B tables for their types.
+        let nonbonded = prmtop.integers("NONBONDED_PARM_INDEX")?;
+        let a = prmtop.reals("LENNARD_JONES_ACOEF")?;
+        let b = prmtop.reals("LENNARD_JONES_BCOEF")?;
+        let lennard_jones = |i: usize, j: usize| -> Result<(f64, f64), AmberError> {
+            let (ti, tj) = (types[i] as usize - 1, types[j] as usize - 1);
+            // negative means a 10-12 hydrogen bond term, which nothing's used since the nineties.
+            Ok(match nonbonded.get(ntypes * ti + tj) {
+                Some(&index) if index > 0 => {
+                    let index = index as usize - 1;
+                    match (a.get(index), b.get(index)) {
+                        (Some(&a), Some(&b)) if a > 0.0 && b > 0.0 => {
+                            (b * b / (4.0 * a), (a / b).powf(1.0 / 6.0))
+                        }
+                        (Some(_), Some(_)) => (0.0, 0.0),
+                        _ => {
+                            return Err(error(
+                                "LENNARD_JONES_ACOEF",
+                                format!("there's no entry {}", index + 1),
+                            ))
+                        }
+                    }
+                }
+                Some(_) => (0.0, 0.0),
+                None => {
+                    return Err(error(
+                        "NONBONDED_PARM_INDEX",
+                        format!("needs {} entries", ntypes * ntypes),
+                    ))
+                }
+            })
+        };
+        // each element takes the diagonal for its atoms' types, named AMBER's way if the file has them.
+        let type_names = prmtop.strings("AMBER_ATOM_TYPE").unwrap_or_default();
+        let mut parameters = PairParameters::new(MixingRule::LorentzBerthelot);
+        let mut element_types = ElementTypes::new();
+        for (k, element) in elements.iter().enumerate() {
+            let (epsilon, sigma) = lennard_jones(k, k)?;
+            let name = match type_names.get(k) {
+                Some(name) => name.clone(),
+                None => types[k].to_string(),
+            };
+            element_types
+                .add(
+                    &mut parameters,
+                    element,
+                    &name,
+                    epsilon as f32,
+                    sigma as f32,
+                )
+                .map_err(|message| error("LENNARD_JONES_ACOEF", message))?;
+        }
+
+        let mut bonded = Bonded::new();
+        let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); natom];
+        let (k_bond, r_bond) = (
+            prmtop.reals("BOND_FORCE_CONSTANT")?,
+            prmtop.reals("BOND_EQUIL_VALUE")?,
+        );
+        for entry in entries(prmtop, ["BONDS_INC_HYDROGEN", "BONDS_WITHOUT_HYDROGEN"], 3)? {
+            let i = atom_index("BONDS", entry[0], natom)?;
+            let j = atom_index("BONDS", entry[1], natom)?;
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+            let k = parameter("BOND_FORCE_CONSTANT", &k_bond, entry[2])?;
+            let r0 = parameter("BOND_EQUIL_VALUE", &r_bond, entry[2])?;
+            bonded.bonds.push((
+                [ids[i].clone(), ids[j].clone()],
+                BondPotential::Harmonic(Harmonic::new(2.0 * k, r0)),
+            ));
+        }
+
+        let (k_angle, theta_angle) = (
+            prmtop.reals("ANGLE_FORCE_CONSTANT")?,
+            prmtop.reals("ANGLE_EQUIL_VALUE")?,
+        );
+        for entry in entries(
+            prmtop,
+            ["ANGLES_INC_HYDROGEN", "ANGLES_WITHOUT_HYDROGEN"],
+            4,
+        )? {
+            let mut atoms = Vec::new();
+            for &value in entry[..3].iter() {
+                atoms.push(ids[atom_index("ANGLES", value, natom)?].clone());
+            }
+            let k = parameter("ANGLE_FORCE_CONSTANT", &k_angle, entry[3])?;
+            let theta0 = parameter("ANGLE_EQUIL_VALUE", &theta_angle, entry[3])?;
+            bonded.angles.push((
+                [atoms[0].clone(), atoms[1].clone(), atoms[2].clone()],
+                AnglePotential::Harmonic {
+                    k: 2.0 * k,
+                    theta0: theta0.to_degrees(),
+                },
+            ));
+        }
+
+        let (k_dihedral, n_dihedral, phase_dihedral) = (
+            prmtop.reals("DIHEDRAL_FORCE_CONSTANT")?,
+            prmtop.reals("DIHEDRAL_PERIODICITY")?,
+            prmtop.reals("DIHEDRAL_PHASE")?,
+        );
+        let scale = |flag: &str, default: f64| -> Result<Vec<f64>, AmberError> {
+            if prmtop.has(flag) {
+                prmtop.reals(flag)
+            } else {
+                Ok(vec![default; k_dihedral.len()])
+            }
+        };
+        let (scee, scnb) = (
+            scale("SCEE_SCALE_FACTOR", 1.2)?,
+            scale("SCNB_SCALE_FACTOR", 2.0)?,
+        );
+        let mut ends = BTreeSet::new();
+        for entry in entries(
+            prmtop,
+            ["DIHEDRALS_INC_HYDROGEN", "DIHEDRALS_WITHOUT_HYDROGEN"],
+            5,
+        )? {
+            let mut atoms = Vec::new();
+            for &value in entry[..4].iter() {
+                atoms.push(ids[atom_index("DIHEDRALS", value, natom)?].clone());
+            }
+            let atoms = [
+                atoms[0].clone(),
+                atoms[1].clone(),
+                atoms[2].clone(),
+                atoms[3].clone(),
+            ];
+            let dihedral = DihedralPotential::Periodic {
+                k: parameter("DIHEDRAL_FORCE_CONSTANT", &k_dihedral, entry[4])?,
+                multiplicity: parameter("DIHEDRAL_PERIODICITY", &n_dihedral, entry[4])?
+                    .abs()
+                    .round() as i32,
+                phase: parameter("DIHEDRAL_PHASE", &phase_dihedral, entry[4])?.to_degrees(),
+            };
+            if entry[3] < 0 {
+                bonded.impropers.push((atoms, dihedral));
+                continue;
+            }
+            bonded.dihedrals.push((atoms, dihedral));
+            let (i, l) = (
+                atom_index("DIHEDRALS", entry[0], natom)?,
+                atom_index("DIHEDRALS", entry[3], natom)?,
+            );
+            if entry[2] < 0 || !ends.insert((i.min(l), i.max(l))) {
+                continue;
+            }
+            let (epsilon, sigma) = lennard_jones(i, l)?;
+            let (scee, scnb) = (
+                parameter("SCEE_SCALE_FACTOR", &scee, entry[4])?,
+                parameter("SCNB_SCALE_FACTOR", &scnb, entry[4])?,
+            );
+            // the stored charges give kcal/mol straight off.
+            bonded.pairs.push((
+                [ids[i].clone(), ids[l].clone()],
+                LennardJones::new(epsilon as f32 / scnb, sigma as f32),
+                Coulomb::new((charges[i] * charges[l]) as f32 / scee),
+            ));
+        }
+
+        // each atom's list only has the ones after it; a lone 0 means none.
+        let mut exclusions = Vec::new();
+        let excluded = prmtop.integers("EXCLUDED_ATOMS_LIST")?;
+        let mut next = 0;
+        for (i, &count) in counts.iter().enumerate().take(natom) {
+            if count < 0 {
+                return Err(error(
+                    "NUMBER_EXCLUDED_ATOMS",
+                    format!("atom {} has {} excluded atoms", i + 1, count),
+                ));
+            }
+            let count = count as usize;
+            if next + count > excluded.len() {
+                return Err(error("EXCLUDED_ATOMS_LIST", "runs out early".to_string()));
+            }
+            let list = &excluded[next..next + count];
+            next += count;
+            for &j in list.iter().filter(|&&j| j > 0) {
+                let j = atom_index("EXCLUDED_ATOMS_LIST", 3 * (j - 1), natom)?;
+                exclusions.push((ids[i].clone(), ids[j].clone()));
+            }
+        }
+
+        let atoms = (0..natom)
+            .map(|k| {
+                AtomBuilder::new()
+                    .element(elements[k].clone())
+                    .id(Some(ids[k].clone()))
+                    .neighbors(neighbors[k].iter().map(|&n| ids[n].clone()).collect())
+                    .mass(masses[k] as f32)
+                    .charge((charges[k] / CHARGE_SCALE) as f32)
+                    .position(vec![0.0; 3])
+                    .velocity(vec![0.0; 3])
+                    .acceleration(vec![0.0; 3])
+                    .build()
+            })
+            .collect();
+        Ok(System {
+            name: prmtop.title(),
+            atoms,
+            bonded,
+            parameters,
+            exclusions,
+            periodic_box: None,
+        })
+    }
+
+    // Positions (and velocities, if it's a restart) from an inpcrd, in the same order as the prmtop, and its box if it
+    // has one.
+    pub fn set_coordinates(&mut self, inpcrd: &Inpcrd) -> Result<(), AmberError> {
+        if inpcrd.positions.len() != self.atoms.len() {
+            return Err(error(
+                "inpcrd",
+                format!(
+                    "has {} atoms, but the topology has {}",
+                    inpcrd.positions.len(),
+                    self.atoms.len()
+                ),
+            ));
+        }
+        for (k, atom) in self.atoms.iter_mut().enumerate() {
+            atom.position = inpcrd.positions[k].iter().map(|&x| x as f32).collect();
+            if let Some(velocities) = &inpcrd.velocities {
+                atom.velocity = velocities[k].iter().map(|&v| v as f32).collect();
+            }
+        }
+        if let Some(lattice) = inpcrd.box_dimensions {
+            let [a, b, c, alpha, beta, gamma] = lattice.map(|x| x as f32);
+            self.periodic_box = Some(PeriodicBox::from_lattice(a, b, c, alpha, beta, gamma));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Legion::ForceFields::SIN::Elements;
+
+    // hydrogen peroxide, H1-O1-O2-H2, with a couple of made up terms on the dihedral.
+    const HOOH: &str = "%VERSION  VERSION_STAMP = V0001.000  DATE = 10/19/26  12:00:00
+%FLAG TITLE
+%FORMAT(20a4)
+hydrogen peroxide
+%FLAG POINTERS
+%FORMAT(10I8)
+       4       2       2       1       2       0       3       0       0       0
+       7       1       1       0       0       2       1       3       2       0
+       0       0       0       0       0       0       0       0       4       0
+       0
+%FLAG ATOM_NAME
+%FORMAT(20a4)
+H1  O1  O2  H2
+%FLAG CHARGE
+%FORMAT(5E16.8)
+  7.47114300E+00 -7.47114300E+00 -7.47114300E+00  7.47114300E+00
+%FLAG ATOMIC_NUMBER
+%FORMAT(10I8)
+       1       8       8       1
+%FLAG MASS
+%FORMAT(5E16.8)
+  1.00800000E+00  1.60000000E+01  1.60000000E+01  1.00800000E+00
+%FLAG ATOM_TYPE_INDEX
+%FORMAT(10I8)
+       1       2       2       1
+%FLAG NUMBER_EXCLUDED_ATOMS
+%FORMAT(10I8)
+       3       2       1       1
+%FLAG NONBONDED_PARM_INDEX
+%FORMAT(10I8)
+       1       2       2       3
+%FLAG RESIDUE_LABEL
+%FORMAT(20a4)
+HOO
+%FLAG RESIDUE_POINTER
+%FORMAT(10I8)
+       1
+%FLAG BOND_FORCE_CONSTANT
+%FORMAT(5E16.8)
+  5.53000000E+02  3.00000000E+02
+%FLAG BOND_EQUIL_VALUE
+%FORMAT(5E16.8)
+  9.60000000E-01  1.45000000E+00
+%FLAG ANGLE_FORCE_CONSTANT
+%FORMAT(5E16.8)
+  5.00000000E+01
+%FLAG ANGLE_EQUIL_VALUE
+%FORMAT(5E16.8)
+  1.74532925E+00
+%FLAG DIHEDRAL_FORCE_CONSTANT
+%FORMAT(5E16.8)
+  1.40000000E+00  5.00000000E-01  2.00000000E+00
+%FLAG DIHEDRAL_PERIODICITY
+%FORMAT(5E16.8)
+  2.00000000E+00  3.00000000E+00  2.00000000E+00
+%FLAG DIHEDRAL_PHASE
+%FORMAT(5E16.8)
+  0.00000000E+00  3.14159265E+00  3.14159265E+00
+%FLAG SCEE_SCALE_FACTOR
+%FORMAT(5E16.8)
+  2.00000000E+00  1.20000000E+00  0.00000000E+00
+%FLAG LENNARD_JONES_ACOEF
+%FORMAT(5E16.8)
+  0.00000000E+00  0.00000000E+00  4.25152800E+05
+%FLAG LENNARD_JONES_BCOEF
+%FORMAT(5E16.8)
+  0.00000000E+00  0.00000000E+00  5.83200000E+02
+%FLAG BONDS_INC_HYDROGEN
+%FORMAT(10I8)
+       0       3       1       9       6       1
+%FLAG BONDS_WITHOUT_HYDROGEN
+%FORMAT(10I8)
+       3       6       2
+%FLAG ANGLES_INC_HYDROGEN
+%FORMAT(10I8)
+       0       3       6       1       9       6       3       1
+%FLAG ANGLES_WITHOUT_HYDROGEN
+%FORMAT(10I8)
+
+%FLAG DIHEDRALS_INC_HYDROGEN
+%FORMAT(10I8)
+       0       3       6       9       1       0       3      -6       9       2
+       0       3       6      -9       3
+%FLAG DIHEDRALS_WITHOUT_HYDROGEN
+%FORMAT(10I8)
+
+%FLAG EXCLUDED_ATOMS_LIST
+%FORMAT(10I8)
+       2       3       4       3       4       4       0
+";
+
+    #[test]
+    fn test_peroxide() {
+        let mut system = System::from_prmtop(&Prmtop::parse(HOOH).unwrap()).unwrap();
+        assert_eq!(system.name, "hydrogen peroxide");
+        let ids: Vec<&str> = system.atoms.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["HOO1:1:H1", "HOO1:2:O1", "HOO1:3:O2", "HOO1:4:H2"]);
+        let o1 = &system.atoms[1];
+        assert_eq!(o1.element, Elements::O(0));
+        assert!((o1.charge + 0.41).abs() < 1e-6);
+        assert_eq!(o1.neighbors, ["HOO1:1:H1", "HOO1:3:O2"]);
+
+        // Lennard-Jones from the diagonal; epsilon 0.2 and sigma 3 for the oxygens, nothing for the hydrogens.
+        let (epsilon, sigma) = system.parameters.get_element(&Elements::O(0));
+        assert!((epsilon - 0.2).abs() < 1e-5 && (sigma - 3.0).abs() < 1e-5);
+        assert_eq!(system.parameters.get_element(&Elements::H(0)), (0.0, 0.0));
+
+        assert_eq!(system.bonded.bonds.len(), 3);
+        assert_eq!(
+            system.bonded.bonds[2].1,
+            BondPotential::Harmonic(Harmonic::new(600.0, 1.45))
+        );
+        match system.bonded.angles[0].1 {
+            AnglePotential::Harmonic { k, theta0 } => {
+                assert_eq!(k, 100.0);
+                assert!((theta0 - 100.0).abs() < 1e-4);
+            }
+            _ => panic!("should be harmonic"),
+        }
+        // two terms on the dihedral, one improper; the negative indices are still the right atoms.
+        assert_eq!(system.bonded.dihedrals.len(), 2);
+        assert_eq!(system.bonded.dihedrals[1].0, system.bonded.dihedrals[0].0);
+        match system.bonded.dihedrals[1].1 {
+            DihedralPotential::Periodic {
+                k,
+                multiplicity,
+                phase,
+            } => {
+                assert_eq!((k, multiplicity), (0.5, 3));
+                assert!((phase - 180.0).abs() < 1e-4);
+            }
+            _ => panic!("should be periodic"),
+        }
+        assert_eq!(system.bonded.impropers.len(), 1);
+        assert_eq!(system.exclusions.len(), 6);
+        // one 1-4, from the first term: the second is marked as a repeat, and the third is the improper.  SCEE is the
+        // file's 2 and SCNB the default, though with no Lennard-Jones on the hydrogens it doesn't show.
+        assert_eq!(system.bonded.pairs.len(), 1);
+        let ([h1, h2], lj, coulomb) = &system.bonded.pairs[0];
+        assert_eq!((h1.as_str(), h2.as_str()), ("HOO1:1:H1", "HOO1:4:H2"));
+        assert_eq!((lj.epsilon, lj.sigma), (0.0, 0.0));
+        assert!((coulomb.qq - 0.41 * 0.41 * CHARGE_SCALE.powi(2) as f32 / 2.0).abs() < 1e-4);
+
+        let inpcrd = Inpcrd::parse(
+            "hydrogen peroxide
+    4
+   0.9600000   0.0000000   0.0000000   0.0000000   0.0000000   0.0000000
+   0.0000000   1.4500000   0.0000000   0.0000000   1.4500000   0.9600000
+",
+        )
+        .unwrap();
+        system.set_coordinates(&inpcrd).unwrap();
+        assert_eq!(system.atoms[3].position, vec![0.0, 1.45, 0.96]);
+        assert_eq!(system.periodic_box, None);
+        let boxed = Inpcrd {
+            box_dimensions: Some([30.0, 30.0, 30.0, 90.0, 90.0, 90.0]),
+            ..inpcrd.clone()
+        };
+        system.set_coordinates(&boxed).unwrap();
+        assert_eq!(system.periodic_box, Some(PeriodicBox::cubic(30.0)));
+        let short = Inpcrd {
+            positions: vec![[0.0; 3]],
+            ..inpcrd
+        };
+        assert!(system.set_coordinates(&short).is_err());
+    }
+
+    #[test]
+    fn test_bad_exclusions() {
+        let err = |text: &str| {
+            System::from_prmtop(&Prmtop::parse(text).unwrap())
+                .err()
+                .unwrap()
+        };
+        let negative = HOOH.replace(
+            "       3       2       1       1\n%FLAG NONBONDED",
+            "       3      -2       1       1\n%FLAG NONBONDED",
+        );
+        assert_eq!(err(&negative).message, "atom 2 has -2 excluded atoms");
+        let long = HOOH.replace(
+            "       3       2       1       1\n%FLAG NONBONDED",
+            "       3       2       1       9\n%FLAG NONBONDED",
+        );
+        assert_eq!(err(&long).message, "runs out early");
+        // the second oxygen on the hydrogens' type, which has no Lennard-Jones, while the first one has.
+        let clash = HOOH.replace(
+            "ATOM_TYPE_INDEX\n%FORMAT(10I8)\n       1       2       2       1",
+            "ATOM_TYPE_INDEX\n%FORMAT(10I8)\n       1       2       1       1",
+        );
+        assert!(err(&clash).message.contains("2 and 1 are both O"));
+    }
+}
@@ -0,0 +1,140 @@
+use super::prmtop::AmberError;
+use std::fs;
+use std::path::Path;
+
+// https://ambermd.org/FileFormats.php#restart
+// ASCII inpcrd / rst7 files:
+//   a title line
+//   the number of atoms, and the time (restarts only)
+//   x y z for every atom, six numbers to a line, twelve characters each (6F12.7)
+//   then velocities in the same way, if it's a restart
+//   then the box, a b c alpha beta gamma, if it's periodic
+// Which of those are there is worked out from how many numbers there are.  NetCDF restarts aren't read here.
+// Positions are in Angstroms, and velocities in Angstroms per 1/20.455 ps, as AMBER keeps them.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inpcrd {
+    pub title: String,
+    pub time: Option<f64>,
+    pub positions: Vec<[f64; 3]>,
+    pub velocities: Option<Vec<[f64; 3]>>,
+    pub box_dimensions: Option<[f64; 6]>,
+}
+
+fn error(line: usize, message: String) -> AmberError {
+    AmberError {
+        section: format!("inpcrd line {}", line),
+        message,
+    }
+}
+
+fn triples(values: &[f64]) -> Vec<[f64; 3]> {
+    values.chunks(3).map(|v| [v[0], v[1], v[2]]).collect()
+}
+
+impl Inpcrd {
+    pub fn from_file(path: &Path) -> Result<Self, AmberError> {
+        let text = fs::read_to_string(path).map_err(|e| AmberError {
+            section: path.display().to_string(),
+            message: e.to_string(),
+        })?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, AmberError> {
+        let mut lines = text.lines();
+        let title = lines.next().unwrap_or("").trim().to_string();
+        let mut header = lines.next().unwrap_or("").split_whitespace();
+        let natom: usize = header
+            .next()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| error(2, "should start with the number of atoms".to_string()))?;
+        let time = match header.next() {
+            Some(time) => Some(
+                time.parse()
+                    .map_err(|_| error(2, format!("{} isn't a time", time)))?,
+            ),
+            None => None,
+        };
+
+        let mut values = Vec::new();
+        for (n, line) in lines.enumerate() {
+            let mut start = 0;
+            while start < line.len() {
+                let field = line
+                    .get(start..(start + 12).min(line.len()))
+                    .unwrap_or("")
+                    .trim();
+                if !field.is_empty() {
+                    let value: f64 = field
+                        .parse()
+                        .map_err(|_| error(n + 3, format!("{} isn't a number", field)))?;
+                    values.push(value);
+                }
+                start += 12;
+            }
+        }
+
+        let n = 3 * natom;
+        let (velocities, box_dimensions) = match values.len() {
+            count if count == n => (false, false),
+            // with two atoms velocities and a box are the same length; only restarts have velocities, and a time.
+            count if count == 2 * n && (count != n + 6 || time.is_some()) => (true, false),
+            count if count == n + 6 => (false, true),
+            count if count == 2 * n + 6 => (true, true),
+            count => {
+                return Err(error(
+                    3,
+                    format!("{} numbers doesn't fit {} atoms", count, natom),
+                ))
+            }
+        };
+        Ok(Self {
+            title,
+            time,
+            positions: triples(&values[..n]),
+            velocities: if velocities {
+                Some(triples(&values[n..2 * n]))
+            } else {
+                None
+            },
+            box_dimensions: if box_dimensions {
+                let b = &values[values.len() - 6..];
+                Some([b[0], b[1], b[2], b[3], b[4], b[5]])
+            } else {
+                None
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart() {
+        let text = "hydrogen peroxide
+    2  0.1000000E+01
+   0.0000000   0.0000000   0.0000000   0.9600000 -10.0000000   0.0000000
+   0.1000000   0.2000000   0.3000000  -0.1000000  -0.2000000  -0.3000000
+  30.0000000  30.0000000  30.0000000  90.0000000  90.0000000  90.0000000
+";
+        let restart = Inpcrd::parse(text).unwrap();
+        assert_eq!(restart.title, "hydrogen peroxide");
+        assert_eq!(restart.time, Some(1.0));
+        assert_eq!(restart.positions[1], [0.96, -10.0, 0.0]);
+        assert_eq!(restart.velocities.unwrap()[1], [-0.1, -0.2, -0.3]);
+        assert_eq!(
+            restart.box_dimensions,
+            Some([30.0, 30.0, 30.0, 90.0, 90.0, 90.0])
+        );
+
+        // just the coordinates, and the wrong number of them.
+        let text = "title\n    1\n   1.0000000   2.0000000   3.0000000\n";
+        let inpcrd = Inpcrd::parse(text).unwrap();
+        assert_eq!(inpcrd.positions, vec![[1.0, 2.0, 3.0]]);
+        assert!(inpcrd.velocities.is_none() && inpcrd.box_dimensions.is_none());
+        assert!(Inpcrd::parse("title\n    2\n   1.0000000   2.0000000   3.0000000\n").is_err());
+    }
+}
@@ -0,0 +1,5 @@
+// AMBER files from AmberTools (tleap and friends).  prmtop reads the %FLAG sections of a topology, inpcrd the ASCII
+// coordinates and restarts, and convert turns them into a Legion system.
+pub mod convert;
+pub mod inpcrd;
+pub mod prmtop;
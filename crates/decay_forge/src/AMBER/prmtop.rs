@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// https://ambermd.org/prmtop.pdf
+// A prmtop is a list of %FLAG sections, each with a Fortran %FORMAT line saying how many values go on a line and how
+// wide each one is; (10I8) is ten eight character integers, (5E16.8) five sixteen character reals, (20a4) twenty four
+// character strings.  The values are fixed width and can run into each other, so they have to be cut up by column
+// rather than split on spaces.  %COMMENT lines are skipped, and so are the old pre-AMBER 7 files without any %FLAGs.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmberError {
+    pub section: String, // the %FLAG, or where in the coordinate file
+    pub message: String,
+}
+
+impl fmt::Display for AmberError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.section, self.message)
+    }
+}
+
+fn error(section: &str, message: String) -> AmberError {
+    AmberError {
+        section: section.to_string(),
+        message,
+    }
+}
+
+// Where things are in POINTERS; the ones we use, anyway.
+pub const NATOM: usize = 0;
+pub const NTYPES: usize = 1;
+pub const NRES: usize = 11;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prmtop {
+    pub version: String,
+    pub flags: HashMap<String, Vec<String>>, // each value as it was in the file, cut up by the section's format
+}
+
+// (count, width) out of something like %FORMAT(5E16.8).
+fn format(line: &str) -> Option<(usize, usize)> {
+    let spec = line
+        .strip_prefix("%FORMAT(")?
+        .trim_end()
+        .strip_suffix(')')?;
+    let kind = spec.find(|c: char| c.is_ascii_alphabetic())?;
+    let count = spec[..kind].parse().ok()?;
+    let width = spec[kind + 1..].split('.').next()?.parse().ok()?;
+    Some((count, width))
+}
+
+impl Prmtop {
+    pub fn from_file(path: &Path) -> Result<Self, AmberError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| error(&path.display().to_string(), e.to_string()))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, AmberError> {
+        let mut prmtop = Self {
+            version: String::new(),
+            flags: HashMap::new(),
+        };
+        let mut flag: Option<String> = None;
+        let mut layout: Option<(usize, usize)> = None;
+        for line in text.lines() {
+            if let Some(version) = line.strip_prefix("%VERSION") {
+                prmtop.version = version.trim().to_string();
+            } else if let Some(name) = line.strip_prefix("%FLAG") {
+                let name = name.trim().to_string();
+                prmtop.flags.insert(name.clone(), Vec::new());
+                flag = Some(name);
+                layout = None;
+            } else if line.starts_with("%FORMAT") {
+                let name = flag.as_deref().unwrap_or("%FORMAT");
+                layout = Some(format(line).ok_or_else(|| {
+                    error(name, format!("can't read the format {}", line.trim()))
+                })?);
+            } else if line.starts_with("%COMMENT") {
+                continue;
+            } else {
+                let (name, (count, width)) = match (&flag, layout) {
+                    (Some(name), Some(layout)) => (name, layout),
+                    (Some(name), None) => {
+                        return Err(error(name, "values before the %FORMAT".to_string()))
+                    }
+                    (None, _) if line.trim().is_empty() => continue,
+                    (None, _) => {
+                        return Err(error(
+                            "prmtop",
+                            "no %FLAG sections; old style prmtops aren't supported".to_string(),
+                        ))
+                    }
+                };
+                let values = prmtop.flags.get_mut(name).unwrap();
+                // a string section can have blanks in it, but a blank line is just an empty section.
+                for k in 0..count {
+                    let value = match line.get(k * width..((k + 1) * width).min(line.len())) {
+                        Some(value) if !value.is_empty() => value,
+                        _ => break,
+                    };
+                    values.push(value.to_string());
+                }
+            }
+        }
+        if prmtop.flags.is_empty() {
+            return Err(error(
+                "prmtop",
+                "there aren't any %FLAG sections".to_string(),
+            ));
+        }
+        Ok(prmtop)
+    }
+
+    pub fn has(&self, flag: &str) -> bool {
+        self.flags.contains_key(flag)
+    }
+
+    fn raw(&self, flag: &str) -> Result<&Vec<String>, AmberError> {
+        self.flags
+            .get(flag)
+            .ok_or_else(|| error(flag, "missing".to_string()))
+    }
+
+    pub fn strings(&self, flag: &str) -> Result<Vec<String>, AmberError> {
+        Ok(self
+            .raw(flag)?
+            .iter()
+            .map(|value| value.trim().to_string())
+            .collect())
+    }
+
+    pub fn integers(&self, flag: &str) -> Result<Vec<i64>, AmberError> {
+        self.strings(flag)?
+            .iter()
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| error(flag, format!("{} isn't an integer", value)))
+            })
+            .collect()
+    }
+
+    pub fn reals(&self, flag: &str) -> Result<Vec<f64>, AmberError> {
+        self.strings(flag)?
+            .iter()
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                // Fortran doubles sometimes come with a D for the exponent.
+                value
+                    .replace(['D', 'd'], "E")
+                    .parse()
+                    .map_err(|_| error(flag, format!("{} isn't a number", value)))
+            })
+            .collect()
+    }
+
+    pub fn pointer(&self, index: usize) -> Result<usize, AmberError> {
+        let pointers = self.integers("POINTERS")?;
+        match pointers.get(index) {
+            Some(&value) if value >= 0 => Ok(value as usize),
+            _ => Err(error("POINTERS", format!("no pointer {}", index))),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        // older files call it TITLE, newer CTITLE.
+        ["TITLE", "CTITLE"]
+            .iter()
+            .find_map(|flag| self.flags.get(*flag))
+            .map_or(String::new(), |title| title.concat().trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        let text = "%VERSION  VERSION_STAMP = V0001.000  DATE = 10/19/26  12:00:00
+%FLAG TITLE
+%FORMAT(20a4)
+two atoms
+%FLAG POINTERS
+%COMMENT   NATOM    NTYPES
+%FORMAT(10I8)
+       2       1
+%FLAG ATOM_NAME
+%FORMAT(20a4)
+C1  CA10
+%FLAG CHARGE
+%FORMAT(5E16.8)
+  1.82223000E+01-1.82223000E+01
+%FLAG EMPTY
+%FORMAT(10I8)
+
+";
+        let prmtop = Prmtop::parse(text).unwrap();
+        assert!(prmtop.version.starts_with("VERSION_STAMP"));
+        assert_eq!(prmtop.title(), "two atoms");
+        assert_eq!(prmtop.pointer(NATOM).unwrap(), 2);
+        assert_eq!(prmtop.pointer(NTYPES).unwrap(), 1);
+        assert!(prmtop.pointer(NRES).is_err());
+        // run together, and cut apart by column.
+        assert_eq!(prmtop.strings("ATOM_NAME").unwrap(), ["C1", "CA10"]);
+        assert_eq!(prmtop.reals("CHARGE").unwrap(), vec![18.2223, -18.2223]);
+        assert!(prmtop.integers("EMPTY").unwrap().is_empty());
+        assert_eq!(
+            prmtop.integers("MASS").unwrap_err().to_string(),
+            "MASS: missing"
+        );
+        assert!(Prmtop::parse("   2   1\n").is_err());
+        assert_eq!(format("%FORMAT(1a80)"), Some((1, 80)));
+    }
+}
@@ -0,0 +1,556 @@
+use super::topology::{
+    AtomType, Interaction, InteractionType, MoleculeType, Topology, TopologyError,
+};
+use crate::system::{element, within_bonds, ElementTypes, System};
+use Legion::Dynamics::bonded::{AnglePotential, BondPotential, Bonded, DihedralPotential};
+use Legion::ForceFields::mixing::{MixingRule, PairParameters};
+use Legion::ForceFields::potentials::{Coulomb, Harmonic, LennardJones, Morse};
+use Legion::Topology::atom::AtomBuilder;
+
+// Turning a Topology into things Legion can run: every copy of every molecule in [ molecules ] becomes atoms (named
+// molecule + copy, atom number, atom name; SOL1:2:HW1), bonds become their neighbor lists, and the bonded terms go
+// straight into a Bonded's lists.  Parameters missing from a term are looked up in the *types sections by bond type,
+// with X as a wildcard and the most specific match winning, the same as grompp does it.
+// Supported functions:
+//   bonds:      1 harmonic, 3 Morse, 5 (connectivity only)
+//   angles:     1 harmonic, 5 Urey-Bradley
+//   dihedrals:  1 and 9 periodic, 3 Ryckaert-Bellemans; 2 harmonic and 4 periodic impropers
+//   pairs:      1, Lennard-Jones and Coulomb between the two
+// Lennard-Jones goes into PairParameters by element, which is all Legion keys on, so atom types sharing an element
+// have to have the same parameters; a topology where they don't is an error.  Exclusions are every pair within nrexcl bonds.  The 1-4s that puts out of reach
+// come back through [ pairs ], into Bonded's pairs: with the pair's own V and W, or [ pairtypes ]', or with gen-pairs
+// mixed from the atom types and scaled by fudgeLJ.  Their charges always get fudgeQQ, and GROMACS's Coulomb constant.
+// There are no coordinates in a topology, so everyone starts at the origin, and there's no box.
+
+// kJ mol^-1 nm e^-2
+const COULOMB: f64 = 138.935458;
+
+// V and W, as the atom types and pairs give them, to (epsilon, sigma).
+fn lennard_jones(comb_rule: u32, v: f64, w: f64) -> (f64, f64) {
+    match comb_rule {
+        // C6 = 4 epsilon sigma^6 and C12 = 4 epsilon sigma^12
+        1 if v > 0.0 && w > 0.0 => (v * v / (4.0 * w), (w / v).powf(1.0 / 6.0)),
+        1 => (0.0, 0.0),
+        _ => (w, v),
+    }
+}
+
+fn unsupported(kind: &str, interaction: &Interaction) -> TopologyError {
+    TopologyError {
+        file: interaction.file.clone(),
+        line: interaction.line,
+        message: format!("{} function {} isn't supported", kind, interaction.function),
+    }
+}
+
+// the first n parameters as f32; anything after that is the B state, which we don't do.
+fn take(
+    interaction: &Interaction,
+    parameters: &[f64],
+    n: usize,
+) -> Result<Vec<f32>, TopologyError> {
+    if parameters.len() < n {
+        return Err(TopologyError {
+            file: interaction.file.clone(),
+            line: interaction.line,
+            message: format!(
+                "function {} needs {} parameters, and there aren't any types to get them from",
+                interaction.function, n
+            ),
+        });
+    }
+    Ok(parameters[..n].iter().map(|&p| p as f32).collect())
+}
+
+// All the entries that match the types either way round, with the fewest wildcards.
+fn lookup<'a>(
+    table: &'a [InteractionType],
+    types: &[&str],
+    function: u32,
+) -> Vec<&'a InteractionType> {
+    let score = |entry: &InteractionType| -> Option<usize> {
+        let matches = |order: &mut dyn Iterator<Item = &&str>| {
+            entry
+                .types
+                .iter()
+                .zip(order)
+                .all(|(t, &want)| t == "X" || t == want)
+        };
+        if entry.function == function
+            && entry.types.len() == types.len()
+            && (matches(&mut types.iter()) || matches(&mut types.iter().rev()))
+        {
+            Some(entry.types.iter().filter(|t| *t == "X").count())
+        } else {
+            None
+        }
+    };
+    let best = table.iter().filter_map(&score).min();
+    table
+        .iter()
+        .filter(|entry| best.is_some() && score(entry) == best)
+        .collect()
+}
+
+impl System {
+    pub fn from_topology(topology: &Topology) -> Result<Self, TopologyError> {
+        let top_error = |message: String| TopologyError {
+            file: String::new(),
+            line: 0,
+            message,
+        };
+        if topology.defaults.nbfunc != 1 {
+            return Err(top_error(
+                "only Lennard-Jones (nbfunc 1) is supported".to_string(),
+            ));
+        }
+        let rule = match topology.defaults.comb_rule {
+            2 => MixingRule::LorentzBerthelot,
+            _ => MixingRule::Geometric,
+        };
+        let mut system = System {
+            name: topology.system.clone(),
+            atoms: Vec::new(),
+            bonded: Bonded::new(),
+            parameters: PairParameters::new(rule),
+            exclusions: Vec::new(),
+            periodic_box: None,
+        };
+        let mut element_types = ElementTypes::new();
+
+        for (name, count) in topology.molecules.iter() {
+            let molecule = topology
+                .molecule(name)
+                .ok_or_else(|| top_error(format!("no [ moleculetype ] called {}", name)))?;
+            let mut types = Vec::new();
+            for atom in molecule.atoms.iter() {
+                let atomtype = topology.atomtype(&atom.atom_type).ok_or_else(|| {
+                    top_error(format!(
+                        "{} uses atom type {}, which isn't defined",
+                        molecule.name, atom.atom_type
+                    ))
+                })?;
+                types.push(atomtype);
+                let (epsilon, sigma) =
+                    lennard_jones(topology.defaults.comb_rule, atomtype.v, atomtype.w);
+                element_types
+                    .add(
+                        &mut system.parameters,
+                        &element(atomtype.atomic_number, atomtype.mass),
+                        &atomtype.name,
+                        epsilon as f32,
+                        sigma as f32,
+                    )
+                    .map_err(top_error)?;
+            }
+            for copy in 0..*count {
+                let ids: Vec<String> = molecule
+                    .atoms
+                    .iter()
+                    .map(|atom| format!("{}{}:{}:{}", molecule.name, copy + 1, atom.nr, atom.name))
+                    .collect();
+                system.add_molecule(topology, molecule, &types, &ids)?;
+            }
+        }
+        Ok(system)
+    }
+
+    fn add_molecule(
+        &mut self,
+        topology: &Topology,
+        molecule: &MoleculeType,
+        types: &[&AtomType],
+        ids: &[String],
+    ) -> Result<(), TopologyError> {
+        let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); ids.len()];
+        for bond in molecule.bonds.iter() {
+            let (i, j) = (bond.atoms[0], bond.atoms[1]);
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+        }
+        for (k, atom) in molecule.atoms.iter().enumerate() {
+            self.atoms.push(
+                AtomBuilder::new()
+                    .element(element(types[k].atomic_number, types[k].mass))
+                    .id(Some(ids[k].clone()))
+                    .neighbors(neighbors[k].iter().map(|&n| ids[n].clone()).collect())
+                    .mass(atom.mass.unwrap_or(types[k].mass) as f32)
+                    .charge(atom.charge as f32)
+                    .position(vec![0.0; 3])
+                    .velocity(vec![0.0; 3])
+                    .acceleration(vec![0.0; 3])
+                    .build(),
+            );
+        }
+
+        for (i, j) in within_bonds(&neighbors, molecule.nrexcl) {
+            self.exclusions.push((ids[i].clone(), ids[j].clone()));
+        }
+
+        // the interaction's own parameters, or every matching type's.
+        let parameters = |interaction: &Interaction, table: &[InteractionType]| -> Vec<Vec<f64>> {
+            if !interaction.parameters.is_empty() {
+                return vec![interaction.parameters.clone()];
+            }
+            let bond_types: Vec<&str> = interaction
+                .atoms
+                .iter()
+                .map(|&k| types[k].bond_type.as_str())
+                .collect();
+            let found: Vec<Vec<f64>> = lookup(table, &bond_types, interaction.function)
+                .iter()
+                .map(|entry| entry.parameters.clone())
+                .collect();
+            // only periodic dihedrals stack up; everything else takes the first.
+            match interaction.function {
+                9 => found,
+                _ => found.into_iter().take(1).collect(),
+            }
+        };
+        let names = |interaction: &Interaction| -> Vec<String> {
+            interaction.atoms.iter().map(|&k| ids[k].clone()).collect()
+        };
+        let none = [Vec::new()];
+
+        for bond in molecule.bonds.iter() {
+            if bond.function == 5 {
+                continue;
+            }
+            for p in parameters(bond, &topology.bondtypes)
+                .iter()
+                .chain(none.iter())
+                .take(1)
+            {
+                let potential = match bond.function {
+                    1 => {
+                        let p = take(bond, p, 2)?;
+                        BondPotential::Harmonic(Harmonic::new(p[1], p[0]))
+                    }
+                    3 => {
+                        let p = take(bond, p, 3)?;
+                        BondPotential::Morse(Morse::new(p[1], p[2], p[0]))
+                    }
+                    _ => return Err(unsupported("bond", bond)),
+                };
+                let n = names(bond);
+                self.bonded
+                    .bonds
+                    .push(([n[0].clone(), n[1].clone()], potential));
+            }
+        }
+
+        for angle in molecule.angles.iter() {
+            for p in parameters(angle, &topology.angletypes)
+                .iter()
+                .chain(none.iter())
+                .take(1)
+            {
+                let potential = match angle.function {
+                    1 => {
+                        let p = take(angle, p, 2)?;
+                        AnglePotential::Harmonic {
+                            k: p[1],
+                            theta0: p[0],
+                        }
+                    }
+                    5 => {
+                        let p = take(angle, p, 4)?;
+                        AnglePotential::UreyBradley {
+                            k: p[1],
+                            theta0: p[0],
+                            k_ub: p[3],
+                            r_ub: p[2],
+                        }
+                    }
+                    _ => return Err(unsupported("angle", angle)),
+                };
+                let n = names(angle);
+                self.bonded
+                    .angles
+                    .push(([n[0].clone(), n[1].clone(), n[2].clone()], potential));
+            }
+        }
+
+        for dihedral in molecule.dihedrals.iter() {
+            let found = parameters(dihedral, &topology.dihedraltypes);
+            // with nothing found, go round once anyway so take() can complain about it.
+            let count = found.len().max(1);
+            for p in found.iter().chain(none.iter()).take(count) {
+                let (potential, improper) = match dihedral.function {
+                    1 | 4 | 9 => {
+                        let p = take(dihedral, p, 3)?;
+                        (
+                            DihedralPotential::Periodic {
+                                k: p[1],
+                                multiplicity: p[2].round() as i32,
+                                phase: p[0],
+                            },
+                            dihedral.function == 4,
+                        )
+                    }
+                    2 => {
+                        let p = take(dihedral, p, 2)?;
+                        (DihedralPotential::Harmonic { k: p[1], xi0: p[0] }, true)
+                    }
+                    3 => {
+                        let p = take(dihedral, p, 6)?;
+                        (
+                            DihedralPotential::RyckaertBellemans {
+                                c: [p[0], p[1], p[2], p[3], p[4], p[5]],
+                            },
+                            false,
+                        )
+                    }
+                    _ => return Err(unsupported("dihedral", dihedral)),
+                };
+                let n = names(dihedral);
+                let atoms = [n[0].clone(), n[1].clone(), n[2].clone(), n[3].clone()];
+                if improper {
+                    self.bonded.impropers.push((atoms, potential));
+                } else {
+                    self.bonded.dihedrals.push((atoms, potential));
+                }
+            }
+        }
+
+        let defaults = &topology.defaults;
+        for pair in molecule.pairs.iter() {
+            if pair.function != 1 {
+                return Err(unsupported("pair", pair));
+            }
+            let (i, j) = (pair.atoms[0], pair.atoms[1]);
+            let (v, w) = match parameters(pair, &topology.pairtypes).first() {
+                // the same mixing as the non-bonded interactions, then scaled down: C6 and C12 both, or just epsilon.
+                None if defaults.gen_pairs => {
+                    let (a, b) = (types[i], types[j]);
+                    let v = match defaults.comb_rule {
+                        2 => (a.v + b.v) / 2.0,
+                        _ => (a.v * b.v).sqrt(),
+                    };
+                    let w = (a.w * b.w).sqrt() * defaults.fudge_lj;
+                    match defaults.comb_rule {
+                        1 => (v * defaults.fudge_lj, w),
+                        _ => (v, w),
+                    }
+                }
+                found => {
+                    let p = found.map_or(&[][..], |p| p.as_slice());
+                    take(pair, p, 2)?;
+                    (p[0], p[1])
+                }
+            };
+            let (epsilon, sigma) = lennard_jones(defaults.comb_rule, v, w);
+            let qq =
+                defaults.fudge_qq * molecule.atoms[i].charge * molecule.atoms[j].charge * COULOMB;
+            self.bonded.pairs.push((
+                [ids[i].clone(), ids[j].clone()],
+                LennardJones::new(epsilon as f32, sigma as f32),
+                Coulomb::new(qq as f32),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Legion::ForceFields::SIN::Elements;
+    use Legion::Topology::cell::ContainsParticles;
+    use Legion::Topology::periodic::PeriodicBox;
+
+    const ETHANE: &str = "
+[ defaults ]
+  1  1  no  1.0  1.0
+
+[ atomtypes ]
+; C6 and C12 this time
+  CT  CT  6  12.011  0.0  A  4.0e-06  4.0e-12
+  HC  HC  1   1.008  0.0  A  0.0      0.0
+
+[ bondtypes ]
+  CT HC 1 0.109 284512.0
+
+[ pairtypes ]
+  HC HC 1 4.0e-06 4.0e-12
+
+[ dihedraltypes ]
+  X  CT CT X  9  0.0  0.6276  3
+  HC CT CT HC 9  0.0  0.5     3
+  HC CT CT HC 9  180.0  0.1   2
+
+[ moleculetype ]
+  ETH 3
+
+[ atoms ]
+  1  CT  1  ETH  C1  1  -0.18
+  2  CT  1  ETH  C2  1  -0.18
+  3  HC  1  ETH  H1  1   0.18
+  4  HC  1  ETH  H2  1   0.18
+
+[ bonds ]
+  1  2  1  0.1529 224262.4
+  1  3  1
+  2  4  1
+
+[ angles ]
+  2  1  3  5  110.7  313.8  0.2  5000.0
+
+[ dihedrals ]
+  3  1  2  4  9
+  4  2  1  3  1  0.0  1.0  3
+
+[ pairs ]
+  3  4  1
+
+[ molecules ]
+  ETH  2
+";
+
+    #[test]
+    fn test_ethane() {
+        let topology = Topology::parse(ETHANE).unwrap();
+        let system = System::from_topology(&topology).unwrap();
+        assert_eq!(system.atoms.len(), 8);
+        let index = system.index();
+        let c1 = &system.atoms[index["ETH2:1:C1"]];
+        assert_eq!(c1.element, Elements::C(0));
+        assert_eq!(c1.mass, 12.011);
+        assert_eq!(c1.charge, -0.18);
+        assert_eq!(c1.neighbors, vec!["ETH2:2:C2", "ETH2:3:H1"]);
+
+        // C6 = 4e-3 and C12 = 4e-6 is epsilon = 1 and sigma = 0.1
+        let (epsilon, sigma) = system.parameters.get_element(&Elements::C(0));
+        assert!((epsilon - 1.0).abs() < 1e-5 && (sigma - 0.1).abs() < 1e-5);
+        assert_eq!(system.parameters.rule, MixingRule::Geometric);
+
+        // three bonds a molecule; the C-H ones from the bond types.
+        assert_eq!(system.bonded.bonds.len(), 6);
+        assert_eq!(
+            system.bonded.bonds[1],
+            (
+                ["ETH1:1:C1".to_string(), "ETH1:3:H1".to_string()],
+                BondPotential::Harmonic(Harmonic::new(284512.0, 0.109))
+            )
+        );
+        assert_eq!(
+            system.bonded.angles[0].1,
+            AnglePotential::UreyBradley {
+                k: 313.8,
+                theta0: 110.7,
+                k_ub: 5000.0,
+                r_ub: 0.2
+            }
+        );
+        // the specific H-C-C-H types beat the wildcard, and both of them apply; then the one given outright.
+        assert_eq!(system.bonded.dihedrals.len(), 6);
+        assert_eq!(
+            system.bonded.dihedrals[1].1,
+            DihedralPotential::Periodic {
+                k: 0.1,
+                multiplicity: 2,
+                phase: 180.0
+            }
+        );
+        assert_eq!(
+            system.bonded.dihedrals[2].1,
+            DihedralPotential::Periodic {
+                k: 1.0,
+                multiplicity: 3,
+                phase: 0.0
+            }
+        );
+        // everyone's within three bonds of everyone else, but not of the other molecule.
+        assert_eq!(system.exclusions.len(), 12);
+        assert!(system
+            .exclusions
+            .contains(&("ETH1:3:H1".to_string(), "ETH1:4:H2".to_string())));
+    }
+
+    #[test]
+    fn test_pairs() {
+        // from [ pairtypes ], as is: epsilon 1 and sigma 0.1 again, and the full charges.
+        let system = System::from_topology(&Topology::parse(ETHANE).unwrap()).unwrap();
+        assert_eq!(system.bonded.pairs.len(), 2);
+        let ([a, b], lj, coulomb) = &system.bonded.pairs[1];
+        assert_eq!((a.as_str(), b.as_str()), ("ETH2:3:H1", "ETH2:4:H2"));
+        assert!((lj.epsilon - 1.0).abs() < 1e-5 && (lj.sigma - 0.1).abs() < 1e-5);
+        assert!((coulomb.qq - 0.18 * 0.18 * 138.935458).abs() < 1e-4);
+
+        // generated from the atom types instead, and scaled.
+        let text = ETHANE
+            .replace("  1  1  no  1.0  1.0", "  1  1  yes  0.5  0.8333")
+            .replace("  HC HC 1 4.0e-06 4.0e-12", "")
+            .replace("0.0      0.0", "1.0e-06  1.0e-12");
+        let system = System::from_topology(&Topology::parse(&text).unwrap()).unwrap();
+        let (_, lj, coulomb) = &system.bonded.pairs[0];
+        assert!((lj.epsilon - 0.5 * 0.25).abs() < 1e-5 && (lj.sigma - 0.1).abs() < 1e-5);
+        assert!((coulomb.qq - 0.8333 * 0.18 * 0.18 * 138.935458).abs() < 1e-4);
+
+        // and with neither, there's nothing to go on.
+        let text = ETHANE.replace("  HC HC 1 4.0e-06 4.0e-12", "");
+        let err = System::from_topology(&Topology::parse(&text).unwrap())
+            .err()
+            .unwrap();
+        assert!(err.message.contains("needs 2 parameters"));
+    }
+
+    #[test]
+    fn test_into_cell() {
+        let mut system = System::from_topology(&Topology::parse(ETHANE).unwrap()).unwrap();
+        system.periodic_box = Some(PeriodicBox::cubic(3.0));
+        let (cell, bonded, parameters) = system.into_cell();
+        assert_eq!(cell.get_particles().len(), 8);
+        assert_eq!(cell.get_particles()["ETH1:2:C2"].charge, -0.18);
+        assert_eq!(cell.get_periodic_box(), Some(&PeriodicBox::cubic(3.0)));
+        // within a molecule everyone's excluded (the 1-4 is the bonded terms' job), and across them nobody is.
+        let name = |s: &str| s.to_string();
+        assert!(cell.is_excluded(&name("ETH1:4:H2"), &name("ETH1:3:H1")));
+        assert!(cell.is_excluded(&name("ETH2:1:C1"), &name("ETH2:2:C2")));
+        assert!(!cell.is_excluded(&name("ETH1:1:C1"), &name("ETH2:1:C1")));
+        assert_eq!(cell.get_exclusions().len(), 12);
+        assert_eq!((bonded.bonds.len(), bonded.pairs.len()), (6, 2));
+        assert_eq!(parameters.rule, MixingRule::Geometric);
+    }
+
+    #[test]
+    fn test_missing_parameters() {
+        let text = ETHANE.replace("  CT HC 1 0.109 284512.0", "");
+        let err = System::from_topology(&Topology::parse(&text).unwrap())
+            .err()
+            .unwrap();
+        // the C-H bond with nothing given.
+        let line = text.lines().position(|l| l.trim() == "1  3  1").unwrap() + 1;
+        assert_eq!(err.line, line);
+        assert!(err.message.contains("needs 2 parameters"));
+
+        let text = ETHANE.replace("1  2  1  0.1529", "1  2  7  0.1529");
+        let err = System::from_topology(&Topology::parse(&text).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(err.message, "bond function 7 isn't supported");
+    }
+
+    #[test]
+    fn test_clashing_atom_types() {
+        // a second hydrogen type with its own Lennard-Jones can't share H with HC.
+        let text = ETHANE
+            .replace(
+                "  HC  HC  1   1.008  0.0  A  0.0      0.0",
+                "  HC  HC  1   1.008  0.0  A  0.0      0.0\n  HO  HO  1   1.008  0.0  A  1.0e-06  1.0e-12",
+            )
+            .replace("4  HC  1  ETH  H2", "4  HO  1  ETH  H2");
+        let err = System::from_topology(&Topology::parse(&text).unwrap())
+            .err()
+            .unwrap();
+        assert!(err.message.contains("HC and HO are both H"));
+        // the same parameters under another name are fine.
+        let text = ETHANE
+            .replace(
+                "  HC  HC  1   1.008  0.0  A  0.0      0.0",
+                "  HC  HC  1   1.008  0.0  A  0.0      0.0\n  HA  HC  1   1.008  0.0  A  0.0      0.0",
+            )
+            .replace("4  HC  1  ETH  H2", "4  HA  1  ETH  H2");
+        assert!(System::from_topology(&Topology::parse(&text).unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,5 @@
+// GROMACS topologies (.top and the .itp files they pull in).  preprocess deals with the #include / #define layer,
+// topology reads the sections, and convert turns the result into Legion atoms, bonded terms and pair parameters.
+pub mod convert;
+pub mod preprocess;
+pub mod topology;
@@ -0,0 +1,515 @@
+use super::topology::TopologyError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The C-ish preprocessor GROMACS runs over topologies before reading them (cpp, more or less).
+//   #include "file"   pulled in from the including file's directory first, then the include directories in order.
+//                     A file that ends up including itself is an error rather than a stack overflow.
+//   #define NAME [text]   and #undef NAME; a defined name standing on its own as a word gets swapped for its text.
+//   #ifdef / #ifndef / #if / #elif / #else / #endif   nest as you'd expect.  #if and #elif take integer expressions
+//                     the C way: defined(NAME), !, && and ||, comparisons, brackets, numbers, and names (their text,
+//                     or 0 if they're not defined).
+// Comments (anything after a ';') are dropped, and a line ending in '\' carries on onto the next one.
+// What comes out is the lines that survive, each still knowing which file and line it came from.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub file: String,
+    pub number: usize, // from 1, like an editor.
+    pub text: String,
+}
+
+pub struct Preprocessor {
+    pub defines: HashMap<String, String>,
+    pub include_dirs: Vec<PathBuf>, // where the force field directories live; $GMXLIB/share/top and friends.
+    lines: Vec<Line>,
+    conditions: Vec<Condition>, // one for each open #if
+    including: Vec<PathBuf>,    // the files being read right now, outermost first
+}
+
+struct Condition {
+    on: bool,        // is this branch on
+    taken: bool,     // has any branch been on yet, so #elif and #else know to stay off
+    seen_else: bool, // nothing can come after the #else
+}
+
+impl Condition {
+    fn new(on: bool) -> Self {
+        Self {
+            on,
+            taken: on,
+            seen_else: false,
+        }
+    }
+}
+
+// The words of an #if expression: numbers, names, brackets and operators.
+fn tokens(expression: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = expression.chars().collect();
+    let mut k = 0;
+    while k < chars.len() {
+        let c = chars[k];
+        if c.is_whitespace() {
+            k += 1;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = k;
+            while k < chars.len() && (chars[k].is_alphanumeric() || chars[k] == '_') {
+                k += 1;
+            }
+            tokens.push(chars[start..k].iter().collect());
+        } else {
+            let pair: String = chars[k..(k + 2).min(chars.len())].iter().collect();
+            let width = match pair.as_str() {
+                "&&" | "||" | "==" | "!=" | "<=" | ">=" => 2,
+                _ => 1,
+            };
+            tokens.push(chars[k..k + width].iter().collect());
+            k += width;
+        }
+    }
+    tokens
+}
+
+// Recursive descent over the tokens, lowest precedence first; None if it doesn't make sense.
+struct Expression<'a> {
+    tokens: Vec<String>,
+    next: usize,
+    defines: &'a HashMap<String, String>,
+}
+
+impl<'a> Expression<'a> {
+    fn evaluate(text: &str, defines: &'a HashMap<String, String>) -> Option<bool> {
+        let mut expression = Self {
+            tokens: tokens(text),
+            next: 0,
+            defines,
+        };
+        let value = expression.or()?;
+        (expression.next == expression.tokens.len()).then_some(value != 0)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.next).map(|t| t.as_str())
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let matched = self.peek() == Some(token);
+        if matched {
+            self.next += 1;
+        }
+        matched
+    }
+
+    fn or(&mut self) -> Option<i64> {
+        let mut value = self.and()?;
+        while self.eat("||") {
+            let other = self.and()?;
+            value = (value != 0 || other != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn and(&mut self) -> Option<i64> {
+        let mut value = self.comparison()?;
+        while self.eat("&&") {
+            let other = self.comparison()?;
+            value = (value != 0 && other != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn comparison(&mut self) -> Option<i64> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.peek().map(|op| op.to_string()) {
+            let compare: fn(&i64, &i64) -> bool = match op.as_str() {
+                "==" => i64::eq,
+                "!=" => i64::ne,
+                "<" => i64::lt,
+                ">" => i64::gt,
+                "<=" => i64::le,
+                ">=" => i64::ge,
+                _ => break,
+            };
+            self.next += 1;
+            let other = self.unary()?;
+            value = compare(&value, &other) as i64;
+        }
+        Some(value)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        if self.eat("!") {
+            return Some((self.unary()? == 0) as i64);
+        }
+        if self.eat("(") {
+            let value = self.or()?;
+            return self.eat(")").then_some(value);
+        }
+        let word = self.peek()?.to_string();
+        self.next += 1;
+        if word == "defined" {
+            let bracketed = self.eat("(");
+            let name = self.peek()?.to_string();
+            self.next += 1;
+            if bracketed && !self.eat(")") {
+                return None;
+            }
+            return Some(self.defines.contains_key(&name) as i64);
+        }
+        if let Ok(value) = word.parse() {
+            return Some(value);
+        }
+        if !word.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return None;
+        }
+        // a name is worth whatever it's defined as, as long as that's a number; undefined ones are 0, like cpp.
+        match self.defines.get(&word) {
+            Some(text) => text.trim().parse().ok(),
+            None => Some(0),
+        }
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self {
+            defines: HashMap::new(),
+            include_dirs: Vec::new(),
+            lines: Vec::new(),
+            conditions: Vec::new(),
+            including: Vec::new(),
+        }
+    }
+
+    // Same as -DNAME=text on the grompp command line; POSRES, FLEXIBLE and so on.
+    pub fn define(mut self, name: &str, text: &str) -> Self {
+        self.defines.insert(name.to_string(), text.to_string());
+        self
+    }
+
+    pub fn include_dir(mut self, dir: &Path) -> Self {
+        self.include_dirs.push(dir.to_path_buf());
+        self
+    }
+
+    pub fn run_file(mut self, path: &Path) -> Result<Vec<Line>, TopologyError> {
+        let text = fs::read_to_string(path).map_err(|e| TopologyError {
+            file: path.display().to_string(),
+            line: 0,
+            message: e.to_string(),
+        })?;
+        self.including
+            .push(path.canonicalize().unwrap_or(path.to_path_buf()));
+        self.process(&text, &path.display().to_string(), path.parent())?;
+        self.finish()
+    }
+
+    // Text that didn't come from a file; includes are looked for relative to dir, if there is one.
+    pub fn run(mut self, text: &str, dir: Option<&Path>) -> Result<Vec<Line>, TopologyError> {
+        self.process(text, "<topology>", dir)?;
+        self.finish()
+    }
+
+    fn finish(self) -> Result<Vec<Line>, TopologyError> {
+        if !self.conditions.is_empty() {
+            return Err(TopologyError {
+                file: self.lines.last().map_or(String::new(), |l| l.file.clone()),
+                line: 0,
+                message: "#ifdef without a matching #endif".to_string(),
+            });
+        }
+        Ok(self.lines)
+    }
+
+    fn active(&self) -> bool {
+        self.conditions.iter().all(|condition| condition.on)
+    }
+
+    // whether the branch being opened or switched to would be on, if everything around it is.
+    fn test(&self, keyword: &str, expression: &str) -> Result<bool, String> {
+        if !self.active() {
+            return Ok(false);
+        }
+        Expression::evaluate(expression, &self.defines)
+            .ok_or_else(|| format!("can't make sense of #{} {}", keyword, expression))
+    }
+
+    fn process(&mut self, text: &str, file: &str, dir: Option<&Path>) -> Result<(), TopologyError> {
+        let error = |number: usize, message: String| TopologyError {
+            file: file.to_string(),
+            line: number,
+            message,
+        };
+        let raw: Vec<&str> = text.lines().collect();
+        let mut n = 0;
+        while n < raw.len() {
+            let number = n + 1;
+            // glue continued lines back together first, so a comment can't hide the backslash.
+            let mut line = raw[n].to_string();
+            while line.trim_end().ends_with('\\') && n + 1 < raw.len() {
+                line = line.trim_end().trim_end_matches('\\').to_string();
+                n += 1;
+                line.push(' ');
+                line.push_str(raw[n]);
+            }
+            n += 1;
+            let line = match line.find(';') {
+                Some(comment) => line[..comment].trim().to_string(),
+                None => line.trim().to_string(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(directive) = line.strip_prefix('#') {
+                let mut words = directive.split_whitespace();
+                let keyword = words.next().unwrap_or("");
+                let argument = words.next();
+                let expression = directive[keyword.len()..].trim();
+                match keyword {
+                    "ifdef" | "ifndef" => {
+                        let name = argument
+                            .ok_or_else(|| error(number, format!("#{} needs a name", keyword)))?;
+                        let defined = self.defines.contains_key(name);
+                        self.conditions
+                            .push(Condition::new(defined == (keyword == "ifdef")));
+                    }
+                    "if" => {
+                        let on = self
+                            .test(keyword, expression)
+                            .map_err(|message| error(number, message))?;
+                        self.conditions.push(Condition::new(on));
+                    }
+                    // the open #if comes off while the test's done, so only the ones around it count.
+                    "elif" => {
+                        let condition = match self.conditions.pop() {
+                            Some(condition) if !condition.seen_else => condition,
+                            _ => return Err(error(number, "#elif without an #if".to_string())),
+                        };
+                        let on = !condition.taken
+                            && self
+                                .test(keyword, expression)
+                                .map_err(|message| error(number, message))?;
+                        self.conditions.push(Condition {
+                            on,
+                            taken: condition.taken || on,
+                            ..condition
+                        });
+                    }
+                    "else" => match self.conditions.last_mut() {
+                        Some(condition) if !condition.seen_else => {
+                            condition.on = !condition.taken;
+                            condition.taken = true;
+                            condition.seen_else = true;
+                        }
+                        _ => return Err(error(number, "#else without an #ifdef".to_string())),
+                    },
+                    "endif" => {
+                        if self.conditions.pop().is_none() {
+                            return Err(error(number, "#endif without an #ifdef".to_string()));
+                        }
+                    }
+                    // everything else only counts when we're not inside a switched off branch.
+                    _ if !self.active() => {}
+                    "define" => {
+                        let name = argument
+                            .ok_or_else(|| error(number, "#define needs a name".to_string()))?;
+                        let text: Vec<&str> = words.collect();
+                        self.defines.insert(name.to_string(), text.join(" "));
+                    }
+                    "undef" => {
+                        if let Some(name) = argument {
+                            self.defines.remove(name);
+                        }
+                    }
+                    "include" => {
+                        let name = directive["include".len()..]
+                            .trim()
+                            .trim_matches(|c| c == '"' || c == '<' || c == '>');
+                        let path = self.find(name, dir).ok_or_else(|| {
+                            error(number, format!("couldn't find included file {}", name))
+                        })?;
+                        let canonical = path.canonicalize().unwrap_or(path.clone());
+                        if self.including.contains(&canonical) {
+                            return Err(error(
+                                number,
+                                format!("{} ends up including itself", path.display()),
+                            ));
+                        }
+                        let text = fs::read_to_string(&path)
+                            .map_err(|e| error(number, format!("{}: {}", path.display(), e)))?;
+                        let open = self.conditions.len();
+                        self.including.push(canonical);
+                        self.process(&text, &path.display().to_string(), path.parent())?;
+                        self.including.pop();
+                        if self.conditions.len() != open {
+                            return Err(error(
+                                number,
+                                format!("unbalanced #ifdef in {}", path.display()),
+                            ));
+                        }
+                    }
+                    _ => return Err(error(number, format!("unknown directive #{}", keyword))),
+                }
+                continue;
+            }
+
+            if self.active() {
+                let text: Vec<&str> = line
+                    .split_whitespace()
+                    .map(|word| self.defines.get(word).map_or(word, |s| s.as_str()))
+                    .collect();
+                self.lines.push(Line {
+                    file: file.to_string(),
+                    number,
+                    text: text.join(" "),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &str, dir: Option<&Path>) -> Option<PathBuf> {
+        dir.into_iter()
+            .chain(self.include_dirs.iter().map(|d| d.as_path()))
+            .map(|d| d.join(name))
+            .chain(std::iter::once(PathBuf::from(name)))
+            .find(|path| path.is_file())
+    }
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defines_and_conditions() {
+        let text = "
+            #define gb_1 0.1000  1.5700e+07 ; a bond type
+            #define POSRES
+            [ bonds ] ; comment
+            1 2 2 gb_1
+            #ifdef POSRES
+            posres on
+            #else
+            posres off
+            #endif
+            #ifndef FLEXIBLE
+            rigid \\
+              water
+            #endif
+        ";
+        let lines = Preprocessor::new().run(text, None).unwrap();
+        let texts: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "[ bonds ]",
+                "1 2 2 0.1000 1.5700e+07",
+                "posres on",
+                "rigid water"
+            ]
+        );
+        assert_eq!(lines[1].number, 5);
+
+        let lines = Preprocessor::new()
+            .define("FLEXIBLE", "")
+            .run("#ifndef FLEXIBLE\nrigid\n#endif\nafter", None)
+            .unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "after");
+
+        assert!(Preprocessor::new().run("#ifdef A\n", None).is_err());
+        assert!(Preprocessor::new().run("#endif\n", None).is_err());
+    }
+
+    #[test]
+    fn test_if_and_elif() {
+        let text = "
+            #if defined(CHARMM) && VERSION >= 36
+            new
+            #elif defined CHARMM
+            old
+            #elif !defined(CHARMM) || (VERSION == 1)
+            other
+            #else
+            never
+            #endif
+            #ifdef OFF
+            #if oh no, this isn't an expression
+            hidden
+            #endif
+            #endif
+            after
+        ";
+        let run = |preprocessor: Preprocessor| -> Vec<String> {
+            let lines = preprocessor.run(text, None).unwrap();
+            lines.into_iter().map(|l| l.text).collect()
+        };
+        let charmm = || Preprocessor::new().define("CHARMM", "");
+        assert_eq!(run(charmm().define("VERSION", "36")), vec!["new", "after"]);
+        assert_eq!(run(charmm().define("VERSION", "27")), vec!["old", "after"]);
+        assert_eq!(run(Preprocessor::new()), vec!["other", "after"]);
+
+        let err = Preprocessor::new().run("#if (A\n#endif", None).unwrap_err();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (1, "can't make sense of #if (A")
+        );
+        assert!(Preprocessor::new().run("#elif A\n", None).is_err());
+        assert!(Preprocessor::new()
+            .run("#if 1\n#else\n#elif 1\n#endif", None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = std::env::temp_dir().join("decay_forge_preprocess");
+        fs::create_dir_all(dir.join("ff")).unwrap();
+        fs::write(
+            dir.join("ff").join("forcefield.itp"),
+            "#define SIGMA 0.3\natoms SIGMA\n",
+        )
+        .unwrap();
+        fs::write(dir.join("water.itp"), "water\n").unwrap();
+
+        let lines = Preprocessor::new()
+            .include_dir(&dir.join("ff"))
+            .run(
+                "#include \"forcefield.itp\"\n#include \"water.itp\"\nSIGMA",
+                Some(&dir),
+            )
+            .unwrap();
+        let texts: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["atoms 0.3", "water", "0.3"]);
+        assert!(lines[0].file.ends_with("forcefield.itp"));
+
+        let missing = Preprocessor::new().run("\n#include \"nope.itp\"", Some(&dir));
+        assert_eq!(missing.unwrap_err().line, 2);
+
+        // a file can be included twice over, just not inside itself.
+        let lines = Preprocessor::new()
+            .run("#include \"water.itp\"\n#include \"water.itp\"", Some(&dir))
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+        fs::write(dir.join("a.itp"), "a\n#include \"b.itp\"\n").unwrap();
+        fs::write(dir.join("b.itp"), "b\n#include \"a.itp\"\n").unwrap();
+        let err = Preprocessor::new()
+            .run_file(&dir.join("a.itp"))
+            .unwrap_err();
+        assert!(err.file.ends_with("b.itp"), "{}", err);
+        assert!(
+            err.message.ends_with("a.itp ends up including itself"),
+            "{}",
+            err
+        );
+    }
+}
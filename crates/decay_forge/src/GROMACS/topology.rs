@@ -0,0 +1,516 @@
+use super::preprocess::{Line, Preprocessor};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+// https://manual.gromacs.org/current/reference-manual/topologies/topology-file-formats.html
+// A .top, after the preprocessor's been over it, is a run of [ sections ].  The ones read here:
+//   [ defaults ]        nbfunc comb-rule gen-pairs fudgeLJ fudgeQQ
+//   [ atomtypes ]       name (bond_type) (at.num) mass charge ptype V W
+//   [ bondtypes ], [ angletypes ], [ dihedraltypes ], [ pairtypes ]   parameters by atom (bond) type, for terms that
+//                       don't give their own
+//   [ moleculetype ]    name nrexcl; starts a molecule, and everything up to the next one belongs to it:
+//     [ atoms ]         nr type resnr residue atom cgnr charge (mass)
+//     [ bonds ], [ angles ], [ dihedrals ], [ pairs ]   atom numbers, function type, then parameters (if any)
+//   [ system ]          a title
+//   [ molecules ]       molecule name and how many, in the order they turn up in the coordinates
+// Anything else ([ settles ], [ exclusions ], [ position_restraints ]...) gets skipped over.
+// Units stay GROMACS's: nm, kJ/mol, amu, e and degrees.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Defaults {
+    pub nbfunc: u32,    // 1 is Lennard-Jones, 2 Buckingham
+    pub comb_rule: u32, // 1 means the atom types give C6 and C12; 2 and 3 give sigma and epsilon
+    pub gen_pairs: bool,
+    pub fudge_lj: f64,
+    pub fudge_qq: f64,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Self {
+            nbfunc: 1,
+            comb_rule: 1,
+            gen_pairs: false,
+            fudge_lj: 1.0,
+            fudge_qq: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtomType {
+    pub name: String,
+    pub bond_type: String, // same as the name when the file doesn't give one
+    pub atomic_number: Option<u32>,
+    pub mass: f64,
+    pub charge: f64,
+    pub ptype: String,
+    pub v: f64, // sigma, or C6 with comb-rule 1
+    pub w: f64, // epsilon, or C12 with comb-rule 1
+}
+
+// A [ bondtypes ] / [ angletypes ] / [ dihedraltypes ] / [ pairtypes ] line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InteractionType {
+    pub types: Vec<String>,
+    pub function: u32,
+    pub parameters: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyAtom {
+    pub nr: usize,
+    pub atom_type: String,
+    pub resnr: i64,
+    pub residue: String,
+    pub name: String,
+    pub cgnr: usize,
+    pub charge: f64,
+    pub mass: Option<f64>, // the atom type's otherwise
+}
+
+// A bond, angle, dihedral or 1-4 pair inside a molecule.  Parameters are empty when they're to come from the types.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub atoms: Vec<usize>, // indices into the molecule's atoms, from 0
+    pub function: u32,
+    pub parameters: Vec<f64>,
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoleculeType {
+    pub name: String,
+    pub nrexcl: usize, // non-bonded interactions are skipped between atoms this many bonds apart or fewer
+    pub atoms: Vec<TopologyAtom>,
+    pub bonds: Vec<Interaction>,
+    pub angles: Vec<Interaction>,
+    pub dihedrals: Vec<Interaction>,
+    pub pairs: Vec<Interaction>, // 1-4s, with V and W like the atom types when they're given
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Topology {
+    pub defaults: Defaults,
+    pub atomtypes: Vec<AtomType>,
+    pub bondtypes: Vec<InteractionType>,
+    pub angletypes: Vec<InteractionType>,
+    pub dihedraltypes: Vec<InteractionType>,
+    pub pairtypes: Vec<InteractionType>,
+    pub moleculetypes: Vec<MoleculeType>,
+    pub system: String,
+    pub molecules: Vec<(String, usize)>,
+}
+
+fn number<T: FromStr>(line: &Line, word: &str, what: &str) -> Result<T, TopologyError> {
+    word.parse().map_err(|_| TopologyError {
+        file: line.file.clone(),
+        line: line.number,
+        message: format!("{} should be a number, not {}", what, word),
+    })
+}
+
+fn numbers(line: &Line, words: &[&str]) -> Result<Vec<f64>, TopologyError> {
+    words
+        .iter()
+        .map(|word| number(line, word, "parameter"))
+        .collect()
+}
+
+fn error(line: &Line, message: String) -> TopologyError {
+    TopologyError {
+        file: line.file.clone(),
+        line: line.number,
+        message,
+    }
+}
+
+// ptype is a single letter; A for atoms, V or D for virtual sites, S for shells.
+fn is_ptype(word: &str) -> bool {
+    matches!(word, "A" | "B" | "D" | "S" | "V")
+}
+
+impl AtomType {
+    // The columns shift about depending on whether the bond type and atomic number are there, so work back from the
+    // end, where it's always mass charge ptype V W.
+    fn parse(line: &Line, words: &[&str]) -> Result<Self, TopologyError> {
+        let n = words.len();
+        if n < 6 || !is_ptype(words[n - 3]) {
+            return Err(error(
+                line,
+                "atom types need at least name mass charge ptype V W".to_string(),
+            ));
+        }
+        let extra = &words[1..n - 5];
+        let atomic_number = match extra.last() {
+            Some(word) if extra.len() == 2 || word.parse::<u32>().is_ok() => {
+                Some(number(line, word, "atomic number")?)
+            }
+            _ => None,
+        };
+        let bond_type = match extra.first() {
+            Some(word) if extra.len() == 2 || atomic_number.is_none() => word.to_string(),
+            _ => words[0].to_string(),
+        };
+        Ok(Self {
+            name: words[0].to_string(),
+            bond_type,
+            atomic_number,
+            mass: number(line, words[n - 5], "mass")?,
+            charge: number(line, words[n - 4], "charge")?,
+            ptype: words[n - 3].to_string(),
+            v: number(line, words[n - 2], "V")?,
+            w: number(line, words[n - 1], "W")?,
+        })
+    }
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self {
+            defaults: Defaults::default(),
+            atomtypes: Vec::new(),
+            bondtypes: Vec::new(),
+            angletypes: Vec::new(),
+            dihedraltypes: Vec::new(),
+            pairtypes: Vec::new(),
+            moleculetypes: Vec::new(),
+            system: String::new(),
+            molecules: Vec::new(),
+        }
+    }
+
+    // A .top on disk, includes and all.  For include directories or defines, run a Preprocessor and use from_lines.
+    pub fn from_file(path: &Path) -> Result<Self, TopologyError> {
+        Self::from_lines(&Preprocessor::new().run_file(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, TopologyError> {
+        Self::from_lines(&Preprocessor::new().run(text, None)?)
+    }
+
+    pub fn from_lines(lines: &[Line]) -> Result<Self, TopologyError> {
+        let mut topology = Self::new();
+        let mut section = String::new();
+        for line in lines {
+            if line.text.starts_with('[') {
+                section = line
+                    .text
+                    .trim_matches(|c: char| c == '[' || c == ']' || c.is_whitespace())
+                    .to_string();
+                continue;
+            }
+            let words: Vec<&str> = line.text.split_whitespace().collect();
+            // molecule sections need a [ moleculetype ] above them.
+            let in_molecule = matches!(
+                section.as_str(),
+                "atoms" | "bonds" | "pairs" | "angles" | "dihedrals"
+            );
+            if in_molecule && topology.moleculetypes.is_empty() {
+                return Err(error(
+                    line,
+                    format!("[ {} ] before any [ moleculetype ]", section),
+                ));
+            }
+            match section.as_str() {
+                "defaults" => {
+                    let word = |k: usize| words.get(k).copied();
+                    let defaults = &mut topology.defaults;
+                    defaults.nbfunc = number(line, word(0).unwrap_or("1"), "nbfunc")?;
+                    defaults.comb_rule = number(line, word(1).unwrap_or("1"), "comb-rule")?;
+                    defaults.gen_pairs = word(2).is_some_and(|w| w.eq_ignore_ascii_case("yes"));
+                    defaults.fudge_lj = number(line, word(3).unwrap_or("1"), "fudgeLJ")?;
+                    defaults.fudge_qq = number(line, word(4).unwrap_or("1"), "fudgeQQ")?;
+                }
+                "atomtypes" => topology.atomtypes.push(AtomType::parse(line, &words)?),
+                "bondtypes" => topology
+                    .bondtypes
+                    .push(Self::interaction_type(line, &words, 2)?),
+                "angletypes" => topology
+                    .angletypes
+                    .push(Self::interaction_type(line, &words, 3)?),
+                "pairtypes" => topology
+                    .pairtypes
+                    .push(Self::interaction_type(line, &words, 2)?),
+                "dihedraltypes" => {
+                    // older files only give the middle two (or outer two, for impropers); pad them out with wildcards.
+                    let typed = if words.len() > 2 && words[2].parse::<u32>().is_ok() {
+                        let function: u32 = number(line, words[2], "function")?;
+                        let types = if function == 2 || function == 4 {
+                            vec![words[0], "X", "X", words[1]]
+                        } else {
+                            vec!["X", words[0], words[1], "X"]
+                        };
+                        InteractionType {
+                            types: types.iter().map(|s| s.to_string()).collect(),
+                            function,
+                            parameters: numbers(line, &words[3..])?,
+                        }
+                    } else {
+                        Self::interaction_type(line, &words, 4)?
+                    };
+                    topology.dihedraltypes.push(typed);
+                }
+                "moleculetype" => {
+                    let name = words[0].to_string();
+                    let nrexcl = number(line, words.get(1).copied().unwrap_or("3"), "nrexcl")?;
+                    topology.moleculetypes.push(MoleculeType {
+                        name,
+                        nrexcl,
+                        atoms: Vec::new(),
+                        bonds: Vec::new(),
+                        angles: Vec::new(),
+                        dihedrals: Vec::new(),
+                        pairs: Vec::new(),
+                    });
+                }
+                "atoms" => {
+                    if words.len() < 7 {
+                        return Err(error(
+                            line,
+                            "atoms need nr type resnr residue atom cgnr charge".to_string(),
+                        ));
+                    }
+                    let molecule = topology.moleculetypes.last_mut().unwrap();
+                    let nr: usize = number(line, words[0], "atom number")?;
+                    if nr != molecule.atoms.len() + 1 {
+                        return Err(error(
+                            line,
+                            format!("atom {} is out of order in {}", nr, molecule.name),
+                        ));
+                    }
+                    molecule.atoms.push(TopologyAtom {
+                        nr,
+                        atom_type: words[1].to_string(),
+                        resnr: number(line, words[2], "residue number")?,
+                        residue: words[3].to_string(),
+                        name: words[4].to_string(),
+                        cgnr: number(line, words[5], "charge group")?,
+                        charge: number(line, words[6], "charge")?,
+                        mass: match words.get(7) {
+                            Some(word) => Some(number(line, word, "mass")?),
+                            None => None,
+                        },
+                    });
+                }
+                "bonds" | "pairs" | "angles" | "dihedrals" => {
+                    let count = match section.as_str() {
+                        "bonds" | "pairs" => 2,
+                        "angles" => 3,
+                        _ => 4,
+                    };
+                    let molecule = topology.moleculetypes.last_mut().unwrap();
+                    if words.len() < count + 1 {
+                        return Err(error(
+                            line,
+                            format!("{} need {} atoms and a function type", section, count),
+                        ));
+                    }
+                    let mut atoms = Vec::new();
+                    for word in &words[..count] {
+                        let nr: usize = number(line, word, "atom number")?;
+                        if nr == 0 || nr > molecule.atoms.len() {
+                            return Err(error(
+                                line,
+                                format!("there's no atom {} in {}", nr, molecule.name),
+                            ));
+                        }
+                        atoms.push(nr - 1);
+                    }
+                    let interaction = Interaction {
+                        atoms,
+                        function: number(line, words[count], "function")?,
+                        parameters: numbers(line, &words[count + 1..])?,
+                        file: line.file.clone(),
+                        line: line.number,
+                    };
+                    match section.as_str() {
+                        "bonds" => molecule.bonds.push(interaction),
+                        "pairs" => molecule.pairs.push(interaction),
+                        "angles" => molecule.angles.push(interaction),
+                        _ => molecule.dihedrals.push(interaction),
+                    }
+                }
+                "system" => {
+                    if !topology.system.is_empty() {
+                        topology.system.push(' ');
+                    }
+                    topology.system.push_str(&line.text);
+                }
+                "molecules" => {
+                    if words.len() != 2 {
+                        return Err(error(line, "molecules are a name and a count".to_string()));
+                    }
+                    if topology.molecule(words[0]).is_none() {
+                        return Err(error(
+                            line,
+                            format!("no [ moleculetype ] called {}", words[0]),
+                        ));
+                    }
+                    let count = number(line, words[1], "molecule count")?;
+                    topology.molecules.push((words[0].to_string(), count));
+                }
+                "" => return Err(error(line, "text before the first [ section ]".to_string())),
+                _ => {}
+            }
+        }
+        Ok(topology)
+    }
+
+    fn interaction_type(
+        line: &Line,
+        words: &[&str],
+        count: usize,
+    ) -> Result<InteractionType, TopologyError> {
+        if words.len() < count + 1 {
+            return Err(error(
+                line,
+                format!("need {} types and a function type", count),
+            ));
+        }
+        Ok(InteractionType {
+            types: words[..count].iter().map(|s| s.to_string()).collect(),
+            function: number(line, words[count], "function")?,
+            parameters: numbers(line, &words[count + 1..])?,
+        })
+    }
+
+    pub fn atomtype(&self, name: &str) -> Option<&AtomType> {
+        self.atomtypes.iter().find(|t| t.name == name)
+    }
+
+    pub fn molecule(&self, name: &str) -> Option<&MoleculeType> {
+        self.moleculetypes.iter().find(|m| m.name == name)
+    }
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WATER: &str = "
+[ defaults ]
+; nbfunc  comb-rule  gen-pairs  fudgeLJ  fudgeQQ
+  1        2          yes        0.5      0.8333
+
+[ atomtypes ]
+; name  at.num  mass     charge  ptype  sigma        epsilon
+  OW    8       15.9994  0.0     A      3.15061e-01  6.36386e-01
+  HW    1       1.008    0.0     A      0.0          0.0
+
+[ bondtypes ]
+  OW HW 1 0.09572 502416.0
+
+[ pairtypes ]
+  HW HW 1 0.1 0.05
+
+[ moleculetype ]
+; name  nrexcl
+  SOL   2
+
+[ atoms ]
+  1  OW  1  SOL  OW   1  -0.834
+  2  HW  1  SOL  HW1  1   0.417
+  3  HW  1  SOL  HW2  1   0.417  1.008
+
+[ bonds ]
+  1  2  1
+  1  3  1  0.09572 502416.0
+
+[ pairs ]
+  2  3  1
+
+[ angles ]
+  2  1  3  1  104.52  628.02
+
+[ system ]
+  Two waters
+
+[ molecules ]
+  SOL  2
+";
+
+    #[test]
+    fn test_water() {
+        let topology = Topology::parse(WATER).unwrap();
+        assert_eq!(topology.defaults.comb_rule, 2);
+        assert!(topology.defaults.gen_pairs);
+        assert_eq!(topology.atomtypes.len(), 2);
+        assert_eq!(topology.atomtype("OW").unwrap().atomic_number, Some(8));
+        let water = topology.molecule("SOL").unwrap();
+        assert_eq!(water.nrexcl, 2);
+        assert_eq!(water.atoms[2].name, "HW2");
+        assert_eq!(water.atoms[2].mass, Some(1.008));
+        assert_eq!(water.atoms[0].mass, None);
+        assert_eq!(water.bonds[0].atoms, vec![0, 1]);
+        assert!(water.bonds[0].parameters.is_empty());
+        assert_eq!(water.angles[0].parameters, vec![104.52, 628.02]);
+        assert_eq!(water.pairs[0].atoms, vec![1, 2]);
+        assert_eq!(topology.pairtypes[0].parameters, vec![0.1, 0.05]);
+        assert_eq!(topology.system, "Two waters");
+        assert_eq!(topology.molecules, vec![("SOL".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_atomtype_columns() {
+        let line = Line {
+            file: String::new(),
+            number: 1,
+            text: String::new(),
+        };
+        let parse = |text: &str| {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            AtomType::parse(&line, &words).unwrap()
+        };
+        // all of them, just the atomic number, just the bond type, and neither.
+        let full = parse("opls_135 CT 6 12.011 -0.18 A 3.5e-01 2.76144e-01");
+        assert_eq!(
+            (full.bond_type.as_str(), full.atomic_number, full.mass),
+            ("CT", Some(6), 12.011)
+        );
+        let numbered = parse("c3 6 12.01 0.0 A 0.339967 0.45773");
+        assert_eq!(
+            (numbered.bond_type.as_str(), numbered.atomic_number),
+            ("c3", Some(6))
+        );
+        let bonded = parse("CH3 C 15.035 0.0 A 0.375 0.8148");
+        assert_eq!(
+            (bonded.bond_type.as_str(), bonded.atomic_number),
+            ("C", None)
+        );
+        let bare = parse("OW 15.9994 0.0 A 0.315 0.636");
+        assert_eq!((bare.bond_type.as_str(), bare.v), ("OW", 0.315));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = Topology::parse("[ atoms ]\n1 OW 1 SOL OW 1 0.0").unwrap_err();
+        assert_eq!(err.line, 2);
+        let err =
+            Topology::parse("[ moleculetype ]\nA 3\n[ atoms ]\n1 X 1 R X 1 0.0\n[ bonds ]\n1 2 1")
+                .unwrap_err();
+        assert_eq!(err.to_string(), "<topology>:6: there's no atom 2 in A");
+        assert!(Topology::parse("[ molecules ]\nSOL 3").is_err());
+    }
+}
@@ -0,0 +1,516 @@
+use super::forcefield::{error, AtomType, ForceField, OpenMMError, Template};
+use crate::system::{within_bonds, ElementTypes, System};
+use crate::PDB::coordinates::CoordinateRecord;
+use crate::PDB::crystal::CrystalRecord;
+use std::collections::HashSet;
+use Legion::Dynamics::bonded::{AnglePotential, BondPotential, Bonded, DihedralPotential};
+use Legion::ForceFields::mixing::{MixingRule, PairParameters};
+use Legion::ForceFields::potentials::{Coulomb, Harmonic, LennardJones};
+use Legion::ForceFields::SIN::Elements;
+use Legion::Topology::atom::AtomBuilder;
+
+// Putting an OpenMM force field onto a PDB structure.  Each residue (a run of atoms with the same chain, number and
+// name) gets the template with exactly its atom names, preferring one with the residue's own name; so terminal and
+// protonation variants (NALA, HIE...) get picked up by their atoms, but the structure does need the force field's
+// atom names, hydrogens and all.  Bonds come from the templates, and between residues by pairing up the atoms with
+// external bonds, closest first, out to EXTERNAL_BOND Angstroms (peptide bonds, disulfides...).
+// From the bond graph, every angle, proper dihedral and improper (each trio of neighbors around an atom with three or
+// more) that the force field has parameters for becomes a term; the ones it doesn't are left out, like OpenMM does.
+// Impropers have their center third, the way OpenMM evaluates them.
+// Lennard-Jones goes by element (types sharing one have to agree), and 1-2, 1-3 and 1-4 pairs are excluded.  The 1-4s (three
+// bonds apart, and no closer round a ring) come back as Bonded's pairs, mixed from their own types and scaled by
+// lj14scale and coulomb14scale, the way OpenMM makes its exceptions.  Positions, and the PDB's unit cell if it has a
+// real one (not the 1 x 1 x 1 placeholder), are converted from Angstroms to nm to go with the parameters.
+
+const EXTERNAL_BOND: f64 = 2.5;
+
+// kJ mol^-1 nm e^-2
+const COULOMB: f64 = 138.935458;
+
+struct Residue<'a> {
+    label: String,
+    atoms: Vec<&'a CoordinateRecord>,
+    template: &'a Template,
+    start: usize, // index of the residue's first atom in the whole structure
+}
+
+// The template for a residue: one with exactly these atom names, its own name if there's a choice.  Then which
+// template atom each of the residue's atoms is.
+fn find_template<'a>(
+    forcefield: &'a ForceField,
+    atoms: &[&CoordinateRecord],
+) -> Option<(&'a Template, Vec<usize>)> {
+    let fits = |template: &'a Template| -> Option<(&'a Template, Vec<usize>)> {
+        if template.atoms.len() != atoms.len() {
+            return None;
+        }
+        let order: Option<Vec<usize>> = atoms.iter().map(|a| template.atom(&a.name)).collect();
+        let order = order?;
+        // and no name twice.
+        let mut seen = vec![false; order.len()];
+        for &k in order.iter() {
+            if seen[k] {
+                return None;
+            }
+            seen[k] = true;
+        }
+        Some((template, order))
+    };
+    let name = &atoms[0].resName;
+    forcefield
+        .templates
+        .iter()
+        .filter(|t| t.name == *name)
+        .chain(forcefield.templates.iter().filter(|t| t.name != *name))
+        .find_map(fits)
+}
+
+fn distance(a: &CoordinateRecord, b: &CoordinateRecord) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+impl System {
+    pub fn from_openmm(
+        forcefield: &ForceField,
+        structure: &[CoordinateRecord],
+        crystal: Option<&CrystalRecord>,
+    ) -> Result<Self, OpenMMError> {
+        // split into residues, then give each its template, with its atoms in the template's order.
+        let mut runs: Vec<Vec<&CoordinateRecord>> = Vec::new();
+        for atom in structure.iter() {
+            let same = runs.last().is_some_and(|run| {
+                let first = run[0];
+                (&first.chainId, first.resSeq, &first.iCode, &first.resName)
+                    == (&atom.chainId, atom.resSeq, &atom.iCode, &atom.resName)
+            });
+            if same {
+                runs.last_mut().unwrap().push(atom);
+            } else {
+                runs.push(vec![atom]);
+            }
+        }
+        let mut residues = Vec::new();
+        let mut start = 0;
+        for run in runs {
+            let label = format!("{}{}", run[0].resName, run[0].resSeq);
+            let (template, order) = find_template(forcefield, &run).ok_or_else(|| {
+                error(
+                    &format!("residue {} (chain {})", label, run[0].chainId),
+                    "no template has these atoms".to_string(),
+                )
+            })?;
+            let mut atoms = run.clone();
+            for (k, &t) in order.iter().enumerate() {
+                atoms[t] = run[k];
+            }
+            let count = atoms.len();
+            residues.push(Residue {
+                label,
+                atoms,
+                template,
+                start,
+            });
+            start += count;
+        }
+        let natom = start;
+
+        let mut ids = Vec::new();
+        let mut types: Vec<&AtomType> = Vec::new();
+        let mut records = Vec::new();
+        let mut charges = Vec::new();
+        let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); natom];
+        let mut external = Vec::new();
+        for residue in residues.iter() {
+            let context = format!("residue {}", residue.label);
+            for (k, record) in residue.atoms.iter().enumerate() {
+                let atom = &residue.template.atoms[k];
+                let atom_type = forcefield.atom_type(&atom.atom_type).ok_or_else(|| {
+                    error(
+                        &context,
+                        format!("{} has an unknown type {}", atom.name, atom.atom_type),
+                    )
+                })?;
+                let charge = atom
+                    .charge
+                    .or_else(|| forcefield.nonbonded_parameters(atom_type)?.charge)
+                    .ok_or_else(|| error(&context, format!("no charge for {}", atom.name)))?;
+                ids.push(format!(
+                    "{}:{}:{}",
+                    residue.label, record.serial, record.name
+                ));
+                types.push(atom_type);
+                records.push(*record);
+                charges.push(charge);
+            }
+            for &(i, j) in residue.template.bonds.iter() {
+                neighbors[residue.start + i].push(residue.start + j);
+                neighbors[residue.start + j].push(residue.start + i);
+            }
+            external.extend(
+                residue
+                    .template
+                    .external_bonds
+                    .iter()
+                    .map(|&i| residue.start + i),
+            );
+        }
+
+        // external bonds, closest first; each one can only be used the once.
+        let residue_of = |i: usize| residues.iter().rposition(|r| r.start <= i).unwrap();
+        let mut candidates = Vec::new();
+        for (a, &i) in external.iter().enumerate() {
+            for &j in external[a + 1..].iter() {
+                let r = distance(records[i], records[j]);
+                if residue_of(i) != residue_of(j) && r < EXTERNAL_BOND {
+                    candidates.push((r, i, j));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut free = external.clone();
+        for (_, i, j) in candidates {
+            if let (Some(a), Some(b)) = (
+                free.iter().position(|&k| k == i),
+                free.iter().position(|&k| k == j),
+            ) {
+                free.remove(a.max(b));
+                free.remove(a.min(b));
+                neighbors[i].push(j);
+                neighbors[j].push(i);
+            }
+        }
+
+        let mut parameters = PairParameters::new(MixingRule::LorentzBerthelot);
+        let mut elements = Vec::new();
+        let mut element_types = ElementTypes::new();
+        for atom_type in types.iter() {
+            let element = Elements::from_symbol(&atom_type.element).unwrap_or(Elements::X(0));
+            let lj = forcefield.nonbonded_parameters(atom_type).ok_or_else(|| {
+                error(
+                    "NonbondedForce",
+                    format!("no parameters for type {}", atom_type.name),
+                )
+            })?;
+            element_types
+                .add(
+                    &mut parameters,
+                    &element,
+                    &atom_type.name,
+                    lj.epsilon as f32,
+                    lj.sigma as f32,
+                )
+                .map_err(|message| error("NonbondedForce", message))?;
+            elements.push(element);
+        }
+
+        let mut bonded = Bonded::new();
+        for i in 0..natom {
+            for &j in neighbors[i].iter().filter(|&&j| i < j) {
+                if let Some(bond) = forcefield.bond_parameters(types[i], types[j]) {
+                    bonded.bonds.push((
+                        [ids[i].clone(), ids[j].clone()],
+                        BondPotential::Harmonic(Harmonic::new(bond.k as f32, bond.length as f32)),
+                    ));
+                }
+                // dihedrals around the i-j bond.
+                for &h in neighbors[i].iter().filter(|&&h| h != j) {
+                    for &k in neighbors[j].iter().filter(|&&k| k != i && k != h) {
+                        if let Some(proper) =
+                            forcefield.proper_parameters([types[h], types[i], types[j], types[k]])
+                        {
+                            for &(periodicity, phase, k_phi) in proper.terms.iter() {
+                                bonded.dihedrals.push((
+                                    [
+                                        ids[h].clone(),
+                                        ids[i].clone(),
+                                        ids[j].clone(),
+                                        ids[k].clone(),
+                                    ],
+                                    DihedralPotential::Periodic {
+                                        k: k_phi as f32,
+                                        multiplicity: periodicity,
+                                        phase: (phase as f32).to_degrees(),
+                                    },
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+            let around = &neighbors[i];
+            for (a, &h) in around.iter().enumerate() {
+                for &k in around[a + 1..].iter() {
+                    if let Some(angle) = forcefield.angle_parameters(types[h], types[i], types[k]) {
+                        bonded.angles.push((
+                            [ids[h].clone(), ids[i].clone(), ids[k].clone()],
+                            AnglePotential::Harmonic {
+                                k: angle.k as f32,
+                                theta0: (angle.angle as f32).to_degrees(),
+                            },
+                        ));
+                    }
+                }
+            }
+            for a in 0..around.len() {
+                for b in a + 1..around.len() {
+                    for c in b + 1..around.len() {
+                        let others = [around[a], around[b], around[c]];
+                        let found = forcefield.improper_parameters(
+                            types[i],
+                            [types[others[0]], types[others[1]], types[others[2]]],
+                        );
+                        if let Some((improper, order)) = found {
+                            let [x, y, z] = order.map(|k| ids[others[k]].clone());
+                            for &(periodicity, phase, k_phi) in improper.terms.iter() {
+                                bonded.impropers.push((
+                                    [x.clone(), y.clone(), ids[i].clone(), z.clone()],
+                                    DihedralPotential::Periodic {
+                                        k: k_phi as f32,
+                                        multiplicity: periodicity,
+                                        phase: (phase as f32).to_degrees(),
+                                    },
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let excluded = within_bonds(&neighbors, 3);
+        let closer: HashSet<(usize, usize)> = within_bonds(&neighbors, 2).into_iter().collect();
+        for &(i, j) in excluded.iter().filter(|pair| !closer.contains(pair)) {
+            let nonbonded = |k: usize| {
+                forcefield.nonbonded_parameters(types[k]).ok_or_else(|| {
+                    error(
+                        "NonbondedForce",
+                        format!("no parameters for type {}", types[k].name),
+                    )
+                })
+            };
+            let (a, b) = (nonbonded(i)?, nonbonded(j)?);
+            let epsilon = (a.epsilon * b.epsilon).sqrt() * forcefield.lj14scale;
+            let qq = charges[i] * charges[j] * forcefield.coulomb14scale * COULOMB;
+            bonded.pairs.push((
+                [ids[i].clone(), ids[j].clone()],
+                LennardJones::new(epsilon as f32, ((a.sigma + b.sigma) / 2.0) as f32),
+                Coulomb::new(qq as f32),
+            ));
+        }
+        let exclusions = excluded
+            .into_iter()
+            .map(|(i, j)| (ids[i].clone(), ids[j].clone()))
+            .collect();
+        let atoms = (0..natom)
+            .map(|k| {
+                let record = records[k];
+                AtomBuilder::new()
+                    .element(elements[k].clone())
+                    .id(Some(ids[k].clone()))
+                    .neighbors(neighbors[k].iter().map(|&n| ids[n].clone()).collect())
+                    .mass(types[k].mass as f32)
+                    .charge(charges[k] as f32)
+                    .position(
+                        [record.x, record.y, record.z]
+                            .iter()
+                            .map(|&x| (0.1 * x) as f32)
+                            .collect(),
+                    )
+                    .velocity(vec![0.0; 3])
+                    .acceleration(vec![0.0; 3])
+                    .build()
+            })
+            .collect();
+        let periodic_box = crystal
+            .filter(|record| record.lattice()[..3] != [1.0; 3])
+            .map(|record| record.periodic_box().scaled(0.1));
+        Ok(System {
+            name: String::new(),
+            atoms,
+            bonded,
+            parameters,
+            exclusions,
+            periodic_box,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PDB::coordinates::atoms;
+
+    // methanol, in two pieces joined by an external bond, and water.  Only some of the parameters are there, and the
+    // types that share an element share their Lennard-Jones, since Legion goes by element.
+    const FORCEFIELD: &str = r#"
+<ForceField>
+ <AtomTypes>
+  <Type name="CT" class="CT" element="C" mass="12.01"/>
+  <Type name="HC" class="HC" element="H" mass="1.008"/>
+  <Type name="OH" class="OH" element="O" mass="16.0"/>
+  <Type name="HO" class="HO" element="H" mass="1.008"/>
+  <Type name="OW" class="OW" element="O" mass="16.0"/>
+  <Type name="HW" class="HW" element="H" mass="1.008"/>
+ </AtomTypes>
+ <Residues>
+  <Residue name="MET">
+   <Atom name="C" type="CT" charge="0.117"/>
+   <Atom name="H1" type="HC" charge="0.028"/>
+   <Atom name="H2" type="HC" charge="0.028"/>
+   <Atom name="H3" type="HC" charge="0.028"/>
+   <Bond atomName1="C" atomName2="H1"/>
+   <Bond atomName1="C" atomName2="H2"/>
+   <Bond atomName1="C" atomName2="H3"/>
+   <ExternalBond atomName="C"/>
+  </Residue>
+  <Residue name="HYD">
+   <Atom name="O" type="OH" charge="-0.599"/>
+   <Atom name="HO" type="HO" charge="0.398"/>
+   <Bond atomName1="O" atomName2="HO"/>
+   <ExternalBond atomName="O"/>
+  </Residue>
+  <Residue name="HOH">
+   <Atom name="O" type="OW" charge="-0.834"/>
+   <Atom name="H1" type="HW" charge="0.417"/>
+   <Atom name="H2" type="HW" charge="0.417"/>
+   <Bond atomName1="O" atomName2="H1"/>
+   <Bond atomName1="O" atomName2="H2"/>
+  </Residue>
+ </Residues>
+ <HarmonicBondForce>
+  <Bond class1="CT" class2="OH" length="0.141" k="267776.0"/>
+  <Bond class1="OW" class2="HW" length="0.09572" k="462750.4"/>
+ </HarmonicBondForce>
+ <HarmonicAngleForce>
+  <Angle class1="HC" class2="CT" class3="OH" angle="1.911135530933791" k="418.4"/>
+ </HarmonicAngleForce>
+ <PeriodicTorsionForce>
+  <Proper class1="" class2="CT" class3="OH" class4="" periodicity1="3" phase1="0.0" k1="0.6276"/>
+  <Proper class1="HC" class2="CT" class3="OH" class4="HO" periodicity1="3" phase1="0.0" k1="0.5" periodicity2="1" phase2="3.141592653589793" k2="0.1"/>
+  <Improper class1="CT" class2="HC" class3="HC" class4="OH" periodicity1="2" phase1="3.141592653589793" k1="1.0"/>
+ </PeriodicTorsionForce>
+ <NonbondedForce coulomb14scale="0.833333" lj14scale="0.5">
+  <Atom type="CT" sigma="0.339967" epsilon="0.4577296"/>
+  <Atom type="HC" sigma="0.264953" epsilon="0.0656888"/>
+  <Atom type="OH" sigma="0.306647" epsilon="0.880314"/>
+  <Atom type="HO" sigma="0.264953" epsilon="0.0656888"/>
+  <Atom type="OW" sigma="0.306647" epsilon="0.880314"/>
+  <Atom type="HW" sigma="0.264953" epsilon="0.0656888"/>
+ </NonbondedForce>
+</ForceField>"#;
+
+    // the hydroxyl's atoms are out of order, to be put right by the template.
+    const STRUCTURE: &str = "\
+HETATM    1  C   MET A   1      -0.748  -0.015   0.024  1.00  0.00           C
+HETATM    2  H1  MET A   1      -1.130   0.992  -0.193  1.00  0.00           H
+HETATM    3  H2  MET A   1      -1.068  -0.340   1.021  1.00  0.00           H
+HETATM    4  H3  MET A   1      -1.149  -0.698  -0.736  1.00  0.00           H
+HETATM    5  HO  HYD A   2       0.960  -0.650  -0.640  1.00  0.00           H
+HETATM    6  O   HYD A   2       0.660  -0.018  -0.006  1.00  0.00           O
+HETATM    7  O   HOH W   1       5.000   5.000   5.000  1.00  0.00           O
+HETATM    8  H1  HOH W   1       5.957   5.000   5.000  1.00  0.00           H
+HETATM    9  H2  HOH W   1       4.760   5.927   5.000  1.00  0.00           H
+";
+
+    #[test]
+    fn test_templates() {
+        let mut forcefield = ForceField::new();
+        forcefield.add_xml(FORCEFIELD, None).unwrap();
+        let system = System::from_openmm(&forcefield, &atoms(STRUCTURE).unwrap(), None).unwrap();
+        assert_eq!(system.atoms.len(), 9);
+        // in the template's order, and bonded across the two residues.
+        assert_eq!(system.atoms[4].id, "HYD2:6:O");
+        assert_eq!(system.atoms[4].neighbors, ["HYD2:5:HO", "MET1:1:C"]);
+        assert_eq!(system.atoms[4].charge, -0.599);
+        assert_eq!(system.atoms[4].element, Elements::O(0));
+        let nm = [0.066, -0.0018, -0.0006];
+        assert!((0..3).all(|k| (system.atoms[4].position[k] - nm[k]).abs() < 1e-6));
+        assert_eq!(
+            system.parameters.get_element(&Elements::O(0)),
+            (0.880314, 0.306647)
+        );
+
+        // C-O and the two O-H, but not the C-H or the hydroxyl's O-H, which have no parameters.
+        assert_eq!(system.bonded.bonds.len(), 3);
+        assert_eq!(system.bonded.angles.len(), 3);
+        match system.bonded.angles[0].1 {
+            AnglePotential::Harmonic { k, theta0 } => {
+                assert_eq!(k, 418.4);
+                assert!((theta0 - 109.5).abs() < 1e-4);
+            }
+            _ => panic!("should be harmonic"),
+        }
+        // three H-C-O-H, each with the specific two terms rather than the wildcard's one.
+        assert_eq!(system.bonded.dihedrals.len(), 6);
+        match system.bonded.dihedrals[1].1 {
+            DihedralPotential::Periodic {
+                k,
+                multiplicity,
+                phase,
+            } => {
+                assert_eq!((k, multiplicity), (0.1, 1));
+                assert!((phase - 180.0).abs() < 1e-4);
+            }
+            _ => panic!("should be periodic"),
+        }
+        // the carbon's trios with the oxygen in them; the center goes third.
+        assert_eq!(system.bonded.impropers.len(), 3);
+        assert_eq!(system.bonded.impropers[0].0[2], "MET1:1:C");
+        assert_eq!(system.bonded.impropers[0].0[3], "HYD2:6:O");
+        // methanol is all within three bonds of itself, and water of itself.
+        assert_eq!(system.exclusions.len(), 15 + 3);
+        // of which the three H-C-O-H ends come back, scaled.
+        assert_eq!(system.bonded.pairs.len(), 3);
+        let ([h, ho], lj, coulomb) = &system.bonded.pairs[0];
+        assert_eq!((h.as_str(), ho.as_str()), ("MET1:2:H1", "HYD2:5:HO"));
+        assert!((lj.epsilon - 0.0656888 * 0.5).abs() < 1e-6);
+        assert!((lj.sigma - 0.264953).abs() < 1e-6);
+        assert!((coulomb.qq - 0.028 * 0.398 * 0.833333 * 138.935458).abs() < 1e-5);
+
+        // a residue with an atom missing has nothing to match.
+        let err = System::from_openmm(
+            &forcefield,
+            &atoms(&STRUCTURE.replace("HETATM    9", "REMARK")).unwrap(),
+            None,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "residue HOH1 (chain W): no template has these atoms"
+        );
+    }
+
+    #[test]
+    fn test_periodic_box() {
+        let mut forcefield = ForceField::new();
+        forcefield.add_xml(FORCEFIELD, None).unwrap();
+        let structure = atoms(STRUCTURE).unwrap();
+        // the cell comes over in nm.
+        let record = CrystalRecord::from_lattice([30.0, 40.0, 50.0, 90.0, 90.0, 90.0]);
+        let system = System::from_openmm(&forcefield, &structure, Some(&record)).unwrap();
+        let periodic = system.periodic_box.unwrap();
+        let lattice = periodic.to_lattice();
+        assert!((0..3).all(|k| (lattice[k] - [3.0, 4.0, 5.0][k]).abs() < 1e-6));
+        // but the 1 x 1 x 1 that non-crystal structures put in isn't a box.
+        let placeholder = CrystalRecord::from_lattice([1.0, 1.0, 1.0, 90.0, 90.0, 90.0]);
+        let system = System::from_openmm(&forcefield, &structure, Some(&placeholder)).unwrap();
+        assert_eq!(system.periodic_box, None);
+    }
+
+    #[test]
+    fn test_clashing_atom_types() {
+        // the hydroxyl hydrogen with its own Lennard-Jones can't be told apart from the methyl ones.
+        let mut forcefield = ForceField::new();
+        let clashing = FORCEFIELD.replace(
+            r#"type="HO" sigma="0.264953" epsilon="0.0656888""#,
+            r#"type="HO" sigma="0.0" epsilon="0.0""#,
+        );
+        forcefield.add_xml(&clashing, None).unwrap();
+        let err = System::from_openmm(&forcefield, &atoms(STRUCTURE).unwrap(), None)
+            .err()
+            .unwrap();
+        assert_eq!(err.context, "NonbondedForce");
+        assert!(err.message.contains("HC and HO are both H"), "{}", err);
+    }
+}
@@ -0,0 +1,566 @@
+use roxmltree::{Document, Node};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// http://docs.openmm.org/latest/userguide/application/05_creating_ffs.html
+// An OpenMM force field is XML:
+//   <AtomTypes>              each type has a name, a class (what most parameters are given by), an element and a mass
+//   <Residues>               templates; atoms (name, type and usually charge), bonds inside the residue, and the atoms
+//                            that bond out of it (ExternalBond)
+//   <HarmonicBondForce>      k/2 (r - length)^2
+//   <HarmonicAngleForce>     k/2 (theta - angle)^2
+//   <PeriodicTorsionForce>   Proper and Improper, each a sum of k (1 + cos(n phi - phase)) terms; an Improper's class1
+//                            is the central atom
+//   <NonbondedForce>         charge, sigma and epsilon for each type, mixed Lorentz-Berthelot
+// Parameters name their atoms by type (type1, type2...) or class (class1, class2...), and an empty one is a wildcard.
+// Bigger force fields come in more than one file (amber14-all.xml and a water model, say), so files add to what's
+// there already, and <Include file=".."/> pulls in another one.  Each file only gets read the once, however many
+// times it's asked for, so files that include each other don't go round forever.  Units are OpenMM's: nm, kJ/mol, radians, amu, e.
+// Patches, virtual sites and the custom forces aren't read.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenMMError {
+    pub context: String, // the file, or the XML element / residue it's about
+    pub message: String,
+}
+
+impl fmt::Display for OpenMMError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.message)
+    }
+}
+
+pub fn error(context: &str, message: String) -> OpenMMError {
+    OpenMMError {
+        context: context.to_string(),
+        message,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtomType {
+    pub name: String,
+    pub class: String,
+    pub element: String, // empty for virtual sites and the like
+    pub mass: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateAtom {
+    pub name: String,
+    pub atom_type: String,
+    pub charge: Option<f64>, // NonbondedForce's, for the type, otherwise
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub atoms: Vec<TemplateAtom>,
+    pub bonds: Vec<(usize, usize)>,
+    pub external_bonds: Vec<usize>,
+}
+
+impl Template {
+    pub fn atom(&self, name: &str) -> Option<usize> {
+        self.atoms.iter().position(|atom| atom.name == name)
+    }
+}
+
+// How a parameter picks out an atom.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Match {
+    Type(String),
+    Class(String),
+    Any,
+}
+
+impl Match {
+    pub fn matches(&self, atom_type: &AtomType) -> bool {
+        match self {
+            Match::Type(name) => *name == atom_type.name,
+            Match::Class(class) => *class == atom_type.class,
+            Match::Any => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BondParameters {
+    pub atoms: [Match; 2],
+    pub length: f64,
+    pub k: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AngleParameters {
+    pub atoms: [Match; 3],
+    pub angle: f64,
+    pub k: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorsionParameters {
+    pub atoms: [Match; 4],
+    pub terms: Vec<(i32, f64, f64)>, // (periodicity, phase, k)
+}
+
+impl TorsionParameters {
+    pub fn wildcards(&self) -> usize {
+        self.atoms.iter().filter(|m| **m == Match::Any).count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NonbondedParameters {
+    pub atom: Match,
+    pub charge: Option<f64>,
+    pub sigma: f64,
+    pub epsilon: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForceField {
+    pub atom_types: Vec<AtomType>,
+    pub templates: Vec<Template>,
+    pub bonds: Vec<BondParameters>,
+    pub angles: Vec<AngleParameters>,
+    pub propers: Vec<TorsionParameters>,
+    pub impropers: Vec<TorsionParameters>,
+    pub nonbonded: Vec<NonbondedParameters>,
+    pub coulomb14scale: f64,
+    pub lj14scale: f64,
+    loaded: HashSet<PathBuf>, // canonicalized
+}
+
+fn attribute<'a>(node: &Node<'a, '_>, name: &str) -> Result<&'a str, OpenMMError> {
+    node.attribute(name).ok_or_else(|| {
+        error(
+            node.tag_name().name(),
+            format!("needs a {} attribute", name),
+        )
+    })
+}
+
+fn number(node: &Node, name: &str) -> Result<f64, OpenMMError> {
+    let value = attribute(node, name)?;
+    value.trim().parse().map_err(|_| {
+        error(
+            node.tag_name().name(),
+            format!("{}=\"{}\" should be a number", name, value),
+        )
+    })
+}
+
+// type1 / class1 and so on; suffix is "" for NonbondedForce's single atom.
+fn matcher(node: &Node, suffix: &str) -> Result<Match, OpenMMError> {
+    let by = |kind: &str| node.attribute(format!("{}{}", kind, suffix).as_str());
+    match (by("type"), by("class")) {
+        (Some(""), _) | (_, Some("")) => Ok(Match::Any),
+        (Some(name), _) => Ok(Match::Type(name.to_string())),
+        (None, Some(class)) => Ok(Match::Class(class.to_string())),
+        (None, None) => Err(error(
+            node.tag_name().name(),
+            format!("needs a type{0} or class{0}", suffix),
+        )),
+    }
+}
+
+fn matchers<const N: usize>(node: &Node) -> Result<[Match; N], OpenMMError> {
+    let mut atoms = Vec::new();
+    for k in 1..=N {
+        atoms.push(matcher(node, &k.to_string())?);
+    }
+    Ok(atoms.try_into().unwrap())
+}
+
+fn elements<'a, 'input>(node: &Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(|child| child.is_element())
+}
+
+impl ForceField {
+    pub fn new() -> Self {
+        Self {
+            atom_types: Vec::new(),
+            templates: Vec::new(),
+            bonds: Vec::new(),
+            angles: Vec::new(),
+            propers: Vec::new(),
+            impropers: Vec::new(),
+            nonbonded: Vec::new(),
+            coulomb14scale: 1.0,
+            lj14scale: 1.0,
+            loaded: HashSet::new(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, OpenMMError> {
+        let mut forcefield = Self::new();
+        forcefield.add_file(path)?;
+        Ok(forcefield)
+    }
+
+    pub fn add_file(&mut self, path: &Path) -> Result<(), OpenMMError> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| error(&path.display().to_string(), e.to_string()))?;
+        if !self.loaded.insert(canonical) {
+            return Ok(());
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|e| error(&path.display().to_string(), e.to_string()))?;
+        self.add_xml(&text, path.parent())
+            .map_err(|e| error(&format!("{}: {}", path.display(), e.context), e.message))
+    }
+
+    // Includes are looked for relative to dir, if there is one.
+    pub fn add_xml(&mut self, text: &str, dir: Option<&Path>) -> Result<(), OpenMMError> {
+        let document = Document::parse(text).map_err(|e| error("xml", e.to_string()))?;
+        let root = document.root_element();
+        if root.tag_name().name() != "ForceField" {
+            return Err(error(
+                root.tag_name().name(),
+                "should be a <ForceField>".to_string(),
+            ));
+        }
+        for section in elements(&root) {
+            match section.tag_name().name() {
+                "Include" => {
+                    let file = attribute(&section, "file")?;
+                    let path = match dir {
+                        Some(dir) => dir.join(file),
+                        None => Path::new(file).to_path_buf(),
+                    };
+                    self.add_file(&path)?;
+                }
+                "AtomTypes" => {
+                    for node in elements(&section).filter(|n| n.has_tag_name("Type")) {
+                        self.atom_types.push(AtomType {
+                            name: attribute(&node, "name")?.to_string(),
+                            class: node.attribute("class").unwrap_or("").to_string(),
+                            element: node.attribute("element").unwrap_or("").to_string(),
+                            mass: number(&node, "mass")?,
+                        });
+                    }
+                }
+                "Residues" => {
+                    for node in elements(&section).filter(|n| n.has_tag_name("Residue")) {
+                        self.templates.push(Self::template(&node)?);
+                    }
+                }
+                "HarmonicBondForce" => {
+                    for node in elements(&section).filter(|n| n.has_tag_name("Bond")) {
+                        self.bonds.push(BondParameters {
+                            atoms: matchers(&node)?,
+                            length: number(&node, "length")?,
+                            k: number(&node, "k")?,
+                        });
+                    }
+                }
+                "HarmonicAngleForce" => {
+                    for node in elements(&section).filter(|n| n.has_tag_name("Angle")) {
+                        self.angles.push(AngleParameters {
+                            atoms: matchers(&node)?,
+                            angle: number(&node, "angle")?,
+                            k: number(&node, "k")?,
+                        });
+                    }
+                }
+                "PeriodicTorsionForce" => {
+                    for node in elements(&section) {
+                        // periodicity1, phase1, k1, then 2 and so on for as many as there are.
+                        let mut terms = Vec::new();
+                        for n in 1.. {
+                            let periodicity = format!("periodicity{}", n);
+                            if node.attribute(periodicity.as_str()).is_none() {
+                                break;
+                            }
+                            terms.push((
+                                number(&node, &periodicity)?.round() as i32,
+                                number(&node, &format!("phase{}", n))?,
+                                number(&node, &format!("k{}", n))?,
+                            ));
+                        }
+                        let torsion = TorsionParameters {
+                            atoms: matchers(&node)?,
+                            terms,
+                        };
+                        match node.tag_name().name() {
+                            "Proper" => self.propers.push(torsion),
+                            "Improper" => self.impropers.push(torsion),
+                            _ => {}
+                        }
+                    }
+                }
+                "NonbondedForce" => {
+                    if section.attribute("coulomb14scale").is_some() {
+                        self.coulomb14scale = number(&section, "coulomb14scale")?;
+                    }
+                    if section.attribute("lj14scale").is_some() {
+                        self.lj14scale = number(&section, "lj14scale")?;
+                    }
+                    for node in elements(&section).filter(|n| n.has_tag_name("Atom")) {
+                        self.nonbonded.push(NonbondedParameters {
+                            atom: matcher(&node, "")?,
+                            charge: match node.attribute("charge") {
+                                Some(_) => Some(number(&node, "charge")?),
+                                None => None,
+                            },
+                            sigma: number(&node, "sigma")?,
+                            epsilon: number(&node, "epsilon")?,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn template(node: &Node) -> Result<Template, OpenMMError> {
+        let mut template = Template {
+            name: attribute(node, "name")?.to_string(),
+            atoms: Vec::new(),
+            bonds: Vec::new(),
+            external_bonds: Vec::new(),
+        };
+        let context = format!("residue {}", template.name);
+        for child in elements(node) {
+            match child.tag_name().name() {
+                "Atom" => template.atoms.push(TemplateAtom {
+                    name: attribute(&child, "name")?.to_string(),
+                    atom_type: attribute(&child, "type")?.to_string(),
+                    charge: match child.attribute("charge") {
+                        Some(_) => Some(number(&child, "charge")?),
+                        None => None,
+                    },
+                }),
+                // by name these days, or by index (from, to) in older files.
+                "Bond" => {
+                    let end = |name: &str, index: &str| match child.attribute(name) {
+                        Some(atom) => template
+                            .atom(atom)
+                            .ok_or_else(|| error(&context, format!("there's no atom {}", atom))),
+                        None => Ok(number(&child, index)? as usize),
+                    };
+                    let bond = (end("atomName1", "from")?, end("atomName2", "to")?);
+                    template.bonds.push(bond);
+                }
+                "ExternalBond" => {
+                    let atom = match child.attribute("atomName") {
+                        Some(atom) => template
+                            .atom(atom)
+                            .ok_or_else(|| error(&context, format!("there's no atom {}", atom)))?,
+                        None => number(&child, "from")? as usize,
+                    };
+                    template.external_bonds.push(atom);
+                }
+                _ => {}
+            }
+        }
+        let count = template.atoms.len();
+        let out_of_range = template
+            .bonds
+            .iter()
+            .flat_map(|&(i, j)| [i, j])
+            .chain(template.external_bonds.iter().copied())
+            .any(|i| i >= count);
+        if out_of_range {
+            return Err(error(
+                &context,
+                "bonds an atom that isn't there".to_string(),
+            ));
+        }
+        Ok(template)
+    }
+
+    pub fn atom_type(&self, name: &str) -> Option<&AtomType> {
+        self.atom_types.iter().find(|t| t.name == name)
+    }
+
+    // the last one given wins, like OpenMM.
+    pub fn nonbonded_parameters(&self, atom_type: &AtomType) -> Option<&NonbondedParameters> {
+        self.nonbonded
+            .iter()
+            .rev()
+            .find(|p| p.atom.matches(atom_type))
+    }
+
+    pub fn bond_parameters(&self, a: &AtomType, b: &AtomType) -> Option<&BondParameters> {
+        self.bonds.iter().find(|p| {
+            let [m1, m2] = &p.atoms;
+            (m1.matches(a) && m2.matches(b)) || (m1.matches(b) && m2.matches(a))
+        })
+    }
+
+    pub fn angle_parameters(
+        &self,
+        a: &AtomType,
+        b: &AtomType,
+        c: &AtomType,
+    ) -> Option<&AngleParameters> {
+        self.angles.iter().find(|p| {
+            let [m1, m2, m3] = &p.atoms;
+            m2.matches(b) && ((m1.matches(a) && m3.matches(c)) || (m1.matches(c) && m3.matches(a)))
+        })
+    }
+
+    // Either way along the chain, and the fewest wildcards wins.
+    pub fn proper_parameters(&self, types: [&AtomType; 4]) -> Option<&TorsionParameters> {
+        let fits = |p: &TorsionParameters, order: [usize; 4]| {
+            p.atoms.iter().zip(order).all(|(m, k)| m.matches(types[k]))
+        };
+        self.propers
+            .iter()
+            .filter(|p| fits(p, [0, 1, 2, 3]) || fits(p, [3, 2, 1, 0]))
+            .min_by_key(|p| p.wildcards())
+    }
+
+    // The center against class1 and the other three in any order; which order it was comes back too, to put the
+    // atoms in.  The fewest wildcards wins here as well.
+    pub fn improper_parameters(
+        &self,
+        center: &AtomType,
+        others: [&AtomType; 3],
+    ) -> Option<(&TorsionParameters, [usize; 3])> {
+        const ORDERS: [[usize; 3]; 6] = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        self.impropers
+            .iter()
+            .filter(|p| p.atoms[0].matches(center))
+            .filter_map(|p| {
+                ORDERS
+                    .iter()
+                    .find(|order| (0..3).all(|k| p.atoms[k + 1].matches(others[order[k]])))
+                    .map(|&order| (p, order))
+            })
+            .min_by_key(|(p, _)| p.wildcards())
+    }
+}
+
+impl Default for ForceField {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let xml = r#"
+<ForceField>
+ <AtomTypes>
+  <Type name="tip3p-O" class="OW" element="O" mass="15.99943"/>
+  <Type name="tip3p-H" class="HW" element="H" mass="1.007947"/>
+ </AtomTypes>
+ <Residues>
+  <Residue name="HOH">
+   <Atom name="O" type="tip3p-O" charge="-0.834"/>
+   <Atom name="H1" type="tip3p-H" charge="0.417"/>
+   <Atom name="H2" type="tip3p-H"/>
+   <Bond atomName1="O" atomName2="H1"/>
+   <Bond from="0" to="2"/>
+  </Residue>
+ </Residues>
+ <HarmonicBondForce>
+  <Bond class1="OW" class2="HW" length="0.09572" k="462750.4"/>
+ </HarmonicBondForce>
+ <HarmonicAngleForce>
+  <Angle type1="tip3p-H" type2="tip3p-O" type3="tip3p-H" angle="1.82421813418" k="836.8"/>
+ </HarmonicAngleForce>
+ <PeriodicTorsionForce>
+  <Proper class1="" class2="OW" class3="OW" class4="" periodicity1="3" phase1="0.0" k1="1.0" periodicity2="1" phase2="3.14" k2="0.5"/>
+  <Improper class1="OW" class2="HW" class3="" class4="OW" periodicity1="2" phase1="3.14" k1="4.6"/>
+ </PeriodicTorsionForce>
+ <NonbondedForce coulomb14scale="0.833333" lj14scale="0.5">
+  <UseAttributeFromResidue name="charge"/>
+  <Atom type="tip3p-O" sigma="0.315" epsilon="0.636"/>
+  <Atom type="tip3p-H" sigma="1" epsilon="0" charge="0.417"/>
+ </NonbondedForce>
+</ForceField>"#;
+        let mut forcefield = ForceField::new();
+        forcefield.add_xml(xml, None).unwrap();
+        let oxygen = forcefield.atom_type("tip3p-O").unwrap().clone();
+        let hydrogen = forcefield.atom_type("tip3p-H").unwrap().clone();
+        assert_eq!(
+            (oxygen.class.as_str(), oxygen.element.as_str()),
+            ("OW", "O")
+        );
+
+        let water = &forcefield.templates[0];
+        assert_eq!(water.bonds, vec![(0, 1), (0, 2)]);
+        assert_eq!(water.atoms[2].charge, None);
+
+        assert_eq!(
+            forcefield.bond_parameters(&hydrogen, &oxygen).unwrap().k,
+            462750.4
+        );
+        assert!(forcefield.bond_parameters(&hydrogen, &hydrogen).is_none());
+        assert_eq!(
+            forcefield
+                .angle_parameters(&hydrogen, &oxygen, &hydrogen)
+                .unwrap()
+                .angle,
+            1.82421813418
+        );
+        let proper = forcefield
+            .proper_parameters([&hydrogen, &oxygen, &oxygen, &hydrogen])
+            .unwrap();
+        assert_eq!(proper.terms, vec![(3, 0.0, 1.0), (1, 3.14, 0.5)]);
+        // the oxygen has to go last, whichever order they come in.
+        let (_, order) = forcefield
+            .improper_parameters(&oxygen, [&oxygen, &hydrogen, &hydrogen])
+            .unwrap();
+        assert_eq!(order, [1, 2, 0]);
+        assert!(forcefield
+            .improper_parameters(&hydrogen, [&oxygen, &hydrogen, &hydrogen])
+            .is_none());
+
+        assert_eq!(forcefield.lj14scale, 0.5);
+        let parameters = forcefield.nonbonded_parameters(&hydrogen).unwrap();
+        assert_eq!((parameters.charge, parameters.sigma), (Some(0.417), 1.0));
+
+        let err = ForceField::new()
+            .add_xml("<ForceField><HarmonicBondForce><Bond class1=\"A\" length=\"1\" k=\"1\"/></HarmonicBondForce></ForceField>", None)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Bond: needs a type2 or class2");
+    }
+
+    #[test]
+    fn test_includes() {
+        // two files that include each other (and one of them twice over).
+        let dir = std::env::temp_dir().join(format!("decay_forge_openmm_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, include: &str, atom_type: &str| {
+            let xml = format!(
+                "<ForceField>{include}<AtomTypes><Type name=\"{atom_type}\" class=\"{atom_type}\" element=\"O\" mass=\"16.0\"/></AtomTypes></ForceField>"
+            );
+            fs::write(dir.join(name), xml).unwrap();
+        };
+        file("a.xml", "<Include file=\"b.xml\"/>", "A");
+        file(
+            "b.xml",
+            "<Include file=\"a.xml\"/><Include file=\"./a.xml\"/>",
+            "B",
+        );
+        let mut forcefield = ForceField::from_file(&dir.join("a.xml")).unwrap();
+        forcefield.add_file(&dir.join("b.xml")).unwrap();
+        let names: Vec<&str> = forcefield
+            .atom_types
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(names, ["B", "A"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,4 @@
+// OpenMM force fields (the XML ones; amber14, charmm36 and friends).  forcefield reads the files, and convert matches
+// its residue templates to a PDB structure to make a Legion system.
+pub mod convert;
+pub mod forcefield;
@@ -1,7 +1,8 @@
+use super::{field, PdbError};
 use std::str::FromStr;
 
 // the ordering here is just the same order from https://www.wwpdb.org/documentation/file-format-content/format33/sect9.html
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CoordinateEnum {
     MODEL,   // start of the model!
     ATOM,    // regular atom
@@ -12,28 +13,29 @@ pub enum CoordinateEnum {
     UNKNOWN, // default value.
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct CoordinateRecord {
-    record_type: CoordinateEnum, // 1 to 6
-    serial: u32,                 // 7 to 11
-    name: String,                // 13 to 16
-    altLoc: String,              // 17
-    resName: String,             // 18 to 20
-    chainId: String,             // 22
-    resSeq: u32,                 // 23 to 26
-    iCode: String,               // 27
-    x: f64,                      // 31 to 38
-    y: f64,                      // 39 to 46
-    z: f64,                      // 47 to 54
-    occupancy: f64,              // 55 to 60
-    tempFactor: f64,             // 61 to 66
-    element: String,             // 77 to 78
-    charge: String,              // 79 to 80
-    u_0_0: String,               // 29 to 35, U(1,1)
-    u_1_1: String,               // 36 to 42, U(2,2)
-    u_2_2: String,               // 43 to 49, U(3,3)
-    u_0_1: String,               // 50 to 56, U(1,2)
-    u_0_2: String,               // 57 to 63, U(1,3)
-    u_1_2: String,               // 64 to 70, U(2,3)
+    pub record_type: CoordinateEnum, // 1 to 6
+    pub serial: u32,                 // 7 to 11
+    pub name: String,                // 13 to 16
+    pub altLoc: String,              // 17
+    pub resName: String,             // 18 to 20
+    pub chainId: String,             // 22
+    pub resSeq: i32,                 // 23 to 26
+    pub iCode: String,               // 27
+    pub x: f64,                      // 31 to 38
+    pub y: f64,                      // 39 to 46
+    pub z: f64,                      // 47 to 54
+    pub occupancy: f64,              // 55 to 60
+    pub tempFactor: f64,             // 61 to 66
+    pub element: String,             // 77 to 78
+    pub charge: String,              // 79 to 80
+    pub u_0_0: String,               // 29 to 35, U(1,1)
+    pub u_1_1: String,               // 36 to 42, U(2,2)
+    pub u_2_2: String,               // 43 to 49, U(3,3)
+    pub u_0_1: String,               // 50 to 56, U(1,2)
+    pub u_0_2: String,               // 57 to 63, U(1,3)
+    pub u_1_2: String,               // 64 to 70, U(2,3)
 }
 
 pub struct CoordinateRecordBuilder<'a> {
@@ -44,7 +46,7 @@ pub struct CoordinateRecordBuilder<'a> {
     altLoc: Option<String>,      // 17
     resName: Option<String>,     // 18 to 20
     chainId: Option<String>,     // 22
-    resSeq: Option<u32>,         // 23 to 26
+    resSeq: Option<i32>,         // 23 to 26
     iCode: Option<String>,       // 27
     x: Option<f64>,              // 31 to 38
     y: Option<f64>,              // 39 to 46
@@ -59,12 +61,13 @@ pub struct CoordinateRecordBuilder<'a> {
     u_0_1: Option<String>,       // 50 to 56, U(1,2)
     u_0_2: Option<String>,       // 57 to 63, U(1,3)
     u_1_2: Option<String>,       // 64 to 70, U(2,3)
+    error: Option<String>,       // the first column that should have been a number and wasn't
 }
 
 impl<'a> CoordinateRecordBuilder<'a> {
     pub fn new(coord: CoordinateEnum, line: &'a str) -> Self {
         Self {
-            line: line,
+            line,
             record_type: coord,
             serial: None,     // 7 to 11
             name: None,       // 13 to 16
@@ -86,157 +89,172 @@ impl<'a> CoordinateRecordBuilder<'a> {
             u_0_1: None,      // 50 to 56, U(1,2)
             u_0_2: None,      // 57 to 63, U(1,3)
             u_1_2: None,      // 64 to 70, U(2,3)
+            error: None,
+        }
+    }
+
+    // a position column; one that isn't a number is an error, rather than an atom quietly sat at the origin.
+    fn coordinate(&mut self, start: usize, end: usize, name: &str) -> Option<f64> {
+        let text = field(self.line, start, end);
+        let value = text.parse().ok();
+        if value.is_none() && self.error.is_none() {
+            self.error = Some(format!("{} should be a number, not '{}'", name, text));
         }
+        value
     }
 
     pub fn serial(mut self) -> Self {
-        self.serial = Some(u32::from_str(&self.line[10..14]).unwrap());
+        self.serial = field(self.line, 6, 11).parse().ok();
         self
     }
 
     pub fn name(mut self) -> Self {
-        self.name = Some(self.line[12..16].to_string());
+        self.name = Some(field(self.line, 12, 16).to_string());
         self
     }
 
     pub fn altLoc(mut self) -> Self {
-        self.altLoc = Some(self.line.chars().nth(16).unwrap().to_string());
+        self.altLoc = Some(field(self.line, 16, 17).to_string());
         self
     }
 
     pub fn resName(mut self) -> Self {
-        self.resName = Some(self.line[17..20].to_string());
+        self.resName = Some(field(self.line, 17, 20).to_string());
         self
     }
 
     pub fn chainId(mut self) -> Self {
-        self.chainId = Some(self.line.chars().nth(22).unwrap().to_string());
+        self.chainId = Some(field(self.line, 21, 22).to_string());
         self
     }
 
     pub fn resSeq(mut self) -> Self {
-        self.resSeq = Some(u32::from_str(&self.line[22..26]).unwrap());
+        self.resSeq = field(self.line, 22, 26).parse().ok();
         self
     }
 
     pub fn iCode(mut self) -> Self {
-        self.iCode = Some(self.line.chars().nth(26).unwrap().to_string());
+        self.iCode = Some(field(self.line, 26, 27).to_string());
         self
     }
 
     pub fn x(mut self) -> Self {
-        self.x = Some(f64::from_str(&self.line[30..38]).unwrap());
+        self.x = self.coordinate(30, 38, "x");
         self
     }
 
     pub fn y(mut self) -> Self {
-        self.y = Some(f64::from_str(&self.line[38..46]).unwrap());
+        self.y = self.coordinate(38, 46, "y");
         self
     }
 
     pub fn z(mut self) -> Self {
-        self.z = Some(f64::from_str(&self.line[46..54]).unwrap());
+        self.z = self.coordinate(46, 54, "z");
         self
     }
 
     pub fn occupancy(mut self) -> Self {
-        self.occupancy = Some(f64::from_str(&self.line[54..60]).unwrap());
+        self.occupancy = field(self.line, 54, 60).parse().ok();
         self
     }
 
     pub fn tempFactor(mut self) -> Self {
-        self.tempFactor = Some(f64::from_str(&self.line[60..66]).unwrap());
+        self.tempFactor = field(self.line, 60, 66).parse().ok();
         self
     }
 
     pub fn element(mut self) -> Self {
-        self.element = Some(self.line[76..78].to_string());
+        self.element = Some(field(self.line, 76, 78).to_string());
         self
     }
 
     pub fn charge(mut self) -> Self {
-        self.charge = Some(self.line[78..80].to_string());
+        self.charge = Some(field(self.line, 78, 80).to_string());
         self
     }
 
     pub fn u_0_0(mut self) -> Self {
-        self.u_0_0 = Some(self.line[28..35].to_string());
+        self.u_0_0 = Some(field(self.line, 28, 35).to_string());
         self
     }
 
     pub fn u_1_1(mut self) -> Self {
-        self.u_1_1 = Some(self.line[35..42].to_string());
+        self.u_1_1 = Some(field(self.line, 35, 42).to_string());
         self
     }
 
     pub fn u_2_2(mut self) -> Self {
-        self.u_2_2 = Some(self.line[42..49].to_string());
+        self.u_2_2 = Some(field(self.line, 42, 49).to_string());
         self
     }
 
     pub fn u_0_1(mut self) -> Self {
-        self.u_0_1 = Some(self.line[49..56].to_string());
+        self.u_0_1 = Some(field(self.line, 49, 56).to_string());
         self
     }
 
     pub fn u_0_2(mut self) -> Self {
-        self.u_0_2 = Some(self.line[56..63].to_string());
+        self.u_0_2 = Some(field(self.line, 56, 63).to_string());
         self
     }
 
     pub fn u_1_2(mut self) -> Self {
-        self.u_1_2 = Some(self.line[63..70].to_string());
+        self.u_1_2 = Some(field(self.line, 63, 70).to_string());
         self
     }
 
-    pub fn build(self) -> CoordinateRecord {
-        CoordinateRecord {
-            record_type: self.record_type,
-            serial: self.serial.unwrap_or_else(|| 0),
-            name: self.name.unwrap_or_else(|| "".to_string()),
-            altLoc: self.altLoc.unwrap_or_else(|| "".to_string()),
-            resName: self.resName.unwrap_or_else(|| "".to_string()),
-            chainId: self.chainId.unwrap_or_else(|| "".to_string()),
-            resSeq: self.resSeq.unwrap_or_else(|| 0),
-            iCode: self.iCode.unwrap_or_else(|| "".to_string()),
-            x: self.x.unwrap_or_else(|| 0.0),
-            y: self.y.unwrap_or_else(|| 0.0),
-            z: self.z.unwrap_or_else(|| 0.0),
-            occupancy: self.occupancy.unwrap_or_else(|| 0.0),
-            tempFactor: self.tempFactor.unwrap_or_else(|| 0.0),
-            element: self.element.unwrap_or_else(|| "".to_string()),
-            charge: self.charge.unwrap_or_else(|| "".to_string()),
-            u_0_0: self.u_0_0.unwrap_or_else(|| "".to_string()),
-            u_1_1: self.u_1_1.unwrap_or_else(|| "".to_string()),
-            u_2_2: self.u_2_2.unwrap_or_else(|| "".to_string()),
-            u_0_1: self.u_0_1.unwrap_or_else(|| "".to_string()),
-            u_0_2: self.u_0_2.unwrap_or_else(|| "".to_string()),
-            u_1_2: self.u_1_2.unwrap_or_else(|| "".to_string()),
+    pub fn build(self) -> Result<CoordinateRecord, String> {
+        if let Some(error) = self.error {
+            return Err(error);
         }
+        Ok(CoordinateRecord {
+            record_type: self.record_type,
+            serial: self.serial.unwrap_or_default(),
+            name: self.name.unwrap_or_default(),
+            altLoc: self.altLoc.unwrap_or_default(),
+            resName: self.resName.unwrap_or_default(),
+            chainId: self.chainId.unwrap_or_default(),
+            resSeq: self.resSeq.unwrap_or_default(),
+            iCode: self.iCode.unwrap_or_default(),
+            x: self.x.unwrap_or_default(),
+            y: self.y.unwrap_or_default(),
+            z: self.z.unwrap_or_default(),
+            occupancy: self.occupancy.unwrap_or_default(),
+            tempFactor: self.tempFactor.unwrap_or_default(),
+            element: self.element.unwrap_or_default(),
+            charge: self.charge.unwrap_or_default(),
+            u_0_0: self.u_0_0.unwrap_or_default(),
+            u_1_1: self.u_1_1.unwrap_or_default(),
+            u_2_2: self.u_2_2.unwrap_or_default(),
+            u_0_1: self.u_0_1.unwrap_or_default(),
+            u_0_2: self.u_0_2.unwrap_or_default(),
+            u_1_2: self.u_1_2.unwrap_or_default(),
+        })
     }
 }
 impl FromStr for CoordinateRecord {
-    type Err = ();
+    type Err = String;
     fn from_str(line: &str) -> Result<Self, Self::Err> {
-        match line[0..5].as_ref() {
-            "MODEL" => Ok(createModel(line)),
-            "ATOM" => Ok(createAtom(line)),
-            "ANISOU" => Ok(createAnisou(line)),
-            "TER" => Ok(createTer(line)),
-            "HETATM" => Ok(createHetatm(line)),
-            "ENDMDL" => Ok(createEndmdl(line)),
-            _ => Err(()),
+        // record names are padded out to six columns; ATOM and TER have spaces after them.
+        match field(line, 0, 6) {
+            "MODEL" => createModel(line),
+            "ATOM" => createAtom(line),
+            "ANISOU" => createAnisou(line),
+            "TER" => createTer(line),
+            "HETATM" => createHetatm(line),
+            "ENDMDL" => createEndmdl(line),
+            record => Err(format!("{} isn't a coordinate record", record)),
         }
     }
 }
 
-fn createModel(line: &str) -> CoordinateRecord {
+fn createModel(line: &str) -> Result<CoordinateRecord, String> {
     CoordinateRecordBuilder::new(CoordinateEnum::MODEL, line)
         .serial()
         .build()
 }
 
-fn createAtom(line: &str) -> CoordinateRecord {
+fn createAtom(line: &str) -> Result<CoordinateRecord, String> {
     CoordinateRecordBuilder::new(CoordinateEnum::ATOM, line)
         .serial()
         .name()
@@ -261,7 +279,7 @@ fn createAtom(line: &str) -> CoordinateRecord {
 // ANISOU values are listed only if they have been provided by the depositor.
 // the order would go ATOM, ANISOU, ATOM, ANISOU, etc.
 
-fn createAnisou(line: &str) -> CoordinateRecord {
+fn createAnisou(line: &str) -> Result<CoordinateRecord, String> {
     CoordinateRecordBuilder::new(CoordinateEnum::ANISOU, line)
         .serial()
         .name()
@@ -281,7 +299,7 @@ fn createAnisou(line: &str) -> CoordinateRecord {
         .build()
 }
 
-fn createTer(line: &str) -> CoordinateRecord {
+fn createTer(line: &str) -> Result<CoordinateRecord, String> {
     CoordinateRecordBuilder::new(CoordinateEnum::TER, line)
         .serial()
         .resName()
@@ -291,7 +309,7 @@ fn createTer(line: &str) -> CoordinateRecord {
         .build()
 }
 
-fn createHetatm(line: &str) -> CoordinateRecord {
+fn createHetatm(line: &str) -> Result<CoordinateRecord, String> {
     CoordinateRecordBuilder::new(CoordinateEnum::HETATM, line)
         .serial()
         .name()
@@ -310,6 +328,77 @@ fn createHetatm(line: &str) -> CoordinateRecord {
         .build()
 }
 
-fn createEndmdl(line: &str) -> CoordinateRecord {
+fn createEndmdl(line: &str) -> Result<CoordinateRecord, String> {
     CoordinateRecordBuilder::new(CoordinateEnum::ENDMDL, line).build()
 }
+
+// The atoms of a structure: ATOM and HETATM records from the first model, taking the first of any alternate locations.
+pub fn atoms(text: &str) -> Result<Vec<CoordinateRecord>, PdbError> {
+    let mut atoms = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        match field(line, 0, 6) {
+            "ENDMDL" => break,
+            "ATOM" | "HETATM" => {}
+            _ => continue,
+        }
+        let record = CoordinateRecord::from_str(line).map_err(|message| PdbError {
+            line: n + 1,
+            message,
+        })?;
+        if record.altLoc.is_empty() || record.altLoc == "A" {
+            atoms.push(record);
+        }
+    }
+    Ok(atoms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atoms() {
+        // the start of lysozyme (1AKI), with a made up alternate location and a second model.
+        let text = "\
+CRYST1   59.062   68.451   30.517  90.00  90.00  90.00 P 21 21 21    4
+MODEL        1
+ATOM      1  N   LYS A   1       3.287  10.092  10.329  1.00  5.89           N
+ATOM      2  CA ALYS A   1       2.445  10.457   9.182  0.50  9.24           C
+ATOM      3  CA BLYS A   1       2.500  10.400   9.100  0.50  9.24           C
+HETATM 1003  O   HOH A 131      -0.114  16.458  20.052  1.00 19.88
+ENDMDL
+MODEL        2
+ATOM      1  N   LYS A   1       3.300  10.100  10.300  1.00  5.89           N
+";
+        let atoms = atoms(text).unwrap();
+        assert_eq!(atoms.len(), 3);
+        assert_eq!(atoms[0].serial, 1);
+        assert_eq!(atoms[0].name, "N");
+        assert_eq!(atoms[0].resName, "LYS");
+        assert_eq!(atoms[0].chainId, "A");
+        assert_eq!(atoms[0].resSeq, 1);
+        assert_eq!(
+            (atoms[0].x, atoms[0].y, atoms[0].z),
+            (3.287, 10.092, 10.329)
+        );
+        assert_eq!(atoms[0].element, "N");
+        assert_eq!(
+            (atoms[1].name.as_str(), atoms[1].altLoc.as_str()),
+            ("CA", "A")
+        );
+        // short lines just don't have an element.
+        assert_eq!(atoms[2].record_type, CoordinateEnum::HETATM);
+        assert_eq!((atoms[2].serial, atoms[2].resSeq), (1003, 131));
+        assert_eq!(atoms[2].element, "");
+        // negative residue numbers are fine, but a coordinate that isn't a number isn't.
+        let negative =
+            "ATOM      1  N   LYS A  -3       3.287  10.092  10.329  1.00  5.89           N";
+        assert_eq!(super::atoms(negative).unwrap()[0].resSeq, -3);
+        let garbled = format!("REMARK hello\n{}", negative.replace("10.092", "10.0x2"));
+        let err = super::atoms(&garbled).unwrap_err();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (2, "y should be a number, not '10.0x2'")
+        );
+    }
+}
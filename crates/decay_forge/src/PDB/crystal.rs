@@ -0,0 +1,158 @@
+use super::{field, PdbError};
+use std::fmt;
+use std::str::FromStr;
+use Legion::Topology::periodic::PeriodicBox;
+
+// https://www.wwpdb.org/documentation/file-format-content/format33/sect8.html#CRYST1
+// The unit cell: lengths in Angstroms and angles in degrees (alpha is between b and c, beta between a and c,
+// gamma between a and b), plus the space group and how many polymer chains are in the cell.
+// Structures that aren't crystals (NMR, cryo-EM) usually put in a 1 x 1 x 1 cube here.
+#[derive(Debug, PartialEq)]
+pub struct CrystalRecord {
+    pub a: f64,         // 7 to 15
+    pub b: f64,         // 16 to 24
+    pub c: f64,         // 25 to 33
+    pub alpha: f64,     // 34 to 40
+    pub beta: f64,      // 41 to 47
+    pub gamma: f64,     // 48 to 54
+    pub sGroup: String, // 56 to 66
+    pub z: u32,         // 67 to 70
+}
+
+impl CrystalRecord {
+    // The lattice as a, b, c, alpha, beta, gamma.
+    pub fn lattice(&self) -> [f64; 6] {
+        [self.a, self.b, self.c, self.alpha, self.beta, self.gamma]
+    }
+
+    pub fn from_lattice(lattice: [f64; 6]) -> Self {
+        let [a, b, c, alpha, beta, gamma] = lattice;
+        Self {
+            a,
+            b,
+            c,
+            alpha,
+            beta,
+            gamma,
+            sGroup: "P 1".to_string(),
+            z: 1,
+        }
+    }
+
+    // The same cell as a Legion box.  Lengths stay in Angstroms; scale it for anything else.
+    pub fn periodic_box(&self) -> PeriodicBox {
+        let [a, b, c, alpha, beta, gamma] = self.lattice().map(|x| x as f32);
+        PeriodicBox::from_lattice(a, b, c, alpha, beta, gamma)
+    }
+
+    pub fn from_periodic_box(periodic: &PeriodicBox) -> Self {
+        Self::from_lattice(periodic.to_lattice().map(|x| x as f64))
+    }
+}
+
+impl FromStr for CrystalRecord {
+    type Err = ();
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        if !line.starts_with("CRYST1") {
+            return Err(());
+        }
+        let number = |start, end| f64::from_str(field(line, start, end)).map_err(|_| ());
+        Ok(Self {
+            a: number(6, 15)?,
+            b: number(15, 24)?,
+            c: number(24, 33)?,
+            alpha: number(33, 40)?,
+            beta: number(40, 47)?,
+            gamma: number(47, 54)?,
+            sGroup: field(line, 55, 66).to_string(),
+            z: u32::from_str(field(line, 66, 70)).unwrap_or(1),
+        })
+    }
+}
+
+// writes the record back out in its fixed columns.
+impl fmt::Display for CrystalRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CRYST1{:9.3}{:9.3}{:9.3}{:7.2}{:7.2}{:7.2} {:<11}{:4}",
+            self.a, self.b, self.c, self.alpha, self.beta, self.gamma, self.sGroup, self.z
+        )
+    }
+}
+
+// The structure's unit cell, from its CRYST1 record, if it has one (only the first model's, like atoms).
+pub fn crystal(text: &str) -> Result<Option<CrystalRecord>, PdbError> {
+    for (n, line) in text.lines().enumerate() {
+        match field(line, 0, 6) {
+            "ENDMDL" => break,
+            "CRYST1" => {}
+            _ => continue,
+        }
+        return CrystalRecord::from_str(line)
+            .map(Some)
+            .map_err(|_| PdbError {
+                line: n + 1,
+                message: "CRYST1 should have six numbers for the cell".to_string(),
+            });
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cryst1() {
+        // lysozyme, 1AKI.
+        let line =
+            "CRYST1   59.062   68.451   30.517  90.00  90.00  90.00 P 21 21 21    4          ";
+        let record = CrystalRecord::from_str(line).unwrap();
+        assert_eq!(record.lattice(), [59.062, 68.451, 30.517, 90.0, 90.0, 90.0]);
+        assert_eq!(record.sGroup, "P 21 21 21");
+        assert_eq!(record.z, 4);
+        assert!(CrystalRecord::from_str("ATOM      1  N   LYS A   1").is_err());
+    }
+
+    #[test]
+    fn test_crystal() {
+        let text = "\
+HEADER    HYDROLASE                               19-MAY-97   1AKI
+CRYST1   59.062   68.451   30.517  90.00  90.00  90.00 P 21 21 21    4
+ATOM      1  N   LYS A   1       3.287  10.092  10.329  1.00  5.89           N
+";
+        let record = crystal(text).unwrap().unwrap();
+        assert_eq!(record.lattice(), [59.062, 68.451, 30.517, 90.0, 90.0, 90.0]);
+        assert_eq!(crystal(&text.replace("CRYST1", "REMARK")).unwrap(), None);
+        let err = crystal(&text.replace("59.062", "  ????")).err().unwrap();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_write_cryst1() {
+        let record = CrystalRecord::from_lattice([42.0, 42.0, 30.5, 90.0, 90.0, 120.0]);
+        let line = record.to_string();
+        assert_eq!(
+            &line[0..54],
+            "CRYST1   42.000   42.000   30.500  90.00  90.00 120.00"
+        );
+        assert_eq!(CrystalRecord::from_str(&line).unwrap(), record);
+    }
+
+    #[test]
+    fn test_periodic_box_round_trip() {
+        // a skewed cell should come back out the same after a trip through the box vectors.
+        let record = CrystalRecord::from_lattice([42.0, 45.0, 30.5, 80.0, 95.0, 120.0]);
+        let periodic = record.periodic_box();
+        assert_eq!(periodic.vectors[0], [42.0, 0.0, 0.0]);
+        let back = CrystalRecord::from_periodic_box(&periodic);
+        for (x, y) in back.lattice().iter().zip(record.lattice()) {
+            assert!((x - y).abs() < 1.0e-3, "{x} vs {y}");
+        }
+        assert_eq!(back.to_string(), record.to_string());
+        // and a plain cube is just a cube.
+        let cube = CrystalRecord::from_lattice([30.0, 30.0, 30.0, 90.0, 90.0, 90.0]);
+        assert_eq!(cube.periodic_box(), PeriodicBox::cubic(30.0));
+    }
+}
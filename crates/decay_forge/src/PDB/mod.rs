@@ -1 +1,21 @@
-pub mod coordinates;
\ No newline at end of file
+use std::fmt;
+
+pub mod coordinates;
+pub mod crystal;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdbError {
+    pub line: usize, // from 1
+    pub message: String,
+}
+
+impl fmt::Display for PdbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// a column range off the line, trimmed; short lines just run out of columns.
+fn field(line: &str, start: usize, end: usize) -> &str {
+    line.get(start..end.min(line.len())).unwrap_or("").trim()
+}
@@ -0,0 +1,7 @@
+// Topology and force field files from other packages, turned into things Legion can run.  Each format gets a module,
+// and they all build a system::System.
+pub mod AMBER;
+pub mod GROMACS;
+pub mod OpenMM;
+pub mod PDB;
+pub mod system;
@@ -1,5 +1,80 @@
-mod PDB;
+use decay_forge::system::System;
+use decay_forge::OpenMM::forcefield::ForceField;
+use decay_forge::AMBER::inpcrd::Inpcrd;
+use decay_forge::AMBER::prmtop::Prmtop;
+use decay_forge::GROMACS::topology::Topology;
+use decay_forge::PDB::coordinates::atoms;
+use decay_forge::PDB::crystal::crystal;
+use std::fmt::Display;
+use std::path::Path;
+use std::process::ExitCode;
+use std::{env, fs};
 
-fn main() {
-    println!("Hello, world!");
+// Reads a system in and says what came out of it, as a quick check that a set of files will import.
+//   decay_forge gromacs topol.top
+//   decay_forge amber system.prmtop [system.inpcrd]
+//   decay_forge openmm structure.pdb forcefield.xml [more.xml ...]
+const USAGE: &str =
+    "usage: decay_forge gromacs <top> | amber <prmtop> [inpcrd] | openmm <pdb> <xml>...";
+
+fn message<E: Display>(e: E) -> String {
+    e.to_string()
+}
+
+fn read(format: &str, files: &[String]) -> Result<System, String> {
+    match (format, files) {
+        ("gromacs", [top]) => {
+            System::from_topology(&Topology::from_file(Path::new(top)).map_err(message)?)
+                .map_err(message)
+        }
+        ("amber", [prmtop, rest @ ..]) if rest.len() <= 1 => {
+            let mut system =
+                System::from_prmtop(&Prmtop::from_file(Path::new(prmtop)).map_err(message)?)
+                    .map_err(message)?;
+            if let Some(inpcrd) = rest.first() {
+                let inpcrd = Inpcrd::from_file(Path::new(inpcrd)).map_err(message)?;
+                system.set_coordinates(&inpcrd).map_err(message)?;
+            }
+            Ok(system)
+        }
+        ("openmm", [pdb, xml @ ..]) if !xml.is_empty() => {
+            let mut forcefield = ForceField::new();
+            for file in xml {
+                forcefield.add_file(Path::new(file)).map_err(message)?;
+            }
+            let text = fs::read_to_string(pdb).map_err(|e| format!("{}: {}", pdb, e))?;
+            let structure = atoms(&text).map_err(|e| format!("{}: {}", pdb, e))?;
+            let cell = crystal(&text).map_err(|e| format!("{}: {}", pdb, e))?;
+            System::from_openmm(&forcefield, &structure, cell.as_ref()).map_err(message)
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((format, files)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let system = match read(format, files) {
+        Ok(system) => system,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let bonded = &system.bonded;
+    println!("{}", system.name);
+    println!("  atoms:       {}", system.atoms.len());
+    println!("  bonds:       {}", bonded.bonds.len());
+    println!("  angles:      {}", bonded.angles.len());
+    println!("  dihedrals:   {}", bonded.dihedrals.len());
+    println!("  impropers:   {}", bonded.impropers.len());
+    println!("  1-4 pairs:   {}", bonded.pairs.len());
+    println!("  exclusions:  {}", system.exclusions.len());
+    if let Some(periodic) = &system.periodic_box {
+        println!("  box:         {:?}", periodic.to_lattice());
+    }
+    ExitCode::SUCCESS
 }
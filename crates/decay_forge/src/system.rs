@@ -0,0 +1,143 @@
+use std::collections::{HashMap, VecDeque};
+use Legion::Dynamics::bonded::Bonded;
+use Legion::ForceFields::mixing::PairParameters;
+use Legion::ForceFields::SIN::Elements;
+use Legion::Topology::atom::Atom;
+use Legion::Topology::cell::Cell;
+use Legion::Topology::periodic::PeriodicBox;
+
+pub type World = Cell<Atom<Elements, f32, Vec<f32>>, f32>;
+
+// What the importers all build: the atoms (with their neighbors), the bonded terms between them (scaled 1-4 pairs
+// included), Lennard-Jones by element (see ElementTypes), the pairs that shouldn't see each other's non-bonded
+// interactions, and the box if the file had one.  Units are whatever the file used.
+pub struct System {
+    pub name: String,
+    pub atoms: Vec<Atom<Elements, f32, Vec<f32>>>, // in the same order as the coordinate file
+    pub bonded: Bonded,
+    pub parameters: PairParameters,
+    pub exclusions: Vec<(String, String)>,
+    pub periodic_box: Option<PeriodicBox>,
+}
+
+impl System {
+    // By id, for putting the coordinates in afterwards.
+    pub fn index(&self) -> HashMap<String, usize> {
+        self.atoms
+            .iter()
+            .enumerate()
+            .map(|(k, atom)| (atom.id.clone(), k))
+            .collect()
+    }
+
+    // Everything needed to run it: a Cell with the atoms, exclusions and box in, the bonded terms to hand the
+    // integrator, and the Lennard-Jones parameters for the force field.
+    pub fn into_cell(self) -> (World, Bonded, PairParameters) {
+        let mut cell = World::new();
+        cell.set_particles(
+            self.atoms
+                .into_iter()
+                .map(|atom| (atom.id.clone(), atom))
+                .collect(),
+        );
+        for (a, b) in self.exclusions.iter() {
+            cell.exclude(a, b);
+        }
+        cell.set_periodic_box(self.periodic_box);
+        (cell, self.bonded, self.parameters)
+    }
+}
+
+// From the atomic number, or failing that the mass; anything Legion doesn't have is X.
+pub fn element(atomic_number: Option<u32>, mass: f64) -> Elements {
+    let z = match atomic_number {
+        Some(z) if z > 0 => z,
+        _ => match mass.round() as u32 {
+            1 => 1,
+            12 => 6,
+            16 => 8,
+            _ => 0,
+        },
+    };
+    match z {
+        1 => Elements::H(0),
+        6 => Elements::C(0),
+        8 => Elements::O(0),
+        _ => Elements::X(0),
+    }
+}
+
+// Lennard-Jones by element, which is all PairParameters (and so Legion) keys on.  Atom types that share an element
+// have to share their parameters too, or there'd be no telling which ones an atom got; rather than quietly keep the
+// first, a clash comes back as a message naming both types, for the importer to wrap in its own error.
+pub struct ElementTypes {
+    seen: HashMap<String, (String, f32, f32)>, // element symbol -> (atom type, epsilon, sigma)
+}
+
+impl ElementTypes {
+    pub fn new() -> Self {
+        Self {
+            seen: HashMap::new(),
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        parameters: &mut PairParameters,
+        element: &Elements,
+        atom_type: &str,
+        epsilon: f32,
+        sigma: f32,
+    ) -> Result<(), String> {
+        let same = |a: f32, b: f32| (a - b).abs() <= 1.0e-5 * a.abs().max(b.abs());
+        match self.seen.get(element.symbol()) {
+            Some((_, e, s)) if same(*e, epsilon) && same(*s, sigma) => Ok(()),
+            Some((first, e, s)) => Err(format!(
+                "atom types {first} and {atom_type} are both {} but have different Lennard-Jones parameters, \
+                 ({e}, {s}) and ({epsilon}, {sigma}); Legion only gives each element one set",
+                element.symbol()
+            )),
+            None => {
+                self.seen.insert(
+                    element.symbol().to_string(),
+                    (atom_type.to_string(), epsilon, sigma),
+                );
+                parameters.set_element(element, epsilon, sigma);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Default for ElementTypes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Every pair (i < j) no more than depth bonds apart, breadth first out from each atom.
+pub fn within_bonds(neighbors: &[Vec<usize>], depth: usize) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for start in 0..neighbors.len() {
+        let mut distance = vec![usize::MAX; neighbors.len()];
+        distance[start] = 0;
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            if distance[i] == depth {
+                continue;
+            }
+            for &j in neighbors[i].iter() {
+                if distance[j] == usize::MAX {
+                    distance[j] = distance[i] + 1;
+                    queue.push_back(j);
+                }
+            }
+        }
+        for (other, d) in distance.iter().enumerate().skip(start + 1) {
+            if *d != usize::MAX {
+                pairs.push((start, other));
+            }
+        }
+    }
+    pairs
+}
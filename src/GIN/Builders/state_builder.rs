@@ -5,7 +5,6 @@ use std::marker::PhantomData;
 use Legion::ForceFields::SIN::ParticleGenerator;
 use crate::GIN::state::State;
 use cgmath::{num_traits::ToPrimitive, prelude::*};
-use rand::{prelude::Distribution, Rng};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use wgpu::util::DeviceExt;
@@ -13,6 +12,7 @@ use winit::window::Window;
 
 use Legion::{
     Dynamics::integrator::Leapfrog,
+    Dynamics::velocities::MaxwellBoltzmann,
     ForceFields::SIN::{self, Elements},
     Topology::atom::{Atom, Atomic, Connected},
     Topology::particle::{HasPhysics, IsSpatial},
@@ -547,10 +547,7 @@ where
     pub fn sin(mut self) -> Self {
         // let's just make some atoms!
         // let's make them use some of the instance things.
-        let sin = SIN::SIN::<EleT> {
-            description: "SIN".to_string(),
-            particle_type: Vec::new(),
-        };
+        let sin = SIN::SIN::<EleT>::new();
         self.sin = Some(sin);
         self
     }
@@ -617,17 +614,6 @@ impl StateBuilderParticles<Elements>
                 instance.position.z.to_f32().unwrap(),
             ];
             atom.set_position(pos);
-            let mut rng = rand::thread_rng();
-            let sign: rand::distributions::Uniform<f32> =
-                rand::distributions::Uniform::from(-1.0..1.1);
-            let applyJitter = true;
-            if applyJitter {
-                let mut vel = vec![0.0; 3];
-                for i in 0..3 {
-                    vel[i] = (rng.gen_range(0.0..1000.0) / 1000.0) * sign.sample(&mut rng);
-                }
-                atom.set_velocity(vel);
-            }
             if priorAtom != "".to_string() {
                 atom.neighbors.push(priorAtom.clone());
             }
@@ -636,6 +622,11 @@ impl StateBuilderParticles<Elements>
             atomHashMap.insert(atom.id.clone(), atom);
         }
         self.cell.as_mut().unwrap().set_particles(atomHashMap);
+        // about as warm as the old random jitter, but with a proper temperature and no drift.
+        let applyThermalVelocities = true;
+        if applyThermalVelocities {
+            MaxwellBoltzmann::new(0.3).assign(self.cell.as_mut().unwrap());
+        }
         self
     }
 }
@@ -92,30 +92,8 @@ impl State<Elements, f32, Atom<Elements, f32, Vec<f32>>, Vec<f32>> {
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
 
-        // update the dynamics!  DO NOT WRITE DURING THIS TIME.
-        // let newWorld: HashMap::<String, Box<dyn Atomic>> = HashMap::new();
-        let mut accVec = HashMap::<String, Vec<f32>>::new();
-        for (name, _) in self.cell.get_particles() {
-            accVec.insert(
-                name.clone(),
-                self.integrator
-                    .calculate_forces(name.clone(), &self.cell, &self.sin),
-            );
-        }
-
-        // NOW we want to write.  So we use a different method: get mut particles!
-        for (atom, acc) in accVec.iter_mut() {
-            let particle = self.cell.get_mut_particles().get_mut(atom);
-            match particle {
-                Some(a) => {
-                    let (pos, vel, acc) = self.integrator.integrate(a, acc.to_vec());
-                    a.set_position(pos);
-                    a.set_velocity(vel);
-                    // a.set_acceleration(acc;)
-                }
-                None => (),
-            }
-        }
+        // update the dynamics!  Forces are all calculated before anything gets written.
+        self.integrator.step(&mut self.cell, &self.sin, &mut []);
 
         for instance in &mut self.instances {
             let amount = cgmath::Quaternion::from_angle_y(cgmath::Rad(ROTATION_SPEED));